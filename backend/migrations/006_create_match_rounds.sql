@@ -0,0 +1,27 @@
+-- One row per round, replacing the opaque matches.rounds_json column
+CREATE TABLE IF NOT EXISTS match_rounds (
+    match_id TEXT NOT NULL REFERENCES matches(id),
+    round_number INTEGER NOT NULL,
+    player1_choice TEXT,
+    player2_choice TEXT,
+    winner_id TEXT REFERENCES users(id),
+    started_at TEXT,
+    player1_decided_at TEXT,
+    player2_decided_at TEXT,
+    PRIMARY KEY (match_id, round_number)
+);
+
+CREATE INDEX IF NOT EXISTS idx_match_rounds_winner_id ON match_rounds(winner_id);
+
+-- Backfill existing matches; rounds_json stored "draw" as the winner of drawn rounds
+INSERT OR IGNORE INTO match_rounds (match_id, round_number, player1_choice, player2_choice, winner_id)
+SELECT
+    m.id,
+    json_extract(r.value, '$.round_number'),
+    json_extract(r.value, '$.player1_choice'),
+    json_extract(r.value, '$.player2_choice'),
+    CASE json_extract(r.value, '$.winner') WHEN 'draw' THEN NULL ELSE json_extract(r.value, '$.winner') END
+FROM matches m, json_each(m.rounds_json) r
+WHERE json_valid(m.rounds_json) AND json_extract(r.value, '$.round_number') IS NOT NULL;
+
+ALTER TABLE matches DROP COLUMN rounds_json;
@@ -0,0 +1,3 @@
+-- Access tokens carry the version they were issued under; bumping it (on ban)
+-- invalidates every token already handed out.
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
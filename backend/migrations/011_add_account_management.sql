@@ -0,0 +1,15 @@
+-- Self-service account changes. A new email address waits in
+-- `pending_email` until its verification link is used; renames are limited
+-- through `username_changed_at` and recorded in `username_history`.
+ALTER TABLE users ADD COLUMN pending_email TEXT;
+ALTER TABLE users ADD COLUMN username_changed_at TEXT;
+
+CREATE TABLE IF NOT EXISTS username_history (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    old_username TEXT NOT NULL,
+    new_username TEXT NOT NULL,
+    changed_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_username_history_user_id ON username_history(user_id);
@@ -0,0 +1,9 @@
+-- Replace the admin flag with a role. What each role may do is defined in
+-- code (`models::role`); existing admins keep full access.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'player';
+UPDATE users SET role = 'admin' WHERE is_admin = 1;
+
+DROP INDEX IF EXISTS idx_users_is_admin;
+ALTER TABLE users DROP COLUMN is_admin;
+
+CREATE INDEX IF NOT EXISTS idx_users_role ON users(role);
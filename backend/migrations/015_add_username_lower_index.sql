@@ -0,0 +1,5 @@
+-- Usernames are unique regardless of case. The check happens before writes
+-- (see `auth::username`); this index keeps the lookup cheap. It isn't
+-- UNIQUE so databases that already hold names differing only in case still
+-- migrate.
+CREATE INDEX IF NOT EXISTS idx_users_username_lower ON users(lower(username));
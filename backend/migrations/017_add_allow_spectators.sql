@@ -0,0 +1,3 @@
+-- Whether others may watch the user's matches. Spectating was opt-out per
+-- queue join before, so everyone starts opted in.
+ALTER TABLE users ADD COLUMN allow_spectators INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,24 @@
+-- Timestamps are stored as UTC without a time zone, matching the SQLite schema
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    google_id TEXT UNIQUE,
+    avatar_url TEXT,
+    elo INTEGER NOT NULL DEFAULT 1000,
+    total_games INTEGER NOT NULL DEFAULT 0,
+    wins INTEGER NOT NULL DEFAULT 0,
+    losses INTEGER NOT NULL DEFAULT 0,
+    draws INTEGER NOT NULL DEFAULT 0,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    is_banned BOOLEAN NOT NULL DEFAULT FALSE,
+    banned_at TIMESTAMP,
+    banned_reason TEXT,
+    is_ai BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_elo ON users(elo);
+CREATE INDEX IF NOT EXISTS idx_users_is_ai ON users(is_ai);
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS matches (
+    id TEXT PRIMARY KEY NOT NULL,
+    player1_id TEXT NOT NULL REFERENCES users(id),
+    player2_id TEXT NOT NULL REFERENCES users(id),
+    winner_id TEXT REFERENCES users(id),
+    is_ranked BOOLEAN NOT NULL DEFAULT TRUE,
+    player1_score INTEGER NOT NULL DEFAULT 0,
+    player2_score INTEGER NOT NULL DEFAULT 0,
+    player1_elo_before INTEGER,
+    player1_elo_after INTEGER,
+    player2_elo_before INTEGER,
+    player2_elo_after INTEGER,
+    status TEXT NOT NULL DEFAULT 'in_progress',
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_matches_player1_id ON matches(player1_id);
+CREATE INDEX IF NOT EXISTS idx_matches_player2_id ON matches(player2_id);
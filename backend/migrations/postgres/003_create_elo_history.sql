@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS elo_history (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    match_id TEXT NOT NULL REFERENCES matches(id),
+    elo_before INTEGER NOT NULL,
+    elo_after INTEGER NOT NULL,
+    elo_change INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX IF NOT EXISTS idx_elo_history_user_id ON elo_history(user_id, created_at);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS match_rounds (
+    match_id TEXT NOT NULL REFERENCES matches(id) ON DELETE CASCADE,
+    round_number INTEGER NOT NULL,
+    player1_choice TEXT,
+    player2_choice TEXT,
+    winner_id TEXT REFERENCES users(id),
+    started_at TIMESTAMP(3),
+    player1_decided_at TIMESTAMP(3),
+    player2_decided_at TIMESTAMP(3),
+    PRIMARY KEY (match_id, round_number)
+);
+
+CREATE INDEX IF NOT EXISTS idx_match_rounds_winner_id ON match_rounds(winner_id);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS guest_matches (
+    id TEXT PRIMARY KEY NOT NULL,
+    player1_id TEXT NOT NULL,
+    player2_id TEXT NOT NULL,
+    winner_id TEXT,
+    player1_score INTEGER NOT NULL,
+    player2_score INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    finished_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX IF NOT EXISTS idx_guest_matches_player1_id ON guest_matches(player1_id);
+CREATE INDEX IF NOT EXISTS idx_guest_matches_player2_id ON guest_matches(player2_id);
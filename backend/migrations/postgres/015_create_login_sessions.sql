@@ -0,0 +1,24 @@
+-- One row per login. The refresh tokens of a login use its id as their
+-- family_id, and its access tokens carry it as `sid`. Revoking the row ends
+-- the login everywhere. ip_hash is keyed with the server secret, so it can
+-- be compared but not reversed.
+CREATE TABLE IF NOT EXISTS login_sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_agent TEXT,
+    ip_hash TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    last_seen_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_sessions_user_id ON login_sessions(user_id);
+
+-- Logins from before this table keep working: each live refresh token
+-- family becomes a session without device details.
+INSERT INTO login_sessions (id, user_id, created_at, last_seen_at, expires_at)
+SELECT family_id, user_id, MIN(created_at), MAX(created_at), MAX(expires_at)
+FROM refresh_tokens
+WHERE revoked_at IS NULL
+GROUP BY family_id, user_id;
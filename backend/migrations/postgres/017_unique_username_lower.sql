@@ -0,0 +1,18 @@
+-- Enforce case-insensitive username uniqueness on write, so two
+-- registrations or renames racing past the check in `auth::username`
+-- can't both succeed. Where names already differ only in case, the oldest
+-- account keeps its name and the others get the first 11 characters of it
+-- followed by `_` and 8 characters of their id, which stays within the
+-- 20 character limit.
+UPDATE users
+SET username = substr(username, 1, 11) || '_' || substr(replace(id, '-', ''), 1, 8)
+WHERE EXISTS (
+    SELECT 1 FROM users AS older
+    WHERE lower(older.username) = lower(users.username)
+      AND (older.created_at < users.created_at
+           OR (older.created_at = users.created_at AND older.id < users.id))
+);
+
+DROP INDEX IF EXISTS idx_users_username_lower;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_username_lower ON users(lower(username));
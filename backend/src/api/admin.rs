@@ -1,18 +1,16 @@
+use actix::Addr;
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 
-use crate::auth::middleware::AuthenticatedUser;
-use crate::db::Database;
+use crate::auth::permissions::{perm, Authorized};
+use crate::auth::sessions;
+use crate::config::AppConfig;
 use crate::errors::AppError;
+use crate::game::matchmaking::{DisconnectUser, MatchmakingActor};
+use crate::models::role::{Permission, Role};
 use crate::models::user::{PlatformStats, User};
-
-// Helper to check admin access
-async fn require_admin(db: &Database, user_id: &str) -> Result<(), AppError> {
-    if !User::is_admin(db, user_id).await? {
-        return Err(AppError::Unauthorized("Admin access required".into()));
-    }
-    Ok(())
-}
+use crate::repository::Repositories;
+use crate::snapshot::SnapshotKey;
 
 #[derive(Serialize)]
 pub struct AdminStatsResponse {
@@ -44,38 +42,48 @@ pub struct UpdateUserRequest {
     pub draws: Option<i32>,
 }
 
+#[derive(Deserialize)]
+pub struct SetRoleRequest {
+    pub role: Role,
+}
+
 #[derive(Deserialize)]
 pub struct BanUserRequest {
     pub reason: String,
 }
 
+#[derive(Deserialize)]
+pub struct SnapshotQuery {
+    #[serde(default)]
+    pub anonymize: bool,
+}
+
 pub async fn get_stats(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+    repos: web::Data<Repositories>,
+    _auth: Authorized<perm::StatsView>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
-
-    let stats = User::get_platform_stats(&db).await?;
+    let stats = repos.users.platform_stats().await?;
 
     Ok(HttpResponse::Ok().json(AdminStatsResponse { stats }))
 }
 
 pub async fn list_users(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+    repos: web::Data<Repositories>,
+    _auth: Authorized<perm::UsersView>,
     query: web::Query<ListUsersQuery>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
-
     let page = query.page.unwrap_or(1).max(1);
-    let limit = query.limit.unwrap_or(20).min(100).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * limit;
 
     let search = query.search.as_deref();
     let sort_by = query.sort_by.as_deref();
 
-    let users = User::list_with_filters(&db, search, sort_by, offset, limit).await?;
-    let total = User::count_all(&db, search).await?;
+    let users = repos
+        .users
+        .list_with_filters(search, sort_by, offset, limit)
+        .await?;
+    let total = repos.users.count_all(search).await?;
 
     Ok(HttpResponse::Ok().json(AdminUsersResponse {
         users,
@@ -86,43 +94,42 @@ pub async fn list_users(
 }
 
 pub async fn update_user(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    auth: Authorized<perm::UsersView>,
     user_id: web::Path<String>,
     body: web::Json<UpdateUserRequest>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
-
     // Prevent editing self
-    if &auth.user_id == user_id.as_str() {
+    if auth.user.id == user_id.as_str() {
         return Err(AppError::BadRequest("Cannot edit your own account".into()));
     }
 
-    // Prevent editing other admins
-    let target_user = User::find_by_id(&db, &user_id)
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    auth.can_manage(&target_user)?;
 
-    if target_user.is_admin {
-        return Err(AppError::BadRequest("Cannot edit admin accounts".into()));
+    if body.username.is_some() {
+        auth.require(Permission::UsersRename)?;
+    }
+    if body.elo.is_some() || body.wins.is_some() || body.losses.is_some() || body.draws.is_some() {
+        auth.require(Permission::UsersEditStats)?;
     }
 
     // Validate inputs
-    if let Some(ref username) = body.username {
-        if username.len() < 3 || username.len() > 20 {
-            return Err(AppError::BadRequest(
-                "Username must be between 3 and 20 characters".into(),
-            ));
-        }
-        if !username.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Err(AppError::BadRequest(
-                "Username can only contain alphanumeric characters and underscores".into(),
-            ));
-        }
+    let username = body.username.as_deref().map(str::trim);
+    if let Some(username) = username {
+        config
+            .username_policy
+            .check(&repos, username, Some(&target_user.id))
+            .await?;
     }
 
     if let Some(elo) = body.elo {
-        if elo < 0 || elo > 5000 {
+        if !(0..=5000).contains(&elo) {
             return Err(AppError::BadRequest(
                 "Elo must be between 0 and 5000".into(),
             ));
@@ -147,18 +154,21 @@ pub async fn update_user(
         }
     }
 
-    User::update_stats(
-        &db,
-        &user_id,
-        body.username.as_deref(),
-        body.elo,
-        body.wins,
-        body.losses,
-        body.draws,
-    )
-    .await?;
-
-    let updated_user = User::find_by_id(&db, &user_id)
+    repos
+        .users
+        .update_stats(
+            &user_id,
+            username,
+            body.elo,
+            body.wins,
+            body.losses,
+            body.draws,
+        )
+        .await?;
+
+    let updated_user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
 
@@ -166,26 +176,23 @@ pub async fn update_user(
 }
 
 pub async fn ban_user(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+    repos: web::Data<Repositories>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth: Authorized<perm::UsersBan>,
     user_id: web::Path<String>,
     body: web::Json<BanUserRequest>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
-
     // Prevent banning self
-    if &auth.user_id == user_id.as_str() {
+    if auth.user.id == user_id.as_str() {
         return Err(AppError::BadRequest("Cannot ban yourself".into()));
     }
 
-    // Prevent banning other admins
-    let target_user = User::find_by_id(&db, &user_id)
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
-
-    if target_user.is_admin {
-        return Err(AppError::BadRequest("Cannot ban admin accounts".into()));
-    }
+    auth.can_manage(&target_user)?;
 
     if body.reason.trim().is_empty() {
         return Err(AppError::BadRequest("Ban reason is required".into()));
@@ -197,7 +204,13 @@ pub async fn ban_user(
         ));
     }
 
-    User::ban(&db, &user_id, &body.reason).await?;
+    // The ban bumps the user's token version, so existing access tokens stop
+    // working; open sockets are closed here.
+    repos.users.ban(&user_id, &body.reason).await?;
+    matchmaking.do_send(DisconnectUser {
+        user_id: user_id.into_inner(),
+        reason: "Account banned".into(),
+    });
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "User banned successfully"
@@ -205,108 +218,279 @@ pub async fn ban_user(
 }
 
 pub async fn unban_user(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+    repos: web::Data<Repositories>,
+    auth: Authorized<perm::UsersBan>,
     user_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
-
-    let target_user = User::find_by_id(&db, &user_id)
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
 
+    auth.can_manage(&target_user)?;
+
     if !target_user.is_banned {
         return Err(AppError::BadRequest("User is not banned".into()));
     }
 
-    User::unban(&db, &user_id).await?;
+    repos.users.unban(&user_id).await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "User unbanned successfully"
     })))
 }
 
-pub async fn delete_user(
-    db: web::Data<Database>,
-    auth: AuthenticatedUser,
+/// The user's signed-in devices.
+pub async fn list_user_sessions(
+    repos: web::Data<Repositories>,
+    _auth: Authorized<perm::UsersView>,
     user_id: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    require_admin(&db, &auth.user_id).await?;
+    if repos.users.find_by_id(&user_id).await?.is_none() {
+        return Err(AppError::NotFound("User not found".into()));
+    }
+    let sessions = repos.sessions.list_sessions(&user_id).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "sessions": sessions })))
+}
 
+/// Sign one of the user's devices out, e.g. one the user reports stolen.
+pub async fn revoke_user_session(
+    repos: web::Data<Repositories>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth: Authorized<perm::UsersBan>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (user_id, session_id) = path.into_inner();
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    auth.can_manage(&target_user)?;
+
+    sessions::end_session(&repos, &matchmaking, &user_id, &session_id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn delete_user(
+    repos: web::Data<Repositories>,
+    auth: Authorized<perm::UsersDelete>,
+    user_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
     // Prevent deleting self
-    if &auth.user_id == user_id.as_str() {
+    if auth.user.id == user_id.as_str() {
         return Err(AppError::BadRequest("Cannot delete yourself".into()));
     }
 
-    // Prevent deleting other admins
-    let target_user = User::find_by_id(&db, &user_id)
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    auth.can_manage(&target_user)?;
 
-    if target_user.is_admin {
-        return Err(AppError::BadRequest("Cannot delete admin accounts".into()));
-    }
-
-    User::delete(&db, &user_id).await?;
+    repos.users.delete(&user_id).await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "User deleted successfully"
     })))
 }
 
+/// Take back a finished match, e.g. one decided by cheating. Both players
+/// get their rating and stats back; the match stays in history as voided.
+pub async fn void_match(
+    repos: web::Data<Repositories>,
+    _auth: Authorized<perm::MatchesVoid>,
+    match_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let voided = repos.matches.void_match(&match_id).await?;
+
+    Ok(HttpResponse::Ok().json(voided))
+}
+
+pub async fn set_user_role(
+    repos: web::Data<Repositories>,
+    auth: Authorized<perm::RolesAssign>,
+    user_id: web::Path<String>,
+    body: web::Json<SetRoleRequest>,
+) -> Result<HttpResponse, AppError> {
+    if auth.user.id == user_id.as_str() {
+        return Err(AppError::BadRequest("Cannot change your own role".into()));
+    }
+
+    let target_user = repos
+        .users
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    auth.can_manage(&target_user)?;
+    auth.can_assign(body.role)?;
+
+    repos.users.set_role(&user_id, body.role).await?;
+
+    let updated_user = repos
+        .users
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    Ok(HttpResponse::Ok().json(updated_user))
+}
+
+/// Connection pool usage for the configured database.
+pub async fn get_pool_metrics(
+    repos: web::Data<Repositories>,
+    _auth: Authorized<perm::SystemView>,
+) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(repos.pool.pool_metrics()))
+}
+
+/// Download every table as a JSON Lines snapshot (see `crate::snapshot`).
+/// It holds password hashes, so API tokens can't call this route.
+pub async fn export_snapshot(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    _auth: Authorized<perm::DataExport>,
+    query: web::Query<SnapshotQuery>,
+) -> Result<HttpResponse, AppError> {
+    let mut snapshot = repos.snapshots.export().await?;
+    if query.anonymize {
+        snapshot.anonymize();
+    }
+
+    let key = config.snapshot_key.as_deref().map(SnapshotKey::new);
+    let mut body = Vec::new();
+    snapshot
+        .write_jsonl(&mut body, key.as_ref())
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let filename = format!(
+        "red-flip-snapshot-{}.jsonl",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ))
+        .body(body))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
-    use crate::db::init_test_db;
-    use crate::models::user::User;
 
-    async fn create_admin_and_target(db: &Database) -> (User, User) {
-        let admin = User::create(db, "admin_api", "admin_api@example.com", "hash")
+    use crate::auth::permissions::RequiredPermission;
+    use crate::auth::username::UsernameViolation;
+    use crate::models::match_record::MatchResult;
+    use crate::repository::memory::MemoryStore;
+    use crate::repository::UserRepository;
+    use crate::snapshot::Snapshot;
+    use crate::test_support::{json_body, test_matchmaking};
+
+    fn test_config(require_admin_2fa: bool) -> AppConfig {
+        AppConfig {
+            require_admin_2fa,
+            ..AppConfig::for_tests()
+        }
+    }
+
+    async fn authorize<P: RequiredPermission>(
+        repos: &Repositories,
+        user_id: &str,
+        require_admin_2fa: bool,
+    ) -> Result<Authorized<P>, AppError> {
+        let user = repos
+            .users
+            .find_by_id(user_id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        Authorized::check(&test_config(require_admin_2fa), user)
+    }
+
+    async fn as_admin<P: RequiredPermission>(repos: &Repositories, user_id: &str) -> Authorized<P> {
+        authorize(repos, user_id, false)
+            .await
+            .expect("admin should be authorized")
+    }
+
+    async fn create_admin_and_target() -> (web::Data<Repositories>, User, User) {
+        let store = Arc::new(MemoryStore::new());
+        let admin = store
+            .create("admin_api", "admin_api@example.com", "hash")
             .await
             .expect("admin user should be created");
-        let target = User::create(db, "target_api", "target_api@example.com", "hash")
+        let target = store
+            .create("target_api", "target_api@example.com", "hash")
             .await
             .expect("target user should be created");
+        store
+            .set_role(&admin.id, Role::Admin)
+            .await
+            .expect("role should be set");
 
-        let conn = db.connect().expect("connection should be available");
-        conn.execute(
-            "UPDATE users SET is_admin = 1 WHERE id = ?1",
-            [admin.id.as_str()],
+        (
+            web::Data::new(Repositories::from_store(store)),
+            admin,
+            target,
         )
-        .await
-        .expect("admin flag update should succeed");
-
-        (admin, target)
     }
 
     #[actix_rt::test]
     async fn non_admin_cannot_access_admin_stats() {
-        let db = web::Data::new(init_test_db().await);
-        let user = User::create(&db, "normal_api", "normal_api@example.com", "hash")
+        let repos = web::Data::new(Repositories::in_memory());
+        let user = repos
+            .users
+            .create("normal_api", "normal_api@example.com", "hash")
             .await
             .expect("user should be created");
 
-        let result = get_stats(
-            db,
-            AuthenticatedUser {
-                user_id: user.id.clone(),
-            },
-        )
-        .await;
+        let result = authorize::<perm::StatsView>(&repos, &user.id, false).await;
 
-        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+
+    #[actix_rt::test]
+    async fn admin_routes_can_require_two_factor() {
+        let (repos, admin, _) = create_admin_and_target().await;
+
+        authorize::<perm::StatsView>(&repos, &admin.id, false)
+            .await
+            .expect("2FA should not be required by default");
+        let refused = authorize::<perm::StatsView>(&repos, &admin.id, true).await;
+        assert!(matches!(refused, Err(AppError::Forbidden(_))));
+
+        repos
+            .two_factor
+            .set_pending_secret(&admin.id, "JBSWY3DPEHPK3PXP")
+            .await
+            .expect("secret should be stored");
+        repos
+            .two_factor
+            .enable(&admin.id, 1, &[])
+            .await
+            .expect("2FA should be enabled");
+        let auth = authorize::<perm::StatsView>(&repos, &admin.id, true)
+            .await
+            .expect("admin with 2FA should get through");
+        get_stats(repos, auth).await.expect("stats should load");
     }
 
     #[actix_rt::test]
     async fn update_user_rejects_self_and_invalid_username() {
-        let db = web::Data::new(init_test_db().await);
-        let (admin, target) = create_admin_and_target(&db).await;
+        let (repos, admin, target) = create_admin_and_target().await;
 
         let self_edit = update_user(
-            db.clone(),
-            AuthenticatedUser {
-                user_id: admin.id.clone(),
-            },
+            repos.clone(),
+            web::Data::new(test_config(false)),
+            as_admin(&repos, &admin.id).await,
             web::Path::from(admin.id.clone()),
             web::Json(UpdateUserRequest {
                 username: Some("new_name".into()),
@@ -320,8 +504,9 @@ mod tests {
         assert!(matches!(self_edit, Err(AppError::BadRequest(_))));
 
         let invalid_username = update_user(
-            db,
-            AuthenticatedUser { user_id: admin.id },
+            repos.clone(),
+            web::Data::new(test_config(false)),
+            as_admin(&repos, &admin.id).await,
             web::Path::from(target.id),
             web::Json(UpdateUserRequest {
                 username: Some("bad-name!".into()),
@@ -332,22 +517,312 @@ mod tests {
             }),
         )
         .await;
-        assert!(matches!(invalid_username, Err(AppError::BadRequest(_))));
+        assert!(matches!(
+            invalid_username,
+            Err(AppError::InvalidUsername(
+                UsernameViolation::InvalidCharacters
+            ))
+        ));
     }
 
     #[actix_rt::test]
-    async fn ban_user_requires_non_empty_reason() {
-        let db = web::Data::new(init_test_db().await);
-        let (admin, target) = create_admin_and_target(&db).await;
+    async fn ban_user_requires_a_reason_and_revokes_tokens() {
+        let (repos, admin, target) = create_admin_and_target().await;
+
+        let matchmaking = test_matchmaking(&repos);
 
         let result = ban_user(
-            db,
-            AuthenticatedUser { user_id: admin.id },
-            web::Path::from(target.id),
+            repos.clone(),
+            matchmaking.clone(),
+            as_admin(&repos, &admin.id).await,
+            web::Path::from(target.id.clone()),
             web::Json(BanUserRequest { reason: " ".into() }),
         )
         .await;
-
         assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        ban_user(
+            repos.clone(),
+            matchmaking,
+            as_admin(&repos, &admin.id).await,
+            web::Path::from(target.id.clone()),
+            web::Json(BanUserRequest {
+                reason: "cheating".into(),
+            }),
+        )
+        .await
+        .expect("ban should succeed");
+        let banned = repos
+            .users
+            .find_by_id(&target.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(banned.is_banned);
+        assert_eq!(banned.token_version, target.token_version + 1);
+    }
+
+    #[actix_rt::test]
+    async fn moderators_can_ban_players_but_not_edit_elo_or_staff() {
+        let (repos, admin, target) = create_admin_and_target().await;
+        let moderator = repos
+            .users
+            .create("moderator_api", "moderator_api@example.com", "hash")
+            .await
+            .expect("moderator should be created");
+        repos
+            .users
+            .set_role(&moderator.id, Role::Moderator)
+            .await
+            .expect("role should be set");
+        let as_moderator = || async {
+            authorize::<perm::UsersView>(&repos, &moderator.id, false)
+                .await
+                .expect("moderators should see users")
+        };
+
+        let edit_elo = update_user(
+            repos.clone(),
+            web::Data::new(test_config(false)),
+            as_moderator().await,
+            web::Path::from(target.id.clone()),
+            web::Json(UpdateUserRequest {
+                username: None,
+                elo: Some(3000),
+                wins: None,
+                losses: None,
+                draws: None,
+            }),
+        )
+        .await;
+        assert!(matches!(edit_elo, Err(AppError::Forbidden(_))));
+        update_user(
+            repos.clone(),
+            web::Data::new(test_config(false)),
+            as_moderator().await,
+            web::Path::from(target.id.clone()),
+            web::Json(UpdateUserRequest {
+                username: Some("renamed_api".into()),
+                elo: None,
+                wins: None,
+                losses: None,
+                draws: None,
+            }),
+        )
+        .await
+        .expect("moderators should be able to rename");
+
+        let delete = authorize::<perm::UsersDelete>(&repos, &moderator.id, false).await;
+        assert!(matches!(delete, Err(AppError::Forbidden(_))));
+
+        let matchmaking = test_matchmaking(&repos);
+        let ban = |user_id: String| {
+            let repos = repos.clone();
+            let matchmaking = matchmaking.clone();
+            let moderator_id = moderator.id.clone();
+            async move {
+                let auth = authorize::<perm::UsersBan>(&repos, &moderator_id, false)
+                    .await
+                    .expect("moderators should be able to ban");
+                ban_user(
+                    repos,
+                    matchmaking,
+                    auth,
+                    web::Path::from(user_id),
+                    web::Json(BanUserRequest {
+                        reason: "cheating".into(),
+                    }),
+                )
+                .await
+            }
+        };
+        let ban_admin = ban(admin.id.clone()).await;
+        assert!(matches!(ban_admin, Err(AppError::Forbidden(_))));
+        ban(target.id.clone())
+            .await
+            .expect("moderators should be able to ban players");
+    }
+
+    #[actix_rt::test]
+    async fn admins_can_list_and_revoke_a_users_sessions() {
+        let (repos, admin, target) = create_admin_and_target().await;
+        let session = repos
+            .refresh_tokens
+            .start_family(
+                &target.id,
+                &Default::default(),
+                "hash",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("session should start");
+        let matchmaking = test_matchmaking(&repos);
+
+        let resp = list_user_sessions(
+            repos.clone(),
+            as_admin(&repos, &admin.id).await,
+            web::Path::from(target.id.clone()),
+        )
+        .await
+        .expect("sessions should list");
+        let body = json_body(resp).await;
+        assert_eq!(body["sessions"][0]["id"], session.id.as_str());
+
+        let revoke = |session_id: &str| {
+            let path = web::Path::from((target.id.clone(), session_id.to_string()));
+            let repos = repos.clone();
+            let matchmaking = matchmaking.clone();
+            let admin_id = admin.id.clone();
+            async move {
+                let auth = as_admin(&repos, &admin_id).await;
+                revoke_user_session(repos, matchmaking, auth, path).await
+            }
+        };
+        revoke(&session.id)
+            .await
+            .expect("session should be revoked");
+        let again = revoke(&session.id).await;
+        assert!(matches!(again, Err(AppError::NotFound(_))));
+        let listed = repos
+            .sessions
+            .list_sessions(&target.id)
+            .await
+            .expect("sessions should list");
+        assert!(listed.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn roles_can_only_be_assigned_below_the_assigner() {
+        let (repos, admin, target) = create_admin_and_target().await;
+
+        let set = |user_id: String, role: Role| {
+            let repos = repos.clone();
+            let admin_id = admin.id.clone();
+            async move {
+                let auth = as_admin(&repos, &admin_id).await;
+                set_user_role(
+                    repos,
+                    auth,
+                    web::Path::from(user_id),
+                    web::Json(SetRoleRequest { role }),
+                )
+                .await
+            }
+        };
+
+        let demote_self = set(admin.id.clone(), Role::Player).await;
+        assert!(matches!(demote_self, Err(AppError::BadRequest(_))));
+
+        set(target.id.clone(), Role::Moderator)
+            .await
+            .expect("admins should be able to appoint moderators");
+        set(target.id.clone(), Role::Admin)
+            .await
+            .expect("admins should be able to appoint admins");
+        let demote_admin = set(target.id.clone(), Role::Player).await;
+        assert!(matches!(demote_admin, Err(AppError::Forbidden(_))));
+
+        let promoted = repos
+            .users
+            .find_by_id(&target.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(promoted.role, Role::Admin);
+    }
+
+    #[actix_rt::test]
+    async fn voiding_a_match_gives_back_ratings_and_stats() {
+        let (repos, admin, target) = create_admin_and_target().await;
+        let recorded = repos
+            .matches
+            .record_result(&MatchResult {
+                id: "void-me".into(),
+                player1_id: admin.id.clone(),
+                player2_id: target.id.clone(),
+                winner_id: Some(target.id.clone()),
+                is_ranked: true,
+                player1_score: 0,
+                player2_score: 3,
+                rounds: Vec::new(),
+                status: "completed".into(),
+            })
+            .await
+            .expect("result should be recorded");
+
+        let denied = authorize::<perm::MatchesVoid>(&repos, &target.id, false).await;
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+
+        let void = || {
+            let repos = repos.clone();
+            let admin_id = admin.id.clone();
+            async move {
+                void_match(
+                    repos.clone(),
+                    as_admin(&repos, &admin_id).await,
+                    web::Path::from("void-me".to_string()),
+                )
+                .await
+            }
+        };
+        void().await.expect("match should be voided");
+        assert!(matches!(void().await, Err(AppError::Conflict(_))));
+
+        let target = repos
+            .users
+            .find_by_id(&target.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!((target.elo, target.total_games, target.wins), (1000, 0, 0));
+        assert!(repos
+            .ratings
+            .for_user(&target.id, 10)
+            .await
+            .expect("history should load")
+            .is_empty());
+        let history = repos
+            .matches
+            .recent_for_user(&target.id, 10)
+            .await
+            .expect("matches should load");
+        assert_eq!(history[0].id, recorded.id);
+        assert_eq!(history[0].status, "voided");
+    }
+
+    #[actix_rt::test]
+    async fn export_snapshot_is_admin_only_and_can_anonymize() {
+        let (repos, admin, target) = create_admin_and_target().await;
+
+        let denied = authorize::<perm::DataExport>(&repos, &target.id, false).await;
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+
+        let resp = export_snapshot(
+            repos.clone(),
+            web::Data::new(test_config(false)),
+            as_admin(&repos, &admin.id).await,
+            web::Query(SnapshotQuery { anonymize: true }),
+        )
+        .await
+        .expect("admin should be able to export");
+        assert_eq!(
+            resp.headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok()),
+            Some("application/x-ndjson")
+        );
+
+        let body = actix_web::body::to_bytes(resp.into_body())
+            .await
+            .expect("body should be readable");
+        let snapshot = Snapshot::read_jsonl(body.as_ref(), None).expect("export should be valid");
+        assert_eq!(snapshot.users.len(), 102);
+        let exported = snapshot
+            .users
+            .iter()
+            .find(|u| u.id == target.id)
+            .expect("target should be exported");
+        assert!(exported.password_hash.is_none());
+        assert!(exported.email.ends_with("@users.invalid"));
     }
 }
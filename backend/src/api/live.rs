@@ -0,0 +1,108 @@
+use actix::Addr;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+
+use crate::errors::AppError;
+use crate::game::registry::{ListSessions, MatchSummary, SessionRegistry};
+
+/// Most matches listed at once.
+const LIVE_MATCHES_LIMIT: usize = 20;
+
+#[derive(Serialize)]
+pub struct LiveMatch {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub summary: MatchSummary,
+}
+
+/// Running matches that can be watched, highest rated first.
+pub async fn list_live_matches(
+    registry: web::Data<Addr<SessionRegistry>>,
+) -> Result<HttpResponse, AppError> {
+    let sessions = registry
+        .send(ListSessions)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let mut matches: Vec<LiveMatch> = sessions
+        .into_iter()
+        .filter(|session| session.summary.spectators_allowed)
+        .map(|session| LiveMatch {
+            session_id: session.id,
+            summary: session.summary,
+        })
+        .collect();
+    matches.sort_by_key(|m| std::cmp::Reverse(m.summary.player1.elo.max(m.summary.player2.elo)));
+    matches.truncate(LIVE_MATCHES_LIMIT);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "matches": matches })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::prelude::*;
+    use std::time::Duration;
+
+    use crate::game::session::GameSessionActor;
+    use crate::game::test_support::player;
+    use crate::repository::Repositories;
+
+    fn start_game(
+        registry: &Addr<SessionRegistry>,
+        players: (&str, &str),
+        elo: i32,
+        allow_spectators: bool,
+    ) {
+        let (sink, _) = player();
+        GameSessionActor::new(
+            players.0.into(),
+            players.0.into(),
+            elo,
+            true,
+            false,
+            sink.clone(),
+            players.1.into(),
+            players.1.into(),
+            elo,
+            true,
+            false,
+            sink,
+            false,
+            Repositories::in_memory(),
+            Duration::from_secs(20),
+            allow_spectators,
+            10,
+            registry.clone(),
+        )
+        .start();
+    }
+
+    #[actix_rt::test]
+    async fn lists_watchable_matches_highest_rated_first() {
+        use actix_web::{test, App};
+
+        let registry = SessionRegistry::default().start();
+        start_game(&registry, ("guest_a", "guest_b"), 1000, true);
+        start_game(&registry, ("guest_c", "guest_d"), 1400, true);
+        start_game(&registry, ("guest_e", "guest_f"), 1800, false);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(registry))
+                .route("/matches/live", web::get().to(list_live_matches)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/matches/live").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let matches = body["matches"].as_array().expect("matches should list");
+        let players: Vec<&str> = matches
+            .iter()
+            .filter_map(|m| m["player1"]["username"].as_str())
+            .collect();
+        assert_eq!(players, vec!["guest_c", "guest_a"]);
+        assert!(matches.iter().all(|m| m["session_id"].is_string()));
+    }
+}
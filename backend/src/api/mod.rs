@@ -1,4 +1,5 @@
 pub mod admin;
 pub mod dashboard;
 pub mod leaderboard;
+pub mod live;
 pub mod user;
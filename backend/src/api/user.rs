@@ -1,35 +1,272 @@
-use actix_web::{web, HttpResponse};
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::Deserialize;
 
+use crate::auth::handlers::{check_password, issue_tokens};
 use crate::auth::middleware::AuthenticatedUser;
-use crate::db::Database;
+use crate::auth::rate_limit::RateLimiter;
+use crate::auth::{email, sessions};
+use crate::config::AppConfig;
 use crate::errors::AppError;
+use crate::game::matchmaking::MatchmakingActor;
+use crate::mail::Mailer;
 use crate::models::user::{PublicUser, User};
+use crate::repository::Repositories;
+
+/// How long after a rename the user has to wait before the next one.
+const RENAME_COOLDOWN: Duration = Duration::days(30);
+const MAX_AVATAR_URL_LEN: usize = 2048;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    pub email: String,
+    /// Required unless the account signs in only through SSO.
+    pub current_password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct AvatarRequest {
+    /// `None` clears the avatar.
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SpectatorsRequest {
+    pub allow_spectators: bool,
+}
 
 pub async fn get_user(
-    db: web::Data<Database>,
+    repos: web::Data<Repositories>,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let user_id = path.into_inner();
-    let user = User::find_by_id(&db, &user_id)
+    let user = repos
+        .users
+        .find_by_id(&user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
 
+    let previous_usernames: Vec<String> = repos
+        .users
+        .username_history(&user.id)
+        .await?
+        .into_iter()
+        .map(|change| change.old_username)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user": PublicUser::from(user),
+        "previous_usernames": previous_usernames,
+    })))
+}
+
+async fn load_user(repos: &Repositories, user_id: &str) -> Result<User, AppError> {
+    repos
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
+
+/// Check the user's current password. Wrong guesses count towards the same
+/// lockout as failed logins, so this can't be used to guess it instead.
+async fn verify_current_password(
+    limiter: &RateLimiter,
+    user: &User,
+    password: &str,
+) -> Result<(), AppError> {
+    let password_hash = user.password_hash.as_ref().ok_or_else(|| {
+        AppError::BadRequest("This account uses single sign-on and has no password".into())
+    })?;
+
+    limiter.check_account(&user.email).await?;
+    if !bcrypt::verify(password, password_hash)? {
+        limiter.record_failure(&user.email).await?;
+        return Err(AppError::Unauthorized(
+            "Current password is incorrect".into(),
+        ));
+    }
+    limiter.record_success(&user.email).await
+}
+
+/// Change the password and sign out every other session, closing their game
+/// sockets. The caller gets a fresh session in the response.
+pub async fn change_password(
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<RateLimiter>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = load_user(&repos, &auth_user.user_id).await?;
+    verify_current_password(&limiter, &user, &body.current_password).await?;
+    check_password(&body.new_password)?;
+
+    let password_hash = bcrypt::hash(&body.new_password, 10)?;
+    repos.users.set_password(&user.id, &password_hash).await?;
+    repos.refresh_tokens.revoke_all_for_user(&user.id).await?;
+    sessions::disconnect(&matchmaking, &user.id, None, "Password changed");
+
+    // Setting the password bumped the token version.
+    let user = load_user(&repos, &user.id).await?;
+    let tokens = issue_tokens(&repos, &config, &user, &sessions::device(&req, &config)).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Start moving the account to a new address. Nothing changes until the
+/// link mailed to the new address is opened.
+pub async fn change_email(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<dyn Mailer>,
+    limiter: web::Data<RateLimiter>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<ChangeEmailRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = load_user(&repos, &auth_user.user_id).await?;
+    if user.password_hash.is_some() {
+        let password = body.current_password.as_deref().unwrap_or_default();
+        verify_current_password(&limiter, &user, password).await?;
+    }
+
+    let new_email = body.email.trim();
+    if !new_email.contains('@') {
+        return Err(AppError::BadRequest("Enter a valid email address".into()));
+    }
+    if new_email.eq_ignore_ascii_case(&user.email) {
+        return Err(AppError::BadRequest(
+            "That is already your email address".into(),
+        ));
+    }
+    if repos.users.find_by_email(new_email).await?.is_some() {
+        return Err(AppError::Conflict("Email already registered".into()));
+    }
+
+    repos
+        .users
+        .set_pending_email(&user.id, Some(new_email))
+        .await?;
+    email::send_email_change(&repos, &config, &**mailer, &user, new_email).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Rename the account. The old name is kept in the user's name history, and
+/// players can rename once per `RENAME_COOLDOWN`.
+pub async fn change_username(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<RenameRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = load_user(&repos, &auth_user.user_id).await?;
+    let username = body.username.trim();
+    if username == user.username {
+        return Err(AppError::BadRequest("That is already your username".into()));
+    }
+    config
+        .username_policy
+        .check(&repos, username, Some(&user.id))
+        .await?;
+
+    let last_change = user
+        .username_changed_at
+        .as_deref()
+        .and_then(|at| NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").ok());
+    if let Some(last_change) = last_change {
+        let next_allowed = last_change.and_utc() + RENAME_COOLDOWN;
+        let now = Utc::now();
+        if next_allowed > now {
+            return Err(AppError::TooManyRequests {
+                message: format!(
+                    "You can change your username again on {}",
+                    next_allowed.format("%Y-%m-%d")
+                ),
+                retry_after_secs: (next_allowed - now).num_seconds().max(1) as u64,
+            });
+        }
+    }
+
+    repos.users.rename(&user.id, username).await?;
+    let user = load_user(&repos, &user.id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user": PublicUser::from(user),
+    })))
+}
+
+/// Set the avatar to an image URL, or clear it with `null`.
+pub async fn set_avatar(
+    repos: web::Data<Repositories>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<AvatarRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = load_user(&repos, &auth_user.user_id).await?;
+    let avatar_url = body
+        .avatar_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty());
+    if let Some(url) = avatar_url {
+        // Avatars are shown to other players, so only https links are allowed.
+        let valid = url.len() <= MAX_AVATAR_URL_LEN
+            && reqwest::Url::parse(url).is_ok_and(|u| u.scheme() == "https" && u.has_host());
+        if !valid {
+            return Err(AppError::BadRequest(
+                "Avatar must be an https URL of at most 2048 characters".into(),
+            ));
+        }
+    }
+
+    repos.users.set_avatar(&user.id, avatar_url).await?;
+    let user = load_user(&repos, &user.id).await?;
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "user": PublicUser::from(user),
     })))
 }
 
+/// Let others watch your matches, or not. Applies from the next match.
+pub async fn set_spectators(
+    repos: web::Data<Repositories>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<SpectatorsRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = load_user(&repos, &auth_user.user_id).await?;
+    repos
+        .users
+        .set_allow_spectators(&user.id, body.allow_spectators)
+        .await?;
+    let user = load_user(&repos, &user.id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "allow_spectators": user.allow_spectators,
+    })))
+}
+
 pub async fn delete_account(
-    db: web::Data<Database>,
+    repos: web::Data<Repositories>,
     auth_user: AuthenticatedUser,
 ) -> Result<HttpResponse, AppError> {
     // Verify the user exists before deleting
-    User::find_by_id(&db, &auth_user.user_id)
+    repos
+        .users
+        .find_by_id(&auth_user.user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
 
     // Delete the user and all related data
-    User::delete(&db, &auth_user.user_id).await?;
+    repos.users.delete(&auth_user.user_id).await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "Account deleted successfully",
@@ -39,33 +276,340 @@ pub async fn delete_account(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::init_test_db;
+    use crate::auth::handlers::{refresh, verify_email, EmailTokenRequest, RefreshTokenRequest};
+
+    use crate::auth::username::UsernameViolation;
+    use crate::models::login_session::SessionDevice;
+    use crate::test_support::{
+        json_body, test_config, test_limiter, test_mailer, test_matchmaking, test_request,
+    };
+
+    async fn player(repos: &Repositories, username: &str, password: &str) -> User {
+        let hash = bcrypt::hash(password, 4).expect("password should hash");
+        repos
+            .users
+            .create(username, &format!("{username}@example.com"), &hash)
+            .await
+            .expect("user should be created")
+    }
+
+    fn auth(user: &User) -> AuthenticatedUser {
+        AuthenticatedUser {
+            user_id: user.id.clone(),
+            session_id: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn change_password_checks_the_current_one_and_signs_out_other_sessions() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let limiter = test_limiter();
+        let matchmaking = test_matchmaking(&repos);
+        let user = player(&repos, "changer", "old-password").await;
+        let old_session = issue_tokens(&repos, &cfg, &user, &SessionDevice::default())
+            .await
+            .expect("tokens should be issued");
+
+        let wrong = change_password(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            limiter.clone(),
+            matchmaking.clone(),
+            auth(&user),
+            web::Json(ChangePasswordRequest {
+                current_password: "guess".into(),
+                new_password: "new-password".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(wrong, Err(AppError::Unauthorized(_))));
+        let too_short = change_password(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            limiter.clone(),
+            matchmaking.clone(),
+            auth(&user),
+            web::Json(ChangePasswordRequest {
+                current_password: "old-password".into(),
+                new_password: "short".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(too_short, Err(AppError::BadRequest(_))));
+
+        let resp = change_password(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            limiter,
+            matchmaking.clone(),
+            auth(&user),
+            web::Json(ChangePasswordRequest {
+                current_password: "old-password".into(),
+                new_password: "new-password".into(),
+            }),
+        )
+        .await
+        .expect("password change should succeed");
+        let body = json_body(resp).await;
+        assert!(body["token"].is_string());
+        assert!(body["refresh_token"].is_string());
+
+        let updated = repos
+            .users
+            .find_by_id(&user.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert!(bcrypt::verify(
+            "new-password",
+            updated.password_hash.as_deref().unwrap_or_default()
+        )
+        .expect("hash should be readable"));
+        assert!(updated.token_version > user.token_version);
+        let old_session = refresh(
+            repos,
+            cfg,
+            matchmaking,
+            web::Json(RefreshTokenRequest {
+                refresh_token: old_session.refresh_token,
+            }),
+        )
+        .await;
+        assert!(matches!(old_session, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn email_changes_once_the_new_address_is_verified() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let (mailer, mailer_data) = test_mailer();
+        let user = player(&repos, "mover", "password").await;
+        player(&repos, "taken", "password").await;
+
+        let request = |email: &str, password: &str| ChangeEmailRequest {
+            email: email.into(),
+            current_password: Some(password.into()),
+        };
+        let taken = change_email(
+            repos.clone(),
+            cfg.clone(),
+            mailer_data.clone(),
+            test_limiter(),
+            auth(&user),
+            web::Json(request("taken@example.com", "password")),
+        )
+        .await;
+        assert!(matches!(taken, Err(AppError::Conflict(_))));
+        let wrong = change_email(
+            repos.clone(),
+            cfg.clone(),
+            mailer_data.clone(),
+            test_limiter(),
+            auth(&user),
+            web::Json(request("new@example.com", "guess")),
+        )
+        .await;
+        assert!(matches!(wrong, Err(AppError::Unauthorized(_))));
+
+        change_email(
+            repos.clone(),
+            cfg.clone(),
+            mailer_data,
+            test_limiter(),
+            auth(&user),
+            web::Json(request("new@example.com", "password")),
+        )
+        .await
+        .expect("email change should start");
+
+        let pending = repos
+            .users
+            .find_by_id(&user.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!(pending.email, "mover@example.com");
+        assert_eq!(pending.pending_email.as_deref(), Some("new@example.com"));
+
+        let sent = mailer.sent();
+        assert!(sent.iter().any(|mail| mail.to == "mover@example.com"));
+        let confirmation = sent
+            .iter()
+            .find(|mail| mail.to == "new@example.com")
+            .expect("the new address should get a link");
+        let link = "http://localhost:3000/verify-email?token=";
+        let start = confirmation
+            .body
+            .find(link)
+            .expect("mail should contain the link")
+            + link.len();
+        let token = confirmation.body[start..]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        verify_email(repos.clone(), cfg, web::Json(EmailTokenRequest { token }))
+            .await
+            .expect("the new address should verify");
+        let moved = repos
+            .users
+            .find_by_id(&user.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!(moved.email, "new@example.com");
+        assert_eq!(moved.pending_email, None);
+        assert!(moved.email_verified);
+    }
+
+    #[actix_rt::test]
+    async fn renames_are_recorded_and_limited_by_a_cooldown() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let user = player(&repos, "first_name", "password").await;
+        player(&repos, "taken_name", "password").await;
+        let cfg = test_config();
+
+        let rename = |username: &str| {
+            change_username(
+                repos.clone(),
+                cfg.clone(),
+                auth(&user),
+                web::Json(RenameRequest {
+                    username: username.into(),
+                }),
+            )
+        };
+        assert!(matches!(
+            rename("ab").await,
+            Err(AppError::InvalidUsername(
+                UsernameViolation::TooShort { .. }
+            ))
+        ));
+        assert!(matches!(
+            rename("Taken_Name").await,
+            Err(AppError::InvalidUsername(UsernameViolation::Taken))
+        ));
+        assert!(matches!(
+            rename("Admin").await,
+            Err(AppError::InvalidUsername(UsernameViolation::Reserved))
+        ));
+
+        let resp = rename("second_name").await.expect("rename should succeed");
+        assert_eq!(json_body(resp).await["user"]["username"], "second_name");
+        assert!(matches!(
+            rename("third_name").await,
+            Err(AppError::TooManyRequests { .. })
+        ));
+
+        let resp = get_user(repos.clone(), web::Path::from(user.id.clone()))
+            .await
+            .expect("user should be found");
+        let body = json_body(resp).await;
+        assert_eq!(body["user"]["username"], "second_name");
+        assert_eq!(
+            body["previous_usernames"],
+            serde_json::json!(["first_name"])
+        );
+    }
+
+    #[actix_rt::test]
+    async fn avatars_must_be_https_urls_and_can_be_cleared() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let user = player(&repos, "pictured", "password").await;
+
+        let set = |avatar_url: Option<&str>| {
+            set_avatar(
+                repos.clone(),
+                auth(&user),
+                web::Json(AvatarRequest {
+                    avatar_url: avatar_url.map(str::to_string),
+                }),
+            )
+        };
+        for invalid in [
+            "http://example.com/a.png",
+            "javascript:alert(1)",
+            "not a url",
+        ] {
+            assert!(matches!(
+                set(Some(invalid)).await,
+                Err(AppError::BadRequest(_))
+            ));
+        }
+        let too_long = format!("https://example.com/{}", "a".repeat(MAX_AVATAR_URL_LEN));
+        assert!(matches!(
+            set(Some(&too_long)).await,
+            Err(AppError::BadRequest(_))
+        ));
+
+        let resp = set(Some("https://example.com/a.png"))
+            .await
+            .expect("avatar should be set");
+        assert_eq!(
+            json_body(resp).await["user"]["avatar_url"],
+            "https://example.com/a.png"
+        );
+        let resp = set(None).await.expect("avatar should be cleared");
+        assert!(json_body(resp).await["user"]["avatar_url"].is_null());
+    }
+
+    #[actix_rt::test]
+    async fn the_spectator_setting_is_stored_on_the_user() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let user = player(&repos, "private", "password").await;
+        assert!(user.allow_spectators);
+
+        let set = |allow_spectators| {
+            set_spectators(
+                repos.clone(),
+                auth(&user),
+                web::Json(SpectatorsRequest { allow_spectators }),
+            )
+        };
+        let resp = set(false).await.expect("setting should be saved");
+        assert_eq!(json_body(resp).await["allow_spectators"], false);
+        let stored = load_user(&repos, &user.id).await.expect("user should load");
+        assert!(!stored.allow_spectators);
+
+        let resp = set(true).await.expect("setting should be saved");
+        assert_eq!(json_body(resp).await["allow_spectators"], true);
+    }
 
     #[actix_rt::test]
     async fn get_user_returns_not_found_for_missing_user() {
-        let db = web::Data::new(init_test_db().await);
-        let result = get_user(db, web::Path::from("missing-id".to_string())).await;
+        let repos = web::Data::new(Repositories::in_memory());
+        let result = get_user(repos, web::Path::from("missing-id".to_string())).await;
         assert!(matches!(result, Err(AppError::NotFound(_))));
     }
 
     #[actix_rt::test]
     async fn delete_account_deletes_existing_user() {
-        let db = web::Data::new(init_test_db().await);
-        let created = User::create(&db, "delete_me", "deleteme@example.com", "hash")
+        let repos = web::Data::new(Repositories::in_memory());
+        let created = repos
+            .users
+            .create("delete_me", "deleteme@example.com", "hash")
             .await
             .expect("user should be created");
 
         let resp = delete_account(
-            db.clone(),
+            repos.clone(),
             AuthenticatedUser {
                 user_id: created.id.clone(),
+                session_id: None,
             },
         )
         .await
         .expect("delete account should succeed");
 
         assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
-        let user = User::find_by_id(&db, &created.id)
+        let user = repos
+            .users
+            .find_by_id(&created.id)
             .await
             .expect("query should succeed");
         assert!(user.is_none());
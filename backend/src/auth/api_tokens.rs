@@ -0,0 +1,302 @@
+//! Personal API tokens for scripts and bots. A token is `rfp_` followed by 32
+//! random bytes as hex, and like refresh tokens only its SHA-256 hash is
+//! stored, so it is shown once, at creation.
+//!
+//! API tokens are refused everywhere except on routes that name the scope
+//! they need by adding `RequiredScope` to the resource's app data.
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::auth::middleware::AuthenticatedUser;
+use crate::auth::refresh;
+use crate::errors::AppError;
+use crate::models::api_token::{ApiScope, ApiToken};
+use crate::models::user::User;
+use crate::repository::Repositories;
+
+/// Tells API tokens apart from session JWTs.
+pub const TOKEN_PREFIX: &str = "rfp_";
+pub const MAX_TOKENS_PER_USER: usize = 25;
+pub const MAX_NAME_LEN: usize = 50;
+pub const MAX_EXPIRY_DAYS: i64 = 365;
+
+/// The scope an API token needs to call a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequiredScope(pub ApiScope);
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// A new token and the hash to store for it.
+pub fn generate() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = std::iter::once(TOKEN_PREFIX.to_string())
+        .chain(bytes.iter().map(|b| format!("{b:02x}")))
+        .collect();
+    let hash = refresh::hash(&token);
+    (token, hash)
+}
+
+/// Load the user an API token belongs to, checking that it carries the scope
+/// the route asks for.
+pub async fn authenticate(
+    repos: &Repositories,
+    token: &str,
+    required: Option<&RequiredScope>,
+) -> Result<User, AppError> {
+    let Some(RequiredScope(scope)) = required else {
+        return Err(AppError::Forbidden(
+            "API tokens can't be used for this request".into(),
+        ));
+    };
+    let api_token = repos
+        .api_tokens
+        .use_token(&refresh::hash(token))
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired API token".into()))?;
+    if !api_token.has_scope(*scope) {
+        return Err(AppError::Forbidden(format!(
+            "API token lacks the {} scope",
+            scope.as_str()
+        )));
+    }
+
+    let user = repos
+        .users
+        .find_by_id(&api_token.user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".into()))?;
+    if user.is_banned {
+        return Err(AppError::Unauthorized("Account banned".into()));
+    }
+    Ok(user)
+}
+
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    /// Never expires when omitted.
+    pub expires_in_days: Option<i64>,
+}
+
+pub async fn create(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+    body: web::Json<CreateTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Token name must be between 1 and {MAX_NAME_LEN} characters"
+        )));
+    }
+    let mut scopes = Vec::new();
+    for &scope in &body.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err(AppError::BadRequest("Pick at least one scope".into()));
+    }
+    let expires_at = match body.expires_in_days {
+        Some(days) if !(1..=MAX_EXPIRY_DAYS).contains(&days) => {
+            return Err(AppError::BadRequest(format!(
+                "Tokens can expire in 1 to {MAX_EXPIRY_DAYS} days"
+            )));
+        }
+        Some(days) => Some(
+            (Utc::now() + Duration::days(days))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        ),
+        None => None,
+    };
+
+    let user = repos
+        .users
+        .find_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    if scopes.contains(&ApiScope::AdminRead) && !user.role.is_staff() {
+        return Err(AppError::Forbidden(
+            "Only staff can create admin:read tokens".into(),
+        ));
+    }
+    if repos.api_tokens.list_tokens(&user.id).await?.len() >= MAX_TOKENS_PER_USER {
+        return Err(AppError::BadRequest(format!(
+            "You can have at most {MAX_TOKENS_PER_USER} API tokens"
+        )));
+    }
+
+    let (token, hash) = generate();
+    let api_token = repos
+        .api_tokens
+        .create_token(&user.id, name, &hash, &scopes, expires_at.as_deref())
+        .await?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "token": token,
+        "api_token": api_token,
+    })))
+}
+
+pub async fn list(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let tokens: Vec<ApiToken> = repos.api_tokens.list_tokens(&auth.user_id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tokens": tokens })))
+}
+
+pub async fn revoke(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+    token_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    if !repos
+        .api_tokens
+        .revoke_token(&auth.user_id, &token_id)
+        .await?
+    {
+        return Err(AppError::NotFound("API token not found".into()));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+
+    use crate::config::AppConfig;
+    use crate::test_support::json_body;
+
+    #[actix_rt::test]
+    async fn tokens_only_work_on_routes_that_accept_their_scope() {
+        let repos = Repositories::in_memory();
+        let user = repos
+            .users
+            .create("scripter", "scripter@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let (token, hash) = generate();
+        assert!(is_api_token(&token));
+        let api_token = repos
+            .api_tokens
+            .create_token(&user.id, "export", &hash, &[ApiScope::ReadProfile], None)
+            .await
+            .expect("token should be stored");
+
+        let whoami = || {
+            web::get()
+                .to(|auth: AuthenticatedUser| async move { HttpResponse::Ok().body(auth.user_id) })
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(repos.clone()))
+                .app_data(web::Data::new(AppConfig::for_tests()))
+                .service(
+                    web::resource("/profile")
+                        .app_data(RequiredScope(ApiScope::ReadProfile))
+                        .route(whoami()),
+                )
+                .service(
+                    web::resource("/matches")
+                        .app_data(RequiredScope(ApiScope::ReadMatches))
+                        .route(whoami()),
+                )
+                .route("/account", whoami()),
+        )
+        .await;
+        let call = |path: &str| {
+            test::TestRequest::get()
+                .uri(path)
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, call("/profile")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, call("/matches")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, call("/account")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let listed = repos
+            .api_tokens
+            .list_tokens(&user.id)
+            .await
+            .expect("tokens should list");
+        assert!(listed[0].last_used_at.is_some());
+
+        assert!(repos
+            .api_tokens
+            .revoke_token(&user.id, &api_token.id)
+            .await
+            .expect("revoke should succeed"));
+        let resp = test::call_service(&app, call("/profile")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn create_validates_scopes_and_shows_the_token_once() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let user = repos
+            .users
+            .create("bot_owner", "bot_owner@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let request = |scopes: Vec<ApiScope>, expires_in_days: Option<i64>| {
+            web::Json(CreateTokenRequest {
+                name: " bot ".into(),
+                scopes,
+                expires_in_days,
+            })
+        };
+        let auth = || AuthenticatedUser {
+            user_id: user.id.clone(),
+            session_id: None,
+        };
+
+        let admin = create(
+            repos.clone(),
+            auth(),
+            request(vec![ApiScope::AdminRead], None),
+        )
+        .await;
+        assert!(matches!(admin, Err(AppError::Forbidden(_))));
+        let empty = create(repos.clone(), auth(), request(Vec::new(), None)).await;
+        assert!(matches!(empty, Err(AppError::BadRequest(_))));
+        let forever = create(
+            repos.clone(),
+            auth(),
+            request(vec![ApiScope::Play], Some(0)),
+        )
+        .await;
+        assert!(matches!(forever, Err(AppError::BadRequest(_))));
+
+        let resp = create(
+            repos.clone(),
+            auth(),
+            request(vec![ApiScope::Play, ApiScope::Play], Some(30)),
+        )
+        .await
+        .expect("token should be created");
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body = json_body(resp).await;
+        assert!(body["token"]
+            .as_str()
+            .is_some_and(|t| t.starts_with(TOKEN_PREFIX)));
+        assert_eq!(body["api_token"]["name"], "bot");
+        assert_eq!(body["api_token"]["scopes"], serde_json::json!(["play"]));
+        assert!(body["api_token"].get("token_hash").is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Links mailed for email verification and password resets. The token in
+//! the link is a signed JWT whose `jti` names a row in `email_tokens`, which
+//! is what makes it single-use. It also names the address it was mailed to,
+//! so a link only ever verifies that address.
+
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::mail::{Email, Mailer};
+use crate::models::email_token::EmailTokenPurpose;
+use crate::models::user::User;
+use crate::repository::Repositories;
+
+/// Keeps these tokens from being accepted as access tokens and vice versa.
+const AUDIENCE: &str = "red-flip:email";
+
+pub const VERIFY_EMAIL_TTL_HOURS: i64 = 24;
+pub const RESET_PASSWORD_TTL_HOURS: i64 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailClaims {
+    sub: String,
+    aud: String,
+    purpose: String,
+    jti: String,
+    email: String,
+    exp: usize,
+}
+
+/// A used link: who it was for and the address it was mailed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailLink {
+    pub user_id: String,
+    pub email: String,
+}
+
+fn ttl(purpose: EmailTokenPurpose) -> Duration {
+    match purpose {
+        EmailTokenPurpose::VerifyEmail => Duration::hours(VERIFY_EMAIL_TTL_HOURS),
+        EmailTokenPurpose::ResetPassword => Duration::hours(RESET_PASSWORD_TTL_HOURS),
+    }
+}
+
+/// Sign a token for the user, to be mailed to `email`, and record it so it
+/// can be used once.
+pub async fn issue(
+    repos: &Repositories,
+    secret: &str,
+    user_id: &str,
+    email: &str,
+    purpose: EmailTokenPurpose,
+) -> Result<String, AppError> {
+    let jti = Uuid::new_v4().to_string();
+    let expires_at = Utc::now() + ttl(purpose);
+    repos
+        .email_tokens
+        .issue(
+            &jti,
+            user_id,
+            purpose,
+            &expires_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        )
+        .await?;
+
+    let claims = EmailClaims {
+        sub: user_id.to_string(),
+        aud: AUDIENCE.to_string(),
+        purpose: purpose.as_str().to_string(),
+        jti,
+        email: email.to_string(),
+        exp: expires_at.timestamp() as usize,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// Check the token and use it up.
+pub async fn consume(
+    repos: &Repositories,
+    secret: &str,
+    token: &str,
+    purpose: EmailTokenPurpose,
+) -> Result<EmailLink, AppError> {
+    let invalid = || AppError::BadRequest("This link is invalid or has expired".into());
+
+    let mut validation = Validation::default();
+    validation.set_audience(&[AUDIENCE]);
+    let claims = decode::<EmailClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .map_err(|_| invalid())?
+    .claims;
+    if claims.purpose != purpose.as_str() {
+        return Err(invalid());
+    }
+
+    if !repos
+        .email_tokens
+        .consume(&claims.jti, &claims.sub, purpose)
+        .await?
+    {
+        return Err(invalid());
+    }
+    Ok(EmailLink {
+        user_id: claims.sub,
+        email: claims.email,
+    })
+}
+
+pub async fn send_verification(
+    repos: &Repositories,
+    config: &AppConfig,
+    mailer: &dyn Mailer,
+    user: &User,
+) -> Result<(), AppError> {
+    let token = issue(
+        repos,
+        &config.jwt_secret,
+        &user.id,
+        &user.email,
+        EmailTokenPurpose::VerifyEmail,
+    )
+    .await?;
+    mailer
+        .send(Email {
+            to: user.email.clone(),
+            subject: "Verify your Red Flip email address".into(),
+            body: format!(
+                "Hi {},\n\nConfirm your email address by opening this link:\n\n{}/verify-email?token={token}\n\nThe link expires in {VERIFY_EMAIL_TTL_HOURS} hours.\n",
+                user.username, config.frontend_url
+            ),
+        })
+        .await
+}
+
+pub async fn send_password_reset(
+    repos: &Repositories,
+    config: &AppConfig,
+    mailer: &dyn Mailer,
+    user: &User,
+) -> Result<(), AppError> {
+    let token = issue(
+        repos,
+        &config.jwt_secret,
+        &user.id,
+        &user.email,
+        EmailTokenPurpose::ResetPassword,
+    )
+    .await?;
+    mailer
+        .send(Email {
+            to: user.email.clone(),
+            subject: "Reset your Red Flip password".into(),
+            body: format!(
+                "Hi {},\n\nSomeone asked to reset your password. If it was you, choose a new one here:\n\n{}/reset-password?token={token}\n\nThe link expires in {RESET_PASSWORD_TTL_HOURS} hour. If you didn't ask for this, you can ignore this email.\n",
+                user.username, config.frontend_url
+            ),
+        })
+        .await
+}
+
+/// Mail a verification link to the address the user wants to switch to,
+/// and let the current address know about the change.
+pub async fn send_email_change(
+    repos: &Repositories,
+    config: &AppConfig,
+    mailer: &dyn Mailer,
+    user: &User,
+    new_email: &str,
+) -> Result<(), AppError> {
+    let token = issue(
+        repos,
+        &config.jwt_secret,
+        &user.id,
+        new_email,
+        EmailTokenPurpose::VerifyEmail,
+    )
+    .await?;
+    mailer
+        .send(Email {
+            to: new_email.to_string(),
+            subject: "Confirm your new Red Flip email address".into(),
+            body: format!(
+                "Hi {},\n\nConfirm that you want to use this address for your account by opening this link:\n\n{}/verify-email?token={token}\n\nThe link expires in {VERIFY_EMAIL_TTL_HOURS} hours.\n",
+                user.username, config.frontend_url
+            ),
+        })
+        .await?;
+    mailer
+        .send(Email {
+            to: user.email.clone(),
+            subject: "Your Red Flip email address is changing".into(),
+            body: format!(
+                "Hi {},\n\nSomeone asked to change your account's email address to {new_email}. It changes once the new address is confirmed. If this wasn't you, reset your password right away.\n",
+                user.username
+            ),
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt::{create_token, validate_token};
+    use crate::auth::keys::KeySet;
+
+    #[actix_rt::test]
+    async fn tokens_are_single_use_and_bound_to_their_purpose() {
+        let repos = Repositories::in_memory();
+        let user = repos
+            .users
+            .create("linker", "linker@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let token = issue(
+            &repos,
+            "secret",
+            &user.id,
+            &user.email,
+            EmailTokenPurpose::VerifyEmail,
+        )
+        .await
+        .expect("token should be issued");
+        assert!(
+            consume(&repos, "secret", &token, EmailTokenPurpose::ResetPassword)
+                .await
+                .is_err()
+        );
+        assert!(
+            consume(&repos, "other", &token, EmailTokenPurpose::VerifyEmail)
+                .await
+                .is_err()
+        );
+        assert!(validate_token(&token, &KeySet::from_secret("secret")).is_err());
+        assert_eq!(
+            consume(&repos, "secret", &token, EmailTokenPurpose::VerifyEmail)
+                .await
+                .expect("token should be accepted once"),
+            EmailLink {
+                user_id: user.id.clone(),
+                email: user.email.clone(),
+            }
+        );
+        assert!(matches!(
+            consume(&repos, "secret", &token, EmailTokenPurpose::VerifyEmail).await,
+            Err(AppError::BadRequest(_))
+        ));
+
+        let access = create_token(&user.id, 0, None, &KeySet::from_secret("secret"))
+            .expect("token should be created");
+        assert!(
+            consume(&repos, "secret", &access, EmailTokenPurpose::VerifyEmail)
+                .await
+                .is_err()
+        );
+
+        // Every link names the address it was mailed to
+        repos
+            .email_tokens
+            .issue(
+                "jti-without-email",
+                &user.id,
+                EmailTokenPurpose::VerifyEmail,
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("token row should be stored");
+        let addressless = encode(
+            &Header::default(),
+            &serde_json::json!({
+                "sub": user.id,
+                "aud": AUDIENCE,
+                "purpose": EmailTokenPurpose::VerifyEmail.as_str(),
+                "jti": "jti-without-email",
+                "exp": (Utc::now() + Duration::hours(1)).timestamp(),
+            }),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .expect("token should be created");
+        assert!(matches!(
+            consume(
+                &repos,
+                "secret",
+                &addressless,
+                EmailTokenPurpose::VerifyEmail
+            )
+            .await,
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+//! Guest identities. A guest token names a guest id and display name, so a
+//! guest keeps both across reconnects. Guests have no `users` row; their
+//! matches go to `guest_matches`, and registering with the token hands them
+//! to the new account (see `MatchRepository::claim_guest_matches`).
+//!
+//! Guest tokens are signed with a key derived from `JWT_SECRET`, not with
+//! the `JWT_KEYS` keyset, so rotating the keyset leaves them valid. Changing
+//! `JWT_SECRET` retires every guest token, and with them the guests'
+//! unclaimed matches.
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use ring::hkdf;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::models::guest_match::{is_guest_id, GUEST_ID_PREFIX};
+
+/// Keeps guest tokens from being accepted as access tokens.
+const GUEST_AUDIENCE: &str = "red-flip:guest";
+
+/// Renewed on every visit, so only guests who stay away this long lose
+/// their identity.
+pub const GUEST_TOKEN_TTL_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Guest {
+    pub id: String,
+    pub username: String,
+}
+
+impl Guest {
+    /// A fresh identity, e.g. `Guest3f9a`.
+    pub fn generate() -> Self {
+        let uuid = Uuid::new_v4().simple().to_string();
+        Self {
+            username: format!("Guest{}", &uuid[..4]),
+            id: format!("{GUEST_ID_PREFIX}{uuid}"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GuestClaims {
+    sub: String,
+    name: String,
+    aud: String,
+    exp: usize,
+}
+
+/// HS256 key length, in bytes.
+struct KeyLen;
+
+impl hkdf::KeyType for KeyLen {
+    fn len(&self) -> usize {
+        32
+    }
+}
+
+/// The key guest tokens are signed with, expanded from `secret` under its
+/// own label so it is never the key access tokens are signed with.
+fn signing_key(secret: &str) -> [u8; 32] {
+    let mut key = [0; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+        .extract(secret.as_bytes())
+        .expand(&[b"red-flip guest token"], KeyLen)
+        .and_then(|okm| okm.fill(&mut key))
+        .expect("32 bytes is a valid HKDF output length");
+    key
+}
+
+pub fn create_token(guest: &Guest, secret: &str) -> Result<String, AppError> {
+    let claims = GuestClaims {
+        sub: guest.id.clone(),
+        name: guest.username.clone(),
+        aud: GUEST_AUDIENCE.to_string(),
+        exp: (Utc::now() + Duration::days(GUEST_TOKEN_TTL_DAYS)).timestamp() as usize,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(&signing_key(secret)),
+    )?)
+}
+
+pub fn validate_token(token: &str, secret: &str) -> Result<Guest, AppError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[GUEST_AUDIENCE]);
+    let claims = decode::<GuestClaims>(
+        token,
+        &DecodingKey::from_secret(&signing_key(secret)),
+        &validation,
+    )?
+    .claims;
+    if !is_guest_id(&claims.sub) {
+        return Err(AppError::Unauthorized("Invalid guest token".into()));
+    }
+    Ok(Guest {
+        id: claims.sub,
+        username: claims.name,
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GuestRequest {
+    /// A previous guest token to renew. Expired or invalid tokens get a new
+    /// identity instead of an error.
+    pub guest_token: Option<String>,
+}
+
+/// `POST /auth/guest`: issue a guest token, keeping the identity of the one
+/// sent in, if any.
+pub async fn issue(
+    config: web::Data<AppConfig>,
+    body: Option<web::Json<GuestRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let guest = body
+        .and_then(|b| b.into_inner().guest_token)
+        .and_then(|token| validate_token(&token, &config.jwt_secret).ok())
+        .unwrap_or_else(Guest::generate);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "guest_token": create_token(&guest, &config.jwt_secret)?,
+        "guest": guest,
+        "expires_in": GUEST_TOKEN_TTL_DAYS * 24 * 60 * 60,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt;
+    use crate::auth::keys::KeySet;
+
+    #[test]
+    fn guest_tokens_round_trip_and_are_not_access_tokens() {
+        let guest = Guest::generate();
+        assert!(is_guest_id(&guest.id));
+        assert!(guest.username.starts_with("Guest"));
+
+        let token = create_token(&guest, "secret").expect("token should be created");
+        assert_eq!(
+            validate_token(&token, "secret").expect("token should validate"),
+            guest
+        );
+        assert!(validate_token(&token, "other-secret").is_err());
+        assert!(jwt::validate_token(&token, &KeySet::from_secret("secret")).is_err());
+
+        // Tokens signed with the raw secret, as access tokens are, are refused
+        let raw = encode(
+            &Header::default(),
+            &GuestClaims {
+                sub: guest.id.clone(),
+                name: guest.username.clone(),
+                aud: GUEST_AUDIENCE.to_string(),
+                exp: (Utc::now() + Duration::days(1)).timestamp() as usize,
+            },
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .expect("token should be created");
+        assert!(validate_token(&raw, "secret").is_err());
+    }
+}
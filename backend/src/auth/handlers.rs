@@ -1,18 +1,29 @@
-use actix_web::{web, HttpResponse};
-use serde::Deserialize;
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
 
-use crate::auth::jwt::create_token;
+use crate::auth::jwt::{create_token, ACCESS_TOKEN_TTL_MINUTES};
 use crate::auth::middleware::AuthenticatedUser;
+use crate::auth::rate_limit::{Action, RateLimiter};
+use crate::auth::{email, guest, refresh, sessions, totp};
 use crate::config::AppConfig;
-use crate::db::Database;
 use crate::errors::AppError;
+use crate::game::matchmaking::MatchmakingActor;
+use crate::mail::Mailer;
+use crate::models::email_token::EmailTokenPurpose;
+use crate::models::login_session::SessionDevice;
+use crate::models::refresh_token::RefreshOutcome;
 use crate::models::user::{PublicUser, User};
+use crate::repository::Repositories;
 
 #[derive(Deserialize)]
 pub struct RegisterRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// The caller's guest token, whose matches move to the new account.
+    #[serde(default)]
+    pub guest_token: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -21,40 +32,183 @@ pub struct LoginRequest {
     pub password: String,
 }
 
-pub async fn register(
-    db: web::Data<Database>,
-    config: web::Data<AppConfig>,
-    body: web::Json<RegisterRequest>,
-) -> Result<HttpResponse, AppError> {
-    if body.username.len() < 3 || body.username.len() > 20 {
-        return Err(AppError::BadRequest(
-            "Username must be between 3 and 20 characters".into(),
-        ));
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailTokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    /// Seconds until the access token expires.
+    pub expires_in: i64,
+}
+
+/// Start a login session on `device`: mint an access token and the first
+/// refresh token of a new family.
+pub(crate) async fn issue_tokens(
+    repos: &Repositories,
+    config: &AppConfig,
+    user: &User,
+    device: &SessionDevice,
+) -> Result<TokenPair, AppError> {
+    let next = refresh::generate();
+    let session = repos
+        .refresh_tokens
+        .start_family(&user.id, device, &next.hash, &next.expires_at)
+        .await?;
+
+    Ok(TokenPair {
+        token: create_token(
+            &user.id,
+            user.token_version,
+            Some(&session.id),
+            &config.jwt_keys,
+        )?,
+        refresh_token: next.token,
+        expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+    })
+}
+
+/// The body of a successful sign-in.
+pub(crate) fn session_json(tokens: TokenPair, user: User) -> serde_json::Value {
+    serde_json::json!({
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in,
+        "email_verified": user.email_verified,
+        "user": PublicUser::from(user),
+    })
+}
+
+/// What a user gets once their first factor checks out.
+pub(crate) enum SignIn {
+    Session(TokenPair),
+    /// 2FA is enabled; the challenge goes to `/auth/2fa/verify` with a code.
+    TwoFactorRequired {
+        challenge_token: String,
+    },
+}
+
+pub(crate) async fn sign_in(
+    repos: &Repositories,
+    config: &AppConfig,
+    user: &User,
+    device: &SessionDevice,
+) -> Result<SignIn, AppError> {
+    if user.totp_enabled {
+        return Ok(SignIn::TwoFactorRequired {
+            challenge_token: totp::create_challenge(user, &config.jwt_secret)?,
+        });
     }
-    if body.password.len() < 6 {
+    Ok(SignIn::Session(
+        issue_tokens(repos, config, user, device).await?,
+    ))
+}
+
+pub(crate) fn check_password(password: &str) -> Result<(), AppError> {
+    if password.len() < 6 {
         return Err(AppError::BadRequest(
             "Password must be at least 6 characters".into(),
         ));
     }
+    Ok(())
+}
+
+pub async fn register(
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<dyn Mailer>,
+    limiter: web::Data<RateLimiter>,
+    body: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, AppError> {
+    limiter.check_ip(&req, Action::Register).await?;
+    let username = body.username.trim();
+    config.username_policy.check(&repos, username, None).await?;
+    check_password(&body.password)?;
 
     let password_hash = bcrypt::hash(&body.password, 10)?;
-    let user = User::create(&db, &body.username, &body.email, &password_hash).await?;
-    let token = create_token(&user.id, &config.jwt_secret)?;
+    let mut user = repos
+        .users
+        .create(username, &body.email, &password_hash)
+        .await?;
+    if let Some(token) = &body.guest_token {
+        user = claim_guest(&repos, &config, token, user).await;
+    }
+    let tokens = issue_tokens(&repos, &config, &user, &sessions::device(&req, &config)).await?;
 
-    Ok(HttpResponse::Created().json(serde_json::json!({
-        "token": token,
-        "user": PublicUser::from(user),
-    })))
+    // The account works without it; the user can ask for another link later.
+    if let Err(e) = email::send_verification(&repos, &config, &**mailer, &user).await {
+        log::warn!("Could not send verification email to user {}: {e}", user.id);
+    }
+
+    Ok(HttpResponse::Created().json(session_json(tokens, user)))
 }
 
+/// Move a guest's matches to the account they just registered. A stale
+/// token or a failed claim doesn't fail the registration.
+async fn claim_guest(repos: &Repositories, config: &AppConfig, token: &str, user: User) -> User {
+    let guest = match guest::validate_token(token, &config.jwt_secret) {
+        Ok(guest) => guest,
+        Err(e) => {
+            log::info!(
+                "Ignoring guest token at registration of user {}: {e}",
+                user.id
+            );
+            return user;
+        }
+    };
+    match repos.matches.claim_guest_matches(&guest.id, &user.id).await {
+        Ok(0) => user,
+        Ok(_) => match repos.users.find_by_id(&user.id).await {
+            Ok(Some(claimed)) => claimed,
+            _ => user,
+        },
+        Err(e) => {
+            log::warn!(
+                "Could not claim guest {} for user {}: {e}",
+                guest.id,
+                user.id
+            );
+            user
+        }
+    }
+}
+
+/// Failed attempts count against both the caller's IP and the email
+/// address, so neither can be used to guess passwords quickly.
 pub async fn login(
-    db: web::Data<Database>,
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
     config: web::Data<AppConfig>,
+    limiter: web::Data<RateLimiter>,
     body: web::Json<LoginRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user = User::find_by_email(&db, &body.email)
-        .await?
-        .ok_or_else(|| AppError::Unauthorized("Invalid email or password".into()))?;
+    limiter.check_ip(&req, Action::Login).await?;
+    limiter.check_account(&body.email).await?;
+
+    let Some(user) = repos.users.find_by_email(&body.email).await? else {
+        limiter.record_failure(&body.email).await?;
+        return Err(AppError::Unauthorized("Invalid email or password".into()));
+    };
 
     // Check if user is banned
     if user.is_banned {
@@ -70,74 +224,309 @@ pub async fn login(
     let password_hash = user
         .password_hash
         .as_ref()
-        .ok_or_else(|| AppError::Unauthorized("This account uses Google sign-in".into()))?;
+        .ok_or_else(|| AppError::Unauthorized("This account uses single sign-on".into()))?;
 
     if !bcrypt::verify(&body.password, password_hash)? {
+        limiter.record_failure(&body.email).await?;
         return Err(AppError::Unauthorized("Invalid email or password".into()));
     }
+    limiter.record_success(&body.email).await?;
 
-    let token = create_token(&user.id, &config.jwt_secret)?;
+    match sign_in(&repos, &config, &user, &sessions::device(&req, &config)).await? {
+        SignIn::Session(tokens) => Ok(HttpResponse::Ok().json(session_json(tokens, user))),
+        SignIn::TwoFactorRequired { challenge_token } => {
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "two_factor_required": true,
+                "challenge_token": challenge_token,
+            })))
+        }
+    }
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "token": token,
-        "user": PublicUser::from(user),
-    })))
+/// Exchange a refresh token for a new access token and a new refresh token.
+/// The old refresh token stops working; replaying it revokes the whole family
+/// and closes the game sockets of its login session.
+pub async fn refresh(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    body: web::Json<RefreshTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let next = refresh::generate();
+    let outcome = repos
+        .refresh_tokens
+        .rotate(
+            &refresh::hash(&body.refresh_token),
+            &next.hash,
+            &next.expires_at,
+        )
+        .await?;
+
+    let (user_id, session_id) = match outcome {
+        RefreshOutcome::Rotated {
+            user_id,
+            session_id,
+        } => (user_id, session_id),
+        RefreshOutcome::Reused {
+            user_id,
+            session_id,
+        } => {
+            log::warn!("Refresh token reuse for user {user_id}; revoked its token family");
+            sessions::disconnect(&matchmaking, &user_id, Some(&session_id), "Session revoked");
+            return Err(AppError::Unauthorized(
+                "Refresh token has already been used".into(),
+            ));
+        }
+        RefreshOutcome::Invalid => {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired refresh token".into(),
+            ));
+        }
+    };
+
+    let user = match repos.users.find_by_id(&user_id).await? {
+        Some(user) if !user.is_banned => user,
+        _ => {
+            repos.refresh_tokens.revoke_family(&next.hash).await?;
+            return Err(AppError::Unauthorized("Account is not available".into()));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(TokenPair {
+        token: create_token(
+            &user.id,
+            user.token_version,
+            Some(&session_id),
+            &config.jwt_keys,
+        )?,
+        refresh_token: next.token,
+        expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+    }))
+}
+
+/// End one session by revoking its refresh token family and closing its
+/// game sockets. Unknown tokens are accepted so that logging out twice is
+/// harmless.
+pub async fn logout(
+    repos: web::Data<Repositories>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    body: web::Json<RefreshTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Some(family) = repos
+        .refresh_tokens
+        .revoke_family(&refresh::hash(&body.refresh_token))
+        .await?
+    {
+        sessions::disconnect(
+            &matchmaking,
+            &family.user_id,
+            Some(&family.session_id),
+            "Signed out",
+        );
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Revoke every session and refresh token the user holds, signing out all
+/// devices and closing their game sockets.
+pub async fn logout_all(
+    repos: web::Data<Repositories>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    repos
+        .refresh_tokens
+        .revoke_all_for_user(&auth.user_id)
+        .await?;
+    sessions::disconnect(&matchmaking, &auth.user_id, None, "Signed out everywhere");
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub async fn me(
-    db: web::Data<Database>,
+    repos: web::Data<Repositories>,
     auth: AuthenticatedUser,
 ) -> Result<HttpResponse, AppError> {
-    let user = User::find_by_id(&db, &auth.user_id)
+    let user = repos
+        .users
+        .find_by_id(&auth.user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("User not found".into()))?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
+        "email_verified": user.email_verified,
+        "pending_email": user.pending_email,
+        "allow_spectators": user.allow_spectators,
+        "permissions": user.role.permissions().iter().map(|p| p.as_str()).collect::<Vec<_>>(),
         "user": PublicUser::from(user),
     })))
 }
 
+/// Mail the signed-in user a fresh verification link.
+pub async fn request_email_verification(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<dyn Mailer>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let user = repos
+        .users
+        .find_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    if user.email_verified {
+        return Err(AppError::Conflict("Email is already verified".into()));
+    }
+
+    email::send_verification(&repos, &config, &**mailer, &user).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Verify the address a link was mailed to: the current one, or the one the
+/// user asked to switch to, which then replaces it.
+pub async fn verify_email(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    body: web::Json<EmailTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let link = email::consume(
+        &repos,
+        &config.jwt_secret,
+        &body.token,
+        EmailTokenPurpose::VerifyEmail,
+    )
+    .await?;
+    let invalid = || AppError::BadRequest("This link is invalid or has expired".into());
+    let user = repos
+        .users
+        .find_by_id(&link.user_id)
+        .await?
+        .ok_or_else(invalid)?;
+
+    if link.email == user.email {
+        repos.users.mark_email_verified(&user.id).await?;
+    } else if user.pending_email.as_deref() == Some(link.email.as_str()) {
+        if !repos
+            .users
+            .confirm_email_change(&user.id, &link.email)
+            .await?
+        {
+            return Err(invalid());
+        }
+    } else {
+        return Err(invalid());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Mail a reset link if the address belongs to an account. The response is
+/// the same either way so it can't be used to probe for accounts.
+pub async fn request_password_reset(
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<dyn Mailer>,
+    limiter: web::Data<RateLimiter>,
+    body: web::Json<PasswordResetRequest>,
+) -> Result<HttpResponse, AppError> {
+    limiter.check_ip(&req, Action::PasswordReset).await?;
+    match repos.users.find_by_email(body.email.trim()).await? {
+        Some(user) if !user.is_banned && !user.is_ai => {
+            if let Err(e) = email::send_password_reset(&repos, &config, &**mailer, &user).await {
+                log::warn!(
+                    "Could not send password reset email to user {}: {e}",
+                    user.id
+                );
+            }
+        }
+        _ => {}
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Set a new password from a reset link. Every existing session is signed
+/// out, and since the link arrived by email the address counts as verified.
+pub async fn reset_password(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    body: web::Json<NewPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    check_password(&body.password)?;
+
+    let link = email::consume(
+        &repos,
+        &config.jwt_secret,
+        &body.token,
+        EmailTokenPurpose::ResetPassword,
+    )
+    .await?;
+    let user = repos
+        .users
+        .find_by_id(&link.user_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("This link is invalid or has expired".into()))?;
+    let password_hash = bcrypt::hash(&body.password, 10)?;
+    repos.users.set_password(&user.id, &password_hash).await?;
+    // A link mailed to an address the account has since moved away from
+    // says nothing about the current one.
+    if link.email == user.email {
+        repos.users.mark_email_verified(&user.id).await?;
+    }
+    repos.refresh_tokens.revoke_all_for_user(&user.id).await?;
+    sessions::disconnect(&matchmaking, &user.id, None, "Password reset");
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::body::to_bytes;
-
-    use crate::db::init_test_db;
 
-    fn test_config() -> web::Data<AppConfig> {
-        web::Data::new(AppConfig {
-            database_url: "unused".into(),
-            database_auth_token: None,
-            jwt_secret: "test-secret".into(),
-            backend_port: 8080,
-            frontend_url: "http://localhost:3000".into(),
-        })
-    }
+    use crate::auth::username::UsernameViolation;
+    use crate::mail::MemoryMailer;
+    use crate::test_support::{
+        json_body, test_config, test_limiter, test_mailer, test_matchmaking, test_request,
+    };
 
     #[actix_rt::test]
     async fn register_rejects_invalid_input() {
-        let db = web::Data::new(init_test_db().await);
+        let repos = web::Data::new(Repositories::in_memory());
         let cfg = test_config();
 
-        let short_username = register(
-            db.clone(),
-            cfg.clone(),
-            web::Json(RegisterRequest {
-                username: "ab".into(),
-                email: "a@example.com".into(),
-                password: "password".into(),
-            }),
-        )
-        .await;
-        assert!(matches!(short_username, Err(AppError::BadRequest(_))));
+        for (username, reason) in [
+            ("ab", UsernameViolation::TooShort { min: 3 }),
+            ("bad-name!", UsernameViolation::InvalidCharacters),
+        ] {
+            let invalid_username = register(
+                test_request(),
+                repos.clone(),
+                cfg.clone(),
+                test_mailer().1,
+                test_limiter(),
+                web::Json(RegisterRequest {
+                    username: username.into(),
+                    email: "a@example.com".into(),
+                    password: "password".into(),
+                    guest_token: None,
+                }),
+            )
+            .await;
+            assert!(
+                matches!(&invalid_username, Err(AppError::InvalidUsername(r)) if *r == reason),
+                "{username}"
+            );
+        }
 
         let short_password = register(
-            db,
+            test_request(),
+            repos,
             cfg,
+            test_mailer().1,
+            test_limiter(),
             web::Json(RegisterRequest {
                 username: "valid_name".into(),
                 email: "b@example.com".into(),
                 password: "123".into(),
+                guest_token: None,
             }),
         )
         .await;
@@ -146,16 +535,20 @@ mod tests {
 
     #[actix_rt::test]
     async fn register_and_login_happy_path_and_banned_path() {
-        let db = web::Data::new(init_test_db().await);
+        let repos = web::Data::new(Repositories::in_memory());
         let cfg = test_config();
 
         let register_response = register(
-            db.clone(),
+            test_request(),
+            repos.clone(),
             cfg.clone(),
+            test_mailer().1,
+            test_limiter(),
             web::Json(RegisterRequest {
                 username: "auth_user".into(),
                 email: "auth@example.com".into(),
                 password: "secure-password".into(),
+                guest_token: None,
             }),
         )
         .await
@@ -164,16 +557,14 @@ mod tests {
             register_response.status(),
             actix_web::http::StatusCode::CREATED
         );
-        let reg_body = to_bytes(register_response.into_body())
-            .await
-            .expect("response body should be readable");
-        let reg_json: serde_json::Value =
-            serde_json::from_slice(&reg_body).expect("register response should be json");
+        let reg_json = json_body(register_response).await;
         assert!(reg_json["token"].as_str().is_some());
 
         let login_response = login(
-            db.clone(),
+            test_request(),
+            repos.clone(),
             cfg.clone(),
+            test_limiter(),
             web::Json(LoginRequest {
                 email: "auth@example.com".into(),
                 password: "secure-password".into(),
@@ -183,17 +574,23 @@ mod tests {
         .expect("login should succeed");
         assert_eq!(login_response.status(), actix_web::http::StatusCode::OK);
 
-        let created_user = User::find_by_email(&db, "auth@example.com")
+        let created_user = repos
+            .users
+            .find_by_email("auth@example.com")
             .await
             .expect("query should succeed")
             .expect("user should exist");
-        User::ban(&db, &created_user.id, "rule violation")
+        repos
+            .users
+            .ban(&created_user.id, "rule violation")
             .await
             .expect("ban should succeed");
 
         let banned_login = login(
-            db,
+            test_request(),
+            repos,
             cfg,
+            test_limiter(),
             web::Json(LoginRequest {
                 email: "auth@example.com".into(),
                 password: "secure-password".into(),
@@ -202,4 +599,450 @@ mod tests {
         .await;
         assert!(matches!(banned_login, Err(AppError::Unauthorized(_))));
     }
+
+    async fn login_refresh_token(
+        repos: &web::Data<Repositories>,
+        cfg: &web::Data<AppConfig>,
+        email: &str,
+    ) -> String {
+        let response = login(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_limiter(),
+            web::Json(LoginRequest {
+                email: email.into(),
+                password: "secure-password".into(),
+            }),
+        )
+        .await
+        .expect("login should succeed");
+        let json = json_body(response).await;
+        json["refresh_token"]
+            .as_str()
+            .expect("login should return a refresh token")
+            .to_string()
+    }
+
+    async fn refresh_with(
+        repos: &web::Data<Repositories>,
+        cfg: &web::Data<AppConfig>,
+        token: &str,
+    ) -> Result<String, AppError> {
+        let response = refresh(
+            repos.clone(),
+            cfg.clone(),
+            test_matchmaking(repos),
+            web::Json(RefreshTokenRequest {
+                refresh_token: token.into(),
+            }),
+        )
+        .await?;
+        let json = json_body(response).await;
+        assert!(json["token"].as_str().is_some());
+        Ok(json["refresh_token"]
+            .as_str()
+            .expect("refresh should return a new refresh token")
+            .to_string())
+    }
+
+    async fn registered(repos: &web::Data<Repositories>, cfg: &web::Data<AppConfig>, email: &str) {
+        register(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_mailer().1,
+            test_limiter(),
+            web::Json(RegisterRequest {
+                username: email.split('@').next().unwrap_or_default().into(),
+                email: email.into(),
+                password: "secure-password".into(),
+                guest_token: None,
+            }),
+        )
+        .await
+        .expect("register should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn refresh_rotates_and_reuse_revokes_the_family() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        registered(&repos, &cfg, "rotate@example.com").await;
+        let first = login_refresh_token(&repos, &cfg, "rotate@example.com").await;
+
+        let second = refresh_with(&repos, &cfg, &first)
+            .await
+            .expect("a fresh token should rotate");
+        assert_ne!(first, second);
+
+        let replayed = refresh_with(&repos, &cfg, &first).await;
+        assert!(matches!(replayed, Err(AppError::Unauthorized(_))));
+
+        // The replay revoked the family, so the legitimate successor is dead too.
+        let successor = refresh_with(&repos, &cfg, &second).await;
+        assert!(matches!(successor, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn logout_revokes_one_session_and_logout_all_revokes_every_session() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        registered(&repos, &cfg, "logout@example.com").await;
+        let laptop = login_refresh_token(&repos, &cfg, "logout@example.com").await;
+        let phone = login_refresh_token(&repos, &cfg, "logout@example.com").await;
+        let tablet = login_refresh_token(&repos, &cfg, "logout@example.com").await;
+
+        let response = logout(
+            repos.clone(),
+            test_matchmaking(&repos),
+            web::Json(RefreshTokenRequest {
+                refresh_token: laptop.clone(),
+            }),
+        )
+        .await
+        .expect("logout should succeed");
+        assert_eq!(response.status(), actix_web::http::StatusCode::NO_CONTENT);
+        assert!(refresh_with(&repos, &cfg, &laptop).await.is_err());
+        let phone = refresh_with(&repos, &cfg, &phone)
+            .await
+            .expect("other sessions should survive a single logout");
+
+        let user = repos
+            .users
+            .find_by_email("logout@example.com")
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        logout_all(
+            repos.clone(),
+            test_matchmaking(&repos),
+            AuthenticatedUser {
+                user_id: user.id.clone(),
+                session_id: None,
+            },
+        )
+        .await
+        .expect("logout-all should succeed");
+        assert!(refresh_with(&repos, &cfg, &phone).await.is_err());
+        assert!(refresh_with(&repos, &cfg, &tablet).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn refresh_is_refused_for_banned_users() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        registered(&repos, &cfg, "banned@example.com").await;
+        let token = login_refresh_token(&repos, &cfg, "banned@example.com").await;
+
+        let user = repos
+            .users
+            .find_by_email("banned@example.com")
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        repos
+            .users
+            .ban(&user.id, "rule violation")
+            .await
+            .expect("ban should succeed");
+
+        let result = refresh_with(&repos, &cfg, &token).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        assert!(matches!(
+            refresh_with(&repos, &cfg, "not-a-token").await,
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    fn link_token(mailer: &MemoryMailer, path: &str) -> String {
+        let mail = mailer.sent().pop().expect("a mail should have been sent");
+        let link = format!("http://localhost:3000/{path}?token=");
+        let start = mail.body.find(&link).expect("mail should contain the link") + link.len();
+        mail.body[start..]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[actix_rt::test]
+    async fn registering_with_a_guest_token_claims_the_guests_matches() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let guest = guest::Guest::generate();
+        let ai = repos.users.random_ai().await.expect("AI should exist");
+        repos
+            .matches
+            .record_guest_match(&crate::models::match_record::MatchResult {
+                id: uuid::Uuid::new_v4().to_string(),
+                player1_id: guest.id.clone(),
+                player2_id: ai.id.clone(),
+                winner_id: Some(guest.id.clone()),
+                is_ranked: false,
+                player1_score: 3,
+                player2_score: 2,
+                rounds: Vec::new(),
+                status: "completed".into(),
+            })
+            .await
+            .expect("guest match should be recorded");
+
+        let response = register(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_mailer().1,
+            test_limiter(),
+            web::Json(RegisterRequest {
+                username: "former_guest".into(),
+                email: "former_guest@example.com".into(),
+                password: "secure-password".into(),
+                guest_token: Some(
+                    guest::create_token(&guest, &cfg.jwt_secret).expect("token should be created"),
+                ),
+            }),
+        )
+        .await
+        .expect("register should succeed");
+        let body = json_body(response).await;
+        assert_eq!(body["user"]["total_games"], 1);
+        assert_eq!(body["user"]["wins"], 1);
+
+        let user_id = body["user"]["id"].as_str().expect("user should have an id");
+        let history = repos
+            .matches
+            .recent_for_user(user_id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].winner_id.as_deref(), Some(user_id));
+    }
+
+    #[actix_rt::test]
+    async fn registration_mails_a_single_use_verification_link() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let (mailer, mailer_data) = test_mailer();
+
+        register(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            mailer_data.clone(),
+            test_limiter(),
+            web::Json(RegisterRequest {
+                username: "verifier".into(),
+                email: "verifier@example.com".into(),
+                password: "secure-password".into(),
+                guest_token: None,
+            }),
+        )
+        .await
+        .expect("register should succeed");
+        let user = repos
+            .users
+            .find_by_email("verifier@example.com")
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert!(!user.email_verified);
+        assert_eq!(mailer.sent()[0].to, "verifier@example.com");
+
+        let auth = || AuthenticatedUser {
+            user_id: user.id.clone(),
+            session_id: None,
+        };
+        request_email_verification(repos.clone(), cfg.clone(), mailer_data.clone(), auth())
+            .await
+            .expect("a new link should be sent");
+        let token = link_token(&mailer, "verify-email");
+
+        let response = verify_email(
+            repos.clone(),
+            cfg.clone(),
+            web::Json(EmailTokenRequest {
+                token: token.clone(),
+            }),
+        )
+        .await
+        .expect("verification should succeed");
+        assert_eq!(response.status(), actix_web::http::StatusCode::NO_CONTENT);
+        let reused = verify_email(
+            repos.clone(),
+            cfg.clone(),
+            web::Json(EmailTokenRequest { token }),
+        )
+        .await;
+        assert!(matches!(reused, Err(AppError::BadRequest(_))));
+
+        let verified = repos
+            .users
+            .find_by_id(&user.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert!(verified.email_verified);
+        let again = request_email_verification(repos, cfg, mailer_data, auth()).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+    }
+
+    #[actix_rt::test]
+    async fn password_reset_sets_the_password_and_signs_out_every_session() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let (mailer, mailer_data) = test_mailer();
+        registered(&repos, &cfg, "forgetful@example.com").await;
+        let session = login_refresh_token(&repos, &cfg, "forgetful@example.com").await;
+
+        let unknown = request_password_reset(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            mailer_data.clone(),
+            test_limiter(),
+            web::Json(PasswordResetRequest {
+                email: "nobody@example.com".into(),
+            }),
+        )
+        .await
+        .expect("unknown addresses should look the same");
+        assert_eq!(unknown.status(), actix_web::http::StatusCode::NO_CONTENT);
+        assert!(mailer.sent().is_empty());
+
+        request_password_reset(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            mailer_data,
+            test_limiter(),
+            web::Json(PasswordResetRequest {
+                email: "forgetful@example.com".into(),
+            }),
+        )
+        .await
+        .expect("reset request should succeed");
+        let token = link_token(&mailer, "reset-password");
+
+        let too_short = reset_password(
+            repos.clone(),
+            cfg.clone(),
+            test_matchmaking(&repos),
+            web::Json(NewPasswordRequest {
+                token: token.clone(),
+                password: "123".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(too_short, Err(AppError::BadRequest(_))));
+
+        reset_password(
+            repos.clone(),
+            cfg.clone(),
+            test_matchmaking(&repos),
+            web::Json(NewPasswordRequest {
+                token: token.clone(),
+                password: "brand-new-password".into(),
+            }),
+        )
+        .await
+        .expect("reset should succeed");
+        let replayed = reset_password(
+            repos.clone(),
+            cfg.clone(),
+            test_matchmaking(&repos),
+            web::Json(NewPasswordRequest {
+                token,
+                password: "another-password".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(replayed, Err(AppError::BadRequest(_))));
+
+        assert!(refresh_with(&repos, &cfg, &session).await.is_err());
+        let old_password = login(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_limiter(),
+            web::Json(LoginRequest {
+                email: "forgetful@example.com".into(),
+                password: "secure-password".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(old_password, Err(AppError::Unauthorized(_))));
+        login(
+            test_request(),
+            repos.clone(),
+            cfg,
+            test_limiter(),
+            web::Json(LoginRequest {
+                email: "forgetful@example.com".into(),
+                password: "brand-new-password".into(),
+            }),
+        )
+        .await
+        .expect("the new password should work");
+        let user = repos
+            .users
+            .find_by_email("forgetful@example.com")
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert!(user.email_verified);
+        assert_eq!(user.token_version, 1);
+    }
+    #[actix_rt::test]
+    async fn repeated_failed_logins_lock_the_account_before_checking_passwords() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config();
+        let limiter = test_limiter();
+        registered(&repos, &cfg, "guessed@example.com").await;
+        let attempt = |password: &str, ip: &str| {
+            login(
+                actix_web::test::TestRequest::default()
+                    .peer_addr(format!("{ip}:40000").parse().expect("valid address"))
+                    .to_http_request(),
+                repos.clone(),
+                cfg.clone(),
+                limiter.clone(),
+                web::Json(LoginRequest {
+                    email: "guessed@example.com".into(),
+                    password: password.into(),
+                }),
+            )
+        };
+
+        // Spread over several IPs so only the account limit applies.
+        for i in 0..6 {
+            let result = attempt("wrong-password", &format!("10.0.0.{i}")).await;
+            assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        }
+        let locked = attempt("secure-password", "10.0.1.1").await;
+        assert!(matches!(
+            locked,
+            Err(AppError::TooManyRequests { retry_after_secs, .. }) if retry_after_secs > 0
+        ));
+
+        // Unknown accounts are limited the same way, and each IP has its own
+        // budget across accounts.
+        for _ in 0..10 {
+            let _ = attempt("wrong-password", "10.0.2.1").await;
+        }
+        let limited = login(
+            actix_web::test::TestRequest::default()
+                .peer_addr("10.0.2.1:40000".parse().expect("valid address"))
+                .to_http_request(),
+            repos.clone(),
+            cfg.clone(),
+            limiter.clone(),
+            web::Json(LoginRequest {
+                email: "nobody@example.com".into(),
+                password: "anything".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(limited, Err(AppError::TooManyRequests { .. })));
+    }
 }
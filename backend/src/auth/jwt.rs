@@ -1,7 +1,7 @@
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
+use crate::auth::keys::KeySet;
 use crate::errors::AppError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -9,30 +9,37 @@ pub struct Claims {
     pub sub: String, // user id
     pub exp: usize,
     pub iat: usize,
+    /// The user's `token_version` when the token was issued.
+    #[serde(default)]
+    pub ver: i32,
+    /// The login session the token was issued to. Tokens minted before
+    /// sessions were tracked have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
-pub fn create_token(user_id: &str, secret: &str) -> Result<String, AppError> {
+/// Access tokens are short-lived; clients renew them with a refresh token.
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+
+pub fn create_token(
+    user_id: &str,
+    token_version: i32,
+    session_id: Option<&str>,
+    keys: &KeySet,
+) -> Result<String, AppError> {
     let now = Utc::now();
     let claims = Claims {
         sub: user_id.to_string(),
         iat: now.timestamp() as usize,
-        exp: (now + Duration::days(7)).timestamp() as usize,
+        exp: (now + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize,
+        ver: token_version,
+        sid: session_id.map(str::to_string),
     };
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )?;
-    Ok(token)
+    keys.sign(&claims)
 }
 
-pub fn validate_token(token: &str, secret: &str) -> Result<Claims, AppError> {
-    let data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )?;
-    Ok(data.claims)
+pub fn validate_token(token: &str, keys: &KeySet) -> Result<Claims, AppError> {
+    keys.verify(token)
 }
 
 #[cfg(test)]
@@ -43,24 +50,28 @@ mod tests {
     #[test]
     fn create_and_validate_token_round_trip() {
         let user_id = "user-123";
-        let secret = "test-secret";
+        let keys = KeySet::from_secret("test-secret");
 
-        let token = create_token(user_id, secret).expect("token should be created");
-        let claims = validate_token(&token, secret).expect("token should validate");
+        let token =
+            create_token(user_id, 3, Some("session-1"), &keys).expect("token should be created");
+        let claims = validate_token(&token, &keys).expect("token should validate");
 
         assert_eq!(claims.sub, user_id);
+        assert_eq!(claims.ver, 3);
+        assert_eq!(claims.sid.as_deref(), Some("session-1"));
         assert!(claims.exp > claims.iat);
 
         let ttl = claims.exp as i64 - claims.iat as i64;
-        assert!((6 * 24 * 60 * 60..=8 * 24 * 60 * 60).contains(&ttl));
+        assert_eq!(ttl, ACCESS_TOKEN_TTL_MINUTES * 60);
         assert!(claims.iat as i64 <= Utc::now().timestamp());
     }
 
     #[test]
     fn validate_token_rejects_wrong_secret() {
-        let token = create_token("user-123", "right-secret").expect("token should be created");
+        let token = create_token("user-123", 0, None, &KeySet::from_secret("right-secret"))
+            .expect("token should be created");
 
-        let result = validate_token(&token, "wrong-secret");
+        let result = validate_token(&token, &KeySet::from_secret("wrong-secret"));
 
         assert!(matches!(result, Err(AppError::Unauthorized(_))));
     }
@@ -1,28 +1,50 @@
 use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
-use std::future::{ready, Ready};
+use std::future::Future;
+use std::pin::Pin;
 
-use crate::auth::jwt::validate_token;
+use crate::auth::api_tokens::{self, RequiredScope};
+use crate::auth::jwt::{validate_token, Claims};
+use crate::auth::keys::KeySet;
 use crate::config::AppConfig;
 use crate::errors::AppError;
+use crate::models::user::User;
+use crate::repository::Repositories;
 
 pub struct AuthenticatedUser {
     pub user_id: String,
+    /// The login session behind the access token. `None` for API tokens and
+    /// for access tokens issued before sessions were tracked.
+    pub session_id: Option<String>,
 }
 
 impl FromRequest for AuthenticatedUser {
     type Error = AppError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        let result = extract_user(req);
-        ready(result)
+        let req = req.clone();
+        Box::pin(async move { extract_user(&req).await })
     }
 }
 
-fn extract_user(req: &HttpRequest) -> Result<AuthenticatedUser, AppError> {
+async fn extract_user(req: &HttpRequest) -> Result<AuthenticatedUser, AppError> {
+    let (user, session_id) = authenticate(req).await?;
+    Ok(AuthenticatedUser {
+        user_id: user.id,
+        session_id,
+    })
+}
+
+/// Load the user the request's bearer token belongs to, and the login
+/// session of a session JWT. API tokens must carry the scope the route
+/// requires.
+pub(crate) async fn authenticate(req: &HttpRequest) -> Result<(User, Option<String>), AppError> {
     let config = req
         .app_data::<web::Data<AppConfig>>()
         .ok_or_else(|| AppError::Internal("Config not found".into()))?;
+    let repos = req
+        .app_data::<web::Data<Repositories>>()
+        .ok_or_else(|| AppError::Internal("Repositories not found".into()))?;
 
     let token = req
         .headers()
@@ -31,27 +53,74 @@ fn extract_user(req: &HttpRequest) -> Result<AuthenticatedUser, AppError> {
         .and_then(|v| v.strip_prefix("Bearer "))
         .ok_or_else(|| AppError::Unauthorized("Missing or invalid Authorization header".into()))?;
 
-    let claims = validate_token(token, &config.jwt_secret)?;
+    if api_tokens::is_api_token(token) {
+        let user = api_tokens::authenticate(repos, token, req.app_data::<RequiredScope>()).await?;
+        return Ok((user, None));
+    }
+    let claims = validate_token(token, &config.jwt_keys)?;
+    let user = load_active_user(repos, &claims).await?;
+    Ok((user, claims.sid))
+}
 
-    Ok(AuthenticatedUser {
-        user_id: claims.sub,
+/// Load the user a valid token belongs to, rejecting banned accounts, tokens
+/// issued before the user's current `token_version` and tokens of revoked
+/// sessions.
+pub async fn load_active_user(repos: &Repositories, claims: &Claims) -> Result<User, AppError> {
+    let user = load_user_at_version(repos, &claims.sub, claims.ver).await?;
+    if let Some(session_id) = &claims.sid {
+        check_session(repos, &user.id, session_id).await?;
+    }
+    Ok(user)
+}
+
+/// Refuse credentials of a login session that was signed out or revoked.
+pub async fn check_session(
+    repos: &Repositories,
+    user_id: &str,
+    session_id: &str,
+) -> Result<(), AppError> {
+    match repos.sessions.find_session(session_id).await? {
+        Some(session) if session.user_id == user_id && session.is_active() => Ok(()),
+        _ => Err(AppError::Unauthorized("Session has been revoked".into())),
+    }
+}
+
+/// Load a user for a credential issued at `token_version`, rejecting banned
+/// accounts and credentials the user has since revoked.
+pub async fn load_user_at_version(
+    repos: &Repositories,
+    user_id: &str,
+    token_version: i32,
+) -> Result<User, AppError> {
+    let user = repos
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".into()))?;
+
+    if user.is_banned {
+        return Err(AppError::Unauthorized("Account banned".into()));
+    }
+    if user.token_version != token_version {
+        return Err(AppError::Unauthorized("Token has been revoked".into()));
+    }
+
+    Ok(user)
+}
+
+/// The first non-empty value of `name` in a query string.
+pub fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name && !value.is_empty()).then_some(value)
     })
 }
 
-/// Extract optional user_id from query parameter (supports guest mode)
-pub fn extract_optional_user_from_query(query: &str, secret: &str) -> Option<String> {
-    let token = query.split('&').find_map(|pair| {
-        let mut parts = pair.splitn(2, '=');
-        let key = parts.next()?;
-        let value = parts.next()?;
-        if key == "token" && !value.is_empty() {
-            Some(value.to_string())
-        } else {
-            None
-        }
-    })?;
-
-    validate_token(&token, secret).ok().map(|claims| claims.sub)
+/// Extract optional claims from the `token` query parameter (supports guest mode)
+pub fn extract_optional_claims_from_query(query: &str, keys: &KeySet) -> Option<Claims> {
+    let token = query_param(query, "token")?;
+
+    validate_token(token, keys).ok()
 }
 
 #[cfg(test)]
@@ -60,25 +129,75 @@ mod tests {
     use crate::auth::jwt::create_token;
 
     #[test]
-    fn extract_optional_user_from_query_returns_user_id_for_valid_token() {
-        let secret = "test-secret";
-        let token = create_token("user-42", secret).expect("token should be created");
+    fn extract_optional_claims_from_query_returns_claims_for_valid_token() {
+        let keys = KeySet::from_secret("test-secret");
+        let token = create_token("user-42", 0, None, &keys).expect("token should be created");
 
-        let user_id = extract_optional_user_from_query(&format!("foo=bar&token={token}"), secret);
+        let claims = extract_optional_claims_from_query(&format!("foo=bar&token={token}"), &keys);
 
-        assert_eq!(user_id.as_deref(), Some("user-42"));
+        assert_eq!(claims.map(|c| c.sub).as_deref(), Some("user-42"));
     }
 
     #[test]
-    fn extract_optional_user_from_query_returns_none_for_missing_or_invalid_token() {
-        let secret = "test-secret";
-        let invalid = extract_optional_user_from_query("foo=bar", secret);
+    fn extract_optional_claims_from_query_returns_none_for_missing_or_invalid_token() {
+        let keys = KeySet::from_secret("test-secret");
+        let invalid = extract_optional_claims_from_query("foo=bar", &keys);
         assert!(invalid.is_none());
 
         let wrong_secret_token =
-            create_token("user-42", "different-secret").expect("token should be created");
+            create_token("user-42", 0, None, &KeySet::from_secret("different-secret"))
+                .expect("token should be created");
         let invalid =
-            extract_optional_user_from_query(&format!("token={wrong_secret_token}"), secret);
+            extract_optional_claims_from_query(&format!("token={wrong_secret_token}"), &keys);
         assert!(invalid.is_none());
     }
+
+    #[actix_rt::test]
+    async fn extractor_rejects_banned_users_and_stale_token_versions() {
+        use actix_web::{http::StatusCode, test, App, HttpResponse};
+
+        let repos = Repositories::in_memory();
+        let config = AppConfig::for_tests();
+        let user = repos
+            .users
+            .create("extractor", "extractor@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let token = create_token(&user.id, user.token_version, None, &config.jwt_keys)
+            .expect("token should be created");
+        let stale = create_token(&user.id, user.token_version - 1, None, &config.jwt_keys)
+            .expect("token should be created");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(repos.clone()))
+                .app_data(web::Data::new(config))
+                .route(
+                    "/whoami",
+                    web::get().to(|auth: AuthenticatedUser| async move {
+                        HttpResponse::Ok().body(auth.user_id)
+                    }),
+                ),
+        )
+        .await;
+        let call = |token: &str| {
+            test::TestRequest::get()
+                .uri("/whoami")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, call(&token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, call(&stale)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        repos
+            .users
+            .ban(&user.id, "cheating")
+            .await
+            .expect("ban should succeed");
+        let resp = test::call_service(&app, call(&token)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
 }
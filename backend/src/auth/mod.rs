@@ -1,3 +1,15 @@
+pub mod api_tokens;
+pub mod email;
+pub mod guest;
 pub mod handlers;
 pub mod jwt;
+pub mod keys;
 pub mod middleware;
+pub mod oidc;
+pub mod permissions;
+pub mod rate_limit;
+pub mod refresh;
+pub mod sessions;
+pub mod totp;
+pub mod username;
+pub mod ws_ticket;
@@ -0,0 +1,125 @@
+//! Per-route permission checks. A handler names the permission it needs in
+//! its signature, e.g. `auth: Authorized<perm::UsersBan>`, and requests from
+//! users whose role lacks it are refused with 403 before the handler runs.
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+
+use crate::auth::middleware::authenticate;
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::models::role::{Permission, Role};
+use crate::models::user::User;
+
+/// Names a permission at the type level, see `perm`.
+pub trait RequiredPermission: 'static {
+    const PERMISSION: Permission;
+}
+
+/// Marker types for `Authorized`. Permissions that only refine a route,
+/// such as `users.edit_stats`, are checked with `Authorized::require`.
+pub mod perm {
+    use super::{Permission, RequiredPermission};
+
+    macro_rules! markers {
+        ($($name:ident),* $(,)?) => {
+            $(
+                pub struct $name;
+
+                impl RequiredPermission for $name {
+                    const PERMISSION: Permission = Permission::$name;
+                }
+            )*
+        };
+    }
+
+    markers!(
+        StatsView,
+        UsersView,
+        UsersBan,
+        UsersDelete,
+        RolesAssign,
+        SystemView,
+        DataExport,
+        MatchesVoid,
+    );
+}
+
+/// A signed-in user whose role grants `P`.
+pub struct Authorized<P> {
+    pub user: User,
+    _permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> Authorized<P> {
+    /// Check that `user` may use routes requiring `P`. With
+    /// `require_admin_2fa`, staff also need 2FA enabled.
+    pub fn check(config: &AppConfig, user: User) -> Result<Self, AppError> {
+        require(&user, P::PERMISSION)?;
+        if config.require_admin_2fa && !user.totp_enabled {
+            return Err(AppError::Forbidden(
+                "Staff accounts must enable two-factor authentication".into(),
+            ));
+        }
+        Ok(Self {
+            user,
+            _permission: PhantomData,
+        })
+    }
+
+    /// Check a further permission, for routes whose needs depend on the request.
+    pub fn require(&self, permission: Permission) -> Result<(), AppError> {
+        require(&self.user, permission)
+    }
+
+    /// Staff can only manage users ranked below them, and never themselves.
+    pub fn can_manage(&self, target: &User) -> Result<(), AppError> {
+        if target.role >= self.user.role {
+            return Err(AppError::Forbidden(format!(
+                "Cannot manage {} accounts",
+                target.role.as_str()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Staff can hand out roles up to their own.
+    pub fn can_assign(&self, role: Role) -> Result<(), AppError> {
+        if role > self.user.role {
+            return Err(AppError::Forbidden(format!(
+                "Cannot assign the {} role",
+                role.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn require(user: &User, permission: Permission) -> Result<(), AppError> {
+    if !user.role.has(permission) {
+        return Err(AppError::Forbidden(format!(
+            "Missing permission {}",
+            permission.as_str()
+        )));
+    }
+    Ok(())
+}
+
+impl<P: RequiredPermission> FromRequest for Authorized<P> {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let (user, _) = authenticate(&req).await?;
+            let config = req
+                .app_data::<web::Data<AppConfig>>()
+                .ok_or_else(|| AppError::Internal("Config not found".into()))?;
+            Self::check(config, user)
+        })
+    }
+}
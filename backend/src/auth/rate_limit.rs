@@ -0,0 +1,399 @@
+//! Brute-force protection for the auth endpoints: sliding-window limits per
+//! client IP, and per-account lockouts after repeated failures that double
+//! in length each time they recur.
+//!
+//! Counting lives behind `RateLimitStore`. `MemoryRateLimitStore` keeps it
+//! in-process, which is enough for a single server; a shared store lets
+//! several servers enforce the same limits.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::AppConfig;
+use crate::errors::AppError;
+
+/// At most `max` hits in any `window`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limit {
+    pub max: u32,
+    pub window: Duration,
+}
+
+/// Failed attempts an account may have in a window before it is locked.
+const ACCOUNT_FAILURES: Limit = Limit {
+    max: 5,
+    window: Duration::minutes(15),
+};
+const LOCKOUT_BASE: Duration = Duration::minutes(1);
+const LOCKOUT_MAX: Duration = Duration::hours(1);
+/// How long after a lockout ends the next one still counts as a repeat.
+pub const LOCKOUT_MEMORY: Duration = Duration::hours(24);
+
+/// Auth actions with a per-IP limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Login,
+    Register,
+    PasswordReset,
+    TwoFactor,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Login => "login",
+            Action::Register => "register",
+            Action::PasswordReset => "password_reset",
+            Action::TwoFactor => "two_factor",
+        }
+    }
+
+    fn limit(self) -> Limit {
+        match self {
+            Action::Login | Action::TwoFactor => Limit {
+                max: 10,
+                window: Duration::minutes(1),
+            },
+            Action::Register | Action::PasswordReset => Limit {
+                max: 5,
+                window: Duration::hours(1),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lockout {
+    pub until: DateTime<Utc>,
+    /// Lockouts in a row, this one included; each doubles the next.
+    pub strikes: u32,
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Records a hit on `key` unless `limit` is already reached in the window
+    /// ending at `now`. Returns `None` if the hit was recorded, otherwise how
+    /// long until the oldest hit leaves the window.
+    async fn hit(
+        &self,
+        key: &str,
+        now: DateTime<Utc>,
+        limit: Limit,
+    ) -> Result<Option<Duration>, AppError>;
+    /// Forgets every hit recorded on `key`.
+    async fn reset(&self, key: &str) -> Result<(), AppError>;
+    async fn lockout(&self, key: &str) -> Result<Option<Lockout>, AppError>;
+    /// Replaces the lockout on `key`; stores may forget it `LOCKOUT_MEMORY`
+    /// after it ends.
+    async fn set_lockout(&self, key: &str, lockout: Option<Lockout>) -> Result<(), AppError>;
+}
+
+/// Drop idle keys once this many hits have been recorded since the last sweep.
+const SWEEP_EVERY: u32 = 1024;
+
+#[derive(Default)]
+struct MemoryState {
+    hits: HashMap<String, (Duration, VecDeque<DateTime<Utc>>)>,
+    lockouts: HashMap<String, Lockout>,
+    hits_since_sweep: u32,
+}
+
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn hit(
+        &self,
+        key: &str,
+        now: DateTime<Utc>,
+        limit: Limit,
+    ) -> Result<Option<Duration>, AppError> {
+        let mut state = self.state();
+
+        state.hits_since_sweep += 1;
+        if state.hits_since_sweep >= SWEEP_EVERY {
+            state.hits_since_sweep = 0;
+            state
+                .hits
+                .retain(|_, (window, times)| times.back().is_some_and(|t| *t + *window > now));
+            state
+                .lockouts
+                .retain(|_, lockout| lockout.until + LOCKOUT_MEMORY > now);
+        }
+
+        let (window, times) = state
+            .hits
+            .entry(key.to_string())
+            .or_insert_with(|| (limit.window, VecDeque::new()));
+        *window = limit.window;
+        while times.front().is_some_and(|t| *t + limit.window <= now) {
+            times.pop_front();
+        }
+        if times.len() >= limit.max as usize {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Ok(Some(oldest + limit.window - now));
+        }
+        times.push_back(now);
+        Ok(None)
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AppError> {
+        self.state().hits.remove(key);
+        Ok(())
+    }
+
+    async fn lockout(&self, key: &str) -> Result<Option<Lockout>, AppError> {
+        Ok(self.state().lockouts.get(key).copied())
+    }
+
+    async fn set_lockout(&self, key: &str, lockout: Option<Lockout>) -> Result<(), AppError> {
+        let mut state = self.state();
+        match lockout {
+            Some(lockout) => state.lockouts.insert(key.to_string(), lockout),
+            None => state.lockouts.remove(key),
+        };
+        Ok(())
+    }
+}
+
+fn too_many(message: &str, retry_after: Duration) -> AppError {
+    // Round up so clients never retry a moment too early.
+    let millis = retry_after.num_milliseconds().max(0) as u64;
+    AppError::TooManyRequests {
+        message: message.into(),
+        retry_after_secs: millis.div_ceil(1000).max(1),
+    }
+}
+
+/// The caller's IP: the peer address, or the client a trusted proxy
+/// reports in `Forwarded`/`X-Forwarded-For`.
+pub(crate) fn client_ip(req: &HttpRequest, trust_forwarded_for: bool) -> String {
+    if trust_forwarded_for {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return ip.to_string();
+        }
+    }
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, trust_forwarded_for: bool) -> Self {
+        Self {
+            store,
+            trust_forwarded_for,
+        }
+    }
+
+    pub fn in_memory(config: &AppConfig) -> Self {
+        Self::new(
+            Arc::new(MemoryRateLimitStore::new()),
+            config.trust_forwarded_for,
+        )
+    }
+
+    /// Counts a request from the caller's IP against the action's limit.
+    pub async fn check_ip(&self, req: &HttpRequest, action: Action) -> Result<(), AppError> {
+        self.check_ip_at(
+            &client_ip(req, self.trust_forwarded_for),
+            action,
+            Utc::now(),
+        )
+        .await
+    }
+
+    async fn check_ip_at(
+        &self,
+        ip: &str,
+        action: Action,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let key = format!("ip:{}:{ip}", action.as_str());
+        match self.store.hit(&key, now, action.limit()).await? {
+            Some(retry_after) => Err(too_many(
+                "Too many requests, please try again later",
+                retry_after,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuses an account that is locked out. Checked before any password
+    /// hashing, so locked accounts cost nothing to turn away.
+    pub async fn check_account(&self, account: &str) -> Result<(), AppError> {
+        self.check_account_at(account, Utc::now()).await
+    }
+
+    async fn check_account_at(&self, account: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        match self.store.lockout(&account_key(account)).await? {
+            Some(lockout) if lockout.until > now => Err(too_many(
+                "Too many failed attempts, please try again later",
+                lockout.until - now,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a failed attempt, locking the account once there are too many.
+    pub async fn record_failure(&self, account: &str) -> Result<(), AppError> {
+        self.record_failure_at(account, Utc::now()).await
+    }
+
+    async fn record_failure_at(&self, account: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        let key = account_key(account);
+        if self.store.hit(&key, now, ACCOUNT_FAILURES).await?.is_none() {
+            return Ok(());
+        }
+
+        let strikes = match self.store.lockout(&key).await? {
+            Some(previous) if previous.until + LOCKOUT_MEMORY > now => previous.strikes + 1,
+            _ => 1,
+        };
+        let length = (1..strikes)
+            .try_fold(LOCKOUT_BASE, |length, _| length.checked_add(&length))
+            .map_or(LOCKOUT_MAX, |length| length.min(LOCKOUT_MAX));
+        log::warn!(
+            "Locking {account} out for {}s after repeated failures",
+            length.num_seconds()
+        );
+
+        self.store
+            .set_lockout(
+                &key,
+                Some(Lockout {
+                    until: now + length,
+                    strikes,
+                }),
+            )
+            .await?;
+        self.store.reset(&key).await
+    }
+
+    /// Clears the account's failures and lockout history.
+    pub async fn record_success(&self, account: &str) -> Result<(), AppError> {
+        let key = account_key(account);
+        self.store.reset(&key).await?;
+        self.store.set_lockout(&key, None).await
+    }
+}
+
+fn account_key(account: &str) -> String {
+    format!("account:{}", account.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(Arc::new(MemoryRateLimitStore::new()), false)
+    }
+
+    fn retry_after(result: Result<(), AppError>) -> u64 {
+        match result {
+            Err(AppError::TooManyRequests {
+                retry_after_secs, ..
+            }) => retry_after_secs,
+            other => panic!("expected 429, got {other:?}"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn ip_limits_slide_and_are_separate_per_action_and_ip() {
+        let limiter = limiter();
+        let start = Utc::now();
+
+        for i in 0..10 {
+            limiter
+                .check_ip_at("10.0.0.1", Action::Login, start + Duration::seconds(i))
+                .await
+                .expect("requests under the limit should pass");
+        }
+        let limited = limiter
+            .check_ip_at("10.0.0.1", Action::Login, start + Duration::seconds(30))
+            .await;
+        assert_eq!(retry_after(limited), 30);
+
+        limiter
+            .check_ip_at("10.0.0.2", Action::Login, start + Duration::seconds(30))
+            .await
+            .expect("other IPs should have their own window");
+        limiter
+            .check_ip_at("10.0.0.1", Action::Register, start + Duration::seconds(30))
+            .await
+            .expect("other actions should have their own window");
+
+        // The first hit leaves the window after a minute, making room for one.
+        let later = start + Duration::seconds(60);
+        limiter
+            .check_ip_at("10.0.0.1", Action::Login, later)
+            .await
+            .expect("the window should have slid");
+        assert!(limiter
+            .check_ip_at("10.0.0.1", Action::Login, later)
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn repeated_failures_lock_accounts_for_longer_each_time() {
+        let limiter = limiter();
+        let mut now = Utc::now();
+
+        for expected_minutes in [1, 2, 4] {
+            for _ in 0..=ACCOUNT_FAILURES.max {
+                limiter
+                    .check_account_at("Player@Example.com", now)
+                    .await
+                    .expect("account should not be locked yet");
+                limiter
+                    .record_failure_at("player@example.com", now)
+                    .await
+                    .expect("failure should be recorded");
+            }
+            let locked = limiter.check_account_at("player@example.com ", now).await;
+            assert_eq!(retry_after(locked), expected_minutes * 60);
+            now += Duration::minutes(expected_minutes as i64);
+        }
+
+        limiter
+            .check_account_at("other@example.com", now)
+            .await
+            .expect("other accounts should be unaffected");
+        limiter
+            .record_success("player@example.com")
+            .await
+            .expect("success should be recorded");
+        for _ in 0..=ACCOUNT_FAILURES.max {
+            limiter
+                .record_failure_at("player@example.com", now)
+                .await
+                .expect("failure should be recorded");
+        }
+        let locked = limiter.check_account_at("player@example.com", now).await;
+        assert_eq!(retry_after(locked), 60);
+    }
+}
@@ -0,0 +1,50 @@
+//! Opaque refresh tokens. Clients get 32 random bytes as hex; the database
+//! only ever sees the SHA-256 hash of the token.
+
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+pub struct NewRefreshToken {
+    /// Handed to the client once and never stored.
+    pub token: String,
+    pub hash: String,
+    pub expires_at: String,
+}
+
+pub fn generate() -> NewRefreshToken {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+    NewRefreshToken {
+        hash: hash(&token),
+        token,
+        expires_at: (Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+    }
+}
+
+pub fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tokens_are_random_and_stored_only_as_hashes() {
+        let a = generate();
+        let b = generate();
+
+        assert_eq!(a.token.len(), 64);
+        assert_ne!(a.token, b.token);
+        assert_eq!(a.hash, hash(&a.token));
+        assert_ne!(a.hash, a.token);
+        assert!(a.expires_at > Utc::now().format("%Y-%m-%d %H:%M:%S").to_string());
+    }
+}
@@ -0,0 +1,240 @@
+//! Login sessions. Every sign-in starts one, holding its refresh token
+//! family and the device it came from. Access tokens name their session in
+//! the `sid` claim, so revoking a session ends its refresh tokens, turns its
+//! access tokens away at once and closes its game sockets.
+
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use ring::{hkdf, hmac};
+use serde::Serialize;
+
+use crate::auth::middleware::AuthenticatedUser;
+use crate::auth::rate_limit::client_ip;
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::game::matchmaking::{DisconnectSession, DisconnectUser, MatchmakingActor};
+use crate::models::login_session::{LoginSession, SessionDevice};
+use crate::repository::Repositories;
+
+/// Longer user agents are cut; they are only shown to help tell devices
+/// apart.
+pub const MAX_USER_AGENT_LEN: usize = 256;
+
+/// The device a sign-in request came from. The IP is only kept as a keyed
+/// hash: enough to tell sessions apart, not to say where anyone is.
+pub fn device(req: &HttpRequest, config: &AppConfig) -> SessionDevice {
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|ua| !ua.is_empty())
+        .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+    let ip = client_ip(req, config.trust_forwarded_for);
+    SessionDevice {
+        user_agent,
+        ip_hash: Some(hash_ip(&ip, &ip_hash_key(config))),
+    }
+}
+
+/// The HMAC key for IP hashes, expanded from `SESSION_IP_HASH_KEY` or else
+/// `JWT_SECRET` under its own label, so it is never the key anything is
+/// signed with.
+fn ip_hash_key(config: &AppConfig) -> hmac::Key {
+    let secret = config
+        .session_ip_hash_key
+        .as_deref()
+        .unwrap_or(&config.jwt_secret);
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &[]).extract(secret.as_bytes());
+    prk.expand(&[b"red-flip session ip hash"], hmac::HMAC_SHA256)
+        .expect("an HMAC key length is a valid HKDF output length")
+        .into()
+}
+
+/// HMAC-SHA256 of the IP as hex. Keyed, because IPv4 addresses are few
+/// enough to reverse a plain hash.
+pub fn hash_ip(ip: &str, key: &hmac::Key) -> String {
+    hmac::sign(key, ip.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    pub session: LoginSession,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+pub async fn list(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let sessions: Vec<SessionResponse> = repos
+        .sessions
+        .list_sessions(&auth.user_id)
+        .await?
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: auth.session_id.as_deref() == Some(session.id.as_str()),
+            session,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "sessions": sessions })))
+}
+
+/// Sign one of the caller's devices out, possibly the caller's own.
+pub async fn revoke(
+    repos: web::Data<Repositories>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth: AuthenticatedUser,
+    session_id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    end_session(&repos, &matchmaking, &auth.user_id, &session_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Revoke the user's session and close the sockets opened with it.
+pub(crate) async fn end_session(
+    repos: &Repositories,
+    matchmaking: &Addr<MatchmakingActor>,
+    user_id: &str,
+    session_id: &str,
+) -> Result<(), AppError> {
+    if !repos.sessions.revoke_session(user_id, session_id).await? {
+        return Err(AppError::NotFound("Session not found".into()));
+    }
+    disconnect(matchmaking, user_id, Some(session_id), "Session revoked");
+    Ok(())
+}
+
+/// Close the game sockets opened with a session the caller has just
+/// revoked, or every socket of the user when all sessions were.
+pub(crate) fn disconnect(
+    matchmaking: &Addr<MatchmakingActor>,
+    user_id: &str,
+    session_id: Option<&str>,
+    reason: &str,
+) {
+    match session_id {
+        Some(session_id) => matchmaking.do_send(DisconnectSession {
+            user_id: user_id.to_string(),
+            session_id: session_id.to_string(),
+            reason: reason.to_string(),
+        }),
+        None => matchmaking.do_send(DisconnectUser {
+            user_id: user_id.to_string(),
+            reason: reason.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix::Actor;
+
+    use super::*;
+    use crate::auth::handlers::issue_tokens;
+
+    use crate::game::registry::SessionRegistry;
+
+    #[test]
+    fn device_truncates_the_user_agent_and_hashes_the_ip() {
+        let config = AppConfig::for_tests();
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("User-Agent", "x".repeat(MAX_USER_AGENT_LEN + 10)))
+            .peer_addr("203.0.113.7:5000".parse().expect("address should parse"))
+            .to_http_request();
+
+        let device = device(&req, &config);
+
+        assert_eq!(
+            device.user_agent.map(|ua| ua.len()),
+            Some(MAX_USER_AGENT_LEN)
+        );
+        let ip_hash = device.ip_hash.expect("ip should be hashed");
+        assert_eq!(ip_hash, hash_ip("203.0.113.7", &ip_hash_key(&config)));
+        assert!(!ip_hash.contains("203.0.113.7"));
+
+        let signing_key = hmac::Key::new(hmac::HMAC_SHA256, config.jwt_secret.as_bytes());
+        assert_ne!(ip_hash, hash_ip("203.0.113.7", &signing_key));
+        let dedicated = AppConfig {
+            session_ip_hash_key: Some("a-key-just-for-ip-hashes".into()),
+            ..AppConfig::for_tests()
+        };
+        assert_ne!(ip_hash, hash_ip("203.0.113.7", &ip_hash_key(&dedicated)));
+    }
+
+    #[actix_rt::test]
+    async fn revoking_a_session_rejects_its_access_token() {
+        use actix_web::{http::StatusCode, test, App};
+
+        let repos = Repositories::in_memory();
+        let config = AppConfig::for_tests();
+        let user = repos
+            .users
+            .create("two_devices", "two_devices@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let phone = issue_tokens(&repos, &config, &user, &SessionDevice::default())
+            .await
+            .expect("tokens should be issued");
+        let laptop = issue_tokens(&repos, &config, &user, &SessionDevice::default())
+            .await
+            .expect("tokens should be issued");
+
+        let matchmaking = MatchmakingActor::new(
+            repos.clone(),
+            config.game.clone(),
+            SessionRegistry::default().start(),
+        )
+        .start();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(repos.clone()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(matchmaking))
+                .route("/sessions", web::get().to(list))
+                .route("/sessions/{id}", web::delete().to(revoke)),
+        )
+        .await;
+        let get = |token: &str| {
+            test::TestRequest::get()
+                .uri("/sessions")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, get(&laptop.token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        let sessions = body["sessions"].as_array().expect("sessions should list");
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions.iter().filter(|s| s["current"] == true).count(), 1);
+        let phone_id = sessions
+            .iter()
+            .find(|s| s["current"] == false)
+            .and_then(|s| s["id"].as_str())
+            .expect("the other session should be listed")
+            .to_string();
+
+        let delete = |id: &str| {
+            test::TestRequest::delete()
+                .uri(&format!("/sessions/{id}"))
+                .insert_header(("Authorization", format!("Bearer {}", laptop.token)))
+                .to_request()
+        };
+        let resp = test::call_service(&app, delete(&phone_id)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let resp = test::call_service(&app, delete(&phone_id)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = test::call_service(&app, get(&phone.token)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, get(&laptop.token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
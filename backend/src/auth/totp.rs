@@ -0,0 +1,633 @@
+//! Two-factor authentication with RFC 6238 TOTP codes.
+//!
+//! Enrolment takes two steps: `setup` stores a new secret and returns its
+//! provisioning URI (and a QR code of it), then `enable` turns 2FA on once
+//! the user proves their authenticator works. Password and OIDC sign-ins for
+//! an enrolled user return a short-lived challenge token instead of a
+//! session, which `verify` exchanges for tokens given a valid code.
+
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use totp_rs::{Builder, Secret, Totp};
+
+use crate::auth::handlers::{issue_tokens, session_json};
+use crate::auth::middleware::AuthenticatedUser;
+use crate::auth::rate_limit::{Action, RateLimiter};
+use crate::auth::{refresh, sessions};
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::game::matchmaking::MatchmakingActor;
+use crate::models::user::User;
+use crate::repository::Repositories;
+
+const ISSUER: &str = "Red Flip";
+/// Keeps challenge tokens from being accepted as access tokens.
+const CHALLENGE_AUDIENCE: &str = "red-flip:2fa";
+
+pub const CHALLENGE_TTL_MINUTES: i64 = 5;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+fn totp(secret: &str, user: &User) -> Result<Totp, AppError> {
+    let secret = Secret::try_from_base32(secret)
+        .map_err(|e| AppError::Internal(format!("Stored TOTP secret is invalid: {e}")))?;
+    Builder::new()
+        .with_secret(secret)
+        .with_account_name(user.email.replace(':', ""))
+        .with_issuer(Some(ISSUER))
+        .build()
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Ten random hex characters per code, shown as `xxxxx-xxxxx`.
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are stored like refresh tokens: only their SHA-256 hash.
+/// Dashes, spaces and case don't matter when one is typed back in.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    refresh::hash(&normalized)
+}
+
+/// Whether `code` is a current TOTP code or an unused recovery code for the
+/// user. Either is used up by a successful check.
+async fn check_second_factor(
+    repos: &Repositories,
+    user: &User,
+    code: &str,
+) -> Result<bool, AppError> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some(secret) = user.totp_secret.as_deref() else {
+        return Ok(false);
+    };
+
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        return match totp(secret, user)?.check_current(&code) {
+            Some(step) => repos.two_factor.accept_step(&user.id, step as i64).await,
+            None => Ok(false),
+        };
+    }
+    repos
+        .two_factor
+        .use_recovery_code(&user.id, &hash_recovery_code(&code))
+        .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: String,
+    aud: String,
+    /// The user's `token_version`. `verify` refuses the challenge once it
+    /// moves, which `ban` and `set_password` both do.
+    ver: i32,
+    exp: usize,
+}
+
+/// A token proving the first factor, to be exchanged through `verify`.
+pub fn create_challenge(user: &User, secret: &str) -> Result<String, AppError> {
+    let claims = ChallengeClaims {
+        sub: user.id.clone(),
+        aud: CHALLENGE_AUDIENCE.to_string(),
+        ver: user.token_version,
+        exp: (Utc::now() + Duration::minutes(CHALLENGE_TTL_MINUTES)).timestamp() as usize,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+fn validate_challenge(token: &str, secret: &str) -> Result<ChallengeClaims, AppError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[CHALLENGE_AUDIENCE]);
+    decode::<ChallengeClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .map_err(|_| AppError::Unauthorized("Sign-in expired, please log in again".into()))
+}
+
+async fn current_user(repos: &Repositories, user_id: &str) -> Result<User, AppError> {
+    repos
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
+
+#[derive(Deserialize)]
+pub struct CodeRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyRequest {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Serialize)]
+pub struct TotpSetup {
+    /// Base32 secret, for authenticators that can't scan the QR code.
+    pub secret: String,
+    pub otpauth_url: String,
+    /// PNG of the QR code for `otpauth_url`, base64 encoded.
+    pub qr_code: String,
+}
+
+pub async fn status(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&repos, &auth.user_id).await?;
+    let recovery_codes_left = if user.totp_enabled {
+        repos.two_factor.recovery_codes_left(&user.id).await?
+    } else {
+        0
+    };
+
+    Ok(HttpResponse::Ok().json(TwoFactorStatus {
+        enabled: user.totp_enabled,
+        recovery_codes_left,
+    }))
+}
+
+/// Start enrolment with a new secret. Calling it again replaces the secret.
+pub async fn setup(
+    repos: web::Data<Repositories>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&repos, &auth.user_id).await?;
+    if user.totp_enabled {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".into(),
+        ));
+    }
+
+    let secret = Secret::generate().to_base32();
+    repos
+        .two_factor
+        .set_pending_secret(&user.id, &secret)
+        .await?;
+    let totp = totp(&secret, &user)?;
+
+    Ok(HttpResponse::Ok().json(TotpSetup {
+        otpauth_url: totp
+            .to_url()
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+        qr_code: totp
+            .to_qr_base64()
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+        secret,
+    }))
+}
+
+/// Finish enrolment with a code from the authenticator. Every other session
+/// is signed out; the caller gets fresh tokens and the recovery codes, which
+/// are never shown again.
+pub async fn enable(
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    matchmaking: web::Data<Addr<MatchmakingActor>>,
+    auth: AuthenticatedUser,
+    body: web::Json<CodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&repos, &auth.user_id).await?;
+    if user.totp_enabled {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".into(),
+        ));
+    }
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or_else(|| AppError::BadRequest("Start two-factor setup before enabling it".into()))?;
+    let step = totp(secret, &user)?
+        .check_current(body.code.trim())
+        .ok_or_else(|| AppError::BadRequest("Invalid two-factor code".into()))?;
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    repos
+        .two_factor
+        .enable(&user.id, step as i64, &hashes)
+        .await?;
+    repos.refresh_tokens.revoke_all_for_user(&user.id).await?;
+    sessions::disconnect(
+        &matchmaking,
+        &user.id,
+        None,
+        "Two-factor authentication enabled",
+    );
+
+    let user = current_user(&repos, &user.id).await?;
+    let tokens = issue_tokens(&repos, &config, &user, &sessions::device(&req, &config)).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "recovery_codes": recovery_codes,
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in,
+    })))
+}
+
+/// Turn 2FA off, given a current code or a recovery code.
+pub async fn disable(
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    auth: AuthenticatedUser,
+    body: web::Json<CodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&repos, &auth.user_id).await?;
+    if !user.totp_enabled {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".into(),
+        ));
+    }
+    if config.require_admin_2fa && user.role.is_staff() {
+        return Err(AppError::BadRequest(
+            "Staff accounts must keep two-factor authentication enabled".into(),
+        ));
+    }
+    if !check_second_factor(&repos, &user, &body.code).await? {
+        return Err(AppError::BadRequest("Invalid two-factor code".into()));
+    }
+
+    repos.two_factor.disable(&user.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Second step of signing in: exchange a challenge token and a code for a
+/// session. Wrong codes count towards locking the account, like passwords.
+pub async fn verify(
+    req: HttpRequest,
+    repos: web::Data<Repositories>,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<RateLimiter>,
+    body: web::Json<VerifyRequest>,
+) -> Result<HttpResponse, AppError> {
+    limiter.check_ip(&req, Action::TwoFactor).await?;
+    let claims = validate_challenge(&body.challenge_token, &config.jwt_secret)?;
+    let user = match repos.users.find_by_id(&claims.sub).await? {
+        Some(user) if !user.is_banned && user.token_version == claims.ver => user,
+        _ => {
+            return Err(AppError::Unauthorized(
+                "Sign-in expired, please log in again".into(),
+            ))
+        }
+    };
+
+    limiter.check_account(&user.id).await?;
+    if user.totp_enabled && !check_second_factor(&repos, &user, &body.code).await? {
+        limiter.record_failure(&user.id).await?;
+        return Err(AppError::Unauthorized("Invalid two-factor code".into()));
+    }
+    limiter.record_success(&user.id).await?;
+
+    let tokens = issue_tokens(&repos, &config, &user, &sessions::device(&req, &config)).await?;
+    Ok(HttpResponse::Ok().json(session_json(tokens, user)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::keys::KeySet;
+    use crate::repository::UserRepository;
+    use crate::test_support::{json_body, test_limiter, test_matchmaking, test_request};
+
+    fn test_config(require_admin_2fa: bool) -> web::Data<AppConfig> {
+        web::Data::new(AppConfig {
+            require_admin_2fa,
+            ..AppConfig::for_tests()
+        })
+    }
+
+    fn code_at(secret: &str, user: &User, time: u64) -> String {
+        totp(secret, user)
+            .expect("secret should be valid")
+            .generate(time)
+            .to_string()
+    }
+
+    /// Enrols the user and returns the secret and recovery codes.
+    async fn enrolled(
+        repos: &web::Data<Repositories>,
+        cfg: &web::Data<AppConfig>,
+        user: &User,
+    ) -> (String, Vec<String>) {
+        let auth = || AuthenticatedUser {
+            user_id: user.id.clone(),
+            session_id: None,
+        };
+        let setup = json_body(
+            setup(repos.clone(), auth())
+                .await
+                .expect("setup should succeed"),
+        )
+        .await;
+        let secret = setup["secret"].as_str().expect("secret").to_string();
+        assert!(setup["otpauth_url"]
+            .as_str()
+            .expect("otpauth url")
+            .starts_with("otpauth://totp/Red%20Flip:"));
+        assert!(!setup["qr_code"].as_str().expect("qr code").is_empty());
+
+        let wrong = enable(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_matchmaking(repos),
+            auth(),
+            web::Json(CodeRequest {
+                code: "000000".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(wrong, Err(AppError::BadRequest(_))));
+
+        // A code from the previous step is still within the allowed skew, and
+        // leaves the current step free for the next sign-in.
+        let previous = Utc::now().timestamp() as u64 - 30;
+        let enabled = json_body(
+            enable(
+                test_request(),
+                repos.clone(),
+                cfg.clone(),
+                test_matchmaking(repos),
+                auth(),
+                web::Json(CodeRequest {
+                    code: code_at(&secret, user, previous),
+                }),
+            )
+            .await
+            .expect("enable should succeed"),
+        )
+        .await;
+        assert!(enabled["token"].as_str().is_some());
+        let codes: Vec<String> = enabled["recovery_codes"]
+            .as_array()
+            .expect("recovery codes")
+            .iter()
+            .map(|c| c.as_str().expect("code").to_string())
+            .collect();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        (secret, codes)
+    }
+
+    #[actix_rt::test]
+    async fn challenges_accept_each_totp_code_and_recovery_code_once() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config(false);
+        let user = repos
+            .users
+            .create("twofactor", "twofactor@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let (secret, recovery_codes) = enrolled(&repos, &cfg, &user).await;
+
+        let user = repos
+            .users
+            .find_by_id(&user.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert!(user.totp_enabled);
+        assert_eq!(user.token_version, 1);
+        let challenge =
+            create_challenge(&user, "test-secret").expect("challenge should be created");
+        let attempt = |code: String| {
+            verify(
+                test_request(),
+                repos.clone(),
+                cfg.clone(),
+                test_limiter(),
+                web::Json(VerifyRequest {
+                    challenge_token: challenge.clone(),
+                    code,
+                }),
+            )
+        };
+
+        assert!(matches!(
+            attempt("123456".into()).await,
+            Err(AppError::Unauthorized(_))
+        ));
+        let now = code_at(&secret, &user, Utc::now().timestamp() as u64);
+        let session = json_body(
+            attempt(now.clone())
+                .await
+                .expect("current code should work"),
+        )
+        .await;
+        assert!(session["token"].as_str().is_some());
+        assert_eq!(session["user"]["id"], user.id.as_str());
+        assert!(matches!(attempt(now).await, Err(AppError::Unauthorized(_))));
+
+        attempt(recovery_codes[0].to_uppercase())
+            .await
+            .expect("recovery code should work");
+        assert!(attempt(recovery_codes[0].clone()).await.is_err());
+        assert_eq!(
+            repos
+                .two_factor
+                .recovery_codes_left(&user.id)
+                .await
+                .expect("count should succeed"),
+            RECOVERY_CODE_COUNT as i64 - 1
+        );
+
+        let access = crate::auth::jwt::create_token(
+            &user.id,
+            user.token_version,
+            None,
+            &KeySet::from_secret("test-secret"),
+        )
+        .expect("token should be created");
+        let forged = verify(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_limiter(),
+            web::Json(VerifyRequest {
+                challenge_token: access,
+                code: recovery_codes[1].clone(),
+            }),
+        )
+        .await;
+        assert!(matches!(forged, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn password_reset_voids_a_pending_challenge() {
+        let repos = web::Data::new(Repositories::in_memory());
+        let cfg = test_config(false);
+        let user = repos
+            .users
+            .create("reset_2fa", "reset_2fa@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let (_, recovery_codes) = enrolled(&repos, &cfg, &user).await;
+        let user = current_user(&repos, &user.id)
+            .await
+            .expect("user should exist");
+        let challenge =
+            create_challenge(&user, "test-secret").expect("challenge should be created");
+
+        repos
+            .users
+            .set_password(&user.id, "new-hash")
+            .await
+            .expect("password should be replaced");
+        let result = verify(
+            test_request(),
+            repos.clone(),
+            cfg.clone(),
+            test_limiter(),
+            web::Json(VerifyRequest {
+                challenge_token: challenge,
+                code: recovery_codes[0].clone(),
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn disabling_needs_a_code_and_is_refused_for_required_admins() {
+        let store = std::sync::Arc::new(crate::repository::memory::MemoryStore::new());
+        let user = store
+            .create("disabler", "disabler@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let admin = store
+            .create("admin_2fa", "admin_2fa@example.com", "hash")
+            .await
+            .expect("admin should be created");
+        store
+            .set_role(&admin.id, crate::models::role::Role::Admin)
+            .await
+            .expect("role should be set");
+        let repos = web::Data::new(Repositories::from_store(store));
+        let (_, recovery_codes) = enrolled(&repos, &test_config(false), &user).await;
+        let (_, admin_codes) = enrolled(&repos, &test_config(true), &admin).await;
+        let disable_with = |user_id: &str, code: &str, cfg| {
+            disable(
+                repos.clone(),
+                cfg,
+                AuthenticatedUser {
+                    user_id: user_id.to_string(),
+                    session_id: None,
+                },
+                web::Json(CodeRequest { code: code.into() }),
+            )
+        };
+
+        assert!(matches!(
+            disable_with(&user.id, "nope", test_config(false)).await,
+            Err(AppError::BadRequest(_))
+        ));
+        disable_with(&user.id, &recovery_codes[0], test_config(false))
+            .await
+            .expect("disable should succeed");
+        let status = json_body(
+            status(
+                repos.clone(),
+                AuthenticatedUser {
+                    user_id: user.id.clone(),
+                    session_id: None,
+                },
+            )
+            .await
+            .expect("status should succeed"),
+        )
+        .await;
+        assert_eq!(status["enabled"], false);
+        assert_eq!(status["recovery_codes_left"], 0);
+
+        assert!(matches!(
+            disable_with(&admin.id, &admin_codes[0], test_config(true)).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert_eq!(
+            repos
+                .two_factor
+                .recovery_codes_left(&admin.id)
+                .await
+                .expect("count should succeed"),
+            RECOVERY_CODE_COUNT as i64
+        );
+    }
+
+    #[actix_rt::test]
+    async fn enabling_closes_game_sockets() {
+        use crate::game::test_support::TestServer;
+
+        let server = TestServer::start();
+        let repos = web::Data::new(server.repos.clone());
+        let user = repos
+            .users
+            .create("sockets", "sockets@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let auth = || AuthenticatedUser {
+            user_id: user.id.clone(),
+            session_id: None,
+        };
+        let setup = json_body(
+            setup(repos.clone(), auth())
+                .await
+                .expect("setup should succeed"),
+        )
+        .await;
+        let secret = setup["secret"].as_str().expect("secret");
+        let mut socket = server.connect(&user, None).await;
+
+        enable(
+            test_request(),
+            repos.clone(),
+            web::Data::new(server.config.clone()),
+            web::Data::new(server.matchmaking.clone()),
+            auth(),
+            web::Json(CodeRequest {
+                code: code_at(secret, &user, Utc::now().timestamp() as u64),
+            }),
+        )
+        .await
+        .expect("enable should succeed");
+        let texts = socket.read_until_closed().await;
+        assert!(
+            texts
+                .iter()
+                .any(|t| t.contains("Two-factor authentication enabled")),
+            "{texts:?}"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! The rules every username passes, whether it is picked at registration,
+//! set by a rename or an admin edit, or derived from an SSO profile.
+//!
+//! Names are 3 to 20 ASCII letters, digits and underscores, unique regardless
+//! of case. Reserved names and blocked words are compared by their skeleton
+//! (see [`skeleton`]), so `Adm1n` is as reserved as `admin`.
+
+use serde::Serialize;
+
+use crate::errors::AppError;
+use crate::repository::Repositories;
+
+pub const MIN_LEN: usize = 3;
+pub const MAX_LEN: usize = 20;
+
+/// Names no player may take. Guest names (`Guest3f9a`) are reserved by
+/// prefix.
+const RESERVED: &[&str] = &[
+    "admin",
+    "administrator",
+    "anonymous",
+    "deleted",
+    "mod",
+    "moderator",
+    "null",
+    "official",
+    "redflip",
+    "root",
+    "staff",
+    "support",
+    "system",
+];
+const RESERVED_PREFIXES: &[&str] = &["guest"];
+
+/// Why a username was refused. Serialized with a `code` the frontend can
+/// turn into its own message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum UsernameViolation {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    InvalidCharacters,
+    /// Uses non-Latin characters that look like Latin letters or digits.
+    Confusable,
+    Reserved,
+    Profanity,
+    Taken,
+}
+
+impl UsernameViolation {
+    pub fn message(&self) -> String {
+        match self {
+            Self::TooShort { min } => format!("Username must be at least {min} characters"),
+            Self::TooLong { max } => format!("Username must be at most {max} characters"),
+            Self::InvalidCharacters => {
+                "Username can only contain letters, digits and underscores".into()
+            }
+            Self::Confusable => {
+                "Username contains characters that imitate Latin letters or digits".into()
+            }
+            Self::Reserved => "That username is reserved".into(),
+            Self::Profanity => "That username isn't allowed".into(),
+            Self::Taken => "Username already taken".into(),
+        }
+    }
+}
+
+impl From<UsernameViolation> for AppError {
+    fn from(violation: UsernameViolation) -> Self {
+        AppError::InvalidUsername(violation)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UsernamePolicy {
+    /// Skeletons of blocked words; a name containing one is refused.
+    blocklist: Vec<String>,
+}
+
+impl UsernamePolicy {
+    pub fn new<I, S>(blocklist: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut blocklist: Vec<String> = blocklist
+            .into_iter()
+            .map(|word| skeleton(word.as_ref().trim()))
+            .filter(|word| !word.is_empty())
+            .collect();
+        blocklist.sort();
+        blocklist.dedup();
+        Self { blocklist }
+    }
+
+    /// Checks a name against every rule that doesn't need the database.
+    pub fn validate(&self, username: &str) -> Result<(), UsernameViolation> {
+        let len = username.chars().count();
+        if len < MIN_LEN {
+            return Err(UsernameViolation::TooShort { min: MIN_LEN });
+        }
+        if len > MAX_LEN {
+            return Err(UsernameViolation::TooLong { max: MAX_LEN });
+        }
+
+        let mut confusable = false;
+        for c in username.chars() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                continue;
+            }
+            if homoglyph(c).is_none() {
+                return Err(UsernameViolation::InvalidCharacters);
+            }
+            confusable = true;
+        }
+        if confusable {
+            return Err(UsernameViolation::Confusable);
+        }
+
+        let folded = skeleton(username);
+        let reserved = RESERVED.iter().any(|name| skeleton(name) == folded)
+            || RESERVED_PREFIXES
+                .iter()
+                .any(|prefix| folded.starts_with(&skeleton(prefix)));
+        if reserved {
+            return Err(UsernameViolation::Reserved);
+        }
+        if self.blocklist.iter().any(|word| folded.contains(word)) {
+            return Err(UsernameViolation::Profanity);
+        }
+        Ok(())
+    }
+
+    /// Checks a name for the account `user_id`, or for a new account when
+    /// `None`. Users may change the case of their own name. Names of AI
+    /// players count as reserved rather than taken. This only gives a
+    /// clearer error; the unique index on `lower(username)` is what stops
+    /// two concurrent writes from both taking a name.
+    pub async fn check(
+        &self,
+        repos: &Repositories,
+        username: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.validate(username)?;
+        match repos.users.find_by_username(username).await? {
+            Some(holder) if Some(holder.id.as_str()) != user_id => Err(if holder.is_ai {
+                UsernameViolation::Reserved
+            } else {
+                UsernameViolation::Taken
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Folds a name to what it looks like: lowercase, without underscores, and
+/// with lookalike digits and letters merged, so `Adm1n`, `ADMIN` and
+/// `a_d_m_i_n` share the skeleton `admin`.
+pub fn skeleton(name: &str) -> String {
+    let folded: String = name
+        .chars()
+        .filter(|&c| c != '_')
+        .map(|c| homoglyph(c).unwrap_or(c).to_ascii_lowercase())
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'l' | '|' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            '8' => 'b',
+            c => c,
+        })
+        .collect();
+    folded.replace("rn", "m").replace("vv", "w")
+}
+
+/// The ASCII character a non-ASCII lookalike imitates.
+fn homoglyph(c: char) -> Option<char> {
+    // Fullwidth forms are plain offsets from ASCII.
+    if ('\u{FF01}'..='\u{FF5E}').contains(&c) {
+        return char::from_u32(c as u32 - 0xFEE0)
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_');
+    }
+    Some(match c {
+        // Cyrillic
+        'а' | 'А' => 'a',
+        'В' | 'в' => 'b',
+        'с' | 'С' => 'c',
+        'ԁ' => 'd',
+        'е' | 'Е' | 'ё' => 'e',
+        'һ' | 'Н' | 'н' => 'h',
+        'і' | 'І' | 'ӏ' => 'i',
+        'ј' | 'Ј' => 'j',
+        'К' | 'к' => 'k',
+        'М' | 'м' => 'm',
+        'о' | 'О' => 'o',
+        'р' | 'Р' => 'p',
+        'ԛ' => 'q',
+        'ѕ' | 'Ѕ' => 's',
+        'Т' | 'т' => 't',
+        'у' | 'У' => 'y',
+        'ԝ' => 'w',
+        'х' | 'Х' => 'x',
+        // Greek
+        'α' | 'Α' => 'a',
+        'Β' | 'β' => 'b',
+        'Ε' | 'ε' => 'e',
+        'Η' => 'h',
+        'ι' | 'Ι' => 'i',
+        'κ' | 'Κ' => 'k',
+        'Μ' => 'm',
+        'ν' | 'Ν' => 'n',
+        'ο' | 'Ο' | 'σ' => 'o',
+        'ρ' | 'Ρ' => 'p',
+        'τ' | 'Τ' => 't',
+        'υ' | 'Υ' => 'y',
+        'χ' | 'Χ' => 'x',
+        'Ζ' => 'z',
+        // Latin lookalikes
+        'ı' => 'i',
+        'ſ' => 's',
+        'ɑ' => 'a',
+        'ɡ' => 'g',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_enforces_length_charset_and_lookalikes() {
+        let policy = UsernamePolicy::default();
+        assert_eq!(policy.validate("rock_fan_42"), Ok(()));
+        assert_eq!(
+            policy.validate("ab"),
+            Err(UsernameViolation::TooShort { min: MIN_LEN })
+        );
+        assert_eq!(
+            policy.validate("a_name_that_is_too_long"),
+            Err(UsernameViolation::TooLong { max: MAX_LEN })
+        );
+        assert_eq!(
+            policy.validate("bad-name!"),
+            Err(UsernameViolation::InvalidCharacters)
+        );
+        assert_eq!(
+            policy.validate("José"),
+            Err(UsernameViolation::InvalidCharacters)
+        );
+        // Cyrillic `а` and `о`.
+        assert_eq!(
+            policy.validate("pаper_rоck"),
+            Err(UsernameViolation::Confusable)
+        );
+    }
+
+    #[test]
+    fn reserved_names_and_blocked_words_match_their_lookalikes() {
+        let policy = UsernamePolicy::new(["heck", " ", "HECK"]);
+        assert_eq!(policy.blocklist, vec!["heck".to_string()]);
+
+        for name in ["admin", "ADMIN", "Adm1n", "a_d_m_i_n", "GuestCafe", "m0d"] {
+            assert_eq!(
+                policy.validate(name),
+                Err(UsernameViolation::Reserved),
+                "{name}"
+            );
+        }
+        for name in ["heckler", "what_the_h3ck"] {
+            assert_eq!(
+                policy.validate(name),
+                Err(UsernameViolation::Profanity),
+                "{name}"
+            );
+        }
+        assert_eq!(policy.validate("admiral"), Ok(()));
+    }
+
+    #[actix_rt::test]
+    async fn check_compares_case_insensitively_and_reserves_ai_names() {
+        let repos = Repositories::in_memory();
+        let user = repos
+            .users
+            .create("Paper_Tiger", "tiger@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let policy = UsernamePolicy::default();
+
+        let taken = policy.check(&repos, "paper_tiger", None).await;
+        assert!(matches!(
+            taken,
+            Err(AppError::InvalidUsername(UsernameViolation::Taken))
+        ));
+        policy
+            .check(&repos, "paper_tiger", Some(&user.id))
+            .await
+            .expect("users should be able to recase their own name");
+
+        let ai = policy.check(&repos, "stonewall", None).await;
+        assert!(matches!(
+            ai,
+            Err(AppError::InvalidUsername(UsernameViolation::Reserved))
+        ));
+    }
+}
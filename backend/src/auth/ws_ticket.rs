@@ -0,0 +1,373 @@
+//! Single-use tickets for opening the game WebSocket. Browsers can't set
+//! headers on a WebSocket upgrade, so a credential has to travel in the URL,
+//! where proxies and access logs see it. A ticket expires within seconds and
+//! works once, so a logged one is useless. Guests trade their guest token
+//! for one too, since that token is their identity for a month.
+//!
+//! Tickets live behind `WsTicketStore`. `MemoryWsTicketStore` keeps them
+//! in-process, so the upgrade must reach the server that issued the ticket;
+//! several servers need a shared store.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use actix_web::{web, HttpResponse};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::guest::{self, Guest};
+use crate::auth::middleware::{check_session, load_user_at_version, AuthenticatedUser};
+use crate::auth::refresh;
+use crate::config::AppConfig;
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::repository::Repositories;
+
+pub const TICKET_TTL_SECS: i64 = 30;
+
+/// Who a ticket was issued to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Holder {
+    User {
+        user_id: String,
+        /// The user's `token_version` at issue, so revoking sessions also
+        /// revokes outstanding tickets.
+        token_version: i32,
+        /// The login session that asked for the ticket, so the socket
+        /// closes when the session is revoked.
+        login_session_id: Option<String>,
+    },
+    Guest(Guest),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ticket {
+    pub holder: Holder,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Who a redeemed ticket connects as.
+#[derive(Debug)]
+pub enum Redeemed {
+    User {
+        user: Box<User>,
+        login_session_id: Option<String>,
+    },
+    Guest(Guest),
+}
+
+#[async_trait]
+pub trait WsTicketStore: Send + Sync {
+    /// Stores a ticket under the hash of its secret.
+    async fn insert(&self, hash: &str, ticket: Ticket) -> Result<(), AppError>;
+    /// Removes and returns the ticket, so it can only be taken once.
+    async fn take(&self, hash: &str) -> Result<Option<Ticket>, AppError>;
+}
+
+#[derive(Default)]
+pub struct MemoryWsTicketStore {
+    tickets: Mutex<HashMap<String, Ticket>>,
+}
+
+impl MemoryWsTicketStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tickets(&self) -> MutexGuard<'_, HashMap<String, Ticket>> {
+        self.tickets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl WsTicketStore for MemoryWsTicketStore {
+    async fn insert(&self, hash: &str, ticket: Ticket) -> Result<(), AppError> {
+        let mut tickets = self.tickets();
+        // Tickets that were never redeemed would otherwise pile up.
+        let now = Utc::now();
+        tickets.retain(|_, ticket| ticket.expires_at > now);
+        tickets.insert(hash.to_string(), ticket);
+        Ok(())
+    }
+
+    async fn take(&self, hash: &str) -> Result<Option<Ticket>, AppError> {
+        Ok(self.tickets().remove(hash))
+    }
+}
+
+pub struct WsTickets {
+    store: Arc<dyn WsTicketStore>,
+}
+
+impl WsTickets {
+    pub fn new(store: Arc<dyn WsTicketStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(MemoryWsTicketStore::new()))
+    }
+
+    pub async fn issue(
+        &self,
+        user: &User,
+        login_session_id: Option<&str>,
+    ) -> Result<String, AppError> {
+        self.issue_at(user_holder(user, login_session_id), Utc::now())
+            .await
+    }
+
+    pub async fn issue_for_guest(&self, guest: &Guest) -> Result<String, AppError> {
+        self.issue_at(Holder::Guest(guest.clone()), Utc::now())
+            .await
+    }
+
+    async fn issue_at(&self, holder: Holder, now: DateTime<Utc>) -> Result<String, AppError> {
+        let secret = refresh::generate().token;
+        self.store
+            .insert(
+                &refresh::hash(&secret),
+                Ticket {
+                    holder,
+                    expires_at: now + Duration::seconds(TICKET_TTL_SECS),
+                },
+            )
+            .await?;
+        Ok(secret)
+    }
+
+    /// Use up the ticket and load its user and login session, refusing
+    /// banned accounts and tickets issued before the user's sessions were
+    /// revoked. Redeeming counts as activity on the login session.
+    pub async fn redeem(&self, repos: &Repositories, ticket: &str) -> Result<Redeemed, AppError> {
+        self.redeem_at(repos, ticket, Utc::now()).await
+    }
+
+    async fn redeem_at(
+        &self,
+        repos: &Repositories,
+        ticket: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Redeemed, AppError> {
+        let ticket = self
+            .store
+            .take(&refresh::hash(ticket))
+            .await?
+            .filter(|ticket| ticket.expires_at > now)
+            .ok_or_else(|| AppError::Unauthorized("Invalid or expired ticket".into()))?;
+        let (user_id, token_version, login_session_id) = match ticket.holder {
+            Holder::User {
+                user_id,
+                token_version,
+                login_session_id,
+            } => (user_id, token_version, login_session_id),
+            Holder::Guest(guest) => return Ok(Redeemed::Guest(guest)),
+        };
+        let user = load_user_at_version(repos, &user_id, token_version).await?;
+        if let Some(session_id) = &login_session_id {
+            check_session(repos, &user.id, session_id).await?;
+            repos.sessions.touch_session(session_id).await?;
+        }
+        Ok(Redeemed::User {
+            user: Box::new(user),
+            login_session_id,
+        })
+    }
+}
+
+fn user_holder(user: &User, login_session_id: Option<&str>) -> Holder {
+    Holder::User {
+        user_id: user.id.clone(),
+        token_version: user.token_version,
+        login_session_id: login_session_id.map(str::to_string),
+    }
+}
+
+#[derive(Serialize)]
+pub struct TicketResponse {
+    pub ticket: String,
+    /// Seconds until the ticket expires.
+    pub expires_in: i64,
+}
+
+/// Issue a ticket for `/ws?ticket=...`.
+pub async fn issue(
+    repos: web::Data<Repositories>,
+    tickets: web::Data<WsTickets>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let user = repos
+        .users
+        .find_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    Ok(HttpResponse::Ok().json(TicketResponse {
+        ticket: tickets.issue(&user, auth.session_id.as_deref()).await?,
+        expires_in: TICKET_TTL_SECS,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct GuestTicketRequest {
+    pub guest_token: String,
+}
+
+/// Issue a ticket for `/ws?ticket=...` that connects as the guest named by
+/// the token in the body.
+pub async fn issue_for_guest(
+    config: web::Data<AppConfig>,
+    tickets: web::Data<WsTickets>,
+    body: web::Json<GuestTicketRequest>,
+) -> Result<HttpResponse, AppError> {
+    let guest = guest::validate_token(&body.guest_token, &config.jwt_secret)?;
+
+    Ok(HttpResponse::Ok().json(TicketResponse {
+        ticket: tickets.issue_for_guest(&guest).await?,
+        expires_in: TICKET_TTL_SECS,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::json_body;
+
+    #[actix_rt::test]
+    async fn tickets_work_once_and_expire() {
+        let repos = Repositories::in_memory();
+        let tickets = WsTickets::in_memory();
+        let user = repos
+            .users
+            .create("socket_user", "socket_user@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let now = Utc::now();
+
+        let ticket = tickets
+            .issue_at(user_holder(&user, None), now)
+            .await
+            .expect("ticket should be issued");
+        let redeemed = tickets
+            .redeem_at(&repos, &ticket, now)
+            .await
+            .expect("ticket should be redeemed");
+        assert!(matches!(redeemed, Redeemed::User { user: u, .. } if u.id == user.id));
+        let reused = tickets.redeem_at(&repos, &ticket, now).await;
+        assert!(matches!(reused, Err(AppError::Unauthorized(_))));
+
+        let stale = tickets
+            .issue_at(user_holder(&user, None), now)
+            .await
+            .expect("ticket should be issued");
+        let late = now + Duration::seconds(TICKET_TTL_SECS);
+        let expired = tickets.redeem_at(&repos, &stale, late).await;
+        assert!(matches!(expired, Err(AppError::Unauthorized(_))));
+        let unknown = tickets.redeem_at(&repos, "not-a-ticket", now).await;
+        assert!(matches!(unknown, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn tickets_are_refused_once_the_user_is_banned() {
+        let repos = Repositories::in_memory();
+        let tickets = WsTickets::in_memory();
+        let user = repos
+            .users
+            .create("banned_socket", "banned_socket@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let ticket = tickets
+            .issue(&user, None)
+            .await
+            .expect("ticket should be issued");
+        repos
+            .users
+            .ban(&user.id, "cheating")
+            .await
+            .expect("ban should succeed");
+        let refused = tickets.redeem(&repos, &ticket).await;
+        assert!(matches!(refused, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn tickets_carry_their_login_session_and_die_with_it() {
+        let repos = Repositories::in_memory();
+        let tickets = WsTickets::in_memory();
+        let user = repos
+            .users
+            .create("session_socket", "session_socket@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let session = repos
+            .refresh_tokens
+            .start_family(&user.id, &Default::default(), "hash", "2999-01-01 00:00:00")
+            .await
+            .expect("session should start");
+
+        let ticket = tickets
+            .issue(&user, Some(&session.id))
+            .await
+            .expect("ticket should be issued");
+        let redeemed = tickets
+            .redeem(&repos, &ticket)
+            .await
+            .expect("ticket should be redeemed");
+        assert!(matches!(
+            redeemed,
+            Redeemed::User { login_session_id: Some(id), .. } if id == session.id
+        ));
+
+        let ticket = tickets
+            .issue(&user, Some(&session.id))
+            .await
+            .expect("ticket should be issued");
+        repos
+            .sessions
+            .revoke_session(&user.id, &session.id)
+            .await
+            .expect("session should be revoked");
+        let refused = tickets.redeem(&repos, &ticket).await;
+        assert!(matches!(refused, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn guest_tokens_trade_for_tickets_that_work_once() {
+        let repos = Repositories::in_memory();
+        let tickets = web::Data::new(WsTickets::in_memory());
+        let config = web::Data::new(AppConfig::for_tests());
+        let guest = Guest::generate();
+
+        let forged = issue_for_guest(
+            config.clone(),
+            tickets.clone(),
+            web::Json(GuestTicketRequest {
+                guest_token: "not-a-token".into(),
+            }),
+        )
+        .await;
+        assert!(matches!(forged, Err(AppError::Unauthorized(_))));
+
+        let token =
+            guest::create_token(&guest, &config.jwt_secret).expect("token should be created");
+        let resp = issue_for_guest(
+            config,
+            tickets.clone(),
+            web::Json(GuestTicketRequest { guest_token: token }),
+        )
+        .await
+        .expect("ticket should be issued");
+        let ticket = json_body(resp).await["ticket"]
+            .as_str()
+            .expect("ticket")
+            .to_string();
+        let redeemed = tickets
+            .redeem(&repos, &ticket)
+            .await
+            .expect("ticket should be redeemed");
+        assert!(matches!(redeemed, Redeemed::Guest(g) if g == guest));
+        let reused = tickets.redeem(&repos, &ticket).await;
+        assert!(matches!(reused, Err(AppError::Unauthorized(_))));
+    }
+}
@@ -0,0 +1,111 @@
+/// Startup modes selected by the first command-line argument.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Apply pending migrations, then run the HTTP server (the default).
+    Serve,
+    /// Apply pending migrations and exit. With `dry_run`, only print them.
+    Migrate { dry_run: bool },
+    /// Write a snapshot of every table to `path`, or stdout when absent.
+    Export {
+        path: Option<String>,
+        anonymize: bool,
+    },
+    /// Apply pending migrations, then load a snapshot into the empty database.
+    Restore { path: String },
+}
+
+pub const USAGE: &str = "Usage:
+  red-flip                      apply pending migrations and start the server
+  red-flip migrate              apply pending migrations and exit
+  red-flip migrate --dry-run    print pending migrations without applying them
+  red-flip export [--anonymize] [FILE]
+                                write a JSON Lines snapshot to FILE (or stdout);
+                                --anonymize strips emails and credentials
+  red-flip restore FILE         load a snapshot into an empty database";
+
+impl Command {
+    pub fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let args: Vec<String> = args.into_iter().collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        match args.as_slice() {
+            [] | ["serve"] => Ok(Command::Serve),
+            ["migrate"] => Ok(Command::Migrate { dry_run: false }),
+            ["migrate", "--dry-run"] => Ok(Command::Migrate { dry_run: true }),
+            ["export", rest @ ..] => {
+                let anonymize = rest.contains(&"--anonymize");
+                let paths: Vec<&str> = rest
+                    .iter()
+                    .copied()
+                    .filter(|a| *a != "--anonymize")
+                    .collect();
+                match paths.as_slice() {
+                    [] => Ok(Command::Export {
+                        path: None,
+                        anonymize,
+                    }),
+                    [path] if !path.starts_with("--") => Ok(Command::Export {
+                        path: Some(path.to_string()),
+                        anonymize,
+                    }),
+                    _ => Err(format!("Unrecognized arguments: {}", args.join(" "))),
+                }
+            }
+            ["restore", path] if !path.starts_with("--") => Ok(Command::Restore {
+                path: path.to_string(),
+            }),
+            _ => Err(format!("Unrecognized arguments: {}", args.join(" "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_recognizes_startup_modes() {
+        assert_eq!(parse(&[]), Ok(Command::Serve));
+        assert_eq!(parse(&["serve"]), Ok(Command::Serve));
+        assert_eq!(parse(&["migrate"]), Ok(Command::Migrate { dry_run: false }));
+        assert_eq!(
+            parse(&["migrate", "--dry-run"]),
+            Ok(Command::Migrate { dry_run: true })
+        );
+        assert!(parse(&["migrate", "--force"]).is_err());
+    }
+
+    #[test]
+    fn parse_recognizes_snapshot_commands() {
+        assert_eq!(
+            parse(&["export"]),
+            Ok(Command::Export {
+                path: None,
+                anonymize: false
+            })
+        );
+        assert_eq!(
+            parse(&["export", "--anonymize", "backup.jsonl"]),
+            Ok(Command::Export {
+                path: Some("backup.jsonl".into()),
+                anonymize: true
+            })
+        );
+        assert_eq!(
+            parse(&["restore", "backup.jsonl"]),
+            Ok(Command::Restore {
+                path: "backup.jsonl".into()
+            })
+        );
+        assert!(parse(&["restore"]).is_err());
+        assert!(parse(&["export", "a.jsonl", "b.jsonl"]).is_err());
+        assert!(parse(&["export", "--force"]).is_err());
+    }
+}
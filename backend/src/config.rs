@@ -1,32 +1,343 @@
 use std::env;
 
+use chrono::{DateTime, Utc};
+use jsonwebtoken::Algorithm;
+
+use crate::auth::keys::{KeyConfig, KeyMaterial, KeySet, DEFAULT_GRACE_SECS};
+use crate::auth::username::UsernamePolicy;
+
+/// An OpenID Connect issuer users can sign in with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OidcProviderConfig {
+    /// Short name used in URLs, e.g. `google` in `/auth/oidc/google/start`.
+    pub id: String,
+    /// Label shown on the sign-in button.
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Unset for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+pub const GOOGLE_ISSUER: &str = "https://accounts.google.com";
+
+pub const DEFAULT_RECONNECT_GRACE_SECS: u64 = 20;
+pub const DEFAULT_MAX_SPECTATORS: usize = 50;
+
+/// How live games treat their players.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    /// How long a game waits, with the round clock stopped, for a dropped
+    /// player to reconnect before they forfeit. Zero forfeits at once.
+    pub reconnect_grace_secs: u64,
+    /// Most spectators one match takes. Zero turns spectating off.
+    pub max_spectators: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_grace_secs: DEFAULT_RECONNECT_GRACE_SECS,
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AppConfig {
     pub database_url: String,
     pub database_auth_token: Option<String>,
+    /// Local file used as an embedded replica of a remote database.
+    pub database_replica_path: Option<String>,
+    /// How often the embedded replica pulls from the remote, in seconds.
+    pub database_sync_interval_secs: Option<u64>,
+    /// Most database connections kept open at once.
+    pub database_pool_size: Option<usize>,
+    /// How long a request waits for a free connection, in seconds.
+    pub database_pool_timeout_secs: Option<u64>,
+    /// How long an unused connection is kept before being closed, in seconds.
+    pub database_pool_idle_timeout_secs: Option<u64>,
+    /// Prepared statements cached per libSQL connection.
+    pub database_statement_cache_size: Option<usize>,
+    /// Signs short-lived internal tokens such as email links and 2FA
+    /// challenges, and access tokens when `JWT_KEYS` is unset.
     pub jwt_secret: String,
+    /// Keys access tokens are signed and verified with.
+    pub jwt_keys: KeySet,
+    /// Keys the hashes of session IPs. Without it, a key is derived from
+    /// `jwt_secret`.
+    pub session_ip_hash_key: Option<String>,
     pub backend_port: u16,
     pub frontend_url: String,
+    /// Externally reachable base URL of this server, used for OIDC redirects.
+    pub backend_public_url: String,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    /// SMTP relay for outgoing mail. Without it mail is only logged.
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    /// Directory the logging mailer writes `.eml` files to.
+    pub mail_outbox_dir: Option<String>,
+    /// Keep players out of ranked queues until they verify their email.
+    pub require_verified_email_for_ranked: bool,
+    /// Refuse admin routes to staff who haven't enabled 2FA, and keep staff
+    /// from disabling it.
+    pub require_admin_2fa: bool,
+    /// Take client IPs for rate limiting from `Forwarded`/`X-Forwarded-For`.
+    /// Only safe behind a proxy that overwrites those headers.
+    pub trust_forwarded_for: bool,
+    /// Still accept access tokens in the WebSocket URL from clients that
+    /// predate connection tickets.
+    pub allow_ws_query_token: bool,
+    /// Rules for usernames, with the words from `USERNAME_BLOCKLIST` and
+    /// `USERNAME_BLOCKLIST_FILE`.
+    pub username_policy: UsernamePolicy,
+    pub game: GameConfig,
+    /// Passphrase that seals 2FA secrets in snapshots. Without it, exports
+    /// leave the secrets out.
+    pub snapshot_key: Option<String>,
 }
 
 impl AppConfig {
     pub fn from_env() -> Self {
+        let backend_port = env::var("BACKEND_PORT")
+            .unwrap_or_else(|_| "8080".into())
+            .parse()
+            .expect("BACKEND_PORT must be a number");
+
+        let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+
         Self {
             database_url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
             database_auth_token: env::var("DATABASE_AUTH_TOKEN")
                 .ok()
                 .filter(|s| !s.is_empty()),
-            jwt_secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
-            backend_port: env::var("BACKEND_PORT")
-                .unwrap_or_else(|_| "8080".into())
-                .parse()
-                .expect("BACKEND_PORT must be a number"),
+            database_replica_path: env::var("DATABASE_REPLICA_PATH")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            database_sync_interval_secs: env::var("DATABASE_SYNC_INTERVAL_SECS")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse()
+                        .expect("DATABASE_SYNC_INTERVAL_SECS must be a number")
+                }),
+            database_pool_size: number_from_env("DATABASE_POOL_SIZE"),
+            database_pool_timeout_secs: number_from_env("DATABASE_POOL_TIMEOUT_SECS"),
+            database_pool_idle_timeout_secs: number_from_env("DATABASE_POOL_IDLE_TIMEOUT_SECS"),
+            database_statement_cache_size: number_from_env("DATABASE_STATEMENT_CACHE_SIZE"),
+            jwt_keys: jwt_keys_from_env(&jwt_secret),
+            jwt_secret,
+            session_ip_hash_key: env::var("SESSION_IP_HASH_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            backend_port,
             frontend_url: env::var("FRONTEND_URL")
                 .unwrap_or_else(|_| "http://localhost:3000".into()),
+            backend_public_url: env::var("BACKEND_PUBLIC_URL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| format!("http://localhost:{backend_port}")),
+            oidc_providers: oidc_providers_from_env(),
+            smtp_url: env::var("SMTP_URL").ok().filter(|s| !s.is_empty()),
+            mail_from: env::var("MAIL_FROM")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "Red Flip <no-reply@localhost>".into()),
+            mail_outbox_dir: env::var("MAIL_OUTBOX_DIR").ok().filter(|s| !s.is_empty()),
+            require_verified_email_for_ranked: flag_from_env("REQUIRE_VERIFIED_EMAIL_FOR_RANKED"),
+            require_admin_2fa: flag_from_env("REQUIRE_ADMIN_2FA"),
+            trust_forwarded_for: flag_from_env("TRUST_FORWARDED_FOR"),
+            allow_ws_query_token: flag_from_env("ALLOW_WS_QUERY_TOKEN"),
+            username_policy: username_policy_from_env(),
+            game: GameConfig {
+                reconnect_grace_secs: number_from_env("RECONNECT_GRACE_SECS")
+                    .unwrap_or(DEFAULT_RECONNECT_GRACE_SECS),
+                max_spectators: number_from_env("MAX_SPECTATORS").unwrap_or(DEFAULT_MAX_SPECTATORS),
+            },
+            snapshot_key: env::var("SNAPSHOT_KEY").ok().filter(|s| !s.is_empty()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl AppConfig {
+    /// A configuration for tests that touches no external services. Tests
+    /// override what they need with struct update syntax.
+    pub fn for_tests() -> Self {
+        Self {
+            database_url: "unused".into(),
+            database_auth_token: None,
+            database_replica_path: None,
+            database_sync_interval_secs: None,
+            database_pool_size: None,
+            database_pool_timeout_secs: None,
+            database_pool_idle_timeout_secs: None,
+            database_statement_cache_size: None,
+            jwt_secret: "test-secret".into(),
+            jwt_keys: KeySet::from_secret("test-secret"),
+            session_ip_hash_key: None,
+            backend_port: 8080,
+            frontend_url: "http://localhost:3000".into(),
+            backend_public_url: "http://localhost:8080".into(),
+            oidc_providers: Vec::new(),
+            smtp_url: None,
+            mail_from: "Red Flip <no-reply@localhost>".into(),
+            mail_outbox_dir: None,
+            require_verified_email_for_ranked: false,
+            require_admin_2fa: false,
+            trust_forwarded_for: false,
+            allow_ws_query_token: false,
+            username_policy: UsernamePolicy::default(),
+            game: GameConfig::default(),
+            snapshot_key: None,
         }
     }
 }
 
+/// Providers listed in `OIDC_PROVIDERS`, each configured through
+/// `OIDC_<ID>_*` variables. `google` only needs a client id and secret.
+fn oidc_providers_from_env() -> Vec<OidcProviderConfig> {
+    let ids = env::var("OIDC_PROVIDERS").unwrap_or_default();
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let var = |key: &str| {
+                env::var(format!("OIDC_{}_{key}", id.to_uppercase()))
+                    .ok()
+                    .filter(|s| !s.is_empty())
+            };
+            let is_google = id == "google";
+            OidcProviderConfig {
+                id: id.to_string(),
+                name: var("NAME").unwrap_or_else(|| {
+                    if is_google {
+                        "Google".into()
+                    } else {
+                        id.to_string()
+                    }
+                }),
+                issuer: var("ISSUER")
+                    .or_else(|| is_google.then(|| GOOGLE_ISSUER.to_string()))
+                    .unwrap_or_else(|| panic!("OIDC_{}_ISSUER must be set", id.to_uppercase())),
+                client_id: var("CLIENT_ID")
+                    .unwrap_or_else(|| panic!("OIDC_{}_CLIENT_ID must be set", id.to_uppercase())),
+                client_secret: var("CLIENT_SECRET"),
+                scopes: var("SCOPES").unwrap_or_else(|| "openid email profile".into()),
+            }
+        })
+        .collect()
+}
+
+/// Keys listed in `JWT_KEYS`, each configured through `JWT_KEY_<KID>_*`
+/// variables. The first one signs. Without `JWT_KEYS`, access tokens are
+/// signed with `JWT_SECRET`; with it, `JWT_SECRET` only verifies tokens
+/// without a `kid` until `JWT_SECRET_RETIRED_AT`, or the server start when
+/// that is unset, plus the grace window.
+fn jwt_keys_from_env(jwt_secret: &str) -> KeySet {
+    let kids = env::var("JWT_KEYS").unwrap_or_default();
+    let kids: Vec<&str> = kids
+        .split(',')
+        .map(str::trim)
+        .filter(|kid| !kid.is_empty())
+        .collect();
+    if kids.is_empty() {
+        return KeySet::from_secret(jwt_secret);
+    }
+
+    let keys = kids
+        .into_iter()
+        .map(|kid| {
+            let prefix = format!(
+                "JWT_KEY_{}",
+                kid.to_uppercase()
+                    .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+            );
+            let var = |key: &str| {
+                env::var(format!("{prefix}_{key}"))
+                    .ok()
+                    .filter(|s| !s.is_empty())
+            };
+            let algorithm = match var("ALG").as_deref() {
+                Some("HS256") => Algorithm::HS256,
+                Some("RS256") => Algorithm::RS256,
+                Some("EdDSA") => Algorithm::EdDSA,
+                _ => panic!("{prefix}_ALG must be HS256, RS256 or EdDSA"),
+            };
+            let material = match (var("SECRET"), var("PRIVATE_KEY_FILE")) {
+                (Some(secret), None) => KeyMaterial::Secret(secret),
+                (None, Some(path)) => KeyMaterial::PrivateKeyPem(
+                    std::fs::read_to_string(&path)
+                        .unwrap_or_else(|e| panic!("{prefix}_PRIVATE_KEY_FILE {path}: {e}")),
+                ),
+                _ => panic!("set one of {prefix}_SECRET or {prefix}_PRIVATE_KEY_FILE"),
+            };
+            let retired_at =
+                var("RETIRED_AT").map(|at| rfc3339(&format!("{prefix}_RETIRED_AT"), &at));
+            KeyConfig {
+                kid: kid.to_string(),
+                algorithm,
+                material,
+                retired_at,
+            }
+        })
+        .collect();
+    let grace = number_from_env("JWT_KEY_GRACE_SECS").unwrap_or(DEFAULT_GRACE_SECS);
+    let secret_retired_at = env::var("JWT_SECRET_RETIRED_AT")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map_or_else(Utc::now, |at| rfc3339("JWT_SECRET_RETIRED_AT", &at));
+    KeySet::new(keys, grace)
+        .unwrap_or_else(|e| panic!("JWT_KEYS: {e}"))
+        .with_retired_secret(jwt_secret, secret_retired_at)
+}
+
+fn rfc3339(name: &str, value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .unwrap_or_else(|_| panic!("{name} must be an RFC 3339 time"))
+        .with_timezone(&Utc)
+}
+
+/// Blocked words from the comma-separated `USERNAME_BLOCKLIST` and from
+/// `USERNAME_BLOCKLIST_FILE`, one per line. Lines starting with `#` are
+/// comments.
+fn username_policy_from_env() -> UsernamePolicy {
+    let mut words: Vec<String> = env::var("USERNAME_BLOCKLIST")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::to_string)
+        .collect();
+    if let Some(path) = env::var("USERNAME_BLOCKLIST_FILE")
+        .ok()
+        .filter(|s| !s.is_empty())
+    {
+        let file = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("USERNAME_BLOCKLIST_FILE {path}: {e}"));
+        words.extend(
+            file.lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+    UsernamePolicy::new(words)
+}
+
+fn flag_from_env(name: &str) -> bool {
+    match env::var(name).unwrap_or_default().trim() {
+        "" | "0" | "false" => false,
+        "1" | "true" => true,
+        _ => panic!("{name} must be true or false"),
+    }
+}
+
+fn number_from_env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().filter(|s| !s.is_empty()).map(|s| {
+        s.parse()
+            .unwrap_or_else(|_| panic!("{name} must be a number"))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,17 +354,211 @@ mod tests {
 
         std::env::set_var("DATABASE_URL", "libsql://example.turso.io");
         std::env::remove_var("DATABASE_AUTH_TOKEN");
+        std::env::remove_var("DATABASE_REPLICA_PATH");
+        std::env::remove_var("DATABASE_SYNC_INTERVAL_SECS");
+        std::env::set_var("DATABASE_POOL_SIZE", "8");
+        std::env::remove_var("DATABASE_POOL_TIMEOUT_SECS");
+        std::env::remove_var("DATABASE_POOL_IDLE_TIMEOUT_SECS");
+        std::env::remove_var("DATABASE_STATEMENT_CACHE_SIZE");
         std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::remove_var("JWT_KEYS");
         std::env::remove_var("BACKEND_PORT");
         std::env::remove_var("FRONTEND_URL");
+        std::env::remove_var("BACKEND_PUBLIC_URL");
+        std::env::remove_var("OIDC_PROVIDERS");
+        std::env::remove_var("SMTP_URL");
+        std::env::remove_var("MAIL_FROM");
+        std::env::remove_var("MAIL_OUTBOX_DIR");
+        std::env::set_var("REQUIRE_VERIFIED_EMAIL_FOR_RANKED", "true");
+        std::env::remove_var("REQUIRE_ADMIN_2FA");
+        std::env::remove_var("TRUST_FORWARDED_FOR");
+        std::env::remove_var("ALLOW_WS_QUERY_TOKEN");
+        std::env::set_var("RECONNECT_GRACE_SECS", "45");
+        std::env::remove_var("MAX_SPECTATORS");
 
         let cfg = AppConfig::from_env();
 
         assert_eq!(cfg.database_url, "libsql://example.turso.io");
         assert_eq!(cfg.database_auth_token, None);
+        assert_eq!(cfg.database_replica_path, None);
+        assert_eq!(cfg.database_sync_interval_secs, None);
+        assert_eq!(cfg.database_pool_size, Some(8));
+        assert_eq!(cfg.database_pool_timeout_secs, None);
         assert_eq!(cfg.jwt_secret, "test-secret");
         assert_eq!(cfg.backend_port, 8080);
         assert_eq!(cfg.frontend_url, "http://localhost:3000");
+        assert_eq!(cfg.backend_public_url, "http://localhost:8080");
+        assert!(cfg.oidc_providers.is_empty());
+        assert_eq!(cfg.smtp_url, None);
+        assert_eq!(cfg.mail_from, "Red Flip <no-reply@localhost>");
+        assert_eq!(cfg.mail_outbox_dir, None);
+        assert!(cfg.require_verified_email_for_ranked);
+        assert!(!cfg.require_admin_2fa);
+        assert!(!cfg.trust_forwarded_for);
+        assert!(!cfg.allow_ws_query_token);
+        assert_eq!(cfg.game.reconnect_grace_secs, 45);
+        assert_eq!(cfg.game.max_spectators, DEFAULT_MAX_SPECTATORS);
+        std::env::remove_var("REQUIRE_VERIFIED_EMAIL_FOR_RANKED");
+        std::env::remove_var("RECONNECT_GRACE_SECS");
+    }
+
+    #[test]
+    fn from_env_reads_oidc_providers_with_google_preset() {
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        std::env::set_var("DATABASE_URL", "file:test.db");
+        std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::set_var("OIDC_PROVIDERS", "google, corp");
+        std::env::set_var("OIDC_GOOGLE_CLIENT_ID", "google-client");
+        std::env::set_var("OIDC_GOOGLE_CLIENT_SECRET", "google-secret");
+        std::env::set_var("OIDC_CORP_ISSUER", "https://sso.example.com");
+        std::env::set_var("OIDC_CORP_CLIENT_ID", "corp-client");
+        std::env::set_var("OIDC_CORP_NAME", "Corp SSO");
+
+        let cfg = AppConfig::from_env();
+        for key in [
+            "OIDC_PROVIDERS",
+            "OIDC_GOOGLE_CLIENT_ID",
+            "OIDC_GOOGLE_CLIENT_SECRET",
+            "OIDC_CORP_ISSUER",
+            "OIDC_CORP_CLIENT_ID",
+            "OIDC_CORP_NAME",
+        ] {
+            std::env::remove_var(key);
+        }
+
+        assert_eq!(
+            cfg.oidc_providers,
+            vec![
+                OidcProviderConfig {
+                    id: "google".into(),
+                    name: "Google".into(),
+                    issuer: GOOGLE_ISSUER.into(),
+                    client_id: "google-client".into(),
+                    client_secret: Some("google-secret".into()),
+                    scopes: "openid email profile".into(),
+                },
+                OidcProviderConfig {
+                    id: "corp".into(),
+                    name: "Corp SSO".into(),
+                    issuer: "https://sso.example.com".into(),
+                    client_id: "corp-client".into(),
+                    client_secret: None,
+                    scopes: "openid email profile".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn from_env_reads_jwt_keyset() {
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        let old_secret = "an-old-secret-that-is-at-least-32-bytes";
+        std::env::set_var("DATABASE_URL", "file:test.db");
+        std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::set_var("JWT_KEYS", "2026-10, 2026-04");
+        std::env::set_var("JWT_KEY_2026_10_ALG", "HS256");
+        std::env::set_var(
+            "JWT_KEY_2026_10_SECRET",
+            "a-new-secret-that-is-at-least-32-bytes",
+        );
+        std::env::set_var("JWT_KEY_2026_04_ALG", "HS256");
+        std::env::set_var("JWT_KEY_2026_04_SECRET", old_secret);
+        std::env::set_var("JWT_KEY_2026_04_RETIRED_AT", "2026-10-01T00:00:00Z");
+        std::env::set_var("JWT_KEY_GRACE_SECS", "60");
+        std::env::set_var("JWT_SECRET_RETIRED_AT", "2026-10-02T00:00:00Z");
+
+        let cfg = AppConfig::from_env();
+        for key in [
+            "JWT_SECRET_RETIRED_AT",
+            "JWT_KEYS",
+            "JWT_KEY_2026_10_ALG",
+            "JWT_KEY_2026_10_SECRET",
+            "JWT_KEY_2026_04_ALG",
+            "JWT_KEY_2026_04_SECRET",
+            "JWT_KEY_2026_04_RETIRED_AT",
+            "JWT_KEY_GRACE_SECS",
+        ] {
+            std::env::remove_var(key);
+        }
+
+        let claims = serde_json::json!({ "sub": "user-1", "exp": 4_000_000_000u64 });
+        let token = cfg.jwt_keys.sign(&claims).expect("token should be signed");
+        let header = jsonwebtoken::decode_header(&token).expect("header should decode");
+        assert_eq!(header.kid.as_deref(), Some("2026-10"));
+
+        let old = KeySet::new(
+            vec![KeyConfig {
+                kid: "2026-04".into(),
+                algorithm: Algorithm::HS256,
+                material: KeyMaterial::Secret(old_secret.into()),
+                retired_at: None,
+            }],
+            DEFAULT_GRACE_SECS,
+        )
+        .expect("keyset should build");
+        let old_token = old.sign(&claims).expect("token should be signed");
+        let retired = DateTime::parse_from_rfc3339("2026-10-01T00:00:00Z")
+            .expect("time should parse")
+            .with_timezone(&Utc);
+        assert!(cfg
+            .jwt_keys
+            .verify_at::<serde_json::Value>(&old_token, retired + chrono::Duration::seconds(30))
+            .is_ok());
+        assert!(cfg
+            .jwt_keys
+            .verify_at::<serde_json::Value>(&old_token, retired + chrono::Duration::seconds(90))
+            .is_err());
+
+        let legacy_token = KeySet::from_secret("test-secret")
+            .sign(&claims)
+            .expect("token should be signed");
+        let secret_retired = retired + chrono::Duration::days(1);
+        assert!(cfg
+            .jwt_keys
+            .verify_at::<serde_json::Value>(
+                &legacy_token,
+                secret_retired + chrono::Duration::seconds(30)
+            )
+            .is_ok());
+        assert!(cfg
+            .jwt_keys
+            .verify_at::<serde_json::Value>(
+                &legacy_token,
+                secret_retired + chrono::Duration::seconds(90)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn from_env_panics_when_an_old_key_has_no_retirement_time() {
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        let vars = [
+            ("DATABASE_URL", "file:test.db"),
+            ("JWT_SECRET", "test-secret"),
+            ("JWT_KEYS", "new,old"),
+            ("JWT_KEY_NEW_ALG", "HS256"),
+            (
+                "JWT_KEY_NEW_SECRET",
+                "a-new-secret-that-is-at-least-32-bytes",
+            ),
+            ("JWT_KEY_OLD_ALG", "HS256"),
+            (
+                "JWT_KEY_OLD_SECRET",
+                "an-old-secret-that-is-at-least-32-bytes",
+            ),
+        ];
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        let result = std::panic::catch_unwind(AppConfig::from_env);
+        for (key, _) in &vars[2..] {
+            std::env::remove_var(key);
+        }
+        let Err(panic) = result else {
+            panic!("an unretired old key should be refused");
+        };
+        let message = panic.downcast_ref::<String>().cloned().unwrap_or_default();
+        assert!(message.contains("`old` doesn't sign"), "{message}");
     }
 
     #[test]
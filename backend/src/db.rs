@@ -1,64 +1,552 @@
-use libsql::Builder;
+use libsql::{Builder, Connection};
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
-pub type Database = Arc<libsql::Database>;
+use crate::config::AppConfig;
 
-pub async fn init_pool(database_url: &str, auth_token: Option<&str>) -> Database {
-    let token = auth_token.expect("AUTH_TOKEN required for remote database");
-    let db = Builder::new_remote(database_url.to_string(), token.to_string())
-        .build()
-        .await
-        .expect("Failed to connect to remote database");
+mod pool;
+
+use self::pool::Pool;
+pub use self::pool::{PoolConfig, PoolMetrics, PooledConnection};
 
-    Arc::new(db)
+const DEFAULT_SYNC_INTERVAL_SECS: u64 = 60;
+
+/// Shared handle to the libSQL database, whichever mode it was opened in.
+#[derive(Clone)]
+pub struct Database {
+    pool: Arc<Pool>,
+    // An in-memory database is dropped as soon as its last connection closes,
+    // so memory mode keeps one connection open for the lifetime of the handle.
+    _keepalive: Option<Arc<Connection>>,
 }
 
-pub async fn run_migrations(db: &Database) {
-    let migrations = [
-        include_str!("../migrations/001_create_users.sql"),
-        include_str!("../migrations/002_create_matches.sql"),
-        include_str!("../migrations/003_create_elo_history.sql"),
-        include_str!("../migrations/004_add_admin_fields.sql"),
-        include_str!("../migrations/005_add_ai_players.sql"),
-    ];
+impl Database {
+    fn new(db: libsql::Database, config: PoolConfig) -> Self {
+        Self {
+            pool: Arc::new(Pool::new(Arc::new(db), config)),
+            _keepalive: None,
+        }
+    }
+
+    /// Borrow a connection from the pool, waiting up to the configured
+    /// timeout when every connection is in use.
+    pub async fn connect(&self) -> libsql::Result<PooledConnection> {
+        self.pool.get().await
+    }
+
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        self.pool.metrics()
+    }
+}
 
-    let conn = db.connect().expect("Failed to get connection");
+/// How the server reaches its database, derived from `DATABASE_URL`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatabaseMode {
+    /// `:memory:` - a throwaway database that lives as long as the process.
+    Memory,
+    /// `file:<path>` - a plain SQLite file on local disk.
+    Local { path: String },
+    /// `libsql://`, `https://`, ... - every query goes to the remote server.
+    Remote { url: String, auth_token: String },
+    /// A remote URL plus `DATABASE_REPLICA_PATH` - reads are served from a local
+    /// copy that syncs from the remote, writes are forwarded to it.
+    EmbeddedReplica {
+        path: String,
+        url: String,
+        auth_token: String,
+        sync_interval: Duration,
+    },
+}
+
+impl DatabaseMode {
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        let url = config.database_url.trim();
+
+        if url == ":memory:" {
+            return match config.database_replica_path {
+                Some(_) => Err("DATABASE_REPLICA_PATH requires a remote DATABASE_URL".into()),
+                None => Ok(DatabaseMode::Memory),
+            };
+        }
+
+        if let Some(path) = url
+            .strip_prefix("file://")
+            .or_else(|| url.strip_prefix("file:"))
+        {
+            if path.is_empty() {
+                return Err("DATABASE_URL `file:` must be followed by a path".into());
+            }
+            if config.database_replica_path.is_some() {
+                return Err("DATABASE_REPLICA_PATH requires a remote DATABASE_URL".into());
+            }
+            return Ok(DatabaseMode::Local {
+                path: path.to_string(),
+            });
+        }
+
+        let is_remote = ["libsql://", "https://", "http://", "wss://", "ws://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme));
+        if !is_remote {
+            return Err(format!(
+                "Unsupported DATABASE_URL `{url}`: expected `:memory:`, `file:<path>` or a libsql:// / https:// URL"
+            ));
+        }
 
-    for migration in &migrations {
-        // Remove comment-only lines, then split into individual statements
-        // (libsql doesn't support batch execution).
-        let sanitized_migration = migration
+        let auth_token = config
+            .database_auth_token
+            .clone()
+            .ok_or("DATABASE_AUTH_TOKEN is required for a remote DATABASE_URL")?;
+
+        Ok(match &config.database_replica_path {
+            Some(path) => DatabaseMode::EmbeddedReplica {
+                path: path.clone(),
+                url: url.to_string(),
+                auth_token,
+                sync_interval: Duration::from_secs(
+                    config
+                        .database_sync_interval_secs
+                        .unwrap_or(DEFAULT_SYNC_INTERVAL_SECS),
+                ),
+            },
+            None => DatabaseMode::Remote {
+                url: url.to_string(),
+                auth_token,
+            },
+        })
+    }
+}
+
+/// Open the database `mode` names. Failures are returned rather than
+/// panicking so `main` can report them like any other configuration error.
+pub async fn init_pool(mode: &DatabaseMode, pool: PoolConfig) -> Result<Database, String> {
+    let db = match mode {
+        DatabaseMode::Memory => {
+            log::info!("Using in-memory database");
+            // A named shared-cache database lets every connection see the same data.
+            let uri = format!(
+                "file:red_flip_{}?mode=memory&cache=shared",
+                uuid::Uuid::new_v4().simple()
+            );
+            let db = Builder::new_local(uri)
+                .build()
+                .await
+                .map_err(|e| format!("Failed to create in-memory database: {e}"))?;
+            let keepalive = db
+                .connect()
+                .map_err(|e| format!("Failed to open in-memory database connection: {e}"))?;
+            Database {
+                _keepalive: Some(Arc::new(keepalive)),
+                ..Database::new(db, pool)
+            }
+        }
+        DatabaseMode::Local { path } => {
+            log::info!("Using local database file {path}");
+            let db = Builder::new_local(path)
+                .build()
+                .await
+                .map_err(|e| format!("Failed to open local database {path}: {e}"))?;
+            Database::new(db, pool)
+        }
+        DatabaseMode::Remote { url, auth_token } => {
+            log::info!("Using remote database {url}");
+            let db = Builder::new_remote(url.clone(), auth_token.clone())
+                .build()
+                .await
+                .map_err(|e| format!("Failed to connect to remote database {url}: {e}"))?;
+            Database::new(db, pool)
+        }
+        DatabaseMode::EmbeddedReplica {
+            path,
+            url,
+            auth_token,
+            sync_interval,
+        } => {
+            log::info!(
+                "Using embedded replica {path} of {url} (sync every {}s)",
+                sync_interval.as_secs()
+            );
+            let db = Builder::new_remote_replica(path, url.clone(), auth_token.clone())
+                .sync_interval(*sync_interval)
+                .build()
+                .await
+                .map_err(|e| format!("Failed to open embedded replica {path}: {e}"))?;
+            // Pull the current state before serving so the first reads aren't stale.
+            db.sync()
+                .await
+                .map_err(|e| format!("Failed to sync embedded replica from {url}: {e}"))?;
+            Database::new(db, pool)
+        }
+    };
+    Ok(db)
+}
+
+/// A schema change shipped with the binary, applied at most once per database.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+
+    /// Split the migration into individual statements, dropping comment-only
+    /// lines (libsql doesn't support batch execution).
+    pub(crate) fn statements(&self) -> Vec<String> {
+        let sanitized = self
+            .sql
             .lines()
             .filter(|line| !line.trim_start().starts_with("--"))
             .collect::<Vec<_>>()
             .join("\n");
 
-        let statements: Vec<&str> = sanitized_migration
+        sanitized
             .split(';')
             .map(|s| s.trim())
             .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        sql: include_str!("../migrations/001_create_users.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "create_matches",
+        sql: include_str!("../migrations/002_create_matches.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_elo_history",
+        sql: include_str!("../migrations/003_create_elo_history.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "add_admin_fields",
+        sql: include_str!("../migrations/004_add_admin_fields.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "add_ai_players",
+        sql: include_str!("../migrations/005_add_ai_players.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "create_match_rounds",
+        sql: include_str!("../migrations/006_create_match_rounds.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "create_refresh_tokens",
+        sql: include_str!("../migrations/007_create_refresh_tokens.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "add_token_version",
+        sql: include_str!("../migrations/008_add_token_version.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "add_email_verification",
+        sql: include_str!("../migrations/009_add_email_verification.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "add_two_factor",
+        sql: include_str!("../migrations/010_add_two_factor.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "add_account_management",
+        sql: include_str!("../migrations/011_add_account_management.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "add_roles",
+        sql: include_str!("../migrations/012_add_roles.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "create_api_tokens",
+        sql: include_str!("../migrations/013_create_api_tokens.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "create_guest_matches",
+        sql: include_str!("../migrations/014_create_guest_matches.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "add_username_lower_index",
+        sql: include_str!("../migrations/015_add_username_lower_index.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "create_login_sessions",
+        sql: include_str!("../migrations/016_create_login_sessions.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "add_allow_spectators",
+        sql: include_str!("../migrations/017_add_allow_spectators.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "unique_username_lower",
+        sql: include_str!("../migrations/018_unique_username_lower.sql"),
+    },
+];
+
+/// Databases created before the ledger existed had every migration up to
+/// this version applied by the old run-everything-on-boot runner.
+const LEGACY_BASELINE_VERSION: i64 = 5;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(String),
+    ChecksumMismatch {
+        version: i64,
+        name: String,
+        recorded: String,
+        expected: String,
+    },
+    OutOfOrder {
+        version: i64,
+        name: String,
+        latest_applied: i64,
+    },
+    Failed {
+        version: i64,
+        name: String,
+        statement: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(msg) => write!(f, "migration ledger error: {msg}"),
+            MigrationError::ChecksumMismatch {
+                version,
+                name,
+                recorded,
+                expected,
+            } => write!(
+                f,
+                "migration {version} ({name}) was modified after being applied: recorded checksum {recorded}, current {expected}"
+            ),
+            MigrationError::OutOfOrder {
+                version,
+                name,
+                latest_applied,
+            } => write!(
+                f,
+                "migration {version} ({name}) is pending but migration {latest_applied} is already applied"
+            ),
+            MigrationError::Failed {
+                version,
+                name,
+                statement,
+                error,
+            } => write!(
+                f,
+                "migration {version} ({name}) failed on statement `{statement}`: {error}"
+            ),
+        }
+    }
+}
+
+impl From<libsql::Error> for MigrationError {
+    fn from(err: libsql::Error) -> Self {
+        MigrationError::Database(err.to_string())
+    }
+}
+
+/// What `run_migrations` would do against the current database.
+pub struct MigrationPlan {
+    pub pending: Vec<&'static Migration>,
+    /// The database predates the ledger; migrations up to the legacy baseline
+    /// will be recorded as applied without being re-run.
+    pub adopt_legacy: bool,
+    /// Versions recorded in the ledger that this binary doesn't know about.
+    pub unknown: Vec<i64>,
+}
+
+async fn table_exists(conn: &Connection, name: &str) -> Result<bool, MigrationError> {
+    let mut rows = conn
+        .query(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+        )
+        .await?;
+    let count: i64 = match rows.next().await? {
+        Some(row) => row.get(0)?,
+        None => 0,
+    };
+    Ok(count > 0)
+}
+
+/// Compare the ledger against `MIGRATIONS` without changing anything.
+pub async fn plan_migrations(db: &Database) -> Result<MigrationPlan, MigrationError> {
+    let conn = db.connect().await?;
+    read_plan(&conn).await
+}
+
+/// Planning inside `run_migrations` reuses its connection, so a pool of one
+/// doesn't wait on itself.
+async fn read_plan(conn: &Connection) -> Result<MigrationPlan, MigrationError> {
+    let mut applied: Vec<(i64, String)> = Vec::new();
+    if table_exists(conn, "schema_migrations").await? {
+        let mut rows = conn
+            .query(
+                "SELECT version, checksum FROM schema_migrations ORDER BY version",
+                (),
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            applied.push((row.get(0)?, row.get(1)?));
+        }
+    }
+
+    let adopt_legacy = applied.is_empty() && table_exists(conn, "users").await?;
+    if adopt_legacy {
+        applied = MIGRATIONS
+            .iter()
+            .filter(|m| m.version <= LEGACY_BASELINE_VERSION)
+            .map(|m| (m.version, m.checksum()))
             .collect();
+    }
+
+    plan_against(MIGRATIONS, &applied, adopt_legacy)
+}
 
-        for statement in statements {
-            match conn.execute(statement, ()).await {
-                Ok(_) => {}
-                Err(e) => {
-                    let err_str = e.to_string();
-                    // Ignore errors for already existing tables/columns/indexes (idempotent migrations)
-                    if err_str.contains("already exists") || err_str.contains("duplicate column") {
-                        log::debug!("Skipping migration (already applied): {}", statement);
-                    } else {
-                        panic!(
-                            "Failed to run migration statement: {}\nError: {}",
-                            statement, err_str
-                        );
-                    }
+/// Check recorded `(version, checksum)` pairs against the migrations shipped
+/// with the binary and work out what is still pending.
+pub(crate) fn plan_against(
+    migrations: &'static [Migration],
+    applied: &[(i64, String)],
+    adopt_legacy: bool,
+) -> Result<MigrationPlan, MigrationError> {
+    let mut unknown = Vec::new();
+    for (version, recorded) in applied {
+        match migrations.iter().find(|m| m.version == *version) {
+            Some(migration) => {
+                let expected = migration.checksum();
+                if *recorded != expected {
+                    return Err(MigrationError::ChecksumMismatch {
+                        version: *version,
+                        name: migration.name.to_string(),
+                        recorded: recorded.clone(),
+                        expected,
+                    });
                 }
             }
+            None => unknown.push(*version),
+        }
+    }
+
+    let latest_applied = applied.iter().map(|(v, _)| *v).max().unwrap_or(0);
+    let pending: Vec<&'static Migration> = migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|(v, _)| *v == m.version))
+        .collect();
+
+    if let Some(m) = pending.iter().find(|m| m.version < latest_applied) {
+        return Err(MigrationError::OutOfOrder {
+            version: m.version,
+            name: m.name.to_string(),
+            latest_applied,
+        });
+    }
+
+    Ok(MigrationPlan {
+        pending,
+        adopt_legacy,
+        unknown,
+    })
+}
+
+/// Apply every pending migration in order, each inside its own transaction,
+/// and return the versions that were applied.
+pub async fn run_migrations(db: &Database) -> Result<Vec<i64>, MigrationError> {
+    let conn = db.connect().await?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )",
+        (),
+    )
+    .await?;
+
+    let plan = read_plan(&conn).await?;
+
+    for version in &plan.unknown {
+        log::warn!("Database has migration {version} applied that this build doesn't know about");
+    }
+
+    if plan.adopt_legacy {
+        log::warn!(
+            "Existing schema has no migration ledger, recording migrations up to {LEGACY_BASELINE_VERSION} as applied"
+        );
+        for migration in MIGRATIONS
+            .iter()
+            .filter(|m| m.version <= LEGACY_BASELINE_VERSION)
+        {
+            conn.execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES (?1, ?2, ?3)",
+                (migration.version, migration.name, migration.checksum()),
+            )
+            .await?;
         }
     }
 
+    let mut applied = Vec::new();
+    for migration in plan.pending {
+        let tx = conn.transaction().await?;
+
+        for statement in migration.statements() {
+            if let Err(e) = tx.execute(&statement, ()).await {
+                return Err(MigrationError::Failed {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                    statement,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES (?1, ?2, ?3)",
+            (migration.version, migration.name, migration.checksum()),
+        )
+        .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Applied migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        applied.push(migration.version);
+    }
+
     log::info!("Database migrations completed");
+    Ok(applied)
 }
 
 #[cfg(test)]
@@ -68,8 +556,10 @@ pub async fn init_test_db() -> Database {
         .build()
         .await
         .expect("Failed to create local test database");
-    let db = Arc::new(db);
-    run_migrations(&db).await;
+    let db = Database::new(db, PoolConfig::default());
+    run_migrations(&db)
+        .await
+        .expect("Failed to migrate test database");
     db
 }
 
@@ -80,9 +570,12 @@ mod tests {
     #[actix_rt::test]
     async fn run_migrations_is_idempotent() {
         let db = init_test_db().await;
-        run_migrations(&db).await;
+        let applied = run_migrations(&db)
+            .await
+            .expect("second run should succeed");
+        assert!(applied.is_empty());
 
-        let conn = db.connect().expect("connection should be available");
+        let conn = db.connect().await.expect("connection should be available");
         let mut rows = conn
             .query(
                 "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'users'",
@@ -99,4 +592,249 @@ mod tests {
         let count: i64 = row.get(0).expect("count column should exist");
         assert_eq!(count, 1);
     }
+
+    #[actix_rt::test]
+    async fn run_migrations_fits_in_a_pool_of_one() {
+        let pool = PoolConfig {
+            max_size: 1,
+            acquire_timeout: Duration::from_millis(200),
+            ..PoolConfig::default()
+        };
+        let db = init_pool(&DatabaseMode::Memory, pool)
+            .await
+            .expect("memory database should open");
+        let applied = run_migrations(&db)
+            .await
+            .expect("migrations should run on one connection");
+        assert_eq!(applied.len(), MIGRATIONS.len());
+    }
+
+    fn config_with(url: &str, token: Option<&str>, replica: Option<&str>) -> AppConfig {
+        AppConfig {
+            database_url: url.into(),
+            database_auth_token: token.map(Into::into),
+            database_replica_path: replica.map(Into::into),
+            ..AppConfig::for_tests()
+        }
+    }
+
+    #[test]
+    fn database_mode_parses_supported_urls() {
+        assert_eq!(
+            DatabaseMode::from_config(&config_with(":memory:", None, None)),
+            Ok(DatabaseMode::Memory)
+        );
+        assert_eq!(
+            DatabaseMode::from_config(&config_with("file:data/red-flip.db", None, None)),
+            Ok(DatabaseMode::Local {
+                path: "data/red-flip.db".into()
+            })
+        );
+        assert_eq!(
+            DatabaseMode::from_config(&config_with("file:///var/lib/red-flip.db", None, None)),
+            Ok(DatabaseMode::Local {
+                path: "/var/lib/red-flip.db".into()
+            })
+        );
+        assert_eq!(
+            DatabaseMode::from_config(&config_with("libsql://db.turso.io", Some("tok"), None)),
+            Ok(DatabaseMode::Remote {
+                url: "libsql://db.turso.io".into(),
+                auth_token: "tok".into()
+            })
+        );
+        assert_eq!(
+            DatabaseMode::from_config(&config_with(
+                "libsql://db.turso.io",
+                Some("tok"),
+                Some("replica.db")
+            )),
+            Ok(DatabaseMode::EmbeddedReplica {
+                path: "replica.db".into(),
+                url: "libsql://db.turso.io".into(),
+                auth_token: "tok".into(),
+                sync_interval: Duration::from_secs(DEFAULT_SYNC_INTERVAL_SECS),
+            })
+        );
+    }
+
+    #[test]
+    fn database_mode_rejects_invalid_combinations() {
+        assert!(
+            DatabaseMode::from_config(&config_with("libsql://db.turso.io", None, None)).is_err()
+        );
+        assert!(
+            DatabaseMode::from_config(&config_with(":memory:", None, Some("replica.db"))).is_err()
+        );
+        assert!(
+            DatabaseMode::from_config(&config_with("postgres://localhost/db", None, None)).is_err()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn memory_mode_shares_data_between_connections() {
+        let db = init_pool(&DatabaseMode::Memory, PoolConfig::default())
+            .await
+            .expect("memory database should open");
+        run_migrations(&db).await.expect("migrations should apply");
+
+        db.connect()
+            .await
+            .expect("connection should be available")
+            .execute(
+                "INSERT INTO users (id, username, email) VALUES ('mem-1', 'mem_user', 'mem@example.com')",
+                (),
+            )
+            .await
+            .expect("insert should succeed");
+
+        let conn = db.connect().await.expect("connection should be available");
+        let mut rows = conn
+            .query("SELECT username FROM users WHERE id = 'mem-1'", ())
+            .await
+            .expect("query should succeed");
+        let row = rows
+            .next()
+            .await
+            .expect("row fetch should succeed")
+            .expect("row should exist");
+        let username: String = row.get(0).expect("username column should exist");
+        assert_eq!(username, "mem_user");
+    }
+
+    async fn ledger_versions(db: &Database) -> Vec<i64> {
+        let conn = db.connect().await.expect("connection should be available");
+        let mut rows = conn
+            .query("SELECT version FROM schema_migrations ORDER BY version", ())
+            .await
+            .expect("ledger query should succeed");
+        let mut versions = Vec::new();
+        while let Some(row) = rows.next().await.expect("row fetch should succeed") {
+            versions.push(row.get::<i64>(0).expect("version column should exist"));
+        }
+        versions
+    }
+
+    #[actix_rt::test]
+    async fn run_migrations_records_every_version_in_ledger() {
+        let db = init_pool(&DatabaseMode::Memory, PoolConfig::default())
+            .await
+            .expect("memory database should open");
+
+        let plan = plan_migrations(&db).await.expect("plan should succeed");
+        assert_eq!(plan.pending.len(), MIGRATIONS.len());
+        assert!(!plan.adopt_legacy);
+
+        let applied = run_migrations(&db).await.expect("migrations should apply");
+        let expected: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(applied, expected);
+        assert_eq!(ledger_versions(&db).await, expected);
+    }
+
+    #[actix_rt::test]
+    async fn run_migrations_adopts_legacy_schema_without_ledger() {
+        // Recreate what the pre-ledger runner left behind: the baseline schema
+        // and no schema_migrations table.
+        let db = init_pool(&DatabaseMode::Memory, PoolConfig::default())
+            .await
+            .expect("memory database should open");
+        let conn = db.connect().await.expect("connection should be available");
+        for migration in MIGRATIONS
+            .iter()
+            .filter(|m| m.version <= LEGACY_BASELINE_VERSION)
+        {
+            for statement in migration.statements() {
+                conn.execute(&statement, ())
+                    .await
+                    .expect("baseline statement should apply");
+            }
+        }
+
+        let plan = plan_migrations(&db).await.expect("plan should succeed");
+        assert!(plan.adopt_legacy);
+        assert!(plan
+            .pending
+            .iter()
+            .all(|m| m.version > LEGACY_BASELINE_VERSION));
+
+        run_migrations(&db)
+            .await
+            .expect("legacy database should migrate");
+        assert_eq!(ledger_versions(&db).await.len(), MIGRATIONS.len());
+    }
+
+    #[actix_rt::test]
+    async fn unique_username_migration_renames_names_differing_only_in_case() {
+        // Roll back to before migration 18, with names that clash by case.
+        let db = init_test_db().await;
+        let conn = db.connect().await.expect("connection should be available");
+        for statement in [
+            "DROP INDEX idx_users_username_lower",
+            "CREATE INDEX idx_users_username_lower ON users(lower(username))",
+            "DELETE FROM schema_migrations WHERE version = 18",
+            "INSERT INTO users (id, username, email, created_at) VALUES ('b0b0b0b0-1', 'Bob', 'bob1@example.com', '2024-01-01 00:00:00')",
+            "INSERT INTO users (id, username, email, created_at) VALUES ('b0b0b0b0-2', 'bob', 'bob2@example.com', '2024-02-01 00:00:00')",
+            "INSERT INTO users (id, username, email, created_at) VALUES ('c0c0c0c0-3', 'Carol', 'carol@example.com', '2024-01-01 00:00:00')",
+        ] {
+            conn.execute(statement, ())
+                .await
+                .expect("setup statement should apply");
+        }
+
+        let applied = run_migrations(&db).await.expect("migration should apply");
+        assert_eq!(applied, vec![18]);
+        let mut rows = conn
+            .query(
+                "SELECT username FROM users WHERE email IN ('bob1@example.com', 'bob2@example.com', 'carol@example.com') ORDER BY email",
+                (),
+            )
+            .await
+            .expect("query should succeed");
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await.expect("row fetch should succeed") {
+            names.push(row.get::<String>(0).expect("username column should exist"));
+        }
+        assert_eq!(names, ["Bob", "bob_b0b0b0b0", "Carol"]);
+
+        let clash = conn
+            .execute(
+                "INSERT INTO users (id, username, email) VALUES ('d', 'CAROL', 'd@example.com')",
+                (),
+            )
+            .await;
+        assert!(clash.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn run_migrations_reports_checksum_mismatch() {
+        let db = init_test_db().await;
+        let conn = db.connect().await.expect("connection should be available");
+        conn.execute(
+            "UPDATE schema_migrations SET checksum = 'tampered' WHERE version = 2",
+            (),
+        )
+        .await
+        .expect("update should succeed");
+
+        let result = run_migrations(&db).await;
+        assert!(matches!(
+            result,
+            Err(MigrationError::ChecksumMismatch { version: 2, .. })
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn run_migrations_reports_out_of_order_migration() {
+        let db = init_test_db().await;
+        let conn = db.connect().await.expect("connection should be available");
+        conn.execute("DELETE FROM schema_migrations WHERE version = 3", ())
+            .await
+            .expect("delete should succeed");
+
+        let result = plan_migrations(&db).await;
+        assert!(matches!(
+            result,
+            Err(MigrationError::OutOfOrder { version: 3, .. })
+        ));
+    }
 }
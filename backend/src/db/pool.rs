@@ -0,0 +1,447 @@
+//! Connection reuse for libSQL.
+//!
+//! Connections are handed out through `Database::connect` and go back to the
+//! pool when dropped. Each pooled connection keeps a small cache of prepared
+//! statements, used through `query_cached` and `execute_cached`.
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use libsql::params::IntoParams;
+use libsql::{Connection, Row, Rows, Statement};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::AppConfig;
+
+const DEFAULT_POOL_SIZE: usize = 16;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_STATEMENT_CACHE_SIZE: usize = 64;
+
+/// Limits for the connection pool, from the `DATABASE_POOL_*` settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    /// Most connections open at once; callers beyond this wait for one to free up.
+    pub max_size: usize,
+    /// How long `connect` waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// Idle connections older than this are closed instead of reused.
+    pub idle_timeout: Duration,
+    /// Prepared statements kept per connection. Zero disables the cache.
+    pub statement_cache_size: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_POOL_SIZE,
+            acquire_timeout: Duration::from_secs(DEFAULT_ACQUIRE_TIMEOUT_SECS),
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            statement_cache_size: DEFAULT_STATEMENT_CACHE_SIZE,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        let defaults = Self::default();
+        let max_size = config.database_pool_size.unwrap_or(defaults.max_size);
+        if max_size == 0 {
+            return Err("DATABASE_POOL_SIZE must be at least 1".into());
+        }
+        Ok(Self {
+            max_size,
+            acquire_timeout: config
+                .database_pool_timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.acquire_timeout),
+            idle_timeout: config
+                .database_pool_idle_timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.idle_timeout),
+            statement_cache_size: config
+                .database_statement_cache_size
+                .unwrap_or(defaults.statement_cache_size),
+        })
+    }
+}
+
+/// Point-in-time pool statistics, reported by `GET /api/admin/pool`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PoolMetrics {
+    pub max_size: usize,
+    pub open: usize,
+    pub idle: usize,
+    pub in_use: usize,
+    /// Callers currently waiting for a connection.
+    pub waiting: usize,
+    pub acquired_total: u64,
+    pub created_total: u64,
+    pub reused_total: u64,
+    /// Connections closed because they expired or were returned mid-transaction.
+    pub discarded_total: u64,
+    pub timeouts_total: u64,
+    pub wait_time_ms_total: u64,
+    pub statement_cache_hits: u64,
+    pub statement_cache_misses: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    waiting: AtomicUsize,
+    acquired: AtomicU64,
+    created: AtomicU64,
+    reused: AtomicU64,
+    discarded: AtomicU64,
+    timeouts: AtomicU64,
+    wait_time_us: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Prepared statements for one connection, least recently used first.
+struct StatementCache {
+    capacity: usize,
+    entries: Vec<(String, Statement)>,
+}
+
+impl StatementCache {
+    fn take(&mut self, sql: &str) -> Option<Statement> {
+        let idx = self.entries.iter().position(|(cached, _)| cached == sql)?;
+        Some(self.entries.remove(idx).1)
+    }
+
+    fn put(&mut self, sql: String, stmt: Statement) {
+        if self.capacity == 0 || self.entries.iter().any(|(cached, _)| *cached == sql) {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((sql, stmt));
+    }
+}
+
+struct Slot {
+    conn: Connection,
+    statements: Mutex<StatementCache>,
+    idle_since: Instant,
+}
+
+pub(super) struct Pool {
+    db: Arc<libsql::Database>,
+    config: PoolConfig,
+    idle: Mutex<Vec<Slot>>,
+    permits: Arc<Semaphore>,
+    counters: Counters,
+}
+
+impl Pool {
+    pub(super) fn new(db: Arc<libsql::Database>, config: PoolConfig) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(config.max_size)),
+            idle: Mutex::new(Vec::with_capacity(config.max_size)),
+            counters: Counters::default(),
+            db,
+            config,
+        }
+    }
+
+    pub(super) async fn get(self: &Arc<Self>) -> libsql::Result<PooledConnection> {
+        let started = Instant::now();
+        self.counters.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = tokio::time::timeout(
+            self.config.acquire_timeout,
+            self.permits.clone().acquire_owned(),
+        )
+        .await;
+        self.counters.waiting.fetch_sub(1, Ordering::Relaxed);
+        self.counters
+            .wait_time_us
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+        let permit = match permit {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(closed)) => return Err(libsql::Error::ConnectionFailed(closed.to_string())),
+            Err(_) => {
+                bump(&self.counters.timeouts);
+                return Err(libsql::Error::ConnectionFailed(format!(
+                    "Timed out after {}s waiting for a database connection",
+                    self.config.acquire_timeout.as_secs_f32()
+                )));
+            }
+        };
+
+        let reusable = loop {
+            let Some(slot) = lock(&self.idle).pop() else {
+                break None;
+            };
+            if slot.idle_since.elapsed() < self.config.idle_timeout {
+                break Some(slot);
+            }
+            bump(&self.counters.discarded);
+        };
+
+        let slot = match reusable {
+            Some(slot) => {
+                bump(&self.counters.reused);
+                slot
+            }
+            None => {
+                let conn = self.db.connect()?;
+                bump(&self.counters.created);
+                Slot {
+                    conn,
+                    statements: Mutex::new(StatementCache {
+                        capacity: self.config.statement_cache_size,
+                        entries: Vec::new(),
+                    }),
+                    idle_since: Instant::now(),
+                }
+            }
+        };
+        bump(&self.counters.acquired);
+
+        Ok(PooledConnection {
+            slot: Some(slot),
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+
+    pub(super) fn metrics(&self) -> PoolMetrics {
+        let idle = lock(&self.idle).len();
+        let in_use = self.config.max_size - self.permits.available_permits();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        PoolMetrics {
+            max_size: self.config.max_size,
+            open: idle + in_use,
+            idle,
+            in_use,
+            waiting: self.counters.waiting.load(Ordering::Relaxed),
+            acquired_total: load(&self.counters.acquired),
+            created_total: load(&self.counters.created),
+            reused_total: load(&self.counters.reused),
+            discarded_total: load(&self.counters.discarded),
+            timeouts_total: load(&self.counters.timeouts),
+            wait_time_ms_total: load(&self.counters.wait_time_us) / 1000,
+            statement_cache_hits: load(&self.counters.cache_hits),
+            statement_cache_misses: load(&self.counters.cache_misses),
+        }
+    }
+}
+
+/// A connection borrowed from the pool. Derefs to `libsql::Connection`, and
+/// returns to the pool on drop unless a transaction was left open on it.
+pub struct PooledConnection {
+    slot: Option<Slot>,
+    pool: Arc<Pool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    fn slot(&self) -> &Slot {
+        self.slot
+            .as_ref()
+            .expect("pooled connection is only emptied on drop")
+    }
+
+    async fn prepare_cached(&self, sql: &str) -> libsql::Result<Statement> {
+        let cached = lock(&self.slot().statements).take(sql);
+        match cached {
+            Some(stmt) => {
+                bump(&self.pool.counters.cache_hits);
+                Ok(stmt)
+            }
+            None => {
+                bump(&self.pool.counters.cache_misses);
+                self.slot().conn.prepare(sql).await
+            }
+        }
+    }
+
+    fn release(&self, sql: &str, stmt: Statement) {
+        stmt.reset();
+        lock(&self.slot().statements).put(sql.to_string(), stmt);
+    }
+
+    /// `Connection::query` through this connection's statement cache.
+    pub async fn query_cached(
+        &self,
+        sql: &str,
+        params: impl IntoParams,
+    ) -> libsql::Result<CachedRows<'_>> {
+        let stmt = self.prepare_cached(sql).await?;
+        match stmt.query(params).await {
+            Ok(rows) => Ok(CachedRows {
+                rows: Some(rows),
+                stmt: Some(stmt),
+                sql: sql.to_string(),
+                conn: self,
+            }),
+            Err(e) => {
+                self.release(sql, stmt);
+                Err(e)
+            }
+        }
+    }
+
+    /// `Connection::execute` through this connection's statement cache.
+    pub async fn execute_cached(&self, sql: &str, params: impl IntoParams) -> libsql::Result<u64> {
+        let stmt = self.prepare_cached(sql).await?;
+        let result = stmt.execute(params).await;
+        self.release(sql, stmt);
+        result.map(|changed| changed as u64)
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.slot().conn
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(mut slot) = self.slot.take() else {
+            return;
+        };
+        // A connection still inside a transaction can't safely be handed to
+        // someone else; close it instead.
+        if !slot.conn.is_autocommit() {
+            bump(&self.pool.counters.discarded);
+            return;
+        }
+        slot.idle_since = Instant::now();
+        lock(&self.pool.idle).push(slot);
+    }
+}
+
+/// Rows from a cached statement. The statement is reset and goes back into
+/// the cache once these are dropped.
+pub struct CachedRows<'a> {
+    rows: Option<Rows>,
+    stmt: Option<Statement>,
+    sql: String,
+    conn: &'a PooledConnection,
+}
+
+impl CachedRows<'_> {
+    pub async fn next(&mut self) -> libsql::Result<Option<Row>> {
+        match self.rows.as_mut() {
+            Some(rows) => rows.next().await,
+            None => Ok(None),
+        }
+    }
+}
+
+impl Drop for CachedRows<'_> {
+    fn drop(&mut self) {
+        self.rows.take();
+        if let Some(stmt) = self.stmt.take() {
+            self.conn.release(&self.sql, stmt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{init_pool, DatabaseMode};
+
+    fn small_pool() -> PoolConfig {
+        PoolConfig {
+            max_size: 2,
+            acquire_timeout: Duration::from_millis(50),
+            ..PoolConfig::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn connections_are_reused_and_capped() {
+        let db = init_pool(&DatabaseMode::Memory, small_pool())
+            .await
+            .expect("memory database should open");
+
+        drop(db.connect().await.expect("first connection should open"));
+        let a = db.connect().await.expect("connection should be reused");
+        let b = db.connect().await.expect("second connection should open");
+
+        let metrics = db.pool_metrics();
+        assert_eq!((metrics.in_use, metrics.created_total), (2, 2));
+        assert_eq!(metrics.reused_total, 1);
+
+        let err = db
+            .connect()
+            .await
+            .err()
+            .expect("a third connection should time out");
+        assert!(err.to_string().contains("Timed out"));
+        assert_eq!(db.pool_metrics().timeouts_total, 1);
+
+        drop((a, b));
+        let metrics = db.pool_metrics();
+        assert_eq!((metrics.in_use, metrics.idle), (0, 2));
+    }
+
+    #[actix_rt::test]
+    async fn statements_are_cached_per_connection() {
+        let db = init_pool(&DatabaseMode::Memory, small_pool())
+            .await
+            .expect("memory database should open");
+        let conn = db.connect().await.expect("connection should open");
+        conn.execute("CREATE TABLE t (v INTEGER)", ())
+            .await
+            .expect("table should be created");
+
+        for v in 0..3 {
+            conn.execute_cached("INSERT INTO t (v) VALUES (?1)", [v])
+                .await
+                .expect("insert should succeed");
+        }
+        for _ in 0..2 {
+            let mut rows = conn
+                .query_cached("SELECT COUNT(*) FROM t", ())
+                .await
+                .expect("query should succeed");
+            let row = rows
+                .next()
+                .await
+                .expect("row fetch should succeed")
+                .expect("row should exist");
+            assert_eq!(row.get::<i64>(0).expect("count should be readable"), 3);
+        }
+
+        let metrics = db.pool_metrics();
+        assert_eq!(metrics.statement_cache_misses, 2);
+        assert_eq!(metrics.statement_cache_hits, 3);
+    }
+
+    #[actix_rt::test]
+    async fn connections_left_in_a_transaction_are_discarded() {
+        let db = init_pool(&DatabaseMode::Memory, small_pool())
+            .await
+            .expect("memory database should open");
+        let conn = db.connect().await.expect("connection should open");
+        conn.execute("BEGIN", ())
+            .await
+            .expect("transaction should start");
+        drop(conn);
+
+        let metrics = db.pool_metrics();
+        assert_eq!((metrics.idle, metrics.discarded_total), (0, 1));
+    }
+}
@@ -1,12 +1,25 @@
+use actix_web::http::header;
 use actix_web::{HttpResponse, ResponseError};
 use std::fmt;
 
+use crate::auth::username::UsernameViolation;
+
 #[derive(Debug)]
 pub enum AppError {
     BadRequest(String),
     Unauthorized(String),
+    /// Signed in, but not allowed to do this.
+    Forbidden(String),
     NotFound(String),
     Conflict(String),
+    /// Answered with 429 and a `Retry-After` header.
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
+    /// Answered with 400, or 409 for a taken name, and the violation as
+    /// `reason` so the client can explain it.
+    InvalidUsername(UsernameViolation),
     Internal(String),
 }
 
@@ -15,8 +28,13 @@ impl fmt::Display for AppError {
         match self {
             AppError::BadRequest(msg) => write!(f, "Bad Request: {msg}"),
             AppError::Unauthorized(msg) => write!(f, "Unauthorized: {msg}"),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {msg}"),
             AppError::NotFound(msg) => write!(f, "Not Found: {msg}"),
             AppError::Conflict(msg) => write!(f, "Conflict: {msg}"),
+            AppError::TooManyRequests { message, .. } => write!(f, "Too Many Requests: {message}"),
+            AppError::InvalidUsername(violation) => {
+                write!(f, "Invalid Username: {}", violation.message())
+            }
             AppError::Internal(msg) => write!(f, "Internal Error: {msg}"),
         }
     }
@@ -27,8 +45,27 @@ impl ResponseError for AppError {
         let (status, message) = match self {
             AppError::BadRequest(msg) => (actix_web::http::StatusCode::BAD_REQUEST, msg.clone()),
             AppError::Unauthorized(msg) => (actix_web::http::StatusCode::UNAUTHORIZED, msg.clone()),
+            AppError::Forbidden(msg) => (actix_web::http::StatusCode::FORBIDDEN, msg.clone()),
             AppError::NotFound(msg) => (actix_web::http::StatusCode::NOT_FOUND, msg.clone()),
             AppError::Conflict(msg) => (actix_web::http::StatusCode::CONFLICT, msg.clone()),
+            AppError::TooManyRequests {
+                message,
+                retry_after_secs,
+            } => {
+                return HttpResponse::TooManyRequests()
+                    .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+                    .json(serde_json::json!({ "error": message }));
+            }
+            AppError::InvalidUsername(violation) => {
+                let status = match violation {
+                    UsernameViolation::Taken => actix_web::http::StatusCode::CONFLICT,
+                    _ => actix_web::http::StatusCode::BAD_REQUEST,
+                };
+                return HttpResponse::build(status).json(serde_json::json!({
+                    "error": violation.message(),
+                    "reason": violation,
+                }));
+            }
             AppError::Internal(msg) => {
                 log::error!("Internal error: {msg}");
                 (
@@ -72,6 +109,40 @@ mod tests {
         assert_eq!(json["error"], "invalid input");
     }
 
+    #[actix_rt::test]
+    async fn forbidden_maps_to_403_with_message() {
+        let resp = AppError::Forbidden("missing permission".into()).error_response();
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let body = to_bytes(resp.into_body())
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("body should be json");
+        assert_eq!(json["error"], "missing permission");
+    }
+
+    #[actix_rt::test]
+    async fn invalid_username_carries_the_reason() {
+        let resp =
+            AppError::InvalidUsername(UsernameViolation::TooShort { min: 3 }).error_response();
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body = to_bytes(resp.into_body())
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("body should be json");
+        assert_eq!(json["error"], "Username must be at least 3 characters");
+        assert_eq!(
+            json["reason"],
+            serde_json::json!({ "code": "too_short", "min": 3 })
+        );
+
+        let resp = AppError::InvalidUsername(UsernameViolation::Taken).error_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
     #[actix_rt::test]
     async fn internal_error_maps_to_500_and_redacts_message() {
         let resp = AppError::Internal("db connection failed".into()).error_response();
@@ -84,4 +155,27 @@ mod tests {
         let json: serde_json::Value = serde_json::from_slice(&body).expect("body should be json");
         assert_eq!(json["error"], "Internal server error");
     }
+
+    #[actix_rt::test]
+    async fn too_many_requests_maps_to_429_with_retry_after() {
+        let resp = AppError::TooManyRequests {
+            message: "slow down".into(),
+            retry_after_secs: 42,
+        }
+        .error_response();
+
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            resp.headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok()),
+            Some("42")
+        );
+
+        let body = to_bytes(resp.into_body())
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("body should be json");
+        assert_eq!(json["error"], "slow down");
+    }
 }
@@ -58,13 +58,11 @@ impl Handler<SendServerMessage> for AiPlayerActor {
 
     fn handle(&mut self, msg: SendServerMessage, ctx: &mut Self::Context) {
         match msg.0 {
-            ServerMessage::RoundStart { .. } => {
-                // Schedule random choice after 3 seconds
-                if self.auto_play_enabled {
-                    ctx.run_later(Duration::from_secs(3), |act, ctx| {
-                        act.make_random_choice(ctx);
-                    });
-                }
+            // Schedule random choice after 3 seconds
+            ServerMessage::RoundStart { .. } if self.auto_play_enabled => {
+                ctx.run_later(Duration::from_secs(3), |act, ctx| {
+                    act.make_random_choice(ctx);
+                });
             }
             ServerMessage::MatchFound { .. } => {
                 // Enable auto-play when match is found
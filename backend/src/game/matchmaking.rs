@@ -1,11 +1,15 @@
 use actix::prelude::*;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
-use crate::db::Database;
+use crate::config::GameConfig;
 use crate::game::ai::AiPlayerActor;
+use crate::game::registry::{FindUserSession, PlayersInGame, SessionRegistry};
 use crate::game::session::GameSessionActor;
-use crate::game::ws::{OpponentInfo, PlayerWsActor, SendServerMessage, ServerMessage, SetSession};
-use crate::models::user::User;
+use crate::game::ws::{
+    CloseConnection, PlayerWsActor, SendServerMessage, ServerMessage, SetSession,
+};
+use crate::repository::Repositories;
 
 /// Queued player info
 struct QueuedPlayer {
@@ -14,23 +18,43 @@ struct QueuedPlayer {
     elo: i32,
     ranked: bool,
     is_guest: bool,
+    allow_spectators: bool,
     addr: Addr<PlayerWsActor>,
     queued_at: Instant,
 }
 
+/// An open socket of a signed-in player.
+struct Connection {
+    /// The login session the socket was opened with, if known.
+    session_id: Option<String>,
+    addr: Addr<PlayerWsActor>,
+}
+
 /// Singleton matchmaking actor
 pub struct MatchmakingActor {
     queue: Vec<QueuedPlayer>,
-    db: Database,
+    /// Open sockets of signed-in players, so they can be closed on a ban or
+    /// when their login session is revoked.
+    connections: HashMap<String, Vec<Connection>>,
+    registry: Addr<SessionRegistry>,
+    repos: Repositories,
+    config: GameConfig,
 }
 
 impl MatchmakingActor {
-    pub fn new(db: Database) -> Self {
+    pub fn new(repos: Repositories, config: GameConfig, registry: Addr<SessionRegistry>) -> Self {
         Self {
             queue: Vec::new(),
-            db,
+            connections: HashMap::new(),
+            registry,
+            repos,
+            config,
         }
     }
+
+    fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.config.reconnect_grace_secs)
+    }
 }
 
 impl Actor for MatchmakingActor {
@@ -49,6 +73,9 @@ pub struct JoinQueue {
     pub elo: i32,
     pub ranked: bool,
     pub is_guest: bool,
+    /// Whether the player lets others watch their matches, read as they
+    /// queue.
+    pub allow_spectators: bool,
     pub addr: Addr<PlayerWsActor>,
 }
 
@@ -58,10 +85,149 @@ pub struct LeaveQueue {
     pub user_id: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Connect {
+    pub user_id: String,
+    pub session_id: Option<String>,
+    pub addr: Addr<PlayerWsActor>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub user_id: String,
+    pub addr: Addr<PlayerWsActor>,
+}
+
+/// Close every open socket of a user, forfeiting any game in progress.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DisconnectUser {
+    pub user_id: String,
+    pub reason: String,
+}
+
+/// Close the sockets a user opened with one login session.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DisconnectSession {
+    pub user_id: String,
+    pub session_id: String,
+    pub reason: String,
+}
+
+/// The game a player is still in, if any.
+#[derive(Message)]
+#[rtype(result = "Option<Addr<GameSessionActor>>")]
+pub struct FindGame {
+    pub user_id: String,
+}
+
+impl Handler<FindGame> for MatchmakingActor {
+    type Result = ResponseFuture<Option<Addr<GameSessionActor>>>;
+
+    fn handle(&mut self, msg: FindGame, _ctx: &mut Self::Context) -> Self::Result {
+        let lookup = self.registry.send(FindUserSession {
+            user_id: msg.user_id,
+        });
+        Box::pin(async move { lookup.await.ok().flatten().map(|session| session.addr) })
+    }
+}
+
+impl Handler<Connect> for MatchmakingActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Connect, _ctx: &mut Self::Context) {
+        self.connections
+            .entry(msg.user_id)
+            .or_default()
+            .push(Connection {
+                session_id: msg.session_id,
+                addr: msg.addr,
+            });
+    }
+}
+
+impl Handler<Disconnect> for MatchmakingActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) {
+        if let Some(connections) = self.connections.get_mut(&msg.user_id) {
+            connections.retain(|c| c.addr != msg.addr);
+            if connections.is_empty() {
+                self.connections.remove(&msg.user_id);
+            }
+        }
+    }
+}
+
+impl Handler<DisconnectUser> for MatchmakingActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: DisconnectUser, _ctx: &mut Self::Context) {
+        self.queue.retain(|p| p.user_id != msg.user_id);
+        for connection in self.connections.remove(&msg.user_id).unwrap_or_default() {
+            connection.addr.do_send(CloseConnection {
+                reason: msg.reason.clone(),
+            });
+        }
+    }
+}
+
+impl Handler<DisconnectSession> for MatchmakingActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: DisconnectSession, _ctx: &mut Self::Context) {
+        let Some(connections) = self.connections.get_mut(&msg.user_id) else {
+            return;
+        };
+        let (closing, open) = std::mem::take(connections)
+            .into_iter()
+            .partition(|c| c.session_id.as_deref() == Some(msg.session_id.as_str()));
+        *connections = open;
+        if connections.is_empty() {
+            self.connections.remove(&msg.user_id);
+        }
+        for connection in closing {
+            self.queue.retain(|p| p.addr != connection.addr);
+            connection.addr.do_send(CloseConnection {
+                reason: msg.reason.clone(),
+            });
+        }
+    }
+}
+
 impl Handler<JoinQueue> for MatchmakingActor {
     type Result = ();
 
     fn handle(&mut self, msg: JoinQueue, ctx: &mut Self::Context) {
+        let in_game = self.registry.send(FindUserSession {
+            user_id: msg.user_id.clone(),
+        });
+        // Finish the check before the next queue change
+        ctx.wait(in_game.into_actor(self).map(|in_game, act, ctx| {
+            if matches!(in_game, Ok(Some(_))) {
+                msg.addr.do_send(SendServerMessage(ServerMessage::Error {
+                    message: "Already in a game".into(),
+                }));
+                return;
+            }
+            act.enqueue(msg, ctx);
+        }));
+    }
+}
+
+impl Handler<LeaveQueue> for MatchmakingActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveQueue, _ctx: &mut Self::Context) {
+        self.queue.retain(|p| p.user_id != msg.user_id);
+    }
+}
+
+impl MatchmakingActor {
+    fn enqueue(&mut self, msg: JoinQueue, ctx: &mut Context<Self>) {
         // Don't allow duplicate queue entries
         if self.queue.iter().any(|p| p.user_id == msg.user_id) {
             msg.addr.do_send(SendServerMessage(ServerMessage::Error {
@@ -81,29 +247,20 @@ impl Handler<JoinQueue> for MatchmakingActor {
             elo: msg.elo,
             ranked: msg.ranked,
             is_guest: msg.is_guest,
+            allow_spectators: msg.allow_spectators,
             addr: msg.addr,
             queued_at: Instant::now(),
         });
 
-        self.try_match();
+        self.try_match(ctx);
 
         // Schedule AI matchmaking check after 3 seconds
         ctx.run_later(Duration::from_secs(3), move |act, ctx| {
             act.check_ai_matchmaking(ctx, &user_id);
         });
     }
-}
-
-impl Handler<LeaveQueue> for MatchmakingActor {
-    type Result = ();
 
-    fn handle(&mut self, msg: LeaveQueue, _ctx: &mut Self::Context) {
-        self.queue.retain(|p| p.user_id != msg.user_id);
-    }
-}
-
-impl MatchmakingActor {
-    fn try_match(&mut self) {
+    fn try_match(&mut self, ctx: &mut Context<Self>) {
         if self.queue.len() < 2 {
             return;
         }
@@ -112,28 +269,37 @@ impl MatchmakingActor {
         let p2 = self.queue.remove(1);
         let p1 = self.queue.remove(0);
 
-        let is_ranked = p1.ranked && p2.ranked;
-
-        // Notify both players
-        p1.addr
-            .do_send(SendServerMessage(ServerMessage::MatchFound {
-                session_id: String::new(), // Will be set by session
-                opponent: OpponentInfo {
-                    username: p2.username.clone(),
-                    elo: p2.elo,
-                },
-            }));
+        // A queued player may have been put into a game since, e.g. by
+        // another socket resuming one. Keep them out of this match rather
+        // than let the session refuse to start with both players in it.
+        let in_game = self.registry.send(PlayersInGame {
+            user_ids: vec![p1.user_id.clone(), p2.user_id.clone()],
+        });
+        // Finish the check before the next queue change
+        ctx.wait(in_game.into_actor(self).map(move |in_game, act, ctx| {
+            let in_game = in_game.unwrap_or_default();
+            if in_game.is_empty() {
+                act.start_match(p1, p2);
+                return;
+            }
+            for player in [p2, p1] {
+                if in_game.contains(&player.user_id) {
+                    player.addr.do_send(SendServerMessage(ServerMessage::Error {
+                        message: "Already in a game".into(),
+                    }));
+                } else {
+                    act.queue.insert(0, player);
+                }
+            }
+            act.try_match(ctx);
+        }));
+    }
 
-        p2.addr
-            .do_send(SendServerMessage(ServerMessage::MatchFound {
-                session_id: String::new(),
-                opponent: OpponentInfo {
-                    username: p1.username.clone(),
-                    elo: p1.elo,
-                },
-            }));
+    fn start_match(&mut self, p1: QueuedPlayer, p2: QueuedPlayer) {
+        let is_ranked = p1.ranked && p2.ranked;
+        let allow_spectators = p1.allow_spectators && p2.allow_spectators;
 
-        // Create game session
+        // The session tells both players once it's registered
         let session = GameSessionActor::new(
             p1.user_id.clone(),
             p1.username.clone(),
@@ -148,7 +314,11 @@ impl MatchmakingActor {
             false, // p2 is not AI
             p2.addr.clone().recipient(),
             is_ranked,
-            self.db.clone(),
+            self.repos.clone(),
+            self.reconnect_grace(),
+            allow_spectators,
+            self.config.max_spectators,
+            self.registry.clone(),
         );
 
         let session_addr = session.start();
@@ -177,40 +347,26 @@ impl MatchmakingActor {
     }
 
     fn match_with_ai(&mut self, player: QueuedPlayer, ctx: &mut Context<Self>) {
-        let db = self.db.clone();
+        let repos = self.repos.clone();
         let player_user_id = player.user_id.clone();
         let player_username = player.username.clone();
         let player_elo = player.elo;
         let player_is_guest = player.is_guest;
         let player_ranked = player.ranked;
+        let player_allows_spectators = player.allow_spectators;
         let player_addr = player.addr.clone();
 
         // Async fetch random AI user
-        let fut = async move { User::get_random_ai(&db).await.ok().map(|ai| (ai, db)) };
-
-        ctx.spawn(fut.into_actor(self).map(move |result, _act, _ctx| {
-            if let Some((ai_user, db)) = result {
-                // Notify player
-                player_addr.do_send(SendServerMessage(ServerMessage::MatchFound {
-                    session_id: String::new(),
-                    opponent: OpponentInfo {
-                        username: ai_user.username.clone(),
-                        elo: ai_user.elo,
-                    },
-                }));
+        let fut = async move {
+            let ai = repos.users.random_ai().await.ok();
+            ai.map(|ai| (ai, repos, player_user_id))
+        };
 
+        ctx.spawn(fut.into_actor(self).map(move |result, act, _ctx| {
+            if let Some((ai_user, repos, player_user_id)) = result {
                 // Create AI actor
                 let ai_actor = AiPlayerActor::new(ai_user.id.clone()).start();
 
-                // Notify AI (for consistency)
-                ai_actor.do_send(SendServerMessage(ServerMessage::MatchFound {
-                    session_id: String::new(),
-                    opponent: OpponentInfo {
-                        username: player_username.clone(),
-                        elo: player_elo,
-                    },
-                }));
-
                 // Create game session
                 let session = GameSessionActor::new(
                     player_user_id,
@@ -226,7 +382,11 @@ impl MatchmakingActor {
                     true,  // AI is AI
                     ai_actor.clone().recipient(),
                     player_ranked,
-                    db,
+                    repos,
+                    act.reconnect_grace(),
+                    player_allows_spectators,
+                    act.config.max_spectators,
+                    act.registry.clone(),
                 );
 
                 let session_addr = session.start();
@@ -240,3 +400,127 @@ impl MatchmakingActor {
         }));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game::registry::ListSessions;
+    use crate::game::test_support::{player, TestServer};
+
+    #[actix_rt::test]
+    async fn a_player_found_in_a_game_is_left_out_and_the_opponent_stays_queued() {
+        let server = TestServer::start();
+        let create = |name: &'static str| {
+            let repos = server.repos.clone();
+            async move {
+                repos
+                    .users
+                    .create(name, &format!("{name}@example.com"), "hash")
+                    .await
+                    .expect("user should be created")
+            }
+        };
+        let (alice, bob, carol) = (
+            create("alice").await,
+            create("bob").await,
+            create("carol").await,
+        );
+        let join = serde_json::json!({ "type": "join_queue", "ranked": false });
+
+        let mut alice_socket = server.connect(&alice, None).await;
+        alice_socket.send(join.clone()).await;
+        let queued = alice_socket.next_message().await;
+        assert_eq!(queued.map(|m| m["type"].clone()), Some("queued".into()));
+
+        // Alice ends up in a game while she waits, e.g. from another device
+        let (stand_in, _) = player();
+        let (opponent, _) = player();
+        let _game = GameSessionActor::new(
+            alice.id.clone(),
+            alice.username.clone(),
+            alice.elo,
+            false,
+            false,
+            stand_in,
+            "dave".into(),
+            "dave".into(),
+            1000,
+            true,
+            false,
+            opponent,
+            false,
+            server.repos.clone(),
+            Duration::from_secs(20),
+            true,
+            10,
+            server.registry.clone(),
+        )
+        .start();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut bob_socket = server.connect(&bob, None).await;
+        bob_socket.send(join.clone()).await;
+        let error = alice_socket
+            .next_message()
+            .await
+            .expect("alice should hear back");
+        assert_eq!(error["message"], "Already in a game");
+        let queued = bob_socket.next_message().await;
+        assert_eq!(queued.map(|m| m["type"].clone()), Some("queued".into()));
+        assert!(bob_socket.next_message().await.is_none());
+
+        let mut carol_socket = server.connect(&carol, None).await;
+        carol_socket.send(join).await;
+        let found = bob_socket
+            .next_message()
+            .await
+            .expect("bob should be matched");
+        assert_eq!(found["type"], "match_found");
+        assert_eq!(found["opponent"]["username"], "carol");
+    }
+
+    #[actix_rt::test]
+    async fn a_player_who_opted_out_of_spectators_gets_a_private_match() {
+        let server = TestServer::start();
+        let create = |name: &'static str| {
+            let repos = server.repos.clone();
+            async move {
+                repos
+                    .users
+                    .create(name, &format!("{name}@example.com"), "hash")
+                    .await
+                    .expect("user should be created")
+            }
+        };
+        let (alice, bob) = (create("alice").await, create("bob").await);
+        server
+            .repos
+            .users
+            .set_allow_spectators(&bob.id, false)
+            .await
+            .expect("setting should be saved");
+        let join = serde_json::json!({ "type": "join_queue", "ranked": false });
+
+        let mut alice_socket = server.connect(&alice, None).await;
+        alice_socket.send(join.clone()).await;
+        let mut bob_socket = server.connect(&bob, None).await;
+        bob_socket.send(join).await;
+        let queued = bob_socket.next_message().await;
+        assert_eq!(queued.map(|m| m["type"].clone()), Some("queued".into()));
+        let found = bob_socket
+            .next_message()
+            .await
+            .expect("bob should be matched");
+        assert_eq!(found["type"], "match_found");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let sessions = server
+            .registry
+            .send(ListSessions)
+            .await
+            .expect("registry should answer");
+        assert_eq!(sessions.len(), 1);
+        assert!(!sessions[0].summary.spectators_allowed);
+    }
+}
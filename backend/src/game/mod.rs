@@ -1,5 +1,9 @@
 pub mod ai;
 pub mod elo;
 pub mod matchmaking;
+pub mod registry;
 pub mod session;
+pub mod spectator;
+#[cfg(test)]
+pub mod test_support;
 pub mod ws;
@@ -0,0 +1,304 @@
+//! Live game sessions by id and by player. Sessions register themselves
+//! when they start and are dropped when they stop, so a player can only be
+//! in one game at a time.
+
+use actix::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::game::session::GameSessionActor;
+use crate::game::ws::OpponentInfo;
+
+/// A running game.
+#[derive(Clone)]
+pub struct LiveSession {
+    pub id: String,
+    pub addr: Addr<GameSessionActor>,
+    /// Human players; AI opponents play any number of games at once.
+    pub player_ids: Vec<String>,
+    pub summary: MatchSummary,
+}
+
+/// Who is playing, as listed to would-be spectators.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchSummary {
+    pub player1: OpponentInfo,
+    pub player2: OpponentInfo,
+    pub is_ranked: bool,
+    /// Whether both players let others watch.
+    pub spectators_allowed: bool,
+}
+
+/// Singleton registry of running games.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: HashMap<String, LiveSession>,
+    /// Session id of every player in a running game.
+    players: HashMap<String, String>,
+}
+
+impl SessionRegistry {
+    fn live(&self, session_id: &str) -> Option<LiveSession> {
+        self.sessions
+            .get(session_id)
+            .filter(|session| session.addr.connected())
+            .cloned()
+    }
+}
+
+impl Actor for SessionRegistry {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        log::info!("SessionRegistry started");
+    }
+}
+
+/// Refused registration: the player is already in another game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyInGame {
+    pub user_id: String,
+}
+
+/// Register a starting game and get its id.
+#[derive(Message)]
+#[rtype(result = "Result<String, AlreadyInGame>")]
+pub struct Register {
+    pub player_ids: Vec<String>,
+    pub addr: Addr<GameSessionActor>,
+    pub summary: MatchSummary,
+}
+
+/// Drop a game that has stopped.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unregister {
+    pub session_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<LiveSession>")]
+pub struct FindSession {
+    pub session_id: String,
+}
+
+/// The game a player is in, if any.
+#[derive(Message)]
+#[rtype(result = "Option<LiveSession>")]
+pub struct FindUserSession {
+    pub user_id: String,
+}
+
+/// Which of these players are in a running game.
+#[derive(Message)]
+#[rtype(result = "Vec<String>")]
+pub struct PlayersInGame {
+    pub user_ids: Vec<String>,
+}
+
+/// Every running game.
+#[derive(Message)]
+#[rtype(result = "Vec<LiveSession>")]
+pub struct ListSessions;
+
+impl Handler<Register> for SessionRegistry {
+    type Result = Result<String, AlreadyInGame>;
+
+    fn handle(&mut self, msg: Register, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(user_id) = msg.player_ids.iter().find(|user_id| {
+            self.players
+                .get(*user_id)
+                .is_some_and(|session_id| self.live(session_id).is_some())
+        }) {
+            return Err(AlreadyInGame {
+                user_id: user_id.clone(),
+            });
+        }
+
+        let id = Uuid::new_v4().to_string();
+        for user_id in &msg.player_ids {
+            self.players.insert(user_id.clone(), id.clone());
+        }
+        self.sessions.insert(
+            id.clone(),
+            LiveSession {
+                id: id.clone(),
+                addr: msg.addr,
+                player_ids: msg.player_ids,
+                summary: msg.summary,
+            },
+        );
+        Ok(id)
+    }
+}
+
+impl Handler<Unregister> for SessionRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unregister, _ctx: &mut Self::Context) {
+        let Some(session) = self.sessions.remove(&msg.session_id) else {
+            return;
+        };
+        for user_id in session.player_ids {
+            if self.players.get(&user_id) == Some(&session.id) {
+                self.players.remove(&user_id);
+            }
+        }
+    }
+}
+
+impl Handler<FindSession> for SessionRegistry {
+    type Result = Option<LiveSession>;
+
+    fn handle(&mut self, msg: FindSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.live(&msg.session_id)
+    }
+}
+
+impl Handler<FindUserSession> for SessionRegistry {
+    type Result = Option<LiveSession>;
+
+    fn handle(&mut self, msg: FindUserSession, _ctx: &mut Self::Context) -> Self::Result {
+        let session_id = self.players.get(&msg.user_id)?;
+        self.live(session_id)
+    }
+}
+
+impl Handler<PlayersInGame> for SessionRegistry {
+    type Result = Vec<String>;
+
+    fn handle(&mut self, msg: PlayersInGame, _ctx: &mut Self::Context) -> Self::Result {
+        msg.user_ids
+            .into_iter()
+            .filter(|user_id| {
+                self.players
+                    .get(user_id)
+                    .is_some_and(|session_id| self.live(session_id).is_some())
+            })
+            .collect()
+    }
+}
+
+impl Handler<ListSessions> for SessionRegistry {
+    type Result = Vec<LiveSession>;
+
+    fn handle(&mut self, _msg: ListSessions, _ctx: &mut Self::Context) -> Self::Result {
+        self.sessions
+            .values()
+            .filter(|session| session.addr.connected())
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::game::session::PlayerDisconnected;
+    use crate::game::test_support::player;
+    use crate::game::ws::{SendServerMessage, ServerMessage};
+    use crate::repository::Repositories;
+
+    fn start_game(
+        registry: &Addr<SessionRegistry>,
+        (p1, p1_addr): (&str, &Recipient<SendServerMessage>),
+        (p2, p2_addr): (&str, &Recipient<SendServerMessage>),
+    ) -> Addr<GameSessionActor> {
+        GameSessionActor::new(
+            p1.into(),
+            p1.into(),
+            1000,
+            true,
+            false,
+            p1_addr.clone(),
+            p2.into(),
+            p2.into(),
+            1000,
+            true,
+            false,
+            p2_addr.clone(),
+            false,
+            Repositories::in_memory(),
+            Duration::from_secs(20),
+            true,
+            10,
+            registry.clone(),
+        )
+        .start()
+    }
+
+    #[actix_rt::test]
+    async fn sessions_get_ids_and_players_stay_in_one_game() {
+        let registry = SessionRegistry::default().start();
+        let (alice, alice_received) = player();
+        let (bob, _) = player();
+        let (carol, carol_received) = player();
+
+        let game = start_game(&registry, ("guest_alice", &alice), ("guest_bob", &bob));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let live = registry
+            .send(FindUserSession {
+                user_id: "guest_alice".into(),
+            })
+            .await
+            .expect("registry should answer")
+            .expect("alice should be in a game");
+        assert!(!live.id.is_empty());
+        assert_eq!(live.addr, game);
+        assert_eq!(live.player_ids, vec!["guest_alice", "guest_bob"]);
+        let by_id = registry
+            .send(FindSession {
+                session_id: live.id.clone(),
+            })
+            .await
+            .expect("registry should answer")
+            .expect("the session should be found by id");
+        assert_eq!(by_id.addr, game);
+        assert!(alice_received.lock().unwrap().iter().any(|m| matches!(
+            m,
+            ServerMessage::MatchFound { session_id, .. } if *session_id == live.id
+        )));
+
+        let second = start_game(&registry, ("guest_carol", &carol), ("guest_alice", &alice));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!second.connected());
+        assert!(carol_received.lock().unwrap().iter().any(|m| matches!(
+            m,
+            ServerMessage::Error { message } if message == "guest_alice is already in a game"
+        )));
+        let carol_game = registry
+            .send(FindUserSession {
+                user_id: "guest_carol".into(),
+            })
+            .await
+            .expect("registry should answer");
+        assert!(carol_game.is_none());
+
+        game.do_send(PlayerDisconnected {
+            user_id: "guest_alice".into(),
+            addr: alice,
+            forfeit: true,
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        for user_id in ["guest_alice", "guest_bob"] {
+            let found = registry
+                .send(FindUserSession {
+                    user_id: user_id.into(),
+                })
+                .await
+                .expect("registry should answer");
+            assert!(found.is_none(), "{user_id} should be out of the game");
+        }
+        let by_id = registry
+            .send(FindSession {
+                session_id: live.id,
+            })
+            .await
+            .expect("registry should answer");
+        assert!(by_id.is_none());
+    }
+}
@@ -1,43 +1,66 @@
 use actix::prelude::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::db::Database;
-use crate::game::elo::calculate_elo;
-use crate::game::ws::{SendServerMessage, ServerMessage};
-use crate::models::elo_history::EloHistory;
-use crate::models::match_record::{MatchRecord, Round};
-use crate::models::user::User;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::game::registry::{AlreadyInGame, MatchSummary, Register, SessionRegistry, Unregister};
+use crate::game::spectator::{SendSpectatorMessage, SpectatorMessage};
+use crate::game::ws::{OpponentInfo, SendServerMessage, ServerMessage};
+use crate::models::match_record::MatchResult;
+use crate::models::match_round::MatchRound;
+use crate::repository::Repositories;
 
 const ROUND_TIMEOUT_SECS: u64 = 15;
 
 /// Per-match game session actor
 pub struct GameSessionActor {
+    /// Assigned by the registry once the session starts.
+    id: Option<String>,
+    registry: Addr<SessionRegistry>,
     p1_id: String,
     p1_username: String,
     p1_elo: i32,
     p1_is_guest: bool,
-    #[allow(dead_code)]
     p1_is_ai: bool,
     p1_addr: Recipient<SendServerMessage>,
     p1_choice: Option<String>,
+    p1_decided_at: Option<String>,
     p2_id: String,
     p2_username: String,
     p2_elo: i32,
     p2_is_guest: bool,
-    #[allow(dead_code)]
     p2_is_ai: bool,
     p2_addr: Recipient<SendServerMessage>,
     p2_choice: Option<String>,
+    p2_decided_at: Option<String>,
     p1_score: i32,
     p2_score: i32,
     current_round: i32,
-    rounds: Vec<Round>,
+    round_started_at: Option<String>,
+    rounds: Vec<MatchRound>,
     is_ranked: bool,
-    db: Database,
+    repos: Repositories,
     finished: bool,
+    /// How long a dropped player has to reconnect before they forfeit.
+    reconnect_grace: Duration,
+    /// Resolves the current round when its time runs out.
+    round_timer: Option<SpawnHandle>,
+    round_deadline: Instant,
+    /// Time left in the round while its clock is stopped for a dropped
+    /// player.
+    paused_time_left: Option<Duration>,
+    /// Forfeit timers of players who dropped and haven't reconnected yet.
+    p1_away: Option<SpawnHandle>,
+    p2_away: Option<SpawnHandle>,
+    /// Both players let others watch.
+    allow_spectators: bool,
+    max_spectators: usize,
+    spectators: Vec<Recipient<SendSpectatorMessage>>,
 }
 
 impl GameSessionActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         p1_id: String,
         p1_username: String,
@@ -52,9 +75,15 @@ impl GameSessionActor {
         p2_is_ai: bool,
         p2_addr: Recipient<SendServerMessage>,
         is_ranked: bool,
-        db: Database,
+        repos: Repositories,
+        reconnect_grace: Duration,
+        allow_spectators: bool,
+        max_spectators: usize,
+        registry: Addr<SessionRegistry>,
     ) -> Self {
         Self {
+            id: None,
+            registry,
             p1_id,
             p1_username,
             p1_elo,
@@ -62,6 +91,7 @@ impl GameSessionActor {
             p1_is_ai,
             p1_addr,
             p1_choice: None,
+            p1_decided_at: None,
             p2_id,
             p2_username,
             p2_elo,
@@ -69,47 +99,202 @@ impl GameSessionActor {
             p2_is_ai,
             p2_addr,
             p2_choice: None,
+            p2_decided_at: None,
             p1_score: 0,
             p2_score: 0,
             current_round: 1,
+            round_started_at: None,
             rounds: Vec::new(),
             is_ranked,
-            db,
+            repos,
             finished: false,
+            reconnect_grace,
+            round_timer: None,
+            round_deadline: Instant::now(),
+            paused_time_left: None,
+            p1_away: None,
+            p2_away: None,
+            allow_spectators,
+            max_spectators,
+            spectators: Vec::new(),
+        }
+    }
+
+    fn side_of(&self, user_id: &str) -> Option<Side> {
+        if user_id == self.p1_id {
+            Some(Side::Player1)
+        } else if user_id == self.p2_id {
+            Some(Side::Player2)
+        } else {
+            None
+        }
+    }
+
+    fn addr(&self, side: Side) -> &Recipient<SendServerMessage> {
+        match side {
+            Side::Player1 => &self.p1_addr,
+            Side::Player2 => &self.p2_addr,
+        }
+    }
+
+    fn away(&mut self, side: Side) -> &mut Option<SpawnHandle> {
+        match side {
+            Side::Player1 => &mut self.p1_away,
+            Side::Player2 => &mut self.p2_away,
         }
     }
 
+    fn player_info(&self, side: Side) -> OpponentInfo {
+        match side {
+            Side::Player1 => OpponentInfo {
+                username: self.p1_username.clone(),
+                elo: self.p1_elo,
+            },
+            Side::Player2 => OpponentInfo {
+                username: self.p2_username.clone(),
+                elo: self.p2_elo,
+            },
+        }
+    }
+
+    fn tell_spectators(&self, msg: SpectatorMessage) {
+        for spectator in &self.spectators {
+            spectator.do_send(SendSpectatorMessage(msg.clone()));
+        }
+    }
+
+    /// Let both players know how many people are watching.
+    fn announce_spectators(&self) {
+        let count = self.spectators.len();
+        for addr in [&self.p1_addr, &self.p2_addr] {
+            addr.do_send(SendServerMessage(ServerMessage::Spectators { count }));
+        }
+    }
+
+    fn anyone_away(&self) -> bool {
+        self.p1_away.is_some() || self.p2_away.is_some()
+    }
+
+    /// Runs the round clock with `time_left` to go.
+    fn start_clock(&mut self, time_left: Duration, ctx: &mut Context<Self>) {
+        self.paused_time_left = None;
+        self.round_deadline = Instant::now() + time_left;
+        self.round_timer = Some(ctx.run_later(time_left, |act, ctx| {
+            act.round_timer = None;
+            act.resolve_round(ctx);
+        }));
+    }
+
+    fn pause_clock(&mut self, ctx: &mut Context<Self>) {
+        if let Some(timer) = self.round_timer.take() {
+            ctx.cancel_future(timer);
+            self.paused_time_left = Some(self.time_left());
+        }
+    }
+
+    fn time_left(&self) -> Duration {
+        self.paused_time_left.unwrap_or_else(|| {
+            self.round_deadline
+                .saturating_duration_since(Instant::now())
+        })
+    }
+
+    /// Everything a player needs to pick the match back up.
+    fn game_state(&self, side: Side) -> ServerMessage {
+        let (your_choice, opponent_chose, your_score, opponent_score) = match side {
+            Side::Player1 => (
+                self.p1_choice.clone(),
+                self.p2_choice.is_some(),
+                self.p1_score,
+                self.p2_score,
+            ),
+            Side::Player2 => (
+                self.p2_choice.clone(),
+                self.p1_choice.is_some(),
+                self.p2_score,
+                self.p1_score,
+            ),
+        };
+        let opponent_connected = match side {
+            Side::Player1 => self.p2_away.is_none(),
+            Side::Player2 => self.p1_away.is_none(),
+        };
+        ServerMessage::GameState {
+            round: self.current_round,
+            timeout_secs: ROUND_TIMEOUT_SECS,
+            time_left_ms: self.time_left().as_millis() as u64,
+            your_choice,
+            opponent_chose,
+            your_score,
+            opponent_score,
+            opponent: self.player_info(side.other()),
+            opponent_connected,
+        }
+    }
+
+    /// Tell both players who they're up against and the session's id.
+    fn announce(&self, session_id: &str) {
+        for side in [Side::Player1, Side::Player2] {
+            self.addr(side)
+                .do_send(SendServerMessage(ServerMessage::MatchFound {
+                    session_id: session_id.to_string(),
+                    opponent: self.player_info(side.other()),
+                }));
+        }
+    }
+
+    /// Call the match off before it begins; nothing is recorded.
+    fn cancel(&mut self, message: String, ctx: &mut Context<Self>) {
+        self.finished = true;
+        for addr in [&self.p1_addr, &self.p2_addr] {
+            addr.do_send(SendServerMessage(ServerMessage::Error {
+                message: message.clone(),
+            }));
+        }
+        ctx.stop();
+    }
+
     fn start_round(&mut self, ctx: &mut Context<Self>) {
         self.p1_choice = None;
         self.p2_choice = None;
+        self.p1_decided_at = None;
+        self.p2_decided_at = None;
+        self.round_started_at = Some(now_timestamp());
 
         let msg = ServerMessage::RoundStart {
             round: self.current_round,
             timeout_secs: ROUND_TIMEOUT_SECS,
         };
 
-        let _ = self.p1_addr.do_send(SendServerMessage(msg.clone()));
-        let _ = self.p2_addr.do_send(SendServerMessage(msg));
-
-        // Round timeout
-        ctx.run_later(Duration::from_secs(ROUND_TIMEOUT_SECS), |act, ctx| {
-            act.resolve_round(ctx);
+        self.p1_addr.do_send(SendServerMessage(msg.clone()));
+        self.p2_addr.do_send(SendServerMessage(msg));
+        self.tell_spectators(SpectatorMessage::RoundStart {
+            round: self.current_round,
+            timeout_secs: ROUND_TIMEOUT_SECS,
         });
+
+        let timeout = Duration::from_secs(ROUND_TIMEOUT_SECS);
+        if self.anyone_away() {
+            // The clock starts once everyone is back
+            self.round_deadline = Instant::now() + timeout;
+            self.paused_time_left = Some(timeout);
+        } else {
+            self.start_clock(timeout, ctx);
+        }
     }
 
     fn resolve_round(&mut self, ctx: &mut Context<Self>) {
         if self.finished {
             return;
         }
+        // Both chose before the time ran out
+        if let Some(timer) = self.round_timer.take() {
+            ctx.cancel_future(timer);
+        }
 
         let p1_choice = self.p1_choice.take();
         let p2_choice = self.p2_choice.take();
 
-        // If both already resolved (both chose), skip
-        if self.rounds.len() >= self.current_round as usize {
-            return;
-        }
-
         let winner = determine_winner(p1_choice.as_deref(), p2_choice.as_deref());
 
         match winner {
@@ -118,15 +303,18 @@ impl GameSessionActor {
             RoundWinner::Draw => {}
         }
 
-        let round = Round {
+        let round = MatchRound {
             round_number: self.current_round,
             player1_choice: p1_choice.clone(),
             player2_choice: p2_choice.clone(),
-            winner: match winner {
+            winner_id: match winner {
                 RoundWinner::Player1 => Some(self.p1_id.clone()),
                 RoundWinner::Player2 => Some(self.p2_id.clone()),
-                RoundWinner::Draw => Some("draw".into()),
+                RoundWinner::Draw => None,
             },
+            started_at: self.round_started_at.take(),
+            player1_decided_at: self.p1_decided_at.take(),
+            player2_decided_at: self.p2_decided_at.take(),
         };
         self.rounds.push(round);
 
@@ -134,8 +322,7 @@ impl GameSessionActor {
         let p2_choice_str = p2_choice.unwrap_or_else(|| "none".into());
 
         // Send round results
-        let _ = self
-            .p1_addr
+        self.p1_addr
             .do_send(SendServerMessage(ServerMessage::RoundResult {
                 round: self.current_round,
                 your_choice: p1_choice_str.clone(),
@@ -149,12 +336,11 @@ impl GameSessionActor {
                 opponent_score: self.p2_score,
             }));
 
-        let _ = self
-            .p2_addr
+        self.p2_addr
             .do_send(SendServerMessage(ServerMessage::RoundResult {
                 round: self.current_round,
-                your_choice: p2_choice_str,
-                opponent_choice: p1_choice_str,
+                your_choice: p2_choice_str.clone(),
+                opponent_choice: p1_choice_str.clone(),
                 winner: match winner {
                     RoundWinner::Player1 => "opponent".into(),
                     RoundWinner::Player2 => "you".into(),
@@ -164,6 +350,15 @@ impl GameSessionActor {
                 opponent_score: self.p1_score,
             }));
 
+        self.tell_spectators(SpectatorMessage::RoundResult {
+            round: self.current_round,
+            player1_choice: p1_choice_str,
+            player2_choice: p2_choice_str,
+            winner: winner.label().into(),
+            player1_score: self.p1_score,
+            player2_score: self.p2_score,
+        });
+
         // Check if match is over (Bo3: first to 2 wins, max 5 rounds)
         if self.p1_score >= 3 || self.p2_score >= 3 {
             self.finish_match(ctx);
@@ -176,136 +371,23 @@ impl GameSessionActor {
     fn finish_match(&mut self, ctx: &mut Context<Self>) {
         self.finished = true;
 
-        let (winner_id, p1_outcome, p2_outcome) = if self.p1_score > self.p2_score {
-            (Some(self.p1_id.clone()), "win", "loss")
-        } else if self.p2_score > self.p1_score {
-            (Some(self.p2_id.clone()), "loss", "win")
-        } else {
-            (None, "draw", "draw")
-        };
-
-        let outcome = if self.p1_score > self.p2_score {
-            1.0
+        let winner = if self.p1_score > self.p2_score {
+            Some(Side::Player1)
         } else if self.p2_score > self.p1_score {
-            0.0
+            Some(Side::Player2)
         } else {
-            0.5
+            None
         };
 
-        // Calculate and persist Elo + match
-        let db = self.db.clone();
-        let p1_id = self.p1_id.clone();
-        let p2_id = self.p2_id.clone();
-        let p1_elo = self.p1_elo;
-        let p2_elo = self.p2_elo;
-        let p1_score = self.p1_score;
-        let p2_score = self.p2_score;
-        let is_ranked = self.is_ranked;
-        let p1_is_guest = self.p1_is_guest;
-        let p2_is_guest = self.p2_is_guest;
-        let rounds_json = serde_json::to_string(&self.rounds).unwrap_or_else(|_| "[]".into());
+        let finished = self.snapshot(winner, self.p1_score, self.p2_score, "completed");
+        self.tell_spectators(finished.spectator_result());
         let p1_addr = self.p1_addr.clone();
         let p2_addr = self.p2_addr.clone();
-        let winner_clone = winner_id.clone();
-
-        let p1_outcome = p1_outcome.to_string();
-        let p2_outcome = p2_outcome.to_string();
 
         actix::spawn(async move {
-            // Skip database operations if either player is a guest
-            let has_guest = p1_is_guest || p2_is_guest;
-            let (new_p1_elo, new_p2_elo) = if is_ranked && !has_guest {
-                // Fetch current game counts
-                let p1_games = User::find_by_id(&db, &p1_id)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|u| u.total_games)
-                    .unwrap_or(0);
-                let p2_games = User::find_by_id(&db, &p2_id)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|u| u.total_games)
-                    .unwrap_or(0);
-
-                calculate_elo(p1_elo, p1_games, p2_elo, p2_games, outcome)
-            } else {
-                (p1_elo, p2_elo)
-            };
-
-            // Create match record (skip for guest players)
-            if !has_guest {
-                if let Ok(m) =
-                    MatchRecord::create(&db, &p1_id, &p2_id, is_ranked, p1_elo, p2_elo).await
-                {
-                    let _ = MatchRecord::finish(
-                        &db,
-                        &m.id,
-                        winner_clone.as_deref(),
-                        p1_score,
-                        p2_score,
-                        &rounds_json,
-                        new_p1_elo,
-                        new_p2_elo,
-                        "completed",
-                    )
-                    .await;
-
-                    if is_ranked {
-                        let _ = User::update_elo(&db, &p1_id, new_p1_elo).await;
-                        let _ = User::update_elo(&db, &p2_id, new_p2_elo).await;
-                        let _ = EloHistory::create(&db, &p1_id, &m.id, p1_elo, new_p1_elo).await;
-                        let _ = EloHistory::create(&db, &p2_id, &m.id, p2_elo, new_p2_elo).await;
-                    }
-
-                    // Update win/loss/draw stats
-                    let p1_won = if p1_score > p2_score {
-                        Some(true)
-                    } else if p1_score < p2_score {
-                        Some(false)
-                    } else {
-                        None
-                    };
-                    let _ = User::increment_stats(&db, &p1_id, p1_won).await;
-                    let _ = User::increment_stats(&db, &p2_id, p1_won.map(|w| !w)).await;
-                }
-            }
-
-            let p1_elo_change = if is_ranked && !has_guest {
-                Some(new_p1_elo - p1_elo)
-            } else {
-                None
-            };
-            let p2_elo_change = if is_ranked && !has_guest {
-                Some(new_p2_elo - p2_elo)
-            } else {
-                None
-            };
-
-            let _ = p1_addr.do_send(SendServerMessage(ServerMessage::MatchComplete {
-                result: p1_outcome,
-                your_score: p1_score,
-                opponent_score: p2_score,
-                elo_change: p1_elo_change,
-                new_elo: if is_ranked && !has_guest {
-                    Some(new_p1_elo)
-                } else {
-                    None
-                },
-            }));
-
-            let _ = p2_addr.do_send(SendServerMessage(ServerMessage::MatchComplete {
-                result: p2_outcome,
-                your_score: p2_score,
-                opponent_score: p1_score,
-                elo_change: p2_elo_change,
-                new_elo: if is_ranked && !has_guest {
-                    Some(new_p2_elo)
-                } else {
-                    None
-                },
-            }));
+            let recorded = finished.record().await;
+            finished.notify(Side::Player1, &p1_addr, &recorded);
+            finished.notify(Side::Player2, &p2_addr, &recorded);
         });
 
         ctx.stop();
@@ -317,113 +399,234 @@ impl GameSessionActor {
         }
         self.finished = true;
 
-        let (winner_addr, loser_id) = if disconnected_user_id == self.p1_id {
-            (&self.p2_addr, &self.p1_id)
+        let loser_is_p1 = disconnected_user_id == self.p1_id;
+        let (winner, winner_addr) = if loser_is_p1 {
+            (Side::Player2, self.p2_addr.clone())
         } else {
-            (&self.p1_addr, &self.p2_id)
+            (Side::Player1, self.p1_addr.clone())
         };
 
-        let _ = winner_addr.do_send(SendServerMessage(ServerMessage::OpponentDisconnected));
+        winner_addr.do_send(SendServerMessage(ServerMessage::OpponentDisconnected));
 
         // Record as forfeit (loser gets full loss Elo penalty)
-        let db = self.db.clone();
-        let p1_id = self.p1_id.clone();
-        let p2_id = self.p2_id.clone();
-        let p1_elo = self.p1_elo;
-        let p2_elo = self.p2_elo;
-        let p1_is_guest = self.p1_is_guest;
-        let p2_is_guest = self.p2_is_guest;
-        let is_ranked = self.is_ranked;
-        let loser_is_p1 = disconnected_user_id == self.p1_id;
-        let rounds_json = serde_json::to_string(&self.rounds).unwrap_or_else(|_| "[]".into());
-        let p1_addr = self.p1_addr.clone();
-        let p2_addr = self.p2_addr.clone();
-
-        let _loser_id = loser_id.clone();
+        let (p1_score, p2_score) = if loser_is_p1 { (0, 2) } else { (2, 0) };
+        let finished = self.snapshot(Some(winner), p1_score, p2_score, "forfeit");
+        self.tell_spectators(finished.spectator_result());
 
         actix::spawn(async move {
-            let has_guest = p1_is_guest || p2_is_guest;
-            let outcome = if loser_is_p1 { 0.0 } else { 1.0 };
-
-            let (new_p1_elo, new_p2_elo) = if is_ranked && !has_guest {
-                let p1_games = User::find_by_id(&db, &p1_id)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|u| u.total_games)
-                    .unwrap_or(0);
-                let p2_games = User::find_by_id(&db, &p2_id)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|u| u.total_games)
-                    .unwrap_or(0);
-                calculate_elo(p1_elo, p1_games, p2_elo, p2_games, outcome)
-            } else {
-                (p1_elo, p2_elo)
-            };
-
-            let winner_id = if loser_is_p1 { &p2_id } else { &p1_id };
-            let (p1_score, p2_score) = if loser_is_p1 { (0, 2) } else { (2, 0) };
-
-            if !has_guest {
-                if let Ok(m) =
-                    MatchRecord::create(&db, &p1_id, &p2_id, is_ranked, p1_elo, p2_elo).await
-                {
-                    let _ = MatchRecord::finish(
-                        &db,
-                        &m.id,
-                        Some(winner_id),
-                        p1_score,
-                        p2_score,
-                        &rounds_json,
-                        new_p1_elo,
-                        new_p2_elo,
-                        "forfeit",
-                    )
-                    .await;
-
-                    if is_ranked {
-                        let _ = User::update_elo(&db, &p1_id, new_p1_elo).await;
-                        let _ = User::update_elo(&db, &p2_id, new_p2_elo).await;
-                        let _ = EloHistory::create(&db, &p1_id, &m.id, p1_elo, new_p1_elo).await;
-                        let _ = EloHistory::create(&db, &p2_id, &m.id, p2_elo, new_p2_elo).await;
-                    }
+            let recorded = finished.record().await;
+            finished.notify(winner, &winner_addr, &recorded);
+        });
+
+        ctx.stop();
+    }
+
+    fn snapshot(
+        &self,
+        winner: Option<Side>,
+        p1_score: i32,
+        p2_score: i32,
+        status: &'static str,
+    ) -> FinishedMatch {
+        FinishedMatch {
+            repos: self.repos.clone(),
+            // Retries of the same match are keyed on this
+            id: self
+                .id
+                .clone()
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            p1_id: self.p1_id.clone(),
+            p2_id: self.p2_id.clone(),
+            p1_score,
+            p2_score,
+            winner,
+            is_ranked: self.is_ranked,
+            has_guest: self.p1_is_guest || self.p2_is_guest,
+            rounds: self.rounds.clone(),
+            status,
+        }
+    }
+}
+
+const RECORD_ATTEMPTS: u32 = 3;
+const RECORD_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Player1,
+    Player2,
+}
+
+impl Side {
+    fn other(self) -> Self {
+        match self {
+            Side::Player1 => Side::Player2,
+            Side::Player2 => Side::Player1,
+        }
+    }
 
-                    let p1_won = !loser_is_p1;
-                    let _ = User::increment_stats(&db, &p1_id, Some(p1_won)).await;
-                    let _ = User::increment_stats(&db, &p2_id, Some(!p1_won)).await;
+    /// How spectators see this player.
+    fn label(self) -> &'static str {
+        match self {
+            Side::Player1 => "player1",
+            Side::Player2 => "player2",
+        }
+    }
+}
+
+/// A player's rating before and after a match.
+#[derive(Clone, Copy)]
+struct RatingChange {
+    before: i32,
+    after: i32,
+}
+
+impl RatingChange {
+    fn of(before: Option<i32>, after: Option<i32>) -> Option<Self> {
+        Some(Self {
+            before: before?,
+            after: after?,
+        })
+    }
+}
+
+/// Outcome of persisting a finished match.
+enum Recorded {
+    /// Stored; holds both players' rating changes if the match was rated.
+    Saved(Option<[RatingChange; 2]>),
+    /// Every attempt failed; nothing was written.
+    Failed,
+}
+
+/// Final state of a match, detached from the actor so it can be persisted
+/// after the session stops.
+struct FinishedMatch {
+    repos: Repositories,
+    id: String,
+    p1_id: String,
+    p2_id: String,
+    p1_score: i32,
+    p2_score: i32,
+    winner: Option<Side>,
+    is_ranked: bool,
+    has_guest: bool,
+    rounds: Vec<MatchRound>,
+    status: &'static str,
+}
+
+impl FinishedMatch {
+    fn spectator_result(&self) -> SpectatorMessage {
+        SpectatorMessage::MatchComplete {
+            winner: self.winner.map_or("draw", Side::label).into(),
+            player1_score: self.p1_score,
+            player2_score: self.p2_score,
+            status: self.status.into(),
+        }
+    }
+
+    async fn record(&self) -> Recorded {
+        let mut attempt = 1;
+        loop {
+            match self.try_record().await {
+                Ok(record) => return Recorded::Saved(record),
+                Err(e) if attempt < RECORD_ATTEMPTS => {
+                    log::warn!(
+                        "Recording match {} vs {} failed (attempt {attempt}/{RECORD_ATTEMPTS}): {e}",
+                        self.p1_id,
+                        self.p2_id
+                    );
+                    tokio::time::sleep(RECORD_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    log::error!(
+                        "Giving up on recording match {} vs {}: {e}",
+                        self.p1_id,
+                        self.p2_id
+                    );
+                    return Recorded::Failed;
                 }
             }
+        }
+    }
 
-            // Notify winner
-            let winner_addr = if loser_is_p1 { &p2_addr } else { &p1_addr };
-            let (winner_score, loser_score) = if loser_is_p1 {
-                (p2_score, p1_score)
-            } else {
-                (p1_score, p2_score)
-            };
-            let winner_new_elo = if loser_is_p1 { new_p2_elo } else { new_p1_elo };
-            let winner_old_elo = if loser_is_p1 { p2_elo } else { p1_elo };
-
-            let _ = winner_addr.do_send(SendServerMessage(ServerMessage::MatchComplete {
-                result: "win".into(),
-                your_score: winner_score,
-                opponent_score: loser_score,
-                elo_change: if is_ranked && !has_guest {
-                    Some(winner_new_elo - winner_old_elo)
-                } else {
-                    None
-                },
-                new_elo: if is_ranked && !has_guest {
-                    Some(winner_new_elo)
-                } else {
-                    None
-                },
-            }));
-        });
+    async fn try_record(&self) -> Result<Option<[RatingChange; 2]>, AppError> {
+        let result = MatchResult {
+            id: self.id.clone(),
+            player1_id: self.p1_id.clone(),
+            player2_id: self.p2_id.clone(),
+            winner_id: match self.winner {
+                Some(Side::Player1) => Some(self.p1_id.clone()),
+                Some(Side::Player2) => Some(self.p2_id.clone()),
+                None => None,
+            },
+            is_ranked: self.is_ranked,
+            player1_score: self.p1_score,
+            player2_score: self.p2_score,
+            rounds: self.rounds.clone(),
+            status: self.status.to_string(),
+        };
+        // Guests have no account to rate or count stats for
+        if self.has_guest {
+            self.repos.matches.record_guest_match(&result).await?;
+            return Ok(None);
+        }
 
-        ctx.stop();
+        let record = self.repos.matches.record_result(&result).await?;
+        if !record.is_ranked {
+            return Ok(None);
+        }
+        Ok(
+            RatingChange::of(record.player1_elo_before, record.player1_elo_after)
+                .zip(RatingChange::of(
+                    record.player2_elo_before,
+                    record.player2_elo_after,
+                ))
+                .map(|(p1, p2)| [p1, p2]),
+        )
+    }
+
+    fn notify(&self, side: Side, addr: &Recipient<SendServerMessage>, recorded: &Recorded) {
+        let (your_score, opponent_score) = match side {
+            Side::Player1 => (self.p1_score, self.p2_score),
+            Side::Player2 => (self.p2_score, self.p1_score),
+        };
+        let result = match self.winner {
+            None => "draw",
+            Some(winner) if winner == side => "win",
+            Some(_) => "loss",
+        }
+        .to_string();
+
+        let msg = match recorded {
+            Recorded::Failed => ServerMessage::ResultNotRecorded {
+                result,
+                your_score,
+                opponent_score,
+            },
+            Recorded::Saved(Some([p1, p2])) => {
+                let rating = match side {
+                    Side::Player1 => p1,
+                    Side::Player2 => p2,
+                };
+                ServerMessage::MatchComplete {
+                    result,
+                    your_score,
+                    opponent_score,
+                    elo_change: Some(rating.after - rating.before),
+                    new_elo: Some(rating.after),
+                }
+            }
+            _ => ServerMessage::MatchComplete {
+                result,
+                your_score,
+                opponent_score,
+                elo_change: None,
+                new_elo: None,
+            },
+        };
+
+        addr.do_send(SendServerMessage(msg));
     }
 }
 
@@ -431,12 +634,59 @@ impl Actor for GameSessionActor {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        log::info!(
-            "GameSession started: {} vs {}",
-            self.p1_username,
-            self.p2_username
+        let player_ids = [(&self.p1_id, self.p1_is_ai), (&self.p2_id, self.p2_is_ai)]
+            .into_iter()
+            .filter(|(_, is_ai)| !is_ai)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let register = self.registry.send(Register {
+            player_ids,
+            addr: ctx.address(),
+            summary: MatchSummary {
+                player1: self.player_info(Side::Player1),
+                player2: self.player_info(Side::Player2),
+                is_ranked: self.is_ranked,
+                spectators_allowed: self.allow_spectators && self.max_spectators > 0,
+            },
+        });
+
+        // Hold other messages until the session has an id
+        ctx.wait(
+            register
+                .into_actor(self)
+                .map(|registered, act, ctx| match registered {
+                    Ok(Ok(id)) => {
+                        log::info!(
+                            "GameSession {id} started: {} vs {}",
+                            act.p1_username,
+                            act.p2_username
+                        );
+                        act.announce(&id);
+                        act.id = Some(id);
+                        act.start_round(ctx);
+                    }
+                    // Matchmaking checks players before pairing them, so
+                    // this only happens when a game starts in between
+                    Ok(Err(AlreadyInGame { user_id })) => {
+                        let username = if user_id == act.p1_id {
+                            act.p1_username.clone()
+                        } else {
+                            act.p2_username.clone()
+                        };
+                        act.cancel(format!("{username} is already in a game"), ctx);
+                    }
+                    Err(e) => {
+                        log::error!("Could not register game session: {e}");
+                        act.cancel("Could not start the match".into(), ctx);
+                    }
+                }),
         );
-        self.start_round(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(session_id) = self.id.take() {
+            self.registry.do_send(Unregister { session_id });
+        }
     }
 }
 
@@ -447,10 +697,59 @@ pub struct PlayerChoice {
     pub choice: String,
 }
 
+/// A player's socket closed. Unless `forfeit` is set, the game waits out
+/// the reconnect grace window before the player forfeits.
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct PlayerDisconnected {
     pub user_id: String,
+    /// The socket that closed. Sockets the player has since replaced are
+    /// ignored.
+    pub addr: Recipient<SendServerMessage>,
+    /// Forfeit at once, e.g. because the player was banned.
+    pub forfeit: bool,
+}
+
+/// Start watching the match. The spectator is sent the current score
+/// and from then on each round as it starts and resolves.
+#[derive(Message)]
+#[rtype(result = "Result<(), SpectateRefused>")]
+pub struct Watch {
+    pub addr: Recipient<SendSpectatorMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unwatch {
+    pub addr: Recipient<SendSpectatorMessage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectateRefused {
+    /// A player opted out, or spectating is turned off.
+    NotAllowed,
+    Full,
+    Ended,
+}
+
+impl SpectateRefused {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::NotAllowed => "This match can't be watched",
+            Self::Full => "This match has as many spectators as it allows",
+            Self::Ended => "Match has ended",
+        }
+    }
+}
+
+/// A player opened a new socket while in this game. Moves the game to it
+/// and sends it the game state; returns false if the game is over or the
+/// user isn't playing it.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Reconnect {
+    pub user_id: String,
+    pub addr: Recipient<SendServerMessage>,
 }
 
 impl Handler<PlayerChoice> for GameSessionActor {
@@ -468,14 +767,14 @@ impl Handler<PlayerChoice> for GameSessionActor {
 
         if msg.user_id == self.p1_id && self.p1_choice.is_none() {
             self.p1_choice = Some(msg.choice);
+            self.p1_decided_at = Some(now_timestamp());
             // Notify opponent that this player has chosen
-            let _ = self
-                .p2_addr
+            self.p2_addr
                 .do_send(SendServerMessage(ServerMessage::OpponentChose));
         } else if msg.user_id == self.p2_id && self.p2_choice.is_none() {
             self.p2_choice = Some(msg.choice);
-            let _ = self
-                .p1_addr
+            self.p2_decided_at = Some(now_timestamp());
+            self.p1_addr
                 .do_send(SendServerMessage(ServerMessage::OpponentChose));
         }
 
@@ -490,16 +789,150 @@ impl Handler<PlayerDisconnected> for GameSessionActor {
     type Result = ();
 
     fn handle(&mut self, msg: PlayerDisconnected, ctx: &mut Self::Context) {
-        self.forfeit(&msg.user_id, ctx);
+        if self.finished {
+            return;
+        }
+        let Some(side) = self.side_of(&msg.user_id) else {
+            return;
+        };
+        if *self.addr(side) != msg.addr {
+            return;
+        }
+        if msg.forfeit || self.reconnect_grace.is_zero() {
+            self.forfeit(&msg.user_id, ctx);
+            return;
+        }
+        if self.away(side).is_some() {
+            return;
+        }
+
+        self.pause_clock(ctx);
+        let user_id = msg.user_id;
+        let timer = ctx.run_later(self.reconnect_grace, move |act, ctx| {
+            act.forfeit(&user_id, ctx);
+        });
+        *self.away(side) = Some(timer);
+
+        self.addr(side.other())
+            .do_send(SendServerMessage(ServerMessage::OpponentReconnecting {
+                grace_secs: self.reconnect_grace.as_secs(),
+            }));
+        self.tell_spectators(SpectatorMessage::PlayerReconnecting {
+            player: side.label().into(),
+            grace_secs: self.reconnect_grace.as_secs(),
+        });
     }
 }
 
+impl Handler<Reconnect> for GameSessionActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Reconnect, ctx: &mut Self::Context) -> bool {
+        if self.finished {
+            return false;
+        }
+        let Some(side) = self.side_of(&msg.user_id) else {
+            return false;
+        };
+        match side {
+            Side::Player1 => self.p1_addr = msg.addr,
+            Side::Player2 => self.p2_addr = msg.addr,
+        }
+
+        if let Some(timer) = self.away(side).take() {
+            ctx.cancel_future(timer);
+            self.addr(side.other())
+                .do_send(SendServerMessage(ServerMessage::OpponentReconnected));
+            self.tell_spectators(SpectatorMessage::PlayerReconnected {
+                player: side.label().into(),
+            });
+            if !self.anyone_away() {
+                if let Some(time_left) = self.paused_time_left {
+                    self.start_clock(time_left, ctx);
+                }
+            }
+        }
+
+        self.addr(side)
+            .do_send(SendServerMessage(self.game_state(side)));
+        if !self.spectators.is_empty() {
+            self.addr(side)
+                .do_send(SendServerMessage(ServerMessage::Spectators {
+                    count: self.spectators.len(),
+                }));
+        }
+        true
+    }
+}
+
+impl Handler<Watch> for GameSessionActor {
+    type Result = Result<(), SpectateRefused>;
+
+    fn handle(&mut self, msg: Watch, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(session_id) = self.id.clone().filter(|_| !self.finished) else {
+            return Err(SpectateRefused::Ended);
+        };
+        if !self.allow_spectators || self.max_spectators == 0 {
+            return Err(SpectateRefused::NotAllowed);
+        }
+        self.spectators.retain(|spectator| spectator.connected());
+        if self.spectators.len() >= self.max_spectators {
+            return Err(SpectateRefused::Full);
+        }
+
+        self.spectators.push(msg.addr.clone());
+        msg.addr
+            .do_send(SendSpectatorMessage(SpectatorMessage::Spectating {
+                session_id,
+                player1: self.player_info(Side::Player1),
+                player2: self.player_info(Side::Player2),
+                round: self.current_round,
+                timeout_secs: ROUND_TIMEOUT_SECS,
+                time_left_ms: self.time_left().as_millis() as u64,
+                player1_score: self.p1_score,
+                player2_score: self.p2_score,
+                spectators: self.spectators.len(),
+            }));
+        self.announce_spectators();
+        Ok(())
+    }
+}
+
+impl Handler<Unwatch> for GameSessionActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unwatch, _ctx: &mut Self::Context) {
+        let before = self.spectators.len();
+        self.spectators.retain(|spectator| *spectator != msg.addr);
+        if self.spectators.len() != before && !self.finished {
+            self.announce_spectators();
+        }
+    }
+}
+
+/// Millisecond-precision UTC timestamp, sortable alongside SQLite's `datetime('now')`.
+fn now_timestamp() -> String {
+    chrono::Utc::now()
+        .format("%Y-%m-%d %H:%M:%S%.3f")
+        .to_string()
+}
+
 enum RoundWinner {
     Player1,
     Player2,
     Draw,
 }
 
+impl RoundWinner {
+    fn label(&self) -> &'static str {
+        match self {
+            RoundWinner::Player1 => Side::Player1.label(),
+            RoundWinner::Player2 => Side::Player2.label(),
+            RoundWinner::Draw => "draw",
+        }
+    }
+}
+
 fn determine_winner(p1: Option<&str>, p2: Option<&str>) -> RoundWinner {
     match (p1, p2) {
         (None, None) => RoundWinner::Draw,
@@ -524,6 +957,216 @@ fn determine_winner(p1: Option<&str>, p2: Option<&str>) -> RoundWinner {
 mod tests {
     use super::*;
 
+    use crate::game::test_support::{player, spectator};
+    use crate::repository::Repositories;
+
+    fn guest_game(
+        p1_addr: Recipient<SendServerMessage>,
+        p2_addr: Recipient<SendServerMessage>,
+        reconnect_grace: Duration,
+    ) -> Addr<GameSessionActor> {
+        watchable_game(p1_addr, p2_addr, reconnect_grace, true, 1)
+    }
+
+    fn watchable_game(
+        p1_addr: Recipient<SendServerMessage>,
+        p2_addr: Recipient<SendServerMessage>,
+        reconnect_grace: Duration,
+        allow_spectators: bool,
+        max_spectators: usize,
+    ) -> Addr<GameSessionActor> {
+        GameSessionActor::new(
+            "guest_p1".into(),
+            "GuestP1".into(),
+            1000,
+            true,
+            false,
+            p1_addr,
+            "guest_p2".into(),
+            "GuestP2".into(),
+            1000,
+            true,
+            false,
+            p2_addr,
+            false,
+            Repositories::in_memory(),
+            reconnect_grace,
+            allow_spectators,
+            max_spectators,
+            SessionRegistry::default().start(),
+        )
+        .start()
+    }
+
+    #[actix_rt::test]
+    async fn dropped_player_can_reconnect_and_gets_the_game_state() {
+        let (p1_addr, _) = player();
+        let (p2_addr, p2_received) = player();
+        let game = guest_game(p1_addr.clone(), p2_addr, Duration::from_millis(300));
+
+        game.do_send(PlayerChoice {
+            user_id: "guest_p1".into(),
+            choice: "rock".into(),
+        });
+        game.do_send(PlayerDisconnected {
+            user_id: "guest_p1".into(),
+            addr: p1_addr.clone(),
+            forfeit: false,
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(p2_received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| matches!(m, ServerMessage::OpponentReconnecting { .. })));
+
+        let (new_addr, new_received) = player();
+        let resumed = game
+            .send(Reconnect {
+                user_id: "guest_p1".into(),
+                addr: new_addr,
+            })
+            .await
+            .expect("session should answer");
+        assert!(resumed);
+        // The old socket closing late must not start another grace window
+        game.do_send(PlayerDisconnected {
+            user_id: "guest_p1".into(),
+            addr: p1_addr,
+            forfeit: false,
+        });
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        assert!(game.connected(), "the game should outlive the grace window");
+        let state = new_received.lock().unwrap();
+        match state.as_slice() {
+            [ServerMessage::GameState {
+                round: 1,
+                time_left_ms,
+                your_choice: Some(choice),
+                opponent_chose: false,
+                your_score: 0,
+                opponent_score: 0,
+                opponent,
+                opponent_connected: true,
+                ..
+            }] => {
+                assert_eq!(choice, "rock");
+                assert_eq!(opponent.username, "GuestP2");
+                // The clock stood still while the player was away
+                assert!(*time_left_ms > (ROUND_TIMEOUT_SECS - 1) * 1000);
+            }
+            other => panic!("expected a game state, got {other:?}"),
+        }
+        let p2_received = p2_received.lock().unwrap();
+        assert!(p2_received
+            .iter()
+            .any(|m| matches!(m, ServerMessage::OpponentReconnected)));
+        assert_eq!(
+            p2_received
+                .iter()
+                .filter(|m| matches!(m, ServerMessage::OpponentReconnecting { .. }))
+                .count(),
+            1
+        );
+        assert!(!p2_received
+            .iter()
+            .any(|m| matches!(m, ServerMessage::OpponentDisconnected)));
+    }
+
+    #[actix_rt::test]
+    async fn dropped_player_forfeits_once_the_grace_window_ends() {
+        let (p1_addr, _) = player();
+        let (p2_addr, p2_received) = player();
+        let game = guest_game(p1_addr.clone(), p2_addr, Duration::from_millis(50));
+
+        game.do_send(PlayerDisconnected {
+            user_id: "guest_p1".into(),
+            addr: p1_addr,
+            forfeit: false,
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(game.connected());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!game.connected());
+        assert!(p2_received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| matches!(m, ServerMessage::OpponentDisconnected)));
+    }
+
+    #[actix_rt::test]
+    async fn spectators_see_choices_only_once_the_round_resolves() {
+        let (p1_addr, p1_received) = player();
+        let (p2_addr, _) = player();
+        let game = guest_game(p1_addr, p2_addr, Duration::from_secs(20));
+
+        let (watcher, watched) = spectator();
+        let watching = game
+            .send(Watch { addr: watcher })
+            .await
+            .expect("session should answer");
+        assert_eq!(watching, Ok(()));
+        let (latecomer, _) = spectator();
+        let full = game
+            .send(Watch { addr: latecomer })
+            .await
+            .expect("session should answer");
+        assert_eq!(full, Err(SpectateRefused::Full));
+
+        game.do_send(PlayerChoice {
+            user_id: "guest_p1".into(),
+            choice: "rock".into(),
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(matches!(
+            watched.lock().unwrap().as_slice(),
+            [SpectatorMessage::Spectating {
+                round: 1,
+                spectators: 1,
+                ..
+            }]
+        ));
+
+        game.do_send(PlayerChoice {
+            user_id: "guest_p2".into(),
+            choice: "scissors".into(),
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(watched.lock().unwrap().iter().any(|m| matches!(
+            m,
+            SpectatorMessage::RoundResult {
+                round: 1,
+                player1_choice,
+                player2_choice,
+                player1_score: 1,
+                player2_score: 0,
+                ..
+            } if player1_choice == "rock" && player2_choice == "scissors"
+        )));
+        assert!(p1_received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| matches!(m, ServerMessage::Spectators { count: 1 })));
+    }
+
+    #[actix_rt::test]
+    async fn players_can_keep_spectators_out() {
+        let (p1_addr, _) = player();
+        let (p2_addr, _) = player();
+        let game = watchable_game(p1_addr, p2_addr, Duration::from_secs(20), false, 10);
+
+        let (watcher, _) = spectator();
+        let refused = game
+            .send(Watch { addr: watcher })
+            .await
+            .expect("session should answer");
+        assert_eq!(refused, Err(SpectateRefused::NotAllowed));
+    }
+
     #[test]
     fn determine_winner_draw_cases() {
         assert!(matches!(determine_winner(None, None), RoundWinner::Draw));
@@ -556,4 +1199,34 @@ mod tests {
             RoundWinner::Player2
         ));
     }
+
+    #[actix_rt::test]
+    async fn unrecordable_match_reports_result_not_recorded() {
+        let finished = FinishedMatch {
+            repos: Repositories::in_memory(),
+            id: "unrecordable".into(),
+            p1_id: "missing-p1".into(),
+            p2_id: "missing-p2".into(),
+            p1_score: 3,
+            p2_score: 1,
+            winner: Some(Side::Player1),
+            is_ranked: true,
+            has_guest: false,
+            rounds: Vec::new(),
+            status: "completed",
+        };
+
+        let recorded = finished.record().await;
+        assert!(matches!(recorded, Recorded::Failed));
+
+        let (addr, received) = player();
+        finished.notify(Side::Player1, &addr, &recorded);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let received = received.lock().unwrap();
+        assert!(matches!(
+            received.as_slice(),
+            [ServerMessage::ResultNotRecorded { result, your_score: 3, opponent_score: 1 }] if result == "win"
+        ));
+    }
 }
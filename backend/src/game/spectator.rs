@@ -0,0 +1,157 @@
+use actix::prelude::*;
+use actix_web_actors::ws;
+use serde::Serialize;
+use std::time::Instant;
+
+use crate::game::session::{GameSessionActor, SpectateRefused, Unwatch, Watch};
+use crate::game::ws::{OpponentInfo, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL};
+
+/// Messages sent to spectators. Players are named `player1` and `player2`
+/// rather than "you" and "opponent", and choices only appear once a round
+/// has been resolved.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum SpectatorMessage {
+    /// Sent once the spectator is let in.
+    #[serde(rename = "spectating")]
+    Spectating {
+        session_id: String,
+        player1: OpponentInfo,
+        player2: OpponentInfo,
+        round: i32,
+        timeout_secs: u64,
+        time_left_ms: u64,
+        player1_score: i32,
+        player2_score: i32,
+        spectators: usize,
+    },
+    #[serde(rename = "round_start")]
+    RoundStart { round: i32, timeout_secs: u64 },
+    #[serde(rename = "round_result")]
+    RoundResult {
+        round: i32,
+        player1_choice: String,
+        player2_choice: String,
+        winner: String, // "player1", "player2", "draw"
+        player1_score: i32,
+        player2_score: i32,
+    },
+    #[serde(rename = "match_complete")]
+    MatchComplete {
+        winner: String, // "player1", "player2", "draw"
+        player1_score: i32,
+        player2_score: i32,
+        status: String, // "completed", "forfeit"
+    },
+    /// A player dropped; the round clock is stopped while they reconnect.
+    #[serde(rename = "player_reconnecting")]
+    PlayerReconnecting { player: String, grace_secs: u64 },
+    #[serde(rename = "player_reconnected")]
+    PlayerReconnected { player: String },
+    #[serde(rename = "error")]
+    Error { message: String },
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendSpectatorMessage(pub SpectatorMessage);
+
+/// Per-connection WebSocket actor of someone watching a match. Spectators
+/// only listen; anything they send is ignored.
+pub struct SpectatorWsActor {
+    session: Addr<GameSessionActor>,
+    hb: Instant,
+}
+
+impl SpectatorWsActor {
+    pub fn new(session: Addr<GameSessionActor>) -> Self {
+        Self {
+            session,
+            hb: Instant::now(),
+        }
+    }
+
+    fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn send_message(&self, msg: &SpectatorMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Ok(json) = serde_json::to_string(msg) {
+            ctx.text(json);
+        }
+    }
+}
+
+impl Actor for SpectatorWsActor {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.heartbeat(ctx);
+        let watch = self.session.send(Watch {
+            addr: ctx.address().recipient(),
+        });
+        ctx.wait(watch.into_actor(self).map(|watching, act, ctx| {
+            let message = match watching {
+                Ok(Ok(())) => return,
+                Ok(Err(refused)) => refused.message().to_string(),
+                Err(_) => SpectateRefused::Ended.message().to_string(),
+            };
+            act.send_message(
+                &SpectatorMessage::Error {
+                    message: message.clone(),
+                },
+                ctx,
+            );
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some(message),
+            }));
+            ctx.stop();
+        }));
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        self.session.do_send(Unwatch {
+            addr: ctx.address().recipient(),
+        });
+    }
+}
+
+impl Handler<SendSpectatorMessage> for SpectatorWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendSpectatorMessage, ctx: &mut Self::Context) {
+        self.send_message(&msg.0, ctx);
+        // Nothing follows the result
+        if matches!(msg.0, SpectatorMessage::MatchComplete { .. }) {
+            ctx.close(Some(ws::CloseCode::Normal.into()));
+            ctx.stop();
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpectatorWsActor {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.hb = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.hb = Instant::now();
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(_) => ctx.stop(),
+        }
+    }
+}
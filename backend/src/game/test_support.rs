@@ -0,0 +1,228 @@
+//! Stand-ins for player and spectator sockets in game tests, and a running
+//! server for tests that need real sockets.
+
+use actix::prelude::*;
+use actix_web::{web, App, HttpServer};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::auth::ws_ticket::WsTickets;
+use crate::config::AppConfig;
+use crate::game::matchmaking::MatchmakingActor;
+use crate::game::registry::SessionRegistry;
+use crate::game::spectator::{SendSpectatorMessage, SpectatorMessage};
+use crate::game::ws::{SendServerMessage, ServerMessage};
+use crate::models::user::User;
+use crate::repository::Repositories;
+use crate::routes;
+
+/// Everything a stand-in socket was sent, in order.
+pub type Received<M> = Arc<Mutex<Vec<M>>>;
+
+/// Keeps every message it is sent instead of writing it to a socket.
+pub struct Collector<M>(Received<M>);
+
+impl<M: Unpin + 'static> Actor for Collector<M> {
+    type Context = Context<Self>;
+}
+
+impl Handler<SendServerMessage> for Collector<ServerMessage> {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendServerMessage, _ctx: &mut Self::Context) {
+        self.0.lock().unwrap().push(msg.0);
+    }
+}
+
+impl Handler<SendSpectatorMessage> for Collector<SpectatorMessage> {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendSpectatorMessage, _ctx: &mut Self::Context) {
+        self.0.lock().unwrap().push(msg.0);
+    }
+}
+
+/// A player socket and what it received.
+pub fn player() -> (Recipient<SendServerMessage>, Received<ServerMessage>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let addr = Collector(received.clone()).start();
+    (addr.recipient(), received)
+}
+
+/// A spectator socket and what it received.
+pub fn spectator() -> (Recipient<SendSpectatorMessage>, Received<SpectatorMessage>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let addr = Collector(received.clone()).start();
+    (addr.recipient(), received)
+}
+
+/// The app on a local port, with in-memory storage.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub repos: Repositories,
+    pub config: AppConfig,
+    pub tickets: web::Data<WsTickets>,
+    pub registry: Addr<SessionRegistry>,
+    pub matchmaking: Addr<MatchmakingActor>,
+}
+
+impl TestServer {
+    pub fn start() -> Self {
+        let repos = Repositories::in_memory();
+        let config = AppConfig::for_tests();
+        let tickets = web::Data::new(WsTickets::in_memory());
+        let registry = SessionRegistry::default().start();
+        let matchmaking =
+            MatchmakingActor::new(repos.clone(), config.game.clone(), registry.clone()).start();
+        let server = {
+            let (repos, config, tickets) = (repos.clone(), config.clone(), tickets.clone());
+            let (registry, matchmaking) = (registry.clone(), matchmaking.clone());
+            HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(repos.clone()))
+                    .app_data(web::Data::new(config.clone()))
+                    .app_data(tickets.clone())
+                    .app_data(web::Data::new(registry.clone()))
+                    .app_data(web::Data::new(matchmaking.clone()))
+                    .configure(routes::configure)
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("server should bind")
+        };
+        let addr = server.addrs()[0];
+        actix_rt::spawn(server.run());
+        Self {
+            addr,
+            repos,
+            config,
+            tickets,
+            registry,
+            matchmaking,
+        }
+    }
+
+    /// Open a game socket as `user`, signed in with `login_session_id`.
+    pub async fn connect(&self, user: &User, login_session_id: Option<&str>) -> TestSocket {
+        let ticket = self
+            .tickets
+            .issue(user, login_session_id)
+            .await
+            .expect("ticket should be issued");
+        TestSocket::open(self.addr, &format!("ticket={ticket}")).await
+    }
+}
+
+/// A game socket spoken to with bare WebSocket frames.
+pub struct TestSocket(TcpStream);
+
+impl TestSocket {
+    /// Upgrade `/ws?{query}`, then ping the socket so it has registered
+    /// with matchmaking before the test goes on.
+    pub async fn open(addr: SocketAddr, query: &str) -> Self {
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .expect("server should accept connections");
+        let handshake = format!(
+            "GET /ws?{query} HTTP/1.1\r\nHost: {addr}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\n\r\n"
+        );
+        stream
+            .write_all(handshake.as_bytes())
+            .await
+            .expect("handshake should be sent");
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(
+                stream
+                    .read_u8()
+                    .await
+                    .expect("handshake should be answered"),
+            );
+        }
+        assert!(head.starts_with(b"HTTP/1.1 101"), "{head:?}");
+
+        let mut socket = Self(stream);
+        socket.write_frame(0x9, b"").await;
+        let frame = socket.next_frame().await;
+        assert_eq!(frame.map(|(opcode, _)| opcode), Some(0xA));
+        socket
+    }
+
+    /// Send a client message.
+    pub async fn send(&mut self, message: serde_json::Value) {
+        self.write_frame(0x1, message.to_string().as_bytes()).await;
+    }
+
+    /// Client frames must be masked; a zero mask leaves the payload as is.
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&[0; 4]);
+        frame.extend_from_slice(payload);
+        self.0
+            .write_all(&frame)
+            .await
+            .expect("frame should be sent");
+    }
+
+    /// The next frame the server sends, as its opcode and payload, or
+    /// `None` when nothing arrives within a second.
+    pub async fn next_frame(&mut self) -> Option<(u8, Vec<u8>)> {
+        let stream = &mut self.0;
+        let read = async {
+            let first = stream.read_u8().await.ok()?;
+            let len = match stream.read_u8().await.ok()? & 0x7f {
+                126 => stream.read_u16().await.ok()? as usize,
+                127 => stream.read_u64().await.ok()? as usize,
+                len => len as usize,
+            };
+            let mut payload = vec![0; len];
+            stream.read_exact(&mut payload).await.ok()?;
+            Some((first & 0x0f, payload))
+        };
+        tokio::time::timeout(Duration::from_secs(1), read)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// The next server message, skipping pings, or `None` when none arrives
+    /// within a second.
+    pub async fn next_message(&mut self) -> Option<serde_json::Value> {
+        loop {
+            match self.next_frame().await? {
+                (0x1, payload) => {
+                    return Some(serde_json::from_slice(&payload).expect("messages should be JSON"))
+                }
+                (0x8, _) => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// The text the server sent before closing the socket.
+    pub async fn read_until_closed(&mut self) -> Vec<String> {
+        let mut texts = Vec::new();
+        loop {
+            match self.next_frame().await {
+                Some((0x1, payload)) => {
+                    texts.push(String::from_utf8(payload).expect("text should be UTF-8"))
+                }
+                Some((0x8, _)) => return texts,
+                Some(_) => {}
+                None => panic!("socket should have been closed; got {texts:?}"),
+            }
+        }
+    }
+}
@@ -3,11 +3,14 @@ use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 
-use crate::game::matchmaking::{JoinQueue, LeaveQueue, MatchmakingActor};
-use crate::game::session::{GameSessionActor, PlayerChoice, PlayerDisconnected};
+use crate::game::matchmaking::{
+    Connect, Disconnect, FindGame, JoinQueue, LeaveQueue, MatchmakingActor,
+};
+use crate::game::session::{GameSessionActor, PlayerChoice, PlayerDisconnected, Reconnect};
+use crate::repository::Repositories;
 
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+pub(crate) const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Messages sent from client to server
 #[derive(Debug, Deserialize)]
@@ -53,8 +56,38 @@ pub enum ServerMessage {
         elo_change: Option<i32>,
         new_elo: Option<i32>,
     },
+    /// The match ended but its result couldn't be saved, so ratings and
+    /// stats are unchanged.
+    #[serde(rename = "result_not_recorded")]
+    ResultNotRecorded {
+        result: String, // "win", "loss", "draw"
+        your_score: i32,
+        opponent_score: i32,
+    },
     #[serde(rename = "opponent_disconnected")]
     OpponentDisconnected,
+    /// The opponent dropped. The round clock is stopped until they're back
+    /// or `grace_secs` pass and they forfeit.
+    #[serde(rename = "opponent_reconnecting")]
+    OpponentReconnecting { grace_secs: u64 },
+    #[serde(rename = "opponent_reconnected")]
+    OpponentReconnected,
+    /// How many people are watching the match.
+    #[serde(rename = "spectators")]
+    Spectators { count: usize },
+    /// Sent to a player whose new socket rejoined a game in progress.
+    #[serde(rename = "game_state")]
+    GameState {
+        round: i32,
+        timeout_secs: u64,
+        time_left_ms: u64,
+        your_choice: Option<String>,
+        opponent_chose: bool,
+        your_score: i32,
+        opponent_score: i32,
+        opponent: OpponentInfo,
+        opponent_connected: bool,
+    },
     #[serde(rename = "error")]
     Error { message: String },
 }
@@ -76,30 +109,82 @@ pub struct PlayerWsActor {
     pub username: String,
     pub elo: i32,
     pub is_guest: bool,
+    /// Ranked play is closed until the player verifies their email.
+    pub must_verify_email: bool,
     pub hb: Instant,
     pub matchmaking: Addr<MatchmakingActor>,
+    /// For reading the player's settings when they queue.
+    pub repos: Repositories,
     pub session: Option<Addr<GameSessionActor>>,
+    /// The login session the socket was opened with, so revoking it closes
+    /// the socket.
+    pub login_session_id: Option<String>,
+    /// Set when the server closes the socket, so the game in progress is
+    /// forfeited rather than held open for a reconnect.
+    pub forfeit_on_stop: bool,
 }
 
 impl PlayerWsActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_id: String,
         username: String,
         elo: i32,
         is_guest: bool,
+        must_verify_email: bool,
+        login_session_id: Option<String>,
         matchmaking: Addr<MatchmakingActor>,
+        repos: Repositories,
     ) -> Self {
         Self {
             user_id,
             username,
             elo,
             is_guest,
+            must_verify_email,
             hb: Instant::now(),
             matchmaking,
+            repos,
             session: None,
+            login_session_id,
+            forfeit_on_stop: false,
         }
     }
 
+    /// Queue with the player's spectator setting as it is now, so a change
+    /// in settings applies to the next game. Read here rather than by the
+    /// matchmaker, which shouldn't wait on the database. Guests have no
+    /// settings and allow spectators; players whose setting can't be read
+    /// don't.
+    fn join_queue(&self, ranked: bool, ctx: &mut ws::WebsocketContext<Self>) {
+        let repos = self.repos.clone();
+        let (user_id, is_guest) = (self.user_id.clone(), self.is_guest);
+        let allow_spectators = async move {
+            is_guest
+                || matches!(
+                    repos.users.find_by_id(&user_id).await,
+                    Ok(Some(user)) if user.allow_spectators
+                )
+        };
+        // Finish the read before handling the next message, so a
+        // `leave_queue` right after can't overtake the join
+        ctx.wait(
+            allow_spectators
+                .into_actor(self)
+                .map(move |allow_spectators, act, ctx| {
+                    act.matchmaking.do_send(JoinQueue {
+                        user_id: act.user_id.clone(),
+                        username: act.username.clone(),
+                        elo: act.elo,
+                        ranked,
+                        is_guest: act.is_guest,
+                        allow_spectators,
+                        addr: ctx.address(),
+                    });
+                }),
+        );
+    }
+
     fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
         ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
             if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
@@ -114,6 +199,32 @@ impl PlayerWsActor {
         });
     }
 
+    /// Rejoin the game this player was in when their last socket dropped.
+    fn resume_game(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let matchmaking = self.matchmaking.clone();
+        let user_id = self.user_id.clone();
+        let addr = ctx.address().recipient();
+        let fut = async move {
+            let session = matchmaking
+                .send(FindGame {
+                    user_id: user_id.clone(),
+                })
+                .await
+                .ok()
+                .flatten()?;
+            let resumed = session
+                .send(Reconnect { user_id, addr })
+                .await
+                .unwrap_or(false);
+            resumed.then_some(session)
+        };
+        ctx.spawn(fut.into_actor(self).map(|session, act, _ctx| {
+            if let Some(session) = session {
+                act.session = Some(session);
+            }
+        }));
+    }
+
     fn send_message(&self, msg: &ServerMessage, ctx: &mut ws::WebsocketContext<Self>) {
         if let Ok(json) = serde_json::to_string(msg) {
             ctx.text(json);
@@ -127,10 +238,24 @@ impl Actor for PlayerWsActor {
     fn started(&mut self, ctx: &mut Self::Context) {
         self.heartbeat(ctx);
         log::info!("PlayerWsActor started for user {}", self.user_id);
+        if !self.is_guest {
+            self.matchmaking.do_send(Connect {
+                user_id: self.user_id.clone(),
+                session_id: self.login_session_id.clone(),
+                addr: ctx.address(),
+            });
+        }
+        self.resume_game(ctx);
     }
 
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
+    fn stopped(&mut self, ctx: &mut Self::Context) {
         log::info!("PlayerWsActor stopped for user {}", self.user_id);
+        if !self.is_guest {
+            self.matchmaking.do_send(Disconnect {
+                user_id: self.user_id.clone(),
+                addr: ctx.address(),
+            });
+        }
         // Leave queue if in queue
         self.matchmaking.do_send(LeaveQueue {
             user_id: self.user_id.clone(),
@@ -139,6 +264,8 @@ impl Actor for PlayerWsActor {
         if let Some(ref session) = self.session {
             session.do_send(PlayerDisconnected {
                 user_id: self.user_id.clone(),
+                addr: ctx.address().recipient(),
+                forfeit: self.forfeit_on_stop,
             });
         }
     }
@@ -152,6 +279,38 @@ impl Handler<SendServerMessage> for PlayerWsActor {
     }
 }
 
+/// Server-initiated close, e.g. when the player is banned. Stopping the actor
+/// forfeits any game in progress.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseConnection {
+    pub reason: String,
+}
+
+impl Handler<CloseConnection> for PlayerWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseConnection, ctx: &mut Self::Context) {
+        log::info!(
+            "Closing WebSocket for user {}: {}",
+            self.user_id,
+            msg.reason
+        );
+        self.send_message(
+            &ServerMessage::Error {
+                message: msg.reason.clone(),
+            },
+            ctx,
+        );
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(msg.reason),
+        }));
+        self.forfeit_on_stop = true;
+        ctx.stop();
+    }
+}
+
 /// Message to set the game session on the player actor
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -186,21 +345,22 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for PlayerWsActor {
             ws::Message::Text(text) => {
                 let parsed: Result<ClientMessage, _> = serde_json::from_str(&text);
                 match parsed {
+                    Ok(ClientMessage::JoinQueue { ranked: Some(true) })
+                        if self.must_verify_email =>
+                    {
+                        self.send_message(
+                            &ServerMessage::Error {
+                                message: "Verify your email address to play ranked".into(),
+                            },
+                            ctx,
+                        );
+                    }
                     Ok(ClientMessage::JoinQueue { ranked }) => {
-                        // Guest users can only play unranked
-                        let ranked = if self.is_guest {
-                            false
-                        } else {
-                            ranked.unwrap_or(true)
-                        };
-                        self.matchmaking.do_send(JoinQueue {
-                            user_id: self.user_id.clone(),
-                            username: self.username.clone(),
-                            elo: self.elo,
-                            ranked,
-                            is_guest: self.is_guest,
-                            addr: ctx.address(),
-                        });
+                        // Guests and unverified players (when verification is
+                        // required) can only play unranked
+                        let ranked =
+                            !self.is_guest && !self.must_verify_email && ranked.unwrap_or(true);
+                        self.join_queue(ranked, ctx);
                     }
                     Ok(ClientMessage::LeaveQueue) => {
                         self.matchmaking.do_send(LeaveQueue {
@@ -254,6 +414,12 @@ mod tests {
             ClientMessage::JoinQueue { ranked: Some(true) }
         ));
 
+        // Older clients still send the spectator setting with each join
+        let older: ClientMessage =
+            serde_json::from_str(r#"{"type":"join_queue","allow_spectators":false}"#)
+                .expect("join_queue should deserialize");
+        assert!(matches!(older, ClientMessage::JoinQueue { ranked: None }));
+
         let leave: ClientMessage =
             serde_json::from_str(r#"{"type":"leave_queue"}"#).expect("leave_queue should parse");
         assert!(matches!(leave, ClientMessage::LeaveQueue));
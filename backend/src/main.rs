@@ -1,32 +1,75 @@
 mod api;
 mod auth;
+mod cli;
 mod config;
 mod db;
 mod errors;
 mod game;
+mod mail;
 mod models;
+mod repository;
 mod routes;
+mod snapshot;
+#[cfg(test)]
+mod test_support;
 
 use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web, App, HttpServer};
 
+use auth::oidc::OidcClient;
+use auth::rate_limit::RateLimiter;
+use auth::ws_ticket::WsTickets;
+use cli::Command;
 use config::AppConfig;
 use game::matchmaking::MatchmakingActor;
+use game::registry::SessionRegistry;
+use repository::Backend;
+use snapshot::{Snapshot, SnapshotKey};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
     env_logger::init();
 
+    let command = Command::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{}", cli::USAGE);
+        std::process::exit(2);
+    });
+
     let config = AppConfig::from_env();
     let port = config.backend_port;
     let frontend_url = config.frontend_url.clone();
 
-    let pool = db::init_pool(&config.database_url, config.database_auth_token.as_deref()).await;
-    db::run_migrations(&pool).await;
+    let backend = Backend::connect(&config).await.unwrap_or_else(|e| {
+        eprintln!("Invalid database configuration: {e}");
+        std::process::exit(1);
+    });
+
+    match command {
+        Command::Serve => {}
+        Command::Migrate { dry_run } => return migrate(&backend, dry_run).await,
+        Command::Export { path, anonymize } => {
+            return export(&backend, &config, path.as_deref(), anonymize).await
+        }
+        Command::Restore { path } => return restore(&backend, &config, &path).await,
+    }
 
-    let matchmaking = MatchmakingActor::new(pool.clone()).start();
+    backend
+        .run_migrations()
+        .await
+        .unwrap_or_else(|e| panic!("Failed to run migrations: {e}"));
+
+    let repos = backend.repositories();
+    let registry = SessionRegistry::default().start();
+    let matchmaking =
+        MatchmakingActor::new(repos.clone(), config.game.clone(), registry.clone()).start();
+    let oidc = web::Data::new(OidcClient::new());
+    let limiter = web::Data::new(RateLimiter::in_memory(&config));
+    let ws_tickets = web::Data::new(WsTickets::in_memory());
+    let mailer: web::Data<dyn mail::Mailer> = web::Data::from(
+        mail::from_config(&config).unwrap_or_else(|e| panic!("Invalid mail configuration: {e}")),
+    );
 
     log::info!("Starting server on port {port}");
 
@@ -42,12 +85,119 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(Logger::default())
             .wrap(cors)
-            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(repos.clone()))
             .app_data(web::Data::new(config.clone()))
             .app_data(web::Data::new(matchmaking.clone()))
+            .app_data(web::Data::new(registry.clone()))
+            .app_data(oidc.clone())
+            .app_data(mailer.clone())
+            .app_data(limiter.clone())
+            .app_data(ws_tickets.clone())
             .configure(routes::configure)
     })
     .bind(("0.0.0.0", port))?
     .run()
     .await
 }
+
+async fn migrate(backend: &Backend, dry_run: bool) -> std::io::Result<()> {
+    if !dry_run {
+        let applied = backend
+            .run_migrations()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        println!("Applied {} migration(s): {applied:?}", applied.len());
+        return Ok(());
+    }
+
+    let plan = backend
+        .plan_migrations()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    if plan.adopt_legacy {
+        println!("Existing schema has no migration ledger; known migrations would be recorded as applied.");
+    }
+    for version in &plan.unknown {
+        println!("Warning: database has unknown migration {version} applied.");
+    }
+    if plan.pending.is_empty() {
+        println!("Database is up to date.");
+    }
+    for migration in &plan.pending {
+        println!(
+            "-- pending {:03}_{} (checksum {})\n{}",
+            migration.version,
+            migration.name,
+            migration.checksum(),
+            migration.sql.trim_end()
+        );
+    }
+    Ok(())
+}
+
+async fn export(
+    backend: &Backend,
+    config: &AppConfig,
+    path: Option<&str>,
+    anonymize: bool,
+) -> std::io::Result<()> {
+    // Exporting never changes the source database, so refuse rather than migrate it.
+    let plan = backend
+        .plan_migrations()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if !plan.pending.is_empty() || plan.adopt_legacy {
+        return Err(std::io::Error::other(
+            "Database schema is out of date; run `red-flip migrate` before exporting",
+        ));
+    }
+
+    let mut snapshot = backend
+        .repositories()
+        .snapshots
+        .export()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if anonymize {
+        snapshot.anonymize();
+    }
+
+    let key = config.snapshot_key.as_deref().map(SnapshotKey::new);
+    if key.is_none() && snapshot.totp_secrets() > 0 {
+        eprintln!(
+            "SNAPSHOT_KEY is not set; {} 2FA secret(s) are left out and will be off after a restore",
+            snapshot.totp_secrets()
+        );
+    }
+    match path {
+        Some(path) => {
+            let file = std::fs::File::create(path)?;
+            snapshot.write_jsonl(std::io::BufWriter::new(file), key.as_ref())?;
+            eprintln!("Wrote {:?} to {path}", snapshot.counts());
+        }
+        None => snapshot.write_jsonl(std::io::stdout().lock(), key.as_ref())?,
+    }
+    Ok(())
+}
+
+async fn restore(backend: &Backend, config: &AppConfig, path: &str) -> std::io::Result<()> {
+    let key = config.snapshot_key.as_deref().map(SnapshotKey::new);
+    let file = std::fs::File::open(path)?;
+    let snapshot = Snapshot::read_jsonl(std::io::BufReader::new(file), key.as_ref())
+        .map_err(|e| std::io::Error::other(format!("Invalid snapshot {path}: {e}")))?;
+
+    backend
+        .run_migrations()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    backend
+        .repositories()
+        .snapshots
+        .restore(&snapshot)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    println!("Restored {:?} from {path}", snapshot.counts());
+    Ok(())
+}
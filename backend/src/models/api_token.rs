@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::errors::AppError;
+
+/// What a personal API token may be used for. Routes opt in to API tokens by
+/// naming the scope they need, see `auth::api_tokens::RequiredScope`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "read:profile")]
+    ReadProfile,
+    #[serde(rename = "read:matches")]
+    ReadMatches,
+    #[serde(rename = "admin:read")]
+    AdminRead,
+    #[serde(rename = "play")]
+    Play,
+}
+
+impl ApiScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ReadProfile => "read:profile",
+            Self::ReadMatches => "read:matches",
+            Self::AdminRead => "admin:read",
+            Self::Play => "play",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "read:profile" => Some(Self::ReadProfile),
+            "read:matches" => Some(Self::ReadMatches),
+            "admin:read" => Some(Self::AdminRead),
+            "play" => Some(Self::Play),
+            _ => None,
+        }
+    }
+
+    /// Scopes as stored in `api_tokens.scopes`: space-separated.
+    pub fn join(scopes: &[ApiScope]) -> String {
+        scopes
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Reads a stored scope list, skipping scopes this build doesn't know.
+    pub fn split(scopes: &str) -> Vec<ApiScope> {
+        scopes.split_whitespace().filter_map(Self::parse).collect()
+    }
+}
+
+/// A personal API token as listed to its owner. The token itself is only
+/// shown once, at creation; the database keeps its SHA-256 hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+impl ApiToken {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    fn from_row(row: &libsql::Row) -> Result<Self, AppError> {
+        let get = |i: i32| {
+            row.get::<String>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        let get_opt = |i: i32| {
+            row.get::<Option<String>>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        Ok(Self {
+            id: get(0)?,
+            user_id: get(1)?,
+            name: get(2)?,
+            scopes: ApiScope::split(&get(3)?),
+            expires_at: get_opt(4)?,
+            last_used_at: get_opt(5)?,
+            created_at: get(6)?,
+        })
+    }
+
+    pub async fn create(
+        db: &Database,
+        user_id: &str,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        expires_at: Option<&str>,
+    ) -> Result<ApiToken, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let token = ApiToken {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            expires_at: expires_at.map(str::to_string),
+            last_used_at: None,
+            created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        conn.execute_cached(
+            "INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                token.id.clone(),
+                token.user_id.clone(),
+                token.name.clone(),
+                token_hash.to_string(),
+                ApiScope::join(scopes),
+                token.expires_at.clone(),
+                token.created_at.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(token)
+    }
+
+    /// The user's tokens, newest first.
+    pub async fn list_for_user(db: &Database, user_id: &str) -> Result<Vec<ApiToken>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT id, user_id, name, scopes, expires_at, last_used_at, created_at FROM api_tokens WHERE user_id = ?1 ORDER BY created_at DESC, id",
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut tokens = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            tokens.push(Self::from_row(&row)?);
+        }
+        Ok(tokens)
+    }
+
+    /// Delete one of the user's tokens. Returns false if they have no such token.
+    pub async fn revoke(db: &Database, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let deleted = conn
+            .execute_cached(
+                "DELETE FROM api_tokens WHERE id = ?1 AND user_id = ?2",
+                [id, user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        Ok(deleted > 0)
+    }
+
+    /// Look up an unexpired token by hash and record that it was used.
+    pub async fn use_token(db: &Database, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "UPDATE api_tokens SET last_used_at = datetime('now') WHERE token_hash = ?1 AND (expires_at IS NULL OR expires_at > datetime('now')) RETURNING id, user_id, name, scopes, expires_at, last_used_at, created_at",
+                [token_hash],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use libsql::{Connection, Row};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -16,8 +17,34 @@ pub struct EloHistory {
 }
 
 impl EloHistory {
+    fn from_row(row: &Row) -> Result<Self, AppError> {
+        Ok(EloHistory {
+            id: row
+                .get::<String>(0)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            user_id: row
+                .get::<String>(1)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            match_id: row
+                .get::<String>(2)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            elo_before: row
+                .get::<i32>(3)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            elo_after: row
+                .get::<i32>(4)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            elo_change: row
+                .get::<i32>(5)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            created_at: row
+                .get::<String>(6)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        })
+    }
+
     pub async fn create(
-        db: &Database,
+        conn: &Connection,
         user_id: &str,
         match_id: &str,
         elo_before: i32,
@@ -25,9 +52,6 @@ impl EloHistory {
     ) -> Result<Self, AppError> {
         let id = Uuid::new_v4().to_string();
         let elo_change = elo_after - elo_before;
-        let conn = db
-            .connect()
-            .map_err(|e| AppError::Internal(e.to_string()))?;
 
         conn.execute(
             "INSERT INTO elo_history (id, user_id, match_id, elo_before, elo_after, elo_change) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
@@ -46,6 +70,41 @@ impl EloHistory {
             created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
         })
     }
+
+    pub async fn delete_for_match(conn: &Connection, match_id: &str) -> Result<(), AppError> {
+        conn.execute("DELETE FROM elo_history WHERE match_id = ?1", [match_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Most recent rating changes for a user, newest first.
+    pub async fn for_user(db: &Database, user_id: &str, limit: i32) -> Result<Vec<Self>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT id, user_id, match_id, elo_before, elo_after, elo_change, created_at FROM elo_history WHERE user_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT ?2",
+                libsql::params![user_id, limit],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut history = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            history.push(Self::from_row(&row)?);
+        }
+
+        Ok(history)
+    }
 }
 
 #[cfg(test)]
@@ -53,22 +112,27 @@ mod tests {
     use super::*;
     use crate::db::init_test_db;
     use crate::models::match_record::MatchRecord;
-    use crate::models::user::User;
+    use crate::models::user::create_test_user;
 
     #[actix_rt::test]
     async fn create_persists_elo_change() {
         let db = init_test_db().await;
-        let p1 = User::create(&db, "elo_p1", "elo_p1@example.com", "hash")
-            .await
-            .expect("user should be created");
-        let p2 = User::create(&db, "elo_p2", "elo_p2@example.com", "hash")
-            .await
-            .expect("user should be created");
-        let m = MatchRecord::create(&db, &p1.id, &p2.id, true, 1000, 1000)
-            .await
-            .expect("match should be created");
+        let p1 = create_test_user(&db, "elo_p1", "elo_p1@example.com").await;
+        let p2 = create_test_user(&db, "elo_p2", "elo_p2@example.com").await;
+        let conn = db.connect().await.expect("connection should be available");
+        let m = MatchRecord::create(
+            &conn,
+            &Uuid::new_v4().to_string(),
+            &p1.id,
+            &p2.id,
+            true,
+            1000,
+            1000,
+        )
+        .await
+        .expect("match should be created");
 
-        let history = EloHistory::create(&db, &p1.id, &m.id, 1000, 1018)
+        let history = EloHistory::create(&conn, &p1.id, &m.id, 1000, 1018)
             .await
             .expect("history should be created");
 
@@ -76,7 +140,6 @@ mod tests {
         assert_eq!(history.elo_before, 1000);
         assert_eq!(history.elo_after, 1018);
 
-        let conn = db.connect().expect("connection should be available");
         let mut rows = conn
             .query(
                 "SELECT COUNT(*) FROM elo_history WHERE id = ?1",
@@ -91,5 +154,16 @@ mod tests {
             .expect("row should exist");
         let count: i64 = row.get(0).expect("count should be present");
         assert_eq!(count, 1);
+
+        EloHistory::create(&conn, &p1.id, &m.id, 1018, 1030)
+            .await
+            .expect("history should be created");
+        let recent = EloHistory::for_user(&db, &p1.id, 10)
+            .await
+            .expect("history query should succeed");
+        assert_eq!(
+            recent.iter().map(|h| h.elo_after).collect::<Vec<_>>(),
+            vec![1030, 1018]
+        );
     }
 }
@@ -0,0 +1,75 @@
+use crate::db::Database;
+use crate::errors::AppError;
+
+/// What a mailed token allows its holder to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl EmailTokenPurpose {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VerifyEmail => "verify_email",
+            Self::ResetPassword => "reset_password",
+        }
+    }
+}
+
+/// Issued email tokens. The token mailed out is a signed JWT whose `jti` is
+/// the row id; the row is what makes it single-use.
+pub struct EmailToken;
+
+impl EmailToken {
+    pub async fn create(
+        db: &Database,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "INSERT INTO email_tokens (id, user_id, purpose, expires_at) VALUES (?1, ?2, ?3, ?4)",
+            (
+                id.to_string(),
+                user_id.to_string(),
+                purpose.as_str(),
+                expires_at.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Mark the token used. Returns false if it is unknown, belongs to someone
+    /// else, has expired or was already used.
+    pub async fn consume(
+        db: &Database,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+    ) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let updated = conn
+            .execute_cached(
+                "UPDATE email_tokens SET used_at = datetime('now') WHERE id = ?1 AND user_id = ?2 AND purpose = ?3 AND used_at IS NULL AND expires_at > datetime('now')",
+                (id.to_string(), user_id.to_string(), purpose.as_str()),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(updated == 1)
+    }
+}
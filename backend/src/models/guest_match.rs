@@ -0,0 +1,204 @@
+use libsql::{Row, TransactionBehavior};
+
+use crate::db::Database;
+use crate::errors::AppError;
+use crate::models::match_record::MatchResult;
+use crate::models::user::User;
+
+/// Guest ids are `guest_` followed by a UUID, so they never collide with
+/// account ids.
+pub const GUEST_ID_PREFIX: &str = "guest_";
+
+pub fn is_guest_id(id: &str) -> bool {
+    id.starts_with(GUEST_ID_PREFIX)
+}
+
+/// A match with a guest player, kept in `guest_matches` until the guest
+/// registers. Only the outcome is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestMatch {
+    pub id: String,
+    pub player1_id: String,
+    pub player2_id: String,
+    pub winner_id: Option<String>,
+    pub player1_score: i32,
+    pub player2_score: i32,
+    pub status: String,
+    pub finished_at: String,
+}
+
+const COLUMNS: &str =
+    "id, player1_id, player2_id, winner_id, player1_score, player2_score, status, finished_at";
+
+impl GuestMatch {
+    /// The match with `guest_id` replaced by the account that claimed it.
+    pub fn claimed_by(mut self, guest_id: &str, user_id: &str) -> Self {
+        for id in [&mut self.player1_id, &mut self.player2_id] {
+            if id == guest_id {
+                *id = user_id.to_string();
+            }
+        }
+        if self.winner_id.as_deref() == Some(guest_id) {
+            self.winner_id = Some(user_id.to_string());
+        }
+        self
+    }
+
+    /// Whether both players have accounts, so the match can move to `matches`.
+    pub fn between_accounts(&self) -> bool {
+        !is_guest_id(&self.player1_id) && !is_guest_id(&self.player2_id)
+    }
+
+    fn from_row(row: &Row) -> Result<Self, AppError> {
+        let get = |i: i32| {
+            row.get::<String>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        let get_i32 = |i: i32| {
+            row.get::<i32>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        Ok(Self {
+            id: get(0)?,
+            player1_id: get(1)?,
+            player2_id: get(2)?,
+            winner_id: row
+                .get::<Option<String>>(3)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            player1_score: get_i32(4)?,
+            player2_score: get_i32(5)?,
+            status: get(6)?,
+            finished_at: get(7)?,
+        })
+    }
+
+    /// Store the outcome of a match with a guest; a match id that is
+    /// already stored is left as it is.
+    pub async fn record(db: &Database, result: &MatchResult) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "INSERT INTO guest_matches (id, player1_id, player2_id, winner_id, player1_score, player2_score, status) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) ON CONFLICT (id) DO NOTHING",
+            (
+                result.id.clone(),
+                result.player1_id.clone(),
+                result.player2_id.clone(),
+                result.winner_id.clone(),
+                result.player1_score,
+                result.player2_score,
+                result.status.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Hand a guest's matches to the account they registered, in one
+    /// transaction. Returns how many became matches of the account.
+    pub async fn claim(db: &Database, guest_id: &str, user_id: &str) -> Result<u64, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = tx
+            .query(
+                &format!(
+                    "SELECT {COLUMNS} FROM guest_matches WHERE player1_id = ?1 OR player2_id = ?1"
+                ),
+                [guest_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let mut matches = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            matches.push(Self::from_row(&row)?.claimed_by(guest_id, user_id));
+        }
+        drop(rows);
+
+        let mut moved = 0;
+        for m in matches {
+            if !m.between_accounts() {
+                tx.execute(
+                    "UPDATE guest_matches SET player1_id = ?1, player2_id = ?2, winner_id = ?3 WHERE id = ?4",
+                    (m.player1_id, m.player2_id, m.winner_id, m.id),
+                )
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+                continue;
+            }
+
+            tx.execute(
+                "INSERT INTO matches (id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, status, created_at, finished_at) VALUES (?1, ?2, ?3, ?4, 0, ?5, ?6, ?7, ?8, ?8)",
+                (
+                    m.id.clone(),
+                    m.player1_id.clone(),
+                    m.player2_id.clone(),
+                    m.winner_id.clone(),
+                    m.player1_score,
+                    m.player2_score,
+                    m.status.clone(),
+                    m.finished_at.clone(),
+                ),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+            let won = m.winner_id.as_ref().map(|winner| winner == user_id);
+            User::increment_stats(&tx, user_id, won).await?;
+            tx.execute("DELETE FROM guest_matches WHERE id = ?1", [m.id])
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            moved += 1;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guest_match(p1: &str, p2: &str, winner: Option<&str>) -> GuestMatch {
+        GuestMatch {
+            id: "m1".into(),
+            player1_id: p1.into(),
+            player2_id: p2.into(),
+            winner_id: winner.map(str::to_string),
+            player1_score: 3,
+            player2_score: 1,
+            status: "completed".into(),
+            finished_at: "2026-01-01 00:00:00".into(),
+        }
+    }
+
+    #[test]
+    fn claimed_by_replaces_only_the_claiming_guest() {
+        let claimed =
+            guest_match("guest_a", "guest_b", Some("guest_a")).claimed_by("guest_a", "user-1");
+        assert_eq!(claimed.player1_id, "user-1");
+        assert_eq!(claimed.player2_id, "guest_b");
+        assert_eq!(claimed.winner_id.as_deref(), Some("user-1"));
+        assert!(!claimed.between_accounts());
+
+        let claimed = claimed.claimed_by("guest_b", "user-2");
+        assert_eq!(claimed.winner_id.as_deref(), Some("user-1"));
+        assert!(claimed.between_accounts());
+    }
+}
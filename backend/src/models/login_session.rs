@@ -0,0 +1,160 @@
+use libsql::{Row, TransactionBehavior};
+use serde::Serialize;
+
+use crate::db::Database;
+use crate::errors::AppError;
+
+/// Where a login came from, recorded when the session starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionDevice {
+    pub user_agent: Option<String>,
+    /// Keyed hash of the client IP, see `auth::sessions::hash_ip`.
+    pub ip_hash: Option<String>,
+}
+
+/// One login: the refresh token family it holds and the device it came
+/// from. `last_seen_at` moves whenever the session refreshes its access
+/// token or opens a game connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoginSession {
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    pub ip_hash: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
+}
+
+const COLUMNS: &str =
+    "id, user_id, user_agent, ip_hash, created_at, last_seen_at, expires_at, revoked_at";
+
+impl LoginSession {
+    /// Whether tokens of this session are still accepted.
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+
+    fn from_row(row: &Row) -> Result<Self, AppError> {
+        let get = |i: i32| {
+            row.get::<String>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        let get_opt = |i: i32| {
+            row.get::<Option<String>>(i)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+        Ok(Self {
+            id: get(0)?,
+            user_id: get(1)?,
+            user_agent: get_opt(2)?,
+            ip_hash: get_opt(3)?,
+            created_at: get(4)?,
+            last_seen_at: get(5)?,
+            expires_at: get(6)?,
+            revoked_at: get_opt(7)?,
+        })
+    }
+
+    pub async fn find(db: &Database, id: &str) -> Result<Option<Self>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                &format!("SELECT {COLUMNS} FROM login_sessions WHERE id = ?1"),
+                [id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The user's unrevoked, unexpired sessions, most recently seen first.
+    pub async fn list_active(db: &Database, user_id: &str) -> Result<Vec<Self>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                &format!(
+                    "SELECT {COLUMNS} FROM login_sessions WHERE user_id = ?1 AND revoked_at IS NULL AND expires_at > datetime('now') ORDER BY last_seen_at DESC, created_at DESC"
+                ),
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut sessions = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            sessions.push(Self::from_row(&row)?);
+        }
+        Ok(sessions)
+    }
+
+    pub async fn touch(db: &Database, id: &str) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE login_sessions SET last_seen_at = datetime('now') WHERE id = ?1",
+            [id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Revoke one of the user's sessions along with its refresh tokens.
+    /// Returns false if the user has no such active session.
+    pub async fn revoke(db: &Database, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let revoked = tx
+            .execute(
+                "UPDATE login_sessions SET revoked_at = datetime('now') WHERE id = ?1 AND user_id = ?2 AND revoked_at IS NULL",
+                [id, user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE family_id = ?1 AND user_id = ?2 AND revoked_at IS NULL",
+            [id, user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(revoked > 0)
+    }
+}
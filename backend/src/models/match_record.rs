@@ -1,9 +1,12 @@
-use libsql::Row;
+use libsql::{Connection, Row, TransactionBehavior};
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
 use crate::db::Database;
 use crate::errors::AppError;
+use crate::game::elo::calculate_elo;
+use crate::models::elo_history::EloHistory;
+use crate::models::match_round::MatchRound;
+use crate::models::user::User;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchRecord {
@@ -14,7 +17,6 @@ pub struct MatchRecord {
     pub is_ranked: bool,
     pub player1_score: i32,
     pub player2_score: i32,
-    pub rounds_json: String,
     pub player1_elo_before: Option<i32>,
     pub player1_elo_after: Option<i32>,
     pub player2_elo_before: Option<i32>,
@@ -22,17 +24,89 @@ pub struct MatchRecord {
     pub status: String,
     pub created_at: String,
     pub finished_at: Option<String>,
+    /// Loaded from `match_rounds`; empty unless the query asked for rounds.
+    #[serde(default)]
+    pub rounds: Vec<MatchRound>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Round {
-    pub round_number: i32,
-    pub player1_choice: Option<String>,
-    pub player2_choice: Option<String>,
-    pub winner: Option<String>, // player1_id, player2_id, or "draw"
+/// Status of a match taken back by staff. It stays in match history but no
+/// longer counts toward ratings or stats.
+pub const VOIDED: &str = "voided";
+
+const COLUMNS: &str = "id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after, status, created_at, finished_at";
+
+/// Everything written when a match ends: the match row, both rating
+/// changes with their history entries, and both players' stat counters.
+/// Ratings are not part of it; they are computed from the players' stored
+/// ratings while the result is written.
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    /// Id of the match row. Recording the same id twice stores it once, so
+    /// a retry after an unacknowledged commit is harmless.
+    pub id: String,
+    pub player1_id: String,
+    pub player2_id: String,
+    pub winner_id: Option<String>,
+    pub is_ranked: bool,
+    pub player1_score: i32,
+    pub player2_score: i32,
+    pub rounds: Vec<MatchRound>,
+    pub status: String,
+}
+
+impl MatchResult {
+    /// Both players' ratings after this match, given each player's current
+    /// `(elo, total_games)`. Unranked matches leave ratings as they are.
+    pub fn ratings_after(&self, player1: (i32, i32), player2: (i32, i32)) -> (i32, i32) {
+        if !self.is_ranked {
+            return (player1.0, player2.0);
+        }
+        let outcome = match self.winner_id.as_deref() {
+            Some(winner) if winner == self.player1_id => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        calculate_elo(player1.0, player1.1, player2.0, player2.1, outcome)
+    }
+}
+
+/// What voiding a match takes back from one of its players.
+#[derive(Debug, Clone, Copy)]
+pub struct Reversal<'a> {
+    pub user_id: &'a str,
+    /// `None` for a draw.
+    pub won: Option<bool>,
+    pub elo_change: i32,
 }
 
 impl MatchRecord {
+    /// What voiding the match takes back from each player. Matches that are
+    /// still running or already voided can't be voided.
+    pub fn void_reversal(&self) -> Result<[Reversal<'_>; 2], AppError> {
+        match self.status.as_str() {
+            "in_progress" => return Err(AppError::Conflict("Match hasn't finished".into())),
+            VOIDED => return Err(AppError::Conflict("Match is already voided".into())),
+            _ => {}
+        }
+        let change = |before: Option<i32>, after: Option<i32>| match (before, after) {
+            (Some(before), Some(after)) if self.is_ranked => after - before,
+            _ => 0,
+        };
+        let won = |user_id: &str| self.winner_id.as_deref().map(|winner| winner == user_id);
+        Ok([
+            Reversal {
+                user_id: &self.player1_id,
+                won: won(&self.player1_id),
+                elo_change: change(self.player1_elo_before, self.player1_elo_after),
+            },
+            Reversal {
+                user_id: &self.player2_id,
+                won: won(&self.player2_id),
+                elo_change: change(self.player2_elo_before, self.player2_elo_after),
+            },
+        ])
+    }
+
     fn from_row(row: &Row) -> Result<Self, AppError> {
         Ok(MatchRecord {
             id: row
@@ -57,65 +131,58 @@ impl MatchRecord {
             player2_score: row
                 .get::<i32>(6)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
-            rounds_json: row
-                .get::<String>(7)
-                .map_err(|e| AppError::Internal(e.to_string()))?,
             player1_elo_before: row
-                .get::<Option<i32>>(8)
+                .get::<Option<i32>>(7)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             player1_elo_after: row
-                .get::<Option<i32>>(9)
+                .get::<Option<i32>>(8)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             player2_elo_before: row
-                .get::<Option<i32>>(10)
+                .get::<Option<i32>>(9)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             player2_elo_after: row
-                .get::<Option<i32>>(11)
+                .get::<Option<i32>>(10)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             status: row
-                .get::<String>(12)
+                .get::<String>(11)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             created_at: row
-                .get::<String>(13)
+                .get::<String>(12)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
             finished_at: row
-                .get::<Option<String>>(14)
+                .get::<Option<String>>(13)
                 .map_err(|e| AppError::Internal(e.to_string()))?,
+            rounds: Vec::new(),
         })
     }
 
     pub async fn create(
-        db: &Database,
+        conn: &Connection,
+        id: &str,
         player1_id: &str,
         player2_id: &str,
         is_ranked: bool,
         p1_elo: i32,
         p2_elo: i32,
     ) -> Result<Self, AppError> {
-        let id = Uuid::new_v4().to_string();
-        let conn = db
-            .connect()
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-
         conn.execute(
             "INSERT INTO matches (id, player1_id, player2_id, is_ranked, player1_elo_before, player2_elo_before) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            (id.clone(), player1_id.to_string(), player2_id.to_string(), is_ranked as i32, p1_elo, p2_elo),
+            (id.to_string(), player1_id.to_string(), player2_id.to_string(), is_ranked as i32, p1_elo, p2_elo),
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        Self::find_by_id(db, &id)
+        Self::fetch(conn, id)
             .await?
             .ok_or_else(|| AppError::Internal("Failed to fetch created match".into()))
     }
 
-    pub async fn find_by_id(db: &Database, id: &str) -> Result<Option<Self>, AppError> {
-        let conn = db
-            .connect()
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-
+    async fn fetch(conn: &Connection, id: &str) -> Result<Option<Self>, AppError> {
         let mut rows = conn
-            .query("SELECT * FROM matches WHERE id = ?1", [id])
+            .query(
+                &format!("SELECT {COLUMNS} FROM matches WHERE id = ?1"),
+                [id],
+            )
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
@@ -129,29 +196,139 @@ impl MatchRecord {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn finish(
-        db: &Database,
+        conn: &Connection,
         match_id: &str,
         winner_id: Option<&str>,
         p1_score: i32,
         p2_score: i32,
-        rounds_json: &str,
         p1_elo_after: i32,
         p2_elo_after: i32,
         status: &str,
     ) -> Result<(), AppError> {
+        conn.execute(
+            "UPDATE matches SET winner_id = ?1, player1_score = ?2, player2_score = ?3, player1_elo_after = ?4, player2_elo_after = ?5, status = ?6, finished_at = datetime('now') WHERE id = ?7",
+            (winner_id.map(|s| s.to_string()), p1_score, p2_score, p1_elo_after, p2_elo_after, status.to_string(), match_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Write a finished match and all of its side effects in one transaction,
+    /// so a failure leaves no partial ratings, history or stats behind.
+    /// Ratings are read and updated under the same write lock, so concurrent
+    /// matches of a player cannot overwrite each other's changes. A match
+    /// whose id is already stored is returned as it was recorded.
+    pub async fn record_result(db: &Database, result: &MatchResult) -> Result<Self, AppError> {
         let conn = db
             .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        conn.execute(
-            "UPDATE matches SET winner_id = ?1, player1_score = ?2, player2_score = ?3, rounds_json = ?4, player1_elo_after = ?5, player2_elo_after = ?6, status = ?7, finished_at = datetime('now') WHERE id = ?8",
-            (winner_id.map(|s| s.to_string()), p1_score, p2_score, rounds_json.to_string(), p1_elo_after, p2_elo_after, status.to_string(), match_id.to_string()),
+        if let Some(mut recorded) = Self::fetch(&tx, &result.id).await? {
+            recorded.rounds = MatchRound::for_matches(&tx, &[recorded.id.clone()])
+                .await?
+                .into_iter()
+                .map(|(_, round)| round)
+                .collect();
+            return Ok(recorded);
+        }
+
+        let p1_before = User::rating(&tx, &result.player1_id).await?;
+        let p2_before = User::rating(&tx, &result.player2_id).await?;
+        let (p1_after, p2_after) = result.ratings_after(p1_before, p2_before);
+
+        let created = Self::create(
+            &tx,
+            &result.id,
+            &result.player1_id,
+            &result.player2_id,
+            result.is_ranked,
+            p1_before.0,
+            p2_before.0,
+        )
+        .await?;
+        Self::finish(
+            &tx,
+            &created.id,
+            result.winner_id.as_deref(),
+            result.player1_score,
+            result.player2_score,
+            p1_after,
+            p2_after,
+            &result.status,
+        )
+        .await?;
+
+        for round in &result.rounds {
+            MatchRound::create(&tx, &created.id, round).await?;
+        }
+
+        let players = [
+            (&result.player1_id, p1_before.0, p1_after),
+            (&result.player2_id, p2_before.0, p2_after),
+        ];
+
+        for (user_id, elo_before, elo_after) in players {
+            if result.is_ranked {
+                User::update_elo(&tx, user_id, elo_after).await?;
+                EloHistory::create(&tx, user_id, &created.id, elo_before, elo_after).await?;
+            }
+
+            let won = result.winner_id.as_ref().map(|winner| winner == user_id);
+            User::increment_stats(&tx, user_id, won).await?;
+        }
+
+        let mut recorded = Self::fetch(&tx, &created.id)
+            .await?
+            .ok_or_else(|| AppError::Internal("Failed to fetch recorded match".into()))?;
+        recorded.rounds = result.rounds.clone();
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(recorded)
+    }
+
+    /// Void a finished match in one transaction: both players get back
+    /// their rating change and stat counters, and its rating history goes.
+    pub async fn void(db: &Database, match_id: &str) -> Result<Self, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut record = Self::fetch(&tx, match_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Match not found".into()))?;
+        for player in record.void_reversal()? {
+            User::revert_match(&tx, player.user_id, player.won, player.elo_change).await?;
+        }
+        EloHistory::delete_for_match(&tx, match_id).await?;
+        tx.execute(
+            "UPDATE matches SET status = ?1 WHERE id = ?2",
+            (VOIDED, match_id.to_string()),
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        Ok(())
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        record.status = VOIDED.to_string();
+        Ok(record)
     }
 
     pub async fn recent_for_user(
@@ -161,11 +338,12 @@ impl MatchRecord {
     ) -> Result<Vec<Self>, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query(
-                "SELECT * FROM matches WHERE (player1_id = ?1 OR player2_id = ?1) AND status != 'in_progress' ORDER BY finished_at DESC LIMIT ?2",
+            .query_cached(
+                &format!("SELECT {COLUMNS} FROM matches WHERE (player1_id = ?1 OR player2_id = ?1) AND status != 'in_progress' ORDER BY finished_at DESC LIMIT ?2"),
                 [user_id, &limit.to_string()],
             )
             .await
@@ -180,6 +358,13 @@ impl MatchRecord {
             matches.push(Self::from_row(&row)?);
         }
 
+        let ids: Vec<String> = matches.iter().map(|m| m.id.clone()).collect();
+        for (match_id, round) in MatchRound::for_matches(&conn, &ids).await? {
+            if let Some(m) = matches.iter_mut().find(|m| m.id == match_id) {
+                m.rounds.push(round);
+            }
+        }
+
         Ok(matches)
     }
 }
@@ -188,30 +373,33 @@ impl MatchRecord {
 mod tests {
     use super::*;
     use crate::db::init_test_db;
-    use crate::models::user::User;
-
-    async fn create_test_user(db: &Database, username: &str, email: &str) -> User {
-        User::create(db, username, email, "hash")
-            .await
-            .expect("user should be created")
-    }
+    use crate::models::user::create_test_user;
+    use uuid::Uuid;
 
     #[actix_rt::test]
     async fn create_finish_find_and_recent_for_user_work() {
         let db = init_test_db().await;
         let p1 = create_test_user(&db, "match_p1", "match_p1@example.com").await;
         let p2 = create_test_user(&db, "match_p2", "match_p2@example.com").await;
+        let conn = db.connect().await.expect("connection should be available");
 
-        let completed = MatchRecord::create(&db, &p1.id, &p2.id, true, 1000, 1000)
-            .await
-            .expect("match should be created");
+        let completed = MatchRecord::create(
+            &conn,
+            &Uuid::new_v4().to_string(),
+            &p1.id,
+            &p2.id,
+            true,
+            1000,
+            1000,
+        )
+        .await
+        .expect("match should be created");
         MatchRecord::finish(
-            &db,
+            &conn,
             &completed.id,
             Some(&p1.id),
             2,
             1,
-            r#"[{"round_number":1}]"#,
             1016,
             984,
             "completed",
@@ -219,11 +407,19 @@ mod tests {
         .await
         .expect("match should be finished");
 
-        let _in_progress = MatchRecord::create(&db, &p1.id, &p2.id, false, 1016, 984)
-            .await
-            .expect("second match should be created");
+        let _in_progress = MatchRecord::create(
+            &conn,
+            &Uuid::new_v4().to_string(),
+            &p1.id,
+            &p2.id,
+            false,
+            1016,
+            984,
+        )
+        .await
+        .expect("second match should be created");
 
-        let found = MatchRecord::find_by_id(&db, &completed.id)
+        let found = MatchRecord::fetch(&conn, &completed.id)
             .await
             .expect("query should succeed")
             .expect("match should exist");
@@ -239,4 +435,163 @@ mod tests {
         assert_eq!(recent.len(), 1);
         assert_eq!(recent[0].id, completed.id);
     }
+
+    fn ranked_result(p1: &User, p2: &User) -> MatchResult {
+        MatchResult {
+            id: Uuid::new_v4().to_string(),
+            player1_id: p1.id.clone(),
+            player2_id: p2.id.clone(),
+            winner_id: Some(p1.id.clone()),
+            is_ranked: true,
+            player1_score: 3,
+            player2_score: 1,
+            rounds: Vec::new(),
+            status: "completed".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn record_result_writes_match_ratings_history_and_stats() {
+        let db = init_test_db().await;
+        let p1 = create_test_user(&db, "record_p1", "record_p1@example.com").await;
+        let p2 = create_test_user(&db, "record_p2", "record_p2@example.com").await;
+
+        let recorded = MatchRecord::record_result(&db, &ranked_result(&p1, &p2))
+            .await
+            .expect("result should be recorded");
+        assert_eq!(recorded.status, "completed");
+        assert_eq!(recorded.player1_elo_after, Some(1020));
+        assert!(recorded.finished_at.is_some());
+
+        let winner = User::find_by_id(&db, &p1.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        let loser = User::find_by_id(&db, &p2.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!((winner.elo, winner.wins, winner.total_games), (1020, 1, 1));
+        assert_eq!((loser.elo, loser.losses, loser.total_games), (980, 1, 1));
+
+        let conn = db.connect().await.expect("connection should be available");
+        let mut rows = conn
+            .query(
+                "SELECT COUNT(*) FROM elo_history WHERE match_id = ?1",
+                [recorded.id.as_str()],
+            )
+            .await
+            .expect("count query should succeed");
+        let row = rows
+            .next()
+            .await
+            .expect("row fetch should succeed")
+            .expect("row should exist");
+        let count: i64 = row.get(0).expect("count should be present");
+        assert_eq!(count, 2);
+    }
+
+    #[actix_rt::test]
+    async fn record_result_rates_from_stored_ratings_and_ignores_retries() {
+        let db = init_test_db().await;
+        let p1 = create_test_user(&db, "retry_p1", "retry_p1@example.com").await;
+        let p2 = create_test_user(&db, "retry_p2", "retry_p2@example.com").await;
+        let first = ranked_result(&p1, &p2);
+        let second = ranked_result(&p1, &p2);
+
+        MatchRecord::record_result(&db, &first)
+            .await
+            .expect("first match should be recorded");
+        let retried = MatchRecord::record_result(&db, &first)
+            .await
+            .expect("a retry should succeed");
+        assert_eq!(retried.id, first.id);
+        assert_eq!(retried.player1_elo_after, Some(1020));
+        let next = MatchRecord::record_result(&db, &second)
+            .await
+            .expect("second match should be recorded");
+        assert_eq!(next.player1_elo_before, Some(1020));
+
+        let winner = User::find_by_id(&db, &p1.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!((winner.wins, winner.total_games), (2, 2));
+        assert_eq!(Some(winner.elo), next.player1_elo_after);
+        assert!(winner.elo > 1020);
+        let recent = MatchRecord::recent_for_user(&db, &p1.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert_eq!(recent.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn void_takes_back_one_match_and_keeps_the_rest() {
+        let db = init_test_db().await;
+        let p1 = create_test_user(&db, "void_p1", "void_p1@example.com").await;
+        let p2 = create_test_user(&db, "void_p2", "void_p2@example.com").await;
+        let first = ranked_result(&p1, &p2);
+        let second = ranked_result(&p1, &p2);
+        MatchRecord::record_result(&db, &first)
+            .await
+            .expect("first match should be recorded");
+        let kept = MatchRecord::record_result(&db, &second)
+            .await
+            .expect("second match should be recorded");
+
+        let voided = MatchRecord::void(&db, &first.id)
+            .await
+            .expect("match should be voided");
+        assert_eq!(voided.status, VOIDED);
+        assert!(matches!(
+            MatchRecord::void(&db, &first.id).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            MatchRecord::void(&db, "missing").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        // Only the first match's +20 comes off; the second one's gain stays.
+        let winner = User::find_by_id(&db, &p1.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        let gained = kept.player1_elo_after.unwrap_or_default() - 1020;
+        assert_eq!(winner.elo, 1000 + gained);
+        assert_eq!((winner.wins, winner.total_games), (1, 1));
+        let loser = User::find_by_id(&db, &p2.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!((loser.losses, loser.total_games), (1, 1));
+        let history = EloHistory::for_user(&db, &p1.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].match_id, kept.id);
+    }
+
+    #[actix_rt::test]
+    async fn record_result_rolls_back_when_a_write_fails() {
+        let db = init_test_db().await;
+        let p1 = create_test_user(&db, "rollback_p1", "rollback_p1@example.com").await;
+        let p2 = create_test_user(&db, "rollback_p2", "rollback_p2@example.com").await;
+
+        let mut result = ranked_result(&p1, &p2);
+        result.player2_id = "missing-user".into();
+
+        let outcome = MatchRecord::record_result(&db, &result).await;
+        assert!(outcome.is_err());
+
+        let p1_after = User::find_by_id(&db, &p1.id)
+            .await
+            .expect("query should succeed")
+            .expect("user should exist");
+        assert_eq!((p1_after.elo, p1_after.total_games), (1000, 0));
+        let recent = MatchRecord::recent_for_user(&db, &p1.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert!(recent.is_empty());
+    }
 }
@@ -0,0 +1,272 @@
+use libsql::{Connection, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::errors::AppError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRound {
+    pub round_number: i32,
+    pub player1_choice: Option<String>,
+    pub player2_choice: Option<String>,
+    pub winner_id: Option<String>, // None for a drawn round
+    pub started_at: Option<String>,
+    pub player1_decided_at: Option<String>,
+    pub player2_decided_at: Option<String>,
+}
+
+/// How often a player picked each option across all of their rounds.
+#[derive(Debug, Default, Serialize)]
+pub struct ChoiceStats {
+    pub rock: i64,
+    pub paper: i64,
+    pub scissors: i64,
+    /// Rounds where the player let the timer run out.
+    pub none: i64,
+}
+
+const COLUMNS: &str = "match_id, round_number, player1_choice, player2_choice, winner_id, started_at, player1_decided_at, player2_decided_at";
+
+impl MatchRound {
+    /// Returns the owning match id alongside the round.
+    fn from_row(row: &Row) -> Result<(String, Self), AppError> {
+        let get_optional = |idx: i32| -> Result<Option<String>, AppError> {
+            row.get::<Option<String>>(idx)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        };
+
+        let match_id = row
+            .get::<String>(0)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let round = MatchRound {
+            round_number: row
+                .get::<i32>(1)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            player1_choice: get_optional(2)?,
+            player2_choice: get_optional(3)?,
+            winner_id: get_optional(4)?,
+            started_at: get_optional(5)?,
+            player1_decided_at: get_optional(6)?,
+            player2_decided_at: get_optional(7)?,
+        };
+        Ok((match_id, round))
+    }
+
+    pub async fn create(conn: &Connection, match_id: &str, round: &Self) -> Result<(), AppError> {
+        conn.execute(
+            &format!(
+                "INSERT INTO match_rounds ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+            ),
+            (
+                match_id.to_string(),
+                round.round_number,
+                round.player1_choice.clone(),
+                round.player2_choice.clone(),
+                round.winner_id.clone(),
+                round.started_at.clone(),
+                round.player1_decided_at.clone(),
+                round.player2_decided_at.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Rounds for several matches at once, ordered by match then round number.
+    pub async fn for_matches(
+        conn: &Connection,
+        match_ids: &[String],
+    ) -> Result<Vec<(String, Self)>, AppError> {
+        if match_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = (1..=match_ids.len())
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT {COLUMNS} FROM match_rounds WHERE match_id IN ({placeholders}) ORDER BY match_id, round_number"
+                ),
+                libsql::params_from_iter(match_ids.to_vec()),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rounds = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            rounds.push(Self::from_row(&row)?);
+        }
+
+        Ok(rounds)
+    }
+
+    pub async fn choice_stats_for_user(
+        db: &Database,
+        user_id: &str,
+    ) -> Result<ChoiceStats, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT CASE WHEN m.player1_id = ?1 THEN r.player1_choice ELSE r.player2_choice END AS choice, COUNT(*)
+                 FROM match_rounds r JOIN matches m ON m.id = r.match_id
+                 WHERE m.player1_id = ?1 OR m.player2_id = ?1
+                 GROUP BY choice",
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut stats = ChoiceStats::default();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            let choice = row
+                .get::<Option<String>>(0)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            let count = row
+                .get::<i64>(1)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            match choice.as_deref() {
+                Some("rock") => stats.rock += count,
+                Some("paper") => stats.paper += count,
+                Some("scissors") => stats.scissors += count,
+                _ => stats.none += count,
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        init_pool, init_test_db, run_migrations, DatabaseMode, PoolConfig, MIGRATIONS,
+    };
+    use crate::models::match_record::{MatchRecord, MatchResult};
+    use crate::models::user::create_test_user;
+
+    fn round(number: i32, p1: Option<&str>, p2: Option<&str>, winner: Option<&str>) -> MatchRound {
+        MatchRound {
+            round_number: number,
+            player1_choice: p1.map(Into::into),
+            player2_choice: p2.map(Into::into),
+            winner_id: winner.map(Into::into),
+            started_at: Some("2026-01-01 12:00:00.000".into()),
+            player1_decided_at: p1.map(|_| "2026-01-01 12:00:02.500".into()),
+            player2_decided_at: p2.map(|_| "2026-01-01 12:00:04.000".into()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn recorded_rounds_are_loaded_and_counted() {
+        let db = init_test_db().await;
+        let p1 = create_test_user(&db, "round_p1", "round_p1@example.com").await;
+        let p2 = create_test_user(&db, "round_p2", "round_p2@example.com").await;
+
+        MatchRecord::record_result(
+            &db,
+            &MatchResult {
+                id: uuid::Uuid::new_v4().to_string(),
+                player1_id: p1.id.clone(),
+                player2_id: p2.id.clone(),
+                winner_id: Some(p1.id.clone()),
+                is_ranked: false,
+                player1_score: 3,
+                player2_score: 0,
+                rounds: vec![
+                    round(1, Some("rock"), Some("scissors"), Some(&p1.id)),
+                    round(2, Some("rock"), Some("rock"), None),
+                    round(3, Some("paper"), None, Some(&p1.id)),
+                    round(4, Some("scissors"), Some("paper"), Some(&p1.id)),
+                ],
+                status: "completed".into(),
+            },
+        )
+        .await
+        .expect("result should be recorded");
+
+        let recent = MatchRecord::recent_for_user(&db, &p2.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert_eq!(recent.len(), 1);
+        let rounds = &recent[0].rounds;
+        assert_eq!(
+            rounds.iter().map(|r| r.round_number).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(rounds[1].winner_id.is_none());
+        assert_eq!(
+            rounds[0].player1_decided_at.as_deref(),
+            Some("2026-01-01 12:00:02.500")
+        );
+
+        let p1_stats = MatchRound::choice_stats_for_user(&db, &p1.id)
+            .await
+            .expect("stats query should succeed");
+        assert_eq!(
+            (p1_stats.rock, p1_stats.paper, p1_stats.scissors),
+            (2, 1, 1)
+        );
+
+        let p2_stats = MatchRound::choice_stats_for_user(&db, &p2.id)
+            .await
+            .expect("stats query should succeed");
+        assert_eq!((p2_stats.rock, p2_stats.paper, p2_stats.none), (1, 1, 1));
+    }
+
+    #[actix_rt::test]
+    async fn migration_backfills_rounds_from_rounds_json() {
+        let db = init_pool(&DatabaseMode::Memory, PoolConfig::default())
+            .await
+            .expect("memory database should open");
+        let conn = db.connect().await.expect("connection should be available");
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= 5) {
+            for statement in migration.statements() {
+                conn.execute(&statement, ())
+                    .await
+                    .expect("baseline statement should apply");
+            }
+        }
+
+        conn.execute(
+            "INSERT INTO users (id, username, email) VALUES ('u1', 'one', 'one@example.com'), ('u2', 'two', 'two@example.com')",
+            (),
+        )
+        .await
+        .expect("users should insert");
+        conn.execute(
+            r#"INSERT INTO matches (id, player1_id, player2_id, winner_id, status, rounds_json) VALUES ('m1', 'u1', 'u2', 'u1', 'completed', '[{"round_number":1,"player1_choice":"rock","player2_choice":"rock","winner":"draw"},{"round_number":2,"player1_choice":"paper","player2_choice":null,"winner":"u1"}]')"#,
+            (),
+        )
+        .await
+        .expect("match should insert");
+
+        run_migrations(&db).await.expect("migrations should apply");
+
+        let rounds = MatchRound::for_matches(&conn, &["m1".to_string()])
+            .await
+            .expect("rounds query should succeed");
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].1.player1_choice.as_deref(), Some("rock"));
+        assert!(rounds[0].1.winner_id.is_none());
+        assert_eq!(rounds[1].1.winner_id.as_deref(), Some("u1"));
+        assert!(rounds[1].1.player2_choice.is_none());
+    }
+}
@@ -1,3 +1,11 @@
+pub mod api_token;
 pub mod elo_history;
+pub mod email_token;
+pub mod guest_match;
+pub mod login_session;
 pub mod match_record;
+pub mod match_round;
+pub mod refresh_token;
+pub mod role;
+pub mod two_factor;
 pub mod user;
@@ -0,0 +1,295 @@
+use libsql::TransactionBehavior;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::errors::AppError;
+use crate::models::login_session::{LoginSession, SessionDevice};
+
+/// What happened when a refresh token was presented for rotation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    /// The token was live and has been replaced by the new one.
+    Rotated { user_id: String, session_id: String },
+    /// The token had already been rotated, so someone is replaying it. Its
+    /// whole family, and so its session, has been revoked.
+    Reused { user_id: String, session_id: String },
+    /// Unknown, expired or revoked.
+    Invalid,
+}
+
+/// The login a revoked refresh token belonged to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevokedFamily {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+/// Refresh tokens as stored: only the SHA-256 hash of the token is kept.
+pub struct RefreshToken;
+
+impl RefreshToken {
+    /// Store the first token of a new family, i.e. a fresh login, along
+    /// with the session the family belongs to.
+    pub async fn create(
+        db: &Database,
+        user_id: &str,
+        device: &SessionDevice,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<LoginSession, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let session = LoginSession {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            user_agent: device.user_agent.clone(),
+            ip_hash: device.ip_hash.clone(),
+            created_at: now.clone(),
+            last_seen_at: now,
+            expires_at: expires_at.to_string(),
+            revoked_at: None,
+        };
+        tx.execute(
+            "INSERT INTO login_sessions (id, user_id, user_agent, ip_hash, created_at, last_seen_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                session.id.clone(),
+                session.user_id.clone(),
+                session.user_agent.clone(),
+                session.ip_hash.clone(),
+                session.created_at.clone(),
+                session.last_seen_at.clone(),
+                session.expires_at.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                Uuid::new_v4().to_string(),
+                user_id.to_string(),
+                session.id.clone(),
+                token_hash.to_string(),
+                expires_at.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(session)
+    }
+
+    /// Swap a live token for a new one in the same family. Presenting a token
+    /// that was already rotated revokes every token in its family.
+    pub async fn rotate(
+        db: &Database,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: &str,
+    ) -> Result<RefreshOutcome, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = tx
+            .query(
+                "SELECT user_id, family_id, expires_at > datetime('now'), rotated_at IS NOT NULL, revoked_at IS NOT NULL FROM refresh_tokens WHERE token_hash = ?1",
+                [token_hash],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+        let user_id = row
+            .get::<String>(0)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let family_id = row
+            .get::<String>(1)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let live = row
+            .get::<i32>(2)
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            != 0;
+        let rotated = row
+            .get::<i32>(3)
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            != 0;
+        let revoked = row
+            .get::<i32>(4)
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            != 0;
+        drop(rows);
+
+        if revoked {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        if rotated {
+            tx.execute(
+                "UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE family_id = ?1 AND revoked_at IS NULL",
+                [family_id.clone()],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+            tx.execute(
+                "UPDATE login_sessions SET revoked_at = datetime('now') WHERE id = ?1 AND revoked_at IS NULL",
+                [family_id.clone()],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+            tx.commit()
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            return Ok(RefreshOutcome::Reused {
+                user_id,
+                session_id: family_id,
+            });
+        }
+
+        if !live {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        tx.execute(
+            "UPDATE refresh_tokens SET rotated_at = datetime('now') WHERE token_hash = ?1",
+            [token_hash],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                Uuid::new_v4().to_string(),
+                user_id.clone(),
+                family_id.clone(),
+                new_token_hash.to_string(),
+                new_expires_at.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "UPDATE login_sessions SET last_seen_at = datetime('now'), expires_at = ?1 WHERE id = ?2",
+            [new_expires_at, family_id.as_str()],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(RefreshOutcome::Rotated {
+            user_id,
+            session_id: family_id,
+        })
+    }
+
+    /// Revoke the family the token belongs to, ending that one login.
+    /// Returns `None` for unknown tokens.
+    pub async fn revoke_family(
+        db: &Database,
+        token_hash: &str,
+    ) -> Result<Option<RevokedFamily>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = tx
+            .query(
+                "SELECT user_id, family_id FROM refresh_tokens WHERE token_hash = ?1",
+                [token_hash],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        else {
+            return Ok(None);
+        };
+        let family = RevokedFamily {
+            user_id: row
+                .get::<String>(0)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            session_id: row
+                .get::<String>(1)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        };
+        drop(rows);
+
+        tx.execute(
+            "UPDATE login_sessions SET revoked_at = datetime('now') WHERE revoked_at IS NULL AND id = ?1",
+            [family.session_id.as_str()],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE revoked_at IS NULL AND family_id = ?1",
+            [family.session_id.as_str()],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(Some(family))
+    }
+
+    /// Revoke every session and refresh token the user holds.
+    pub async fn revoke_all_for_user(db: &Database, user_id: &str) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        tx.execute(
+            "UPDATE login_sessions SET revoked_at = datetime('now') WHERE user_id = ?1 AND revoked_at IS NULL",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE user_id = ?1 AND revoked_at IS NULL",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+/// Something staff can do through the admin API. Routes check these rather
+/// than roles, see `auth::permissions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    StatsView,
+    UsersView,
+    UsersRename,
+    UsersEditStats,
+    UsersBan,
+    UsersDelete,
+    RolesAssign,
+    SystemView,
+    DataExport,
+    MatchesVoid,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 10] = [
+        Self::StatsView,
+        Self::UsersView,
+        Self::UsersRename,
+        Self::UsersEditStats,
+        Self::UsersBan,
+        Self::UsersDelete,
+        Self::RolesAssign,
+        Self::SystemView,
+        Self::DataExport,
+        Self::MatchesVoid,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StatsView => "stats.view",
+            Self::UsersView => "users.view",
+            Self::UsersRename => "users.rename",
+            Self::UsersEditStats => "users.edit_stats",
+            Self::UsersBan => "users.ban",
+            Self::UsersDelete => "users.delete",
+            Self::RolesAssign => "roles.assign",
+            Self::SystemView => "system.view",
+            Self::DataExport => "data.export",
+            Self::MatchesVoid => "matches.void",
+        }
+    }
+}
+
+/// A user's role, stored as text in `users.role`. Roles are ordered by
+/// rank: staff can only manage users ranked below them.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Player,
+    Support,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Player => "player",
+            Self::Support => "support",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "player" => Some(Self::Player),
+            "support" => Some(Self::Support),
+            "moderator" => Some(Self::Moderator),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn permissions(self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Self::Player => &[],
+            Self::Support => &[StatsView, UsersView],
+            Self::Moderator => &[StatsView, UsersView, UsersRename, UsersBan, MatchesVoid],
+            Self::Admin => &Permission::ALL,
+        }
+    }
+
+    pub fn has(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    /// Any role with permissions.
+    pub fn is_staff(self) -> bool {
+        self != Self::Player
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_round_trip_and_grant_increasing_permissions() {
+        for role in [Role::Player, Role::Support, Role::Moderator, Role::Admin] {
+            assert_eq!(Role::parse(role.as_str()), Some(role));
+        }
+        assert_eq!(Role::parse("owner"), None);
+
+        assert!(!Role::Player.is_staff());
+        assert!(Role::Moderator.has(Permission::UsersBan));
+        assert!(Role::Moderator.has(Permission::MatchesVoid));
+        assert!(!Role::Support.has(Permission::MatchesVoid));
+        assert!(!Role::Moderator.has(Permission::UsersEditStats));
+        assert!(!Role::Support.has(Permission::UsersBan));
+        assert!(Permission::ALL.iter().all(|&p| Role::Admin.has(p)));
+
+        let ranks = [Role::Player, Role::Support, Role::Moderator, Role::Admin];
+        for pair in ranks.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].permissions().iter().all(|&p| pair[1].has(p)));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use libsql::TransactionBehavior;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::errors::AppError;
+
+/// TOTP enrolment state on `users` plus the user's recovery codes.
+pub struct TwoFactor;
+
+impl TwoFactor {
+    pub async fn set_pending_secret(
+        db: &Database,
+        user_id: &str,
+        secret: &str,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET totp_secret = ?1, totp_enabled = 0, totp_last_step = NULL, updated_at = datetime('now') WHERE id = ?2",
+            (secret.to_string(), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Turn 2FA on with a fresh set of recovery codes, bumping the token
+    /// version so that sessions started without the second factor end.
+    pub async fn enable(
+        db: &Database,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        tx.execute(
+            "UPDATE users SET totp_enabled = 1, totp_last_step = ?1, token_version = token_version + 1, updated_at = datetime('now') WHERE id = ?2",
+            (step, user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        for hash in recovery_code_hashes {
+            tx.execute(
+                "INSERT INTO recovery_codes (id, user_id, code_hash) VALUES (?1, ?2, ?3)",
+                (
+                    Uuid::new_v4().to_string(),
+                    user_id.to_string(),
+                    hash.clone(),
+                ),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn disable(db: &Database, user_id: &str) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        tx.execute(
+            "UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = NULL, updated_at = datetime('now') WHERE id = ?1",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Record `step` as used. False if it, or a later step, already was.
+    pub async fn accept_step(db: &Database, user_id: &str, step: i64) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let updated = conn
+            .execute_cached(
+                "UPDATE users SET totp_last_step = ?1 WHERE id = ?2 AND (totp_last_step IS NULL OR totp_last_step < ?1)",
+                (step, user_id.to_string()),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(updated == 1)
+    }
+
+    pub async fn use_recovery_code(
+        db: &Database,
+        user_id: &str,
+        code_hash: &str,
+    ) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let updated = conn
+            .execute_cached(
+                "UPDATE recovery_codes SET used_at = datetime('now') WHERE user_id = ?1 AND code_hash = ?2 AND used_at IS NULL",
+                (user_id.to_string(), code_hash.to_string()),
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(updated == 1)
+    }
+
+    pub async fn recovery_codes_left(db: &Database, user_id: &str) -> Result<i64, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1 AND used_at IS NULL",
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            Some(row) => row
+                .get::<i64>(0)
+                .map_err(|e| AppError::Internal(e.to_string())),
+            None => Ok(0),
+        }
+    }
+}
@@ -1,19 +1,24 @@
-use libsql::Row;
+use libsql::{Connection, Row, TransactionBehavior};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::db::Database;
 use crate::errors::AppError;
+use crate::models::role::Role;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub username: String,
     pub email: String,
+    pub email_verified: bool,
     #[serde(skip_serializing)]
     pub password_hash: Option<String>,
     #[serde(skip_serializing)]
     pub avatar_url: Option<String>,
+    /// Subject of the linked OIDC identity, see `auth::oidc`.
+    #[serde(skip_serializing)]
+    pub google_id: Option<String>,
     pub elo: i32,
     pub total_games: i32,
     pub wins: i32,
@@ -21,11 +26,36 @@ pub struct User {
     pub draws: i32,
     pub created_at: String,
     pub updated_at: String,
-    pub is_admin: bool,
+    /// What the user may do through the admin API, see `models::role`.
+    #[serde(default)]
+    pub role: Role,
     pub is_banned: bool,
     pub banned_at: Option<String>,
     pub banned_reason: Option<String>,
     pub is_ai: bool,
+    /// Must match the `ver` claim of an access token for it to be accepted.
+    #[serde(skip_serializing, default)]
+    pub token_version: i32,
+    /// Base32 TOTP secret, set from 2FA setup onwards.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    /// Whether sign-in requires a second factor, see `auth::totp`.
+    #[serde(default)]
+    pub totp_enabled: bool,
+    /// New address waiting for its verification link, see `api::user`.
+    #[serde(skip_serializing)]
+    pub pending_email: Option<String>,
+    /// When the user last renamed themselves; renames have a cooldown.
+    #[serde(skip_serializing)]
+    pub username_changed_at: Option<String>,
+    /// Whether others may watch the user's matches. A match can be watched
+    /// only when both players allow it.
+    #[serde(default = "allowed")]
+    pub allow_spectators: bool,
+}
+
+fn allowed() -> bool {
+    true
 }
 
 #[derive(Debug, Serialize)]
@@ -38,7 +68,15 @@ pub struct PublicUser {
     pub wins: i32,
     pub losses: i32,
     pub draws: i32,
-    pub is_admin: bool,
+    pub role: Role,
+}
+
+/// A rename recorded in `username_history`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsernameChange {
+    pub old_username: String,
+    pub new_username: String,
+    pub changed_at: String,
 }
 
 impl From<User> for PublicUser {
@@ -52,7 +90,7 @@ impl From<User> for PublicUser {
             wins: u.wins,
             losses: u.losses,
             draws: u.draws,
-            is_admin: u.is_admin,
+            role: u.role,
         }
     }
 }
@@ -97,8 +135,10 @@ impl User {
             id: get_required("id")?,
             username: get_required("username")?,
             email: get_required("email")?,
+            email_verified: get_bool("email_verified", false)?,
             password_hash: get_optional("password_hash")?,
             avatar_url: get_optional("avatar_url")?,
+            google_id: get_optional("google_id")?,
             elo: get_i32("elo", 1000)?,
             total_games: get_i32("total_games", 0)?,
             wins: get_i32("wins", 0)?,
@@ -106,11 +146,20 @@ impl User {
             draws: get_i32("draws", 0)?,
             created_at: get_required("created_at")?,
             updated_at: get_required("updated_at")?,
-            is_admin: get_bool("is_admin", false)?,
+            // Unknown roles get no permissions.
+            role: get_optional("role")?
+                .and_then(|role| Role::parse(&role))
+                .unwrap_or_default(),
             is_banned: get_bool("is_banned", false)?,
             banned_at: get_optional("banned_at")?,
             banned_reason: get_optional("banned_reason")?,
             is_ai: get_bool("is_ai", false)?,
+            token_version: get_i32("token_version", 0)?,
+            totp_secret: get_optional("totp_secret")?,
+            totp_enabled: get_bool("totp_enabled", false)?,
+            pending_email: get_optional("pending_email")?,
+            username_changed_at: get_optional("username_changed_at")?,
+            allow_spectators: get_bool("allow_spectators", true)?,
         })
     }
 
@@ -123,9 +172,10 @@ impl User {
         let id = Uuid::new_v4().to_string();
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        conn.execute(
+        conn.execute_cached(
             "INSERT INTO users (id, username, email, password_hash) VALUES (?1, ?2, ?3, ?4)",
             (
                 id.clone(),
@@ -153,13 +203,308 @@ impl User {
             .ok_or_else(|| AppError::Internal("Failed to fetch created user".into()))
     }
 
+    /// Create an account for a single sign-on user, who has no password. The
+    /// provider has already verified the email.
+    pub async fn create_external(
+        db: &Database,
+        username: &str,
+        email: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<Self, AppError> {
+        let id = Uuid::new_v4().to_string();
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "INSERT INTO users (id, username, email, google_id, avatar_url, email_verified) VALUES (?1, ?2, ?3, ?4, ?5, 1)",
+            (
+                id.clone(),
+                username.to_string(),
+                email.to_string(),
+                google_id.to_string(),
+                avatar_url.map(str::to_string),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            let err_str = e.to_string();
+            if err_str.contains("UNIQUE") {
+                if err_str.contains("username") {
+                    AppError::Conflict("Username already taken".into())
+                } else {
+                    AppError::Conflict("Email already registered".into())
+                }
+            } else {
+                AppError::Internal(err_str)
+            }
+        })?;
+
+        Self::find_by_id(db, &id)
+            .await?
+            .ok_or_else(|| AppError::Internal("Failed to fetch created user".into()))
+    }
+
+    pub async fn find_by_google_id(
+        db: &Database,
+        google_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached("SELECT * FROM users WHERE google_id = ?1", [google_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Attach an external identity to an existing account, which also
+    /// verifies its email. An avatar the user already has is kept.
+    pub async fn link_google_id(
+        db: &Database,
+        user_id: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET google_id = ?1, avatar_url = COALESCE(avatar_url, ?2), email_verified = 1, updated_at = datetime('now') WHERE id = ?3",
+            (
+                google_id.to_string(),
+                avatar_url.map(str::to_string),
+                user_id.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Replace the password hash. Bumping the token version signs the user out
+    /// of every access token issued with the old password.
+    pub async fn set_password(
+        db: &Database,
+        user_id: &str,
+        password_hash: &str,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET password_hash = ?1, token_version = token_version + 1, updated_at = datetime('now') WHERE id = ?2",
+            (password_hash.to_string(), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn mark_email_verified(db: &Database, user_id: &str) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET email_verified = 1, updated_at = datetime('now') WHERE id = ?1",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn set_pending_email(
+        db: &Database,
+        user_id: &str,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET pending_email = ?1, updated_at = datetime('now') WHERE id = ?2",
+            (email.map(str::to_string), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn confirm_email_change(
+        db: &Database,
+        user_id: &str,
+        email: &str,
+    ) -> Result<bool, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let changed = conn
+            .execute_cached(
+                "UPDATE users SET email = pending_email, pending_email = NULL, email_verified = 1, updated_at = datetime('now') WHERE id = ?1 AND pending_email = ?2",
+                (user_id.to_string(), email.to_string()),
+            )
+            .await
+            .map_err(|e| {
+                let err_str = e.to_string();
+                if err_str.contains("UNIQUE") {
+                    AppError::Conflict("Email already registered".into())
+                } else {
+                    AppError::Internal(err_str)
+                }
+            })?;
+
+        Ok(changed > 0)
+    }
+
+    pub async fn rename(db: &Database, user_id: &str, username: &str) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        tx.execute(
+            "INSERT INTO username_history (id, user_id, old_username, new_username) SELECT ?1, id, username, ?2 FROM users WHERE id = ?3",
+            (
+                Uuid::new_v4().to_string(),
+                username.to_string(),
+                user_id.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        tx.execute(
+            "UPDATE users SET username = ?1, username_changed_at = datetime('now'), updated_at = datetime('now') WHERE id = ?2",
+            (username.to_string(), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| {
+            let err_str = e.to_string();
+            if err_str.contains("UNIQUE") {
+                AppError::Conflict("Username already taken".into())
+            } else {
+                AppError::Internal(err_str)
+            }
+        })?;
+        tx.commit()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn username_history(
+        db: &Database,
+        user_id: &str,
+    ) -> Result<Vec<UsernameChange>, AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT old_username, new_username, changed_at FROM username_history WHERE user_id = ?1 ORDER BY changed_at DESC, rowid DESC",
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut changes = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            changes.push(UsernameChange {
+                old_username: row.get(0).map_err(|e| AppError::Internal(e.to_string()))?,
+                new_username: row.get(1).map_err(|e| AppError::Internal(e.to_string()))?,
+                changed_at: row.get(2).map_err(|e| AppError::Internal(e.to_string()))?,
+            });
+        }
+
+        Ok(changes)
+    }
+
+    pub async fn set_avatar(
+        db: &Database,
+        user_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET avatar_url = ?1, updated_at = datetime('now') WHERE id = ?2",
+            (avatar_url.map(str::to_string), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn set_allow_spectators(
+        db: &Database,
+        user_id: &str,
+        allow: bool,
+    ) -> Result<(), AppError> {
+        let conn = db
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "UPDATE users SET allow_spectators = ?1, updated_at = datetime('now') WHERE id = ?2",
+            (allow as i32, user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
+    }
+
     pub async fn find_by_id(db: &Database, id: &str) -> Result<Option<Self>, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query("SELECT * FROM users WHERE id = ?1", [id])
+            .query_cached("SELECT * FROM users WHERE id = ?1", [id])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
@@ -176,10 +521,11 @@ impl User {
     pub async fn find_by_email(db: &Database, email: &str) -> Result<Option<Self>, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query("SELECT * FROM users WHERE email = ?1", [email])
+            .query_cached("SELECT * FROM users WHERE email = ?1", [email])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
@@ -193,11 +539,59 @@ impl User {
         }
     }
 
-    pub async fn update_elo(db: &Database, user_id: &str, new_elo: i32) -> Result<(), AppError> {
+    pub async fn find_by_username(db: &Database, username: &str) -> Result<Option<Self>, AppError> {
         let conn = db
             .connect()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let mut rows = conn
+            .query_cached(
+                "SELECT * FROM users WHERE lower(username) = lower(?1) LIMIT 1",
+                [username],
+            )
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
+        match rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The user's `(elo, total_games)`, which is what a rating change is
+    /// computed from.
+    pub async fn rating(conn: &Connection, user_id: &str) -> Result<(i32, i32), AppError> {
+        let mut rows = conn
+            .query(
+                "SELECT elo, total_games FROM users WHERE id = ?1",
+                [user_id],
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let row = rows
+            .next()
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            .ok_or_else(|| AppError::NotFound(format!("User {user_id} not found")))?;
+
+        Ok((
+            row.get::<i32>(0)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            row.get::<i32>(1)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        ))
+    }
+
+    pub async fn update_elo(
+        conn: &Connection,
+        user_id: &str,
+        new_elo: i32,
+    ) -> Result<(), AppError> {
         conn.execute(
             "UPDATE users SET elo = ?1, updated_at = datetime('now') WHERE id = ?2",
             (new_elo, user_id.to_string()),
@@ -208,15 +602,38 @@ impl User {
         Ok(())
     }
 
-    pub async fn increment_stats(
-        db: &Database,
+    /// Undo what a match did to the user: its rating change and the stat
+    /// counters `increment_stats` bumped.
+    pub async fn revert_match(
+        conn: &Connection,
         user_id: &str,
         won: Option<bool>,
+        elo_change: i32,
     ) -> Result<(), AppError> {
-        let conn = db
-            .connect()
+        let (wins, losses, draws) = match won {
+            Some(true) => (1, 0, 0),
+            Some(false) => (0, 1, 0),
+            None => (0, 0, 1),
+        };
+        let updated = conn
+            .execute(
+                "UPDATE users SET elo = elo - ?2, total_games = MAX(total_games - 1, 0), wins = MAX(wins - ?3, 0), losses = MAX(losses - ?4, 0), draws = MAX(draws - ?5, 0), updated_at = datetime('now') WHERE id = ?1",
+                (user_id.to_string(), elo_change, wins, losses, draws),
+            )
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("User {user_id} not found")));
+        }
+
+        Ok(())
+    }
 
+    pub async fn increment_stats(
+        conn: &Connection,
+        user_id: &str,
+        won: Option<bool>,
+    ) -> Result<(), AppError> {
         let query = match won {
             Some(true) => {
                 "UPDATE users SET total_games = total_games + 1, wins = wins + 1, updated_at = datetime('now') WHERE id = ?1"
@@ -229,9 +646,13 @@ impl User {
             }
         };
 
-        conn.execute(query, [user_id])
+        let updated = conn
+            .execute(query, [user_id])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("User {user_id} not found")));
+        }
 
         Ok(())
     }
@@ -239,10 +660,11 @@ impl User {
     pub async fn top_by_elo(db: &Database, limit: i32) -> Result<Vec<Self>, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query("SELECT * FROM users ORDER BY elo DESC LIMIT ?1", [limit])
+            .query_cached("SELECT * FROM users ORDER BY elo DESC LIMIT ?1", [limit])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
@@ -261,22 +683,61 @@ impl User {
     pub async fn delete(db: &Database, user_id: &str) -> Result<(), AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         // Delete related records first (cascade)
-        conn.execute("DELETE FROM elo_history WHERE user_id = ?1", [user_id])
+        conn.execute_cached("DELETE FROM refresh_tokens WHERE user_id = ?1", [user_id])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        conn.execute(
+        conn.execute_cached("DELETE FROM login_sessions WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached("DELETE FROM email_tokens WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached("DELETE FROM api_tokens WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached("DELETE FROM username_history WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached("DELETE FROM elo_history WHERE user_id = ?1", [user_id])
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
+            "DELETE FROM match_rounds WHERE match_id IN (SELECT id FROM matches WHERE player1_id = ?1 OR player2_id = ?1)",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        conn.execute_cached(
             "DELETE FROM matches WHERE player1_id = ?1 OR player2_id = ?1",
             [user_id],
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
+        conn.execute_cached(
+            "DELETE FROM guest_matches WHERE player1_id = ?1 OR player2_id = ?1",
+            [user_id],
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
         // Finally delete the user
-        conn.execute("DELETE FROM users WHERE id = ?1", [user_id])
+        conn.execute_cached("DELETE FROM users WHERE id = ?1", [user_id])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
@@ -284,27 +745,20 @@ impl User {
     }
 
     // Admin methods
-    pub async fn is_admin(db: &Database, user_id: &str) -> Result<bool, AppError> {
+    pub async fn set_role(db: &Database, user_id: &str, role: Role) -> Result<(), AppError> {
         let conn = db
             .connect()
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-
-        let mut rows = conn
-            .query("SELECT is_admin FROM users WHERE id = ?1", [user_id])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        match rows
-            .next()
-            .await
-            .map_err(|e| AppError::Internal(e.to_string()))?
-        {
-            Some(row) => {
-                let is_admin: i32 = row.get(0).map_err(|e| AppError::Internal(e.to_string()))?;
-                Ok(is_admin != 0)
-            }
-            None => Ok(false),
-        }
+        conn.execute_cached(
+            "UPDATE users SET role = ?1, updated_at = datetime('now') WHERE id = ?2",
+            (role.as_str(), user_id.to_string()),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(())
     }
 
     pub async fn list_with_filters(
@@ -316,6 +770,7 @@ impl User {
     ) -> Result<Vec<Self>, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut query = String::from("SELECT * FROM users WHERE 1=1");
@@ -347,11 +802,11 @@ impl User {
         params.push(offset.to_string());
 
         let mut rows = if params.len() == 2 {
-            conn.query(&query, [params[0].as_str(), params[1].as_str()])
+            conn.query_cached(&query, [params[0].as_str(), params[1].as_str()])
                 .await
                 .map_err(|e| AppError::Internal(e.to_string()))?
         } else {
-            conn.query(
+            conn.query_cached(
                 &query,
                 [params[0].as_str(), params[1].as_str(), params[2].as_str()],
             )
@@ -374,6 +829,7 @@ impl User {
     pub async fn count_all(db: &Database, search: Option<&str>) -> Result<i64, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut query = String::from("SELECT COUNT(*) FROM users WHERE 1=1");
@@ -383,16 +839,16 @@ impl User {
                 query.push_str(
                     " AND (username LIKE '%' || ?1 || '%' OR email LIKE '%' || ?1 || '%')",
                 );
-                conn.query(&query, [s])
+                conn.query_cached(&query, [s])
                     .await
                     .map_err(|e| AppError::Internal(e.to_string()))?
             } else {
-                conn.query(&query, [] as [&str; 0])
+                conn.query_cached(&query, [] as [&str; 0])
                     .await
                     .map_err(|e| AppError::Internal(e.to_string()))?
             }
         } else {
-            conn.query(&query, [] as [&str; 0])
+            conn.query_cached(&query, [] as [&str; 0])
                 .await
                 .map_err(|e| AppError::Internal(e.to_string()))?
         };
@@ -413,10 +869,11 @@ impl User {
     pub async fn ban(db: &Database, user_id: &str, reason: &str) -> Result<(), AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        conn.execute(
-            "UPDATE users SET is_banned = 1, banned_at = datetime('now'), banned_reason = ?1, updated_at = datetime('now') WHERE id = ?2",
+        conn.execute_cached(
+            "UPDATE users SET is_banned = 1, banned_at = datetime('now'), banned_reason = ?1, token_version = token_version + 1, updated_at = datetime('now') WHERE id = ?2",
             (reason.to_string(), user_id.to_string()),
         )
         .await
@@ -428,9 +885,10 @@ impl User {
     pub async fn unban(db: &Database, user_id: &str) -> Result<(), AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        conn.execute(
+        conn.execute_cached(
             "UPDATE users SET is_banned = 0, banned_at = NULL, banned_reason = NULL, updated_at = datetime('now') WHERE id = ?1",
             [user_id],
         )
@@ -451,6 +909,7 @@ impl User {
     ) -> Result<(), AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut query = String::from("UPDATE users SET ");
@@ -491,12 +950,12 @@ impl User {
 
         // Execute with appropriate number of parameters
         let param_refs: Vec<&str> = params.iter().map(|s| s.as_str()).collect();
-        conn.execute(&query, param_refs)
+        conn.execute_cached(&query, param_refs)
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         if should_refresh_total_games {
-            conn.execute(
+            conn.execute_cached(
                 "UPDATE users SET total_games = wins + losses + draws, updated_at = datetime('now') WHERE id = ?1",
                 [user_id],
             )
@@ -510,10 +969,11 @@ impl User {
     pub async fn get_platform_stats(db: &Database) -> Result<PlatformStats, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query("SELECT COUNT(*) FROM users", [] as [&str; 0])
+            .query_cached("SELECT COUNT(*) FROM users", [] as [&str; 0])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
         let total_users: i64 = rows
@@ -524,7 +984,7 @@ impl User {
             .get(0)
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
-        let mut rows = conn.query(
+        let mut rows = conn.query_cached(
             "SELECT COUNT(DISTINCT user_id) FROM elo_history WHERE created_at > datetime('now', '-30 days')",
             [] as [&str; 0],
         ).await.map_err(|e| AppError::Internal(e.to_string()))?;
@@ -537,7 +997,7 @@ impl User {
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query("SELECT COUNT(*) FROM matches", [] as [&str; 0])
+            .query_cached("SELECT COUNT(*) FROM matches", [] as [&str; 0])
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
         let total_matches: i64 = rows
@@ -549,7 +1009,7 @@ impl User {
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         let mut rows = conn
-            .query(
+            .query_cached(
                 "SELECT COUNT(*) FROM users WHERE is_banned = 1",
                 [] as [&str; 0],
             )
@@ -574,9 +1034,10 @@ impl User {
     pub async fn get_random_ai(db: &Database) -> Result<Self, AppError> {
         let conn = db
             .connect()
+            .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
         let mut rows = conn
-            .query(
+            .query_cached(
                 "SELECT * FROM users WHERE is_ai = 1 ORDER BY RANDOM() LIMIT 1",
                 [] as [&str; 0],
             )
@@ -602,18 +1063,19 @@ pub struct PlatformStats {
     pub banned_users: i64,
 }
 
+#[cfg(test)]
+pub async fn create_test_user(db: &Database, username: &str, email: &str) -> User {
+    User::create(db, username, email, "hashed-password")
+        .await
+        .expect("user should be created")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db::init_test_db;
     use crate::errors::AppError;
 
-    async fn create_test_user(db: &Database, username: &str, email: &str) -> User {
-        User::create(db, username, email, "hashed-password")
-            .await
-            .expect("user should be created")
-    }
-
     #[actix_rt::test]
     async fn create_and_find_user_with_defaults() {
         let db = init_test_db().await;
@@ -627,12 +1089,17 @@ mod tests {
             .await
             .expect("query should succeed")
             .expect("user should exist");
+        let by_username = User::find_by_username(&db, "ALICE_Test")
+            .await
+            .expect("query should succeed")
+            .expect("usernames should match regardless of case");
 
         assert_eq!(by_id.id, created.id);
         assert_eq!(by_email.username, "alice_test");
+        assert_eq!(by_username.id, created.id);
         assert_eq!(by_id.elo, 1000);
         assert_eq!(by_id.total_games, 0);
-        assert!(!by_id.is_admin);
+        assert_eq!(by_id.role, Role::Player);
         assert!(!by_id.is_ai);
     }
 
@@ -644,6 +1111,8 @@ mod tests {
         let duplicate_username =
             User::create(&db, "duplicate_name", "second@example.com", "hash").await;
         assert!(matches!(duplicate_username, Err(AppError::Conflict(_))));
+        let recased = User::create(&db, "Duplicate_Name", "third@example.com", "hash").await;
+        assert!(matches!(recased, Err(AppError::Conflict(msg)) if msg.contains("Username")));
 
         let duplicate_email = User::create(&db, "other_name", "first@example.com", "hash").await;
         assert!(matches!(duplicate_email, Err(AppError::Conflict(_))));
@@ -653,17 +1122,18 @@ mod tests {
     async fn update_elo_and_stats_paths_work() {
         let db = init_test_db().await;
         let user = create_test_user(&db, "stats_user", "stats@example.com").await;
+        let conn = db.connect().await.expect("connection should be available");
 
-        User::update_elo(&db, &user.id, 1450)
+        User::update_elo(&conn, &user.id, 1450)
             .await
             .expect("elo update should succeed");
-        User::increment_stats(&db, &user.id, Some(true))
+        User::increment_stats(&conn, &user.id, Some(true))
             .await
             .expect("win increment should succeed");
-        User::increment_stats(&db, &user.id, Some(false))
+        User::increment_stats(&conn, &user.id, Some(false))
             .await
             .expect("loss increment should succeed");
-        User::increment_stats(&db, &user.id, None)
+        User::increment_stats(&conn, &user.id, None)
             .await
             .expect("draw increment should succeed");
 
@@ -698,20 +1168,18 @@ mod tests {
         let admin = create_test_user(&db, "admin_user", "admin@example.com").await;
         let target = create_test_user(&db, "target_user", "target@example.com").await;
 
-        let conn = db.connect().expect("connection should be available");
-        conn.execute(
-            "UPDATE users SET is_admin = 1 WHERE id = ?1",
-            [admin.id.as_str()],
-        )
-        .await
-        .expect("admin update should succeed");
-
-        assert!(User::is_admin(&db, &admin.id)
-            .await
-            .expect("admin check should succeed"));
-        assert!(!User::is_admin(&db, &target.id)
+        let conn = db.connect().await.expect("connection should be available");
+        User::set_role(&db, &admin.id, Role::Admin)
             .await
-            .expect("admin check should succeed"));
+            .expect("role update should succeed");
+
+        for (user, role) in [(&admin, Role::Admin), (&target, Role::Player)] {
+            let stored = User::find_by_id(&db, &user.id)
+                .await
+                .expect("lookup should succeed")
+                .expect("user should exist");
+            assert_eq!(stored.role, role);
+        }
 
         User::ban(&db, &target.id, "test reason")
             .await
@@ -733,10 +1201,10 @@ mod tests {
         assert!(!unbanned.is_banned);
         assert!(unbanned.banned_reason.is_none());
 
-        User::update_elo(&db, &admin.id, 2000)
+        User::update_elo(&conn, &admin.id, 2000)
             .await
             .expect("elo update should succeed");
-        User::update_elo(&db, &target.id, 1800)
+        User::update_elo(&conn, &target.id, 1800)
             .await
             .expect("elo update should succeed");
 
@@ -0,0 +1,1524 @@
+use async_trait::async_trait;
+use libsql::{params_from_iter, Connection, Row, TransactionBehavior, Value};
+
+use super::{
+    ApiTokenRepository, ConnectionPool, EmailTokenRepository, MatchRepository,
+    RatingHistoryRepository, RefreshTokenRepository, SessionRepository, SnapshotRepository,
+    TwoFactorRepository, UserRepository,
+};
+use crate::db::{Database, PoolMetrics};
+use crate::errors::AppError;
+use crate::models::api_token::{ApiScope, ApiToken};
+use crate::models::elo_history::EloHistory;
+use crate::models::email_token::{EmailToken, EmailTokenPurpose};
+use crate::models::guest_match::GuestMatch;
+use crate::models::login_session::{LoginSession, SessionDevice};
+use crate::models::match_record::{MatchRecord, MatchResult};
+use crate::models::match_round::{ChoiceStats, MatchRound};
+use crate::models::refresh_token::{RefreshOutcome, RefreshToken, RevokedFamily};
+use crate::models::role::Role;
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::{PlatformStats, User, UsernameChange};
+use crate::snapshot::{
+    GuestMatchRow, HistoryRow, MatchRow, RecoveryCodeRow, RoundRow, Snapshot, UserRow,
+    UsernameChangeRow,
+};
+
+/// Repositories backed by the libSQL model layer.
+pub struct LibsqlStore {
+    db: Database,
+}
+
+impl LibsqlStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserRepository for LibsqlStore {
+    async fn create(
+        &self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<User, AppError> {
+        User::create(&self.db, username, email, password_hash).await
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, AppError> {
+        User::find_by_id(&self.db, id).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        User::find_by_email(&self.db, email).await
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        User::find_by_username(&self.db, username).await
+    }
+
+    async fn create_external(
+        &self,
+        username: &str,
+        email: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<User, AppError> {
+        User::create_external(&self.db, username, email, google_id, avatar_url).await
+    }
+
+    async fn find_by_google_id(&self, google_id: &str) -> Result<Option<User>, AppError> {
+        User::find_by_google_id(&self.db, google_id).await
+    }
+
+    async fn link_google_id(
+        &self,
+        user_id: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError> {
+        User::link_google_id(&self.db, user_id, google_id, avatar_url).await
+    }
+
+    async fn set_password(&self, user_id: &str, password_hash: &str) -> Result<(), AppError> {
+        User::set_password(&self.db, user_id, password_hash).await
+    }
+
+    async fn mark_email_verified(&self, user_id: &str) -> Result<(), AppError> {
+        User::mark_email_verified(&self.db, user_id).await
+    }
+
+    async fn set_pending_email(&self, user_id: &str, email: Option<&str>) -> Result<(), AppError> {
+        User::set_pending_email(&self.db, user_id, email).await
+    }
+
+    async fn confirm_email_change(&self, user_id: &str, email: &str) -> Result<bool, AppError> {
+        User::confirm_email_change(&self.db, user_id, email).await
+    }
+
+    async fn rename(&self, user_id: &str, username: &str) -> Result<(), AppError> {
+        User::rename(&self.db, user_id, username).await
+    }
+
+    async fn username_history(&self, user_id: &str) -> Result<Vec<UsernameChange>, AppError> {
+        User::username_history(&self.db, user_id).await
+    }
+
+    async fn set_avatar(&self, user_id: &str, avatar_url: Option<&str>) -> Result<(), AppError> {
+        User::set_avatar(&self.db, user_id, avatar_url).await
+    }
+
+    async fn set_allow_spectators(&self, user_id: &str, allow: bool) -> Result<(), AppError> {
+        User::set_allow_spectators(&self.db, user_id, allow).await
+    }
+
+    async fn top_by_elo(&self, limit: i32) -> Result<Vec<User>, AppError> {
+        User::top_by_elo(&self.db, limit).await
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AppError> {
+        User::delete(&self.db, user_id).await
+    }
+
+    async fn set_role(&self, user_id: &str, role: Role) -> Result<(), AppError> {
+        User::set_role(&self.db, user_id, role).await
+    }
+
+    async fn list_with_filters(
+        &self,
+        search: Option<&str>,
+        sort_by: Option<&str>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, AppError> {
+        User::list_with_filters(&self.db, search, sort_by, offset, limit).await
+    }
+
+    async fn count_all(&self, search: Option<&str>) -> Result<i64, AppError> {
+        User::count_all(&self.db, search).await
+    }
+
+    async fn ban(&self, user_id: &str, reason: &str) -> Result<(), AppError> {
+        User::ban(&self.db, user_id, reason).await
+    }
+
+    async fn unban(&self, user_id: &str) -> Result<(), AppError> {
+        User::unban(&self.db, user_id).await
+    }
+
+    async fn update_stats(
+        &self,
+        user_id: &str,
+        username: Option<&str>,
+        elo: Option<i32>,
+        wins: Option<i32>,
+        losses: Option<i32>,
+        draws: Option<i32>,
+    ) -> Result<(), AppError> {
+        User::update_stats(&self.db, user_id, username, elo, wins, losses, draws).await
+    }
+
+    async fn platform_stats(&self) -> Result<PlatformStats, AppError> {
+        User::get_platform_stats(&self.db).await
+    }
+
+    async fn random_ai(&self) -> Result<User, AppError> {
+        User::get_random_ai(&self.db).await
+    }
+}
+
+#[async_trait]
+impl MatchRepository for LibsqlStore {
+    async fn record_result(&self, result: &MatchResult) -> Result<MatchRecord, AppError> {
+        MatchRecord::record_result(&self.db, result).await
+    }
+
+    async fn recent_for_user(
+        &self,
+        user_id: &str,
+        limit: i32,
+    ) -> Result<Vec<MatchRecord>, AppError> {
+        MatchRecord::recent_for_user(&self.db, user_id, limit).await
+    }
+
+    async fn choice_stats_for_user(&self, user_id: &str) -> Result<ChoiceStats, AppError> {
+        MatchRound::choice_stats_for_user(&self.db, user_id).await
+    }
+
+    async fn void_match(&self, match_id: &str) -> Result<MatchRecord, AppError> {
+        MatchRecord::void(&self.db, match_id).await
+    }
+
+    async fn record_guest_match(&self, result: &MatchResult) -> Result<(), AppError> {
+        GuestMatch::record(&self.db, result).await
+    }
+
+    async fn claim_guest_matches(&self, guest_id: &str, user_id: &str) -> Result<u64, AppError> {
+        GuestMatch::claim(&self.db, guest_id, user_id).await
+    }
+}
+
+#[async_trait]
+impl RatingHistoryRepository for LibsqlStore {
+    async fn for_user(&self, user_id: &str, limit: i32) -> Result<Vec<EloHistory>, AppError> {
+        EloHistory::for_user(&self.db, user_id, limit).await
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for LibsqlStore {
+    async fn start_family(
+        &self,
+        user_id: &str,
+        device: &SessionDevice,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<LoginSession, AppError> {
+        RefreshToken::create(&self.db, user_id, device, token_hash, expires_at).await
+    }
+
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: &str,
+    ) -> Result<RefreshOutcome, AppError> {
+        RefreshToken::rotate(&self.db, token_hash, new_token_hash, new_expires_at).await
+    }
+
+    async fn revoke_family(&self, token_hash: &str) -> Result<Option<RevokedFamily>, AppError> {
+        RefreshToken::revoke_family(&self.db, token_hash).await
+    }
+
+    async fn revoke_all_for_user(&self, user_id: &str) -> Result<(), AppError> {
+        RefreshToken::revoke_all_for_user(&self.db, user_id).await
+    }
+}
+
+#[async_trait]
+impl SessionRepository for LibsqlStore {
+    async fn find_session(&self, id: &str) -> Result<Option<LoginSession>, AppError> {
+        LoginSession::find(&self.db, id).await
+    }
+
+    async fn list_sessions(&self, user_id: &str) -> Result<Vec<LoginSession>, AppError> {
+        LoginSession::list_active(&self.db, user_id).await
+    }
+
+    async fn touch_session(&self, id: &str) -> Result<(), AppError> {
+        LoginSession::touch(&self.db, id).await
+    }
+
+    async fn revoke_session(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        LoginSession::revoke(&self.db, user_id, id).await
+    }
+}
+
+#[async_trait]
+impl EmailTokenRepository for LibsqlStore {
+    async fn issue(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        EmailToken::create(&self.db, id, user_id, purpose, expires_at).await
+    }
+
+    async fn consume(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+    ) -> Result<bool, AppError> {
+        EmailToken::consume(&self.db, id, user_id, purpose).await
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for LibsqlStore {
+    async fn set_pending_secret(&self, user_id: &str, secret: &str) -> Result<(), AppError> {
+        TwoFactor::set_pending_secret(&self.db, user_id, secret).await
+    }
+
+    async fn enable(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        TwoFactor::enable(&self.db, user_id, step, recovery_code_hashes).await
+    }
+
+    async fn disable(&self, user_id: &str) -> Result<(), AppError> {
+        TwoFactor::disable(&self.db, user_id).await
+    }
+
+    async fn accept_step(&self, user_id: &str, step: i64) -> Result<bool, AppError> {
+        TwoFactor::accept_step(&self.db, user_id, step).await
+    }
+
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str) -> Result<bool, AppError> {
+        TwoFactor::use_recovery_code(&self.db, user_id, code_hash).await
+    }
+
+    async fn recovery_codes_left(&self, user_id: &str) -> Result<i64, AppError> {
+        TwoFactor::recovery_codes_left(&self.db, user_id).await
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for LibsqlStore {
+    async fn create_token(
+        &self,
+        user_id: &str,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        expires_at: Option<&str>,
+    ) -> Result<ApiToken, AppError> {
+        ApiToken::create(&self.db, user_id, name, token_hash, scopes, expires_at).await
+    }
+
+    async fn list_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>, AppError> {
+        ApiToken::list_for_user(&self.db, user_id).await
+    }
+
+    async fn revoke_token(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        ApiToken::revoke(&self.db, user_id, id).await
+    }
+
+    async fn use_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        ApiToken::use_token(&self.db, token_hash).await
+    }
+}
+
+impl ConnectionPool for LibsqlStore {
+    fn pool_metrics(&self) -> PoolMetrics {
+        self.db.pool_metrics()
+    }
+}
+
+const SNAPSHOT_USER_COLUMNS: &str = "id, username, email, password_hash, google_id, avatar_url, elo, total_games, wins, losses, draws, role, is_banned, banned_at, banned_reason, is_ai, created_at, updated_at, email_verified, totp_secret, totp_enabled, totp_last_step, token_version, pending_email, username_changed_at, allow_spectators";
+const SNAPSHOT_MATCH_COLUMNS: &str = "id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after, status, created_at, finished_at";
+const SNAPSHOT_ROUND_COLUMNS: &str = "match_id, round_number, player1_choice, player2_choice, winner_id, started_at, player1_decided_at, player2_decided_at";
+const SNAPSHOT_HISTORY_COLUMNS: &str =
+    "id, user_id, match_id, elo_before, elo_after, elo_change, created_at";
+const SNAPSHOT_USERNAME_COLUMNS: &str = "id, user_id, old_username, new_username, changed_at";
+const SNAPSHOT_RECOVERY_CODE_COLUMNS: &str = "id, user_id, code_hash, used_at, created_at";
+const SNAPSHOT_GUEST_MATCH_COLUMNS: &str =
+    "id, player1_id, player2_id, winner_id, player1_score, player2_score, status, finished_at";
+
+fn internal(e: libsql::Error) -> AppError {
+    AppError::Internal(e.to_string())
+}
+
+async fn select_all<T>(
+    conn: &Connection,
+    sql: &str,
+    from_row: fn(&Row) -> libsql::Result<T>,
+) -> Result<Vec<T>, AppError> {
+    let mut rows = conn.query(sql, ()).await.map_err(internal)?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next().await.map_err(internal)? {
+        out.push(from_row(&row).map_err(internal)?);
+    }
+    Ok(out)
+}
+
+async fn insert_all<T>(
+    conn: &Connection,
+    table: &str,
+    columns: &str,
+    rows: &[T],
+    values: fn(&T) -> Vec<Value>,
+) -> Result<(), AppError> {
+    let count = columns.split(", ").count();
+    let placeholders: Vec<String> = (1..=count).map(|i| format!("?{i}")).collect();
+    let sql = format!(
+        "INSERT INTO {table} ({columns}) VALUES ({})",
+        placeholders.join(", ")
+    );
+    for row in rows {
+        conn.execute(&sql, params_from_iter(values(row)))
+            .await
+            .map_err(internal)?;
+    }
+    Ok(())
+}
+
+fn user_row(row: &Row) -> libsql::Result<UserRow> {
+    Ok(UserRow {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        email: row.get(2)?,
+        password_hash: row.get(3)?,
+        google_id: row.get(4)?,
+        avatar_url: row.get(5)?,
+        elo: row.get(6)?,
+        total_games: row.get(7)?,
+        wins: row.get(8)?,
+        losses: row.get(9)?,
+        draws: row.get(10)?,
+        role: Role::parse(&row.get::<String>(11)?).unwrap_or_default(),
+        is_banned: row.get::<i64>(12)? != 0,
+        banned_at: row.get(13)?,
+        banned_reason: row.get(14)?,
+        is_ai: row.get::<i64>(15)? != 0,
+        created_at: row.get(16)?,
+        updated_at: row.get(17)?,
+        email_verified: row.get::<i64>(18)? != 0,
+        totp_secret: row.get(19)?,
+        totp_enabled: row.get::<i64>(20)? != 0,
+        totp_last_step: row.get(21)?,
+        token_version: row.get(22)?,
+        pending_email: row.get(23)?,
+        username_changed_at: row.get(24)?,
+        allow_spectators: row.get::<i64>(25)? != 0,
+        sealed_totp_secret: None,
+        plain_totp_secret: None,
+        is_admin: false,
+    })
+}
+
+fn match_row(row: &Row) -> libsql::Result<MatchRow> {
+    Ok(MatchRow {
+        id: row.get(0)?,
+        player1_id: row.get(1)?,
+        player2_id: row.get(2)?,
+        winner_id: row.get(3)?,
+        is_ranked: row.get::<i64>(4)? != 0,
+        player1_score: row.get(5)?,
+        player2_score: row.get(6)?,
+        player1_elo_before: row.get(7)?,
+        player1_elo_after: row.get(8)?,
+        player2_elo_before: row.get(9)?,
+        player2_elo_after: row.get(10)?,
+        status: row.get(11)?,
+        created_at: row.get(12)?,
+        finished_at: row.get(13)?,
+    })
+}
+
+fn round_row(row: &Row) -> libsql::Result<RoundRow> {
+    Ok(RoundRow {
+        match_id: row.get(0)?,
+        round_number: row.get(1)?,
+        player1_choice: row.get(2)?,
+        player2_choice: row.get(3)?,
+        winner_id: row.get(4)?,
+        started_at: row.get(5)?,
+        player1_decided_at: row.get(6)?,
+        player2_decided_at: row.get(7)?,
+    })
+}
+
+fn history_row(row: &Row) -> libsql::Result<HistoryRow> {
+    Ok(HistoryRow {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        match_id: row.get(2)?,
+        elo_before: row.get(3)?,
+        elo_after: row.get(4)?,
+        elo_change: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn username_change_row(row: &Row) -> libsql::Result<UsernameChangeRow> {
+    Ok(UsernameChangeRow {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        old_username: row.get(2)?,
+        new_username: row.get(3)?,
+        changed_at: row.get(4)?,
+    })
+}
+
+fn recovery_code_row(row: &Row) -> libsql::Result<RecoveryCodeRow> {
+    Ok(RecoveryCodeRow {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        code_hash: row.get(2)?,
+        used_at: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn guest_match_row(row: &Row) -> libsql::Result<GuestMatchRow> {
+    Ok(GuestMatchRow {
+        id: row.get(0)?,
+        player1_id: row.get(1)?,
+        player2_id: row.get(2)?,
+        winner_id: row.get(3)?,
+        player1_score: row.get(4)?,
+        player2_score: row.get(5)?,
+        status: row.get(6)?,
+        finished_at: row.get(7)?,
+    })
+}
+
+fn user_values(u: &UserRow) -> Vec<Value> {
+    vec![
+        u.id.clone().into(),
+        u.username.clone().into(),
+        u.email.clone().into(),
+        u.password_hash.clone().into(),
+        u.google_id.clone().into(),
+        u.avatar_url.clone().into(),
+        u.elo.into(),
+        u.total_games.into(),
+        u.wins.into(),
+        u.losses.into(),
+        u.draws.into(),
+        u.role.as_str().into(),
+        u.is_banned.into(),
+        u.banned_at.clone().into(),
+        u.banned_reason.clone().into(),
+        u.is_ai.into(),
+        u.created_at.clone().into(),
+        u.updated_at.clone().into(),
+        u.email_verified.into(),
+        u.totp_secret.clone().into(),
+        u.totp_enabled.into(),
+        u.totp_last_step.into(),
+        u.token_version.into(),
+        u.pending_email.clone().into(),
+        u.username_changed_at.clone().into(),
+        u.allow_spectators.into(),
+    ]
+}
+
+fn username_change_values(c: &UsernameChangeRow) -> Vec<Value> {
+    vec![
+        c.id.clone().into(),
+        c.user_id.clone().into(),
+        c.old_username.clone().into(),
+        c.new_username.clone().into(),
+        c.changed_at.clone().into(),
+    ]
+}
+
+fn recovery_code_values(c: &RecoveryCodeRow) -> Vec<Value> {
+    vec![
+        c.id.clone().into(),
+        c.user_id.clone().into(),
+        c.code_hash.clone().into(),
+        c.used_at.clone().into(),
+        c.created_at.clone().into(),
+    ]
+}
+
+fn guest_match_values(m: &GuestMatchRow) -> Vec<Value> {
+    vec![
+        m.id.clone().into(),
+        m.player1_id.clone().into(),
+        m.player2_id.clone().into(),
+        m.winner_id.clone().into(),
+        m.player1_score.into(),
+        m.player2_score.into(),
+        m.status.clone().into(),
+        m.finished_at.clone().into(),
+    ]
+}
+
+fn match_values(m: &MatchRow) -> Vec<Value> {
+    vec![
+        m.id.clone().into(),
+        m.player1_id.clone().into(),
+        m.player2_id.clone().into(),
+        m.winner_id.clone().into(),
+        m.is_ranked.into(),
+        m.player1_score.into(),
+        m.player2_score.into(),
+        m.player1_elo_before.into(),
+        m.player1_elo_after.into(),
+        m.player2_elo_before.into(),
+        m.player2_elo_after.into(),
+        m.status.clone().into(),
+        m.created_at.clone().into(),
+        m.finished_at.clone().into(),
+    ]
+}
+
+fn round_values(r: &RoundRow) -> Vec<Value> {
+    vec![
+        r.match_id.clone().into(),
+        r.round_number.into(),
+        r.player1_choice.clone().into(),
+        r.player2_choice.clone().into(),
+        r.winner_id.clone().into(),
+        r.started_at.clone().into(),
+        r.player1_decided_at.clone().into(),
+        r.player2_decided_at.clone().into(),
+    ]
+}
+
+fn history_values(h: &HistoryRow) -> Vec<Value> {
+    vec![
+        h.id.clone().into(),
+        h.user_id.clone().into(),
+        h.match_id.clone().into(),
+        h.elo_before.into(),
+        h.elo_after.into(),
+        h.elo_change.into(),
+        h.created_at.clone().into(),
+    ]
+}
+
+#[async_trait]
+impl SnapshotRepository for LibsqlStore {
+    async fn export(&self) -> Result<Snapshot, AppError> {
+        let conn = self.db.connect().await.map_err(internal)?;
+        // A read transaction keeps every table at the same point in time.
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::ReadOnly)
+            .await
+            .map_err(internal)?;
+
+        let snapshot = Snapshot {
+            created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            users: select_all(
+                &tx,
+                &format!("SELECT {SNAPSHOT_USER_COLUMNS} FROM users ORDER BY id"),
+                user_row,
+            )
+            .await?,
+            username_history: select_all(
+                &tx,
+                &format!("SELECT {SNAPSHOT_USERNAME_COLUMNS} FROM username_history ORDER BY id"),
+                username_change_row,
+            )
+            .await?,
+            recovery_codes: select_all(
+                &tx,
+                &format!("SELECT {SNAPSHOT_RECOVERY_CODE_COLUMNS} FROM recovery_codes ORDER BY id"),
+                recovery_code_row,
+            )
+            .await?,
+            matches: select_all(
+                &tx,
+                &format!("SELECT {SNAPSHOT_MATCH_COLUMNS} FROM matches ORDER BY id"),
+                match_row,
+            )
+            .await?,
+            rounds: select_all(
+                &tx,
+                &format!(
+                    "SELECT {SNAPSHOT_ROUND_COLUMNS} FROM match_rounds ORDER BY match_id, round_number"
+                ),
+                round_row,
+            )
+            .await?,
+            history: select_all(
+                &tx,
+                &format!(
+                    "SELECT {SNAPSHOT_HISTORY_COLUMNS} FROM elo_history ORDER BY id"
+                ),
+                history_row,
+            )
+            .await?,
+            guest_matches: select_all(
+                &tx,
+                &format!("SELECT {SNAPSHOT_GUEST_MATCH_COLUMNS} FROM guest_matches ORDER BY id"),
+                guest_match_row,
+            )
+            .await?,
+        };
+
+        tx.commit().await.map_err(internal)?;
+        Ok(snapshot)
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<(), AppError> {
+        snapshot.validate().map_err(AppError::BadRequest)?;
+
+        let conn = self.db.connect().await.map_err(internal)?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(internal)?;
+
+        let mut rows = tx
+            .query(
+                "SELECT (SELECT COUNT(*) FROM users WHERE is_ai = 0)
+                      + (SELECT COUNT(*) FROM matches)
+                      + (SELECT COUNT(*) FROM elo_history)
+                      + (SELECT COUNT(*) FROM guest_matches)",
+                (),
+            )
+            .await
+            .map_err(internal)?;
+        let existing: i64 = match rows.next().await.map_err(internal)? {
+            Some(row) => row.get(0).map_err(internal)?,
+            None => 0,
+        };
+        if existing > 0 {
+            return Err(AppError::Conflict(
+                "Snapshots can only be restored into an empty database".into(),
+            ));
+        }
+
+        tx.execute("DELETE FROM users", ())
+            .await
+            .map_err(internal)?;
+        insert_all(
+            &tx,
+            "users",
+            SNAPSHOT_USER_COLUMNS,
+            &snapshot.users,
+            user_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "username_history",
+            SNAPSHOT_USERNAME_COLUMNS,
+            &snapshot.username_history,
+            username_change_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "recovery_codes",
+            SNAPSHOT_RECOVERY_CODE_COLUMNS,
+            &snapshot.recovery_codes,
+            recovery_code_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "matches",
+            SNAPSHOT_MATCH_COLUMNS,
+            &snapshot.matches,
+            match_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "match_rounds",
+            SNAPSHOT_ROUND_COLUMNS,
+            &snapshot.rounds,
+            round_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "elo_history",
+            SNAPSHOT_HISTORY_COLUMNS,
+            &snapshot.history,
+            history_values,
+        )
+        .await?;
+        insert_all(
+            &tx,
+            "guest_matches",
+            SNAPSHOT_GUEST_MATCH_COLUMNS,
+            &snapshot.guest_matches,
+            guest_match_values,
+        )
+        .await?;
+
+        tx.commit().await.map_err(internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::init_test_db;
+
+    #[actix_rt::test]
+    async fn snapshot_round_trips_into_an_empty_database() {
+        let source = LibsqlStore::new(init_test_db().await);
+        let p1 = source
+            .create("snap_p1", "snap_p1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = source
+            .create("snap_p2", "snap_p2@example.com", "hash")
+            .await
+            .expect("user should be created");
+        source
+            .record_result(&MatchResult {
+                id: uuid::Uuid::new_v4().to_string(),
+                player1_id: p1.id.clone(),
+                player2_id: p2.id.clone(),
+                winner_id: Some(p1.id.clone()),
+                is_ranked: true,
+                player1_score: 1,
+                player2_score: 0,
+                rounds: vec![MatchRound {
+                    round_number: 1,
+                    player1_choice: Some("paper".into()),
+                    player2_choice: Some("rock".into()),
+                    winner_id: Some(p1.id.clone()),
+                    started_at: Some("2026-01-01 12:00:00.000".into()),
+                    player1_decided_at: Some("2026-01-01 12:00:01.500".into()),
+                    player2_decided_at: None,
+                }],
+                status: "completed".into(),
+            })
+            .await
+            .expect("result should be recorded");
+        source
+            .ban(&p2.id, "spam")
+            .await
+            .expect("ban should succeed");
+        source
+            .rename(&p2.id, "snap_p2_renamed")
+            .await
+            .expect("rename should succeed");
+        source
+            .set_allow_spectators(&p2.id, false)
+            .await
+            .expect("spectator setting should be stored");
+        source
+            .set_pending_secret(&p1.id, "JBSWY3DPEHPK3PXP")
+            .await
+            .expect("secret should be stored");
+        source
+            .enable(&p1.id, 42, &["code-1".into(), "code-2".into()])
+            .await
+            .expect("2FA should be enabled");
+        source
+            .record_guest_match(&MatchResult {
+                id: uuid::Uuid::new_v4().to_string(),
+                player1_id: p1.id.clone(),
+                player2_id: "guest_snap".into(),
+                winner_id: None,
+                is_ranked: false,
+                player1_score: 1,
+                player2_score: 1,
+                rounds: Vec::new(),
+                status: "completed".into(),
+            })
+            .await
+            .expect("guest match should be recorded");
+
+        let exported = source.export().await.expect("export should succeed");
+        assert_eq!(exported.users.len(), 102);
+        assert_eq!(
+            (
+                exported.matches.len(),
+                exported.rounds.len(),
+                exported.history.len()
+            ),
+            (1, 1, 2)
+        );
+        assert_eq!(
+            (
+                exported.username_history.len(),
+                exported.recovery_codes.len(),
+                exported.guest_matches.len()
+            ),
+            (1, 2, 1)
+        );
+
+        let target = LibsqlStore::new(init_test_db().await);
+        target
+            .restore(&exported)
+            .await
+            .expect("restore into a fresh database should succeed");
+
+        let mut reexported = target.export().await.expect("export should succeed");
+        reexported.created_at = exported.created_at.clone();
+        assert_eq!(reexported, exported);
+
+        let restored = target
+            .find_by_id(&p2.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should be restored");
+        assert!(restored.is_banned);
+        assert_eq!(restored.password_hash.as_deref(), Some("hash"));
+        assert_eq!(
+            target
+                .recovery_codes_left(&p1.id)
+                .await
+                .expect("codes should count"),
+            2
+        );
+        assert_eq!(
+            target
+                .username_history(&p2.id)
+                .await
+                .expect("history should load")[0]
+                .old_username,
+            "snap_p2"
+        );
+
+        let again = target.restore(&exported).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+    }
+
+    #[actix_rt::test]
+    async fn refresh_tokens_rotate_and_reuse_revokes_the_family() {
+        let store = LibsqlStore::new(init_test_db().await);
+
+        let user = store
+            .create("refresher", "refresher@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let session = store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "hash-1",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "stale",
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+
+        assert_eq!(
+            store
+                .rotate("hash-1", "hash-2", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Rotated {
+                user_id: user.id.clone(),
+                session_id: session.id.clone(),
+            }
+        );
+        assert_eq!(
+            store
+                .rotate("stale", "never", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+        assert_eq!(
+            store
+                .rotate("hash-1", "hash-3", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Reused {
+                user_id: user.id.clone(),
+                session_id: session.id.clone(),
+            }
+        );
+        let reused = store
+            .find_session(&session.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("session should exist");
+        assert!(!reused.is_active());
+        assert_eq!(
+            store
+                .rotate("hash-2", "hash-4", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "hash-5",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn login_sessions_list_touch_and_revoke() {
+        let store = LibsqlStore::new(init_test_db().await);
+
+        let user = store
+            .create("sessions", "sessions@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let other = store
+            .create("other_sessions", "other_sessions@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let device = SessionDevice {
+            user_agent: Some("Firefox".into()),
+            ip_hash: Some("abc".into()),
+        };
+        let phone = store
+            .start_family(&user.id, &device, "phone-1", "2999-01-01 00:00:00")
+            .await
+            .expect("family should start");
+        assert_eq!(phone.user_agent.as_deref(), Some("Firefox"));
+        let laptop = store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "laptop-1",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "old-1",
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+
+        let found = store
+            .find_session(&phone.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("session should exist");
+        assert_eq!(found, phone);
+        store
+            .touch_session(&phone.id)
+            .await
+            .expect("touch should succeed");
+        let listed = store
+            .list_sessions(&user.id)
+            .await
+            .expect("sessions should list");
+        let ids: Vec<&str> = listed.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids.len(), 2, "expired sessions are not listed");
+        assert!(ids.contains(&phone.id.as_str()) && ids.contains(&laptop.id.as_str()));
+
+        assert!(!store
+            .revoke_session(&other.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .revoke_session(&user.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(!store
+            .revoke_session(&user.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert_eq!(
+            store
+                .rotate("phone-1", "phone-2", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+
+        store
+            .revoke_family("laptop-1")
+            .await
+            .expect("revoke should succeed");
+        assert!(store
+            .list_sessions(&user.id)
+            .await
+            .expect("sessions should list")
+            .is_empty());
+        store.delete(&user.id).await.expect("delete should succeed");
+        assert!(store
+            .find_session(&laptop.id)
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+    }
+
+    #[actix_rt::test]
+    async fn external_identities_can_be_created_and_linked() {
+        let store = LibsqlStore::new(init_test_db().await);
+
+        let external = store
+            .create_external(
+                "sso_user",
+                "sso@example.com",
+                "sub-1",
+                Some("https://a/p.png"),
+            )
+            .await
+            .expect("external user should be created");
+        assert!(external.password_hash.is_none());
+        assert!(external.email_verified);
+        assert_eq!(external.google_id.as_deref(), Some("sub-1"));
+        let found = store
+            .find_by_google_id("sub-1")
+            .await
+            .expect("lookup should succeed")
+            .expect("identity should be found");
+        assert_eq!(found.id, external.id);
+        assert_eq!(found.avatar_url.as_deref(), Some("https://a/p.png"));
+
+        let local = store
+            .create("local_user", "local@example.com", "hash")
+            .await
+            .expect("user should be created");
+        store
+            .link_google_id(&local.id, "corp:sub-2", Some("https://a/q.png"))
+            .await
+            .expect("link should succeed");
+        let linked = store
+            .find_by_google_id("corp:sub-2")
+            .await
+            .expect("lookup should succeed")
+            .expect("identity should be linked");
+        assert_eq!(linked.id, local.id);
+        assert_eq!(linked.password_hash.as_deref(), Some("hash"));
+        assert_eq!(linked.avatar_url.as_deref(), Some("https://a/q.png"));
+        assert!(linked.email_verified);
+    }
+
+    #[actix_rt::test]
+    async fn email_tokens_are_single_use_and_scoped() {
+        let store = LibsqlStore::new(init_test_db().await);
+
+        let user = store
+            .create("mailer", "mailer@example.com", "hash")
+            .await
+            .expect("user should be created");
+        assert!(!user.email_verified);
+        store
+            .issue(
+                "jti-1",
+                &user.id,
+                EmailTokenPurpose::VerifyEmail,
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("token should be issued");
+        store
+            .issue(
+                "jti-2",
+                &user.id,
+                EmailTokenPurpose::ResetPassword,
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("token should be issued");
+
+        assert!(!store
+            .consume("jti-1", &user.id, EmailTokenPurpose::ResetPassword)
+            .await
+            .expect("consume should succeed"));
+        assert!(!store
+            .consume("jti-1", "someone-else", EmailTokenPurpose::VerifyEmail)
+            .await
+            .expect("consume should succeed"));
+        assert!(store
+            .consume("jti-1", &user.id, EmailTokenPurpose::VerifyEmail)
+            .await
+            .expect("consume should succeed"));
+        assert!(!store
+            .consume("jti-1", &user.id, EmailTokenPurpose::VerifyEmail)
+            .await
+            .expect("consume should succeed"));
+        assert!(!store
+            .consume("jti-2", &user.id, EmailTokenPurpose::ResetPassword)
+            .await
+            .expect("consume should succeed"));
+
+        store
+            .mark_email_verified(&user.id)
+            .await
+            .expect("verify should succeed");
+        store
+            .set_password(&user.id, "new-hash")
+            .await
+            .expect("password should be set");
+        let updated = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(updated.email_verified);
+        assert_eq!(updated.password_hash.as_deref(), Some("new-hash"));
+        assert_eq!(updated.token_version, user.token_version + 1);
+
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn two_factor_steps_and_recovery_codes_are_single_use() {
+        let store = LibsqlStore::new(init_test_db().await);
+
+        let user = store
+            .create("totp_user", "totp_user@example.com", "hash")
+            .await
+            .expect("user should be created");
+        store
+            .set_pending_secret(&user.id, "JBSWY3DPEHPK3PXP")
+            .await
+            .expect("secret should be stored");
+        let pending = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(pending.totp_secret.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        assert!(!pending.totp_enabled);
+
+        store
+            .enable(&user.id, 100, &["code-a".to_string(), "code-b".to_string()])
+            .await
+            .expect("2FA should be enabled");
+        let enabled = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(enabled.totp_enabled);
+        assert_eq!(enabled.token_version, user.token_version + 1);
+
+        assert!(!store
+            .accept_step(&user.id, 100)
+            .await
+            .expect("step check should succeed"));
+        assert!(store
+            .accept_step(&user.id, 101)
+            .await
+            .expect("step check should succeed"));
+        assert!(!store
+            .accept_step(&user.id, 101)
+            .await
+            .expect("step check should succeed"));
+
+        assert!(store
+            .use_recovery_code(&user.id, "code-a")
+            .await
+            .expect("recovery code check should succeed"));
+        assert!(!store
+            .use_recovery_code(&user.id, "code-a")
+            .await
+            .expect("recovery code check should succeed"));
+        assert!(!store
+            .use_recovery_code("someone-else", "code-b")
+            .await
+            .expect("recovery code check should succeed"));
+        assert_eq!(
+            store
+                .recovery_codes_left(&user.id)
+                .await
+                .expect("count should succeed"),
+            1
+        );
+
+        store
+            .disable(&user.id)
+            .await
+            .expect("2FA should be disabled");
+        let disabled = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(!disabled.totp_enabled);
+        assert!(disabled.totp_secret.is_none());
+        assert_eq!(
+            store
+                .recovery_codes_left(&user.id)
+                .await
+                .expect("count should succeed"),
+            0
+        );
+
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn account_changes_keep_name_history_and_confirm_pending_emails() {
+        let store = LibsqlStore::new(init_test_db().await);
+        let user = store
+            .create("old_name", "old_name@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let other = store
+            .create("other_name", "other_name@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        store
+            .rename(&user.id, "new_name")
+            .await
+            .expect("rename should succeed");
+        assert!(matches!(
+            store.rename(&user.id, "other_name").await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            store.rename(&user.id, "Other_Name").await,
+            Err(AppError::Conflict(_))
+        ));
+        let history = store
+            .username_history(&user.id)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].old_username, "old_name");
+        assert_eq!(history[0].new_username, "new_name");
+
+        store
+            .set_pending_email(&user.id, Some("other_name@example.com"))
+            .await
+            .expect("pending email should be stored");
+        assert!(matches!(
+            store
+                .confirm_email_change(&user.id, "other_name@example.com")
+                .await,
+            Err(AppError::Conflict(_))
+        ));
+        store
+            .set_pending_email(&user.id, Some("moved@example.com"))
+            .await
+            .expect("pending email should be stored");
+        assert!(!store
+            .confirm_email_change(&user.id, "stale@example.com")
+            .await
+            .expect("confirm should run"));
+        assert!(store
+            .confirm_email_change(&user.id, "moved@example.com")
+            .await
+            .expect("confirm should run"));
+        store
+            .set_avatar(&user.id, Some("https://example.com/a.png"))
+            .await
+            .expect("avatar should be set");
+        store
+            .set_allow_spectators(&user.id, false)
+            .await
+            .expect("spectator setting should be stored");
+
+        let updated = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(updated.username, "new_name");
+        assert!(updated.username_changed_at.is_some());
+        assert_eq!(updated.email, "moved@example.com");
+        assert_eq!(updated.pending_email, None);
+        assert!(updated.email_verified);
+        assert_eq!(
+            updated.avatar_url.as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert!(!updated.allow_spectators);
+
+        store.delete(&user.id).await.expect("delete should succeed");
+        store
+            .delete(&other.id)
+            .await
+            .expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn api_tokens_are_listed_used_and_revoked_until_they_expire() {
+        let store = LibsqlStore::new(init_test_db().await);
+        let user = store
+            .create("token_owner", "token_owner@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let live = store
+            .create_token(
+                &user.id,
+                "export",
+                "live-hash",
+                &[ApiScope::ReadProfile, ApiScope::Play],
+                Some("2999-01-01 00:00:00"),
+            )
+            .await
+            .expect("token should be stored");
+        assert_eq!(live.scopes, vec![ApiScope::ReadProfile, ApiScope::Play]);
+        store
+            .create_token(
+                &user.id,
+                "old",
+                "expired-hash",
+                &[ApiScope::Play],
+                Some("2000-01-01 00:00:00"),
+            )
+            .await
+            .expect("token should be stored");
+
+        let used = store
+            .use_token("live-hash")
+            .await
+            .expect("lookup should succeed")
+            .expect("live token should be found");
+        assert_eq!(used.id, live.id);
+        assert!(used.last_used_at.is_some());
+        assert!(store
+            .use_token("expired-hash")
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+        assert_eq!(
+            store
+                .list_tokens(&user.id)
+                .await
+                .expect("tokens should list")
+                .len(),
+            2
+        );
+
+        assert!(!store
+            .revoke_token("someone-else", &live.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .revoke_token(&user.id, &live.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .use_token("live-hash")
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+
+        store.delete(&user.id).await.expect("delete should succeed");
+        assert!(store
+            .list_tokens(&user.id)
+            .await
+            .expect("tokens should list")
+            .is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn guest_matches_move_to_the_accounts_that_claim_them() {
+        let store = LibsqlStore::new(init_test_db().await);
+        let host = store
+            .create("guest_host", "guest_host@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let first = store
+            .create("claimer_a", "claimer_a@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let second = store
+            .create("claimer_b", "claimer_b@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let guest_match = |p1: &str, p2: &str, winner: &str| MatchResult {
+            id: uuid::Uuid::new_v4().to_string(),
+            player1_id: p1.into(),
+            player2_id: p2.into(),
+            winner_id: Some(winner.into()),
+            is_ranked: false,
+            player1_score: 3,
+            player2_score: 1,
+            rounds: Vec::new(),
+            status: "completed".into(),
+        };
+        store
+            .record_guest_match(&guest_match("guest_a", &host.id, "guest_a"))
+            .await
+            .expect("guest match should be recorded");
+        store
+            .record_guest_match(&guest_match("guest_b", "guest_a", "guest_b"))
+            .await
+            .expect("guest match should be recorded");
+
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_a", &first.id)
+                .await
+                .expect("claim should succeed"),
+            1
+        );
+        let history = store
+            .recent_for_user(&first.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert!(!history[0].is_ranked);
+        assert_eq!(history[0].winner_id.as_deref(), Some(first.id.as_str()));
+        assert_eq!(history[0].player1_elo_before, None);
+        assert!(history[0].finished_at.is_some());
+        let claimed = store
+            .find_by_id(&first.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(
+            (claimed.total_games, claimed.wins, claimed.elo),
+            (1, 1, 1000)
+        );
+        let untouched = store
+            .find_by_id(&host.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(untouched.total_games, 0);
+
+        // The match between the two guests moves once the other one registers
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_b", &second.id)
+                .await
+                .expect("claim should succeed"),
+            1
+        );
+        let history = store
+            .recent_for_user(&second.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].player2_id, first.id);
+        assert_eq!(history[0].winner_id.as_deref(), Some(second.id.as_str()));
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_a", &first.id)
+                .await
+                .expect("claim should succeed"),
+            0
+        );
+
+        for user in [&host, &first, &second] {
+            store.delete(&user.id).await.expect("delete should succeed");
+        }
+    }
+}
@@ -0,0 +1,1557 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+use uuid::Uuid;
+
+use super::{
+    ApiTokenRepository, ConnectionPool, EmailTokenRepository, MatchRepository,
+    RatingHistoryRepository, RefreshTokenRepository, SessionRepository, SnapshotRepository,
+    TwoFactorRepository, UserRepository,
+};
+use crate::db::{PoolMetrics, MIGRATIONS};
+use crate::errors::AppError;
+use crate::models::api_token::{ApiScope, ApiToken};
+use crate::models::elo_history::EloHistory;
+use crate::models::email_token::EmailTokenPurpose;
+use crate::models::guest_match::GuestMatch;
+use crate::models::login_session::{LoginSession, SessionDevice};
+use crate::models::match_record::{MatchRecord, MatchResult, VOIDED};
+use crate::models::match_round::{ChoiceStats, MatchRound};
+use crate::models::refresh_token::{RefreshOutcome, RevokedFamily};
+use crate::models::role::Role;
+use crate::models::user::{PlatformStats, User, UsernameChange};
+use crate::snapshot::{
+    GuestMatchRow, HistoryRow, MatchRow, RecoveryCodeRow, RoundRow, Snapshot, UserRow,
+    UsernameChangeRow,
+};
+
+#[derive(Default)]
+struct State {
+    users: Vec<User>,
+    matches: Vec<MatchRecord>,
+    guest_matches: Vec<GuestMatch>,
+    history: Vec<EloHistory>,
+    refresh_tokens: Vec<StoredRefreshToken>,
+    login_sessions: Vec<LoginSession>,
+    email_tokens: Vec<StoredEmailToken>,
+    recovery_codes: Vec<StoredRecoveryCode>,
+    /// Last accepted TOTP step per user.
+    totp_steps: HashMap<String, i64>,
+    /// Renames, oldest first.
+    username_history: Vec<StoredUsernameChange>,
+    /// API tokens with their hashes, oldest first.
+    api_tokens: Vec<(String, ApiToken)>,
+}
+
+struct StoredRefreshToken {
+    user_id: String,
+    family_id: String,
+    token_hash: String,
+    expires_at: String,
+    rotated: bool,
+    revoked: bool,
+}
+
+struct StoredEmailToken {
+    id: String,
+    user_id: String,
+    purpose: EmailTokenPurpose,
+    expires_at: String,
+    used: bool,
+}
+
+struct StoredRecoveryCode {
+    id: String,
+    user_id: String,
+    code_hash: String,
+    used_at: Option<String>,
+    created_at: String,
+}
+
+struct StoredUsernameChange {
+    id: String,
+    user_id: String,
+    change: UsernameChange,
+}
+
+/// Repositories held entirely in memory, for fast isolated tests.
+///
+/// Starts out like a freshly migrated database, AI players included.
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn matches_search(user: &User, search: Option<&str>) -> bool {
+    match search {
+        Some(s) if !s.is_empty() => {
+            let s = s.to_lowercase();
+            user.username.to_lowercase().contains(&s) || user.email.to_lowercase().contains(&s)
+        }
+        _ => true,
+    }
+}
+
+fn new_user(id: String, username: &str, email: &str) -> User {
+    let created_at = now();
+    User {
+        id,
+        username: username.to_string(),
+        email: email.to_string(),
+        email_verified: false,
+        password_hash: None,
+        avatar_url: None,
+        google_id: None,
+        elo: 1000,
+        total_games: 0,
+        wins: 0,
+        losses: 0,
+        draws: 0,
+        updated_at: created_at.clone(),
+        created_at,
+        role: Role::Player,
+        is_banned: false,
+        banned_at: None,
+        banned_reason: None,
+        is_ai: false,
+        token_version: 0,
+        totp_secret: None,
+        totp_enabled: false,
+        pending_email: None,
+        username_changed_at: None,
+        allow_spectators: true,
+    }
+}
+
+/// The AI roster inserted by the `add_ai_players` migration.
+fn seeded_ai_users() -> Vec<User> {
+    let sql = MIGRATIONS
+        .iter()
+        .find(|m| m.name == "add_ai_players")
+        .map(|m| m.sql)
+        .unwrap_or_default();
+
+    sql.lines()
+        .filter_map(|line| {
+            let values = line.trim().strip_prefix("('ai-")?;
+            let values = values.trim_end_matches([',', ';']).strip_suffix(')')?;
+            let fields: Vec<&str> = values.split(", ").collect();
+            let [id, username, email, _, elo, _] = fields.as_slice() else {
+                return None;
+            };
+            let mut user = new_user(
+                format!("ai-{}", id.trim_end_matches('\'')),
+                username.trim_matches('\''),
+                email.trim_matches('\''),
+            );
+            user.elo = elo.parse().ok()?;
+            user.is_ai = true;
+            Some(user)
+        })
+        .collect()
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                users: seeded_ai_users(),
+                ..State::default()
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    fn user_mut(&mut self, user_id: &str) -> Result<&mut User, AppError> {
+        self.users
+            .iter_mut()
+            .find(|u| u.id == user_id)
+            .ok_or_else(|| AppError::NotFound(format!("User {user_id} not found")))
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryStore {
+    async fn create(
+        &self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<User, AppError> {
+        let mut state = self.state();
+        if state
+            .users
+            .iter()
+            .any(|u| u.username.eq_ignore_ascii_case(username))
+        {
+            return Err(AppError::Conflict("Username already taken".into()));
+        }
+        if state.users.iter().any(|u| u.email == email) {
+            return Err(AppError::Conflict("Email already registered".into()));
+        }
+
+        let mut user = new_user(Uuid::new_v4().to_string(), username, email);
+        user.password_hash = Some(password_hash.to_string());
+        state.users.push(user.clone());
+        Ok(user)
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, AppError> {
+        Ok(self.state().users.iter().find(|u| u.id == id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        Ok(self
+            .state()
+            .users
+            .iter()
+            .find(|u| u.email == email)
+            .cloned())
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        Ok(self
+            .state()
+            .users
+            .iter()
+            .find(|u| u.username.to_lowercase() == username.to_lowercase())
+            .cloned())
+    }
+
+    async fn create_external(
+        &self,
+        username: &str,
+        email: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<User, AppError> {
+        let mut state = self.state();
+        if state
+            .users
+            .iter()
+            .any(|u| u.username.eq_ignore_ascii_case(username))
+        {
+            return Err(AppError::Conflict("Username already taken".into()));
+        }
+        if state.users.iter().any(|u| u.email == email) {
+            return Err(AppError::Conflict("Email already registered".into()));
+        }
+
+        let mut user = new_user(Uuid::new_v4().to_string(), username, email);
+        user.google_id = Some(google_id.to_string());
+        user.avatar_url = avatar_url.map(str::to_string);
+        user.email_verified = true;
+        state.users.push(user.clone());
+        Ok(user)
+    }
+
+    async fn find_by_google_id(&self, google_id: &str) -> Result<Option<User>, AppError> {
+        Ok(self
+            .state()
+            .users
+            .iter()
+            .find(|u| u.google_id.as_deref() == Some(google_id))
+            .cloned())
+    }
+
+    async fn link_google_id(
+        &self,
+        user_id: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.google_id = Some(google_id.to_string());
+        if user.avatar_url.is_none() {
+            user.avatar_url = avatar_url.map(str::to_string);
+        }
+        user.email_verified = true;
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn set_password(&self, user_id: &str, password_hash: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.password_hash = Some(password_hash.to_string());
+        user.token_version += 1;
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, user_id: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.email_verified = true;
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn set_pending_email(&self, user_id: &str, email: Option<&str>) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.pending_email = email.map(str::to_string);
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn confirm_email_change(&self, user_id: &str, email: &str) -> Result<bool, AppError> {
+        let mut state = self.state();
+        if state
+            .users
+            .iter()
+            .any(|u| u.email == email && u.id != user_id)
+        {
+            return Err(AppError::Conflict("Email already registered".into()));
+        }
+        let user = state.user_mut(user_id)?;
+        if user.pending_email.as_deref() != Some(email) {
+            return Ok(false);
+        }
+        user.email = email.to_string();
+        user.pending_email = None;
+        user.email_verified = true;
+        user.updated_at = now();
+        Ok(true)
+    }
+
+    async fn rename(&self, user_id: &str, username: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        if state
+            .users
+            .iter()
+            .any(|u| u.username.eq_ignore_ascii_case(username) && u.id != user_id)
+        {
+            return Err(AppError::Conflict("Username already taken".into()));
+        }
+        let user = state.user_mut(user_id)?;
+        let change = UsernameChange {
+            old_username: std::mem::replace(&mut user.username, username.to_string()),
+            new_username: username.to_string(),
+            changed_at: now(),
+        };
+        user.username_changed_at = Some(change.changed_at.clone());
+        user.updated_at = now();
+        state.username_history.push(StoredUsernameChange {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            change,
+        });
+        Ok(())
+    }
+
+    async fn username_history(&self, user_id: &str) -> Result<Vec<UsernameChange>, AppError> {
+        Ok(self
+            .state()
+            .username_history
+            .iter()
+            .rev()
+            .filter(|c| c.user_id == user_id)
+            .map(|c| c.change.clone())
+            .collect())
+    }
+
+    async fn set_avatar(&self, user_id: &str, avatar_url: Option<&str>) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.avatar_url = avatar_url.map(str::to_string);
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn set_allow_spectators(&self, user_id: &str, allow: bool) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.allow_spectators = allow;
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn top_by_elo(&self, limit: i32) -> Result<Vec<User>, AppError> {
+        let mut users = self.state().users.clone();
+        users.sort_by_key(|u| Reverse(u.elo));
+        users.truncate(limit.max(0) as usize);
+        Ok(users)
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        state.refresh_tokens.retain(|t| t.user_id != user_id);
+        state.login_sessions.retain(|s| s.user_id != user_id);
+        state.email_tokens.retain(|t| t.user_id != user_id);
+        state.recovery_codes.retain(|c| c.user_id != user_id);
+        state.api_tokens.retain(|(_, t)| t.user_id != user_id);
+        state.totp_steps.remove(user_id);
+        state.username_history.retain(|c| c.user_id != user_id);
+        state.history.retain(|h| h.user_id != user_id);
+        state
+            .matches
+            .retain(|m| m.player1_id != user_id && m.player2_id != user_id);
+        state
+            .guest_matches
+            .retain(|m| m.player1_id != user_id && m.player2_id != user_id);
+        state.users.retain(|u| u.id != user_id);
+        Ok(())
+    }
+
+    async fn set_role(&self, user_id: &str, role: Role) -> Result<(), AppError> {
+        if let Some(user) = self.state().users.iter_mut().find(|u| u.id == user_id) {
+            user.role = role;
+            user.updated_at = now();
+        }
+        Ok(())
+    }
+
+    async fn list_with_filters(
+        &self,
+        search: Option<&str>,
+        sort_by: Option<&str>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, AppError> {
+        let mut users: Vec<User> = self
+            .state()
+            .users
+            .iter()
+            .rev()
+            .filter(|u| matches_search(u, search))
+            .cloned()
+            .collect();
+
+        match sort_by {
+            Some("elo") => users.sort_by_key(|u| Reverse(u.elo)),
+            Some("total_games") => users.sort_by_key(|u| Reverse(u.total_games)),
+            _ => users.sort_by(|a, b| b.created_at.cmp(&a.created_at)),
+        }
+
+        Ok(users
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn count_all(&self, search: Option<&str>) -> Result<i64, AppError> {
+        Ok(self
+            .state()
+            .users
+            .iter()
+            .filter(|u| matches_search(u, search))
+            .count() as i64)
+    }
+
+    async fn ban(&self, user_id: &str, reason: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        if let Ok(user) = state.user_mut(user_id) {
+            user.is_banned = true;
+            user.banned_at = Some(now());
+            user.banned_reason = Some(reason.to_string());
+            user.token_version += 1;
+            user.updated_at = now();
+        }
+        Ok(())
+    }
+
+    async fn unban(&self, user_id: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        if let Ok(user) = state.user_mut(user_id) {
+            user.is_banned = false;
+            user.banned_at = None;
+            user.banned_reason = None;
+            user.updated_at = now();
+        }
+        Ok(())
+    }
+
+    async fn update_stats(
+        &self,
+        user_id: &str,
+        username: Option<&str>,
+        elo: Option<i32>,
+        wins: Option<i32>,
+        losses: Option<i32>,
+        draws: Option<i32>,
+    ) -> Result<(), AppError> {
+        let mut state = self.state();
+        if let Some(name) = username {
+            if state
+                .users
+                .iter()
+                .any(|u| u.username.eq_ignore_ascii_case(name) && u.id != user_id)
+            {
+                return Err(AppError::Conflict("Username already taken".into()));
+            }
+        }
+
+        let Ok(user) = state.user_mut(user_id) else {
+            return Ok(());
+        };
+        if let Some(name) = username {
+            user.username = name.to_string();
+        }
+        user.elo = elo.unwrap_or(user.elo);
+        user.wins = wins.unwrap_or(user.wins);
+        user.losses = losses.unwrap_or(user.losses);
+        user.draws = draws.unwrap_or(user.draws);
+        if wins.is_some() || losses.is_some() || draws.is_some() {
+            user.total_games = user.wins + user.losses + user.draws;
+        }
+        user.updated_at = now();
+        Ok(())
+    }
+
+    async fn platform_stats(&self) -> Result<PlatformStats, AppError> {
+        let state = self.state();
+        let cutoff = (chrono::Utc::now() - chrono::Duration::days(30))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let active: HashSet<&str> = state
+            .history
+            .iter()
+            .filter(|h| h.created_at > cutoff)
+            .map(|h| h.user_id.as_str())
+            .collect();
+
+        Ok(PlatformStats {
+            total_users: state.users.len() as i64,
+            active_users: active.len() as i64,
+            total_matches: state.matches.len() as i64,
+            banned_users: state.users.iter().filter(|u| u.is_banned).count() as i64,
+        })
+    }
+
+    async fn random_ai(&self) -> Result<User, AppError> {
+        let state = self.state();
+        let ai_users: Vec<&User> = state.users.iter().filter(|u| u.is_ai).collect();
+        ai_users
+            .choose(&mut rand::thread_rng())
+            .map(|u| (*u).clone())
+            .ok_or_else(|| AppError::Internal("No AI users available".into()))
+    }
+}
+
+#[async_trait]
+impl MatchRepository for MemoryStore {
+    async fn record_result(&self, result: &MatchResult) -> Result<MatchRecord, AppError> {
+        let mut state = self.state();
+        if let Some(recorded) = state.matches.iter().find(|m| m.id == result.id) {
+            return Ok(recorded.clone());
+        }
+        // Check everything that can fail before touching any state.
+        let mut rating = |user_id: &str| {
+            state
+                .user_mut(user_id)
+                .map(|user| (user.elo, user.total_games))
+        };
+        let p1_before = rating(&result.player1_id)?;
+        let p2_before = rating(&result.player2_id)?;
+        let (p1_after, p2_after) = result.ratings_after(p1_before, p2_before);
+
+        let finished_at = now();
+        let record = MatchRecord {
+            id: result.id.clone(),
+            player1_id: result.player1_id.clone(),
+            player2_id: result.player2_id.clone(),
+            winner_id: result.winner_id.clone(),
+            is_ranked: result.is_ranked,
+            player1_score: result.player1_score,
+            player2_score: result.player2_score,
+            player1_elo_before: Some(p1_before.0),
+            player1_elo_after: Some(p1_after),
+            player2_elo_before: Some(p2_before.0),
+            player2_elo_after: Some(p2_after),
+            status: result.status.clone(),
+            created_at: finished_at.clone(),
+            finished_at: Some(finished_at.clone()),
+            rounds: result.rounds.clone(),
+        };
+
+        let players = [
+            (&result.player1_id, p1_before.0, p1_after),
+            (&result.player2_id, p2_before.0, p2_after),
+        ];
+
+        for (user_id, elo_before, elo_after) in players {
+            let won = result.winner_id.as_ref().map(|winner| winner == user_id);
+            let user = state.user_mut(user_id)?;
+            if result.is_ranked {
+                user.elo = elo_after;
+            }
+            user.total_games += 1;
+            match won {
+                Some(true) => user.wins += 1,
+                Some(false) => user.losses += 1,
+                None => user.draws += 1,
+            }
+            user.updated_at = finished_at.clone();
+
+            if result.is_ranked {
+                state.history.push(EloHistory {
+                    id: Uuid::new_v4().to_string(),
+                    user_id: user_id.clone(),
+                    match_id: record.id.clone(),
+                    elo_before,
+                    elo_after,
+                    elo_change: elo_after - elo_before,
+                    created_at: finished_at.clone(),
+                });
+            }
+        }
+
+        state.matches.push(record.clone());
+        Ok(record)
+    }
+
+    async fn recent_for_user(
+        &self,
+        user_id: &str,
+        limit: i32,
+    ) -> Result<Vec<MatchRecord>, AppError> {
+        let mut matches: Vec<MatchRecord> = self
+            .state()
+            .matches
+            .iter()
+            .rev()
+            .filter(|m| {
+                (m.player1_id == user_id || m.player2_id == user_id) && m.status != "in_progress"
+            })
+            .cloned()
+            .collect();
+        matches.sort_by(|a, b| b.finished_at.cmp(&a.finished_at));
+        matches.truncate(limit.max(0) as usize);
+        Ok(matches)
+    }
+
+    async fn choice_stats_for_user(&self, user_id: &str) -> Result<ChoiceStats, AppError> {
+        let mut stats = ChoiceStats::default();
+        for m in self.state().matches.iter() {
+            let is_player1 = m.player1_id == user_id;
+            if !is_player1 && m.player2_id != user_id {
+                continue;
+            }
+            for round in &m.rounds {
+                let choice = if is_player1 {
+                    &round.player1_choice
+                } else {
+                    &round.player2_choice
+                };
+                match choice.as_deref() {
+                    Some("rock") => stats.rock += 1,
+                    Some("paper") => stats.paper += 1,
+                    Some("scissors") => stats.scissors += 1,
+                    _ => stats.none += 1,
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn void_match(&self, match_id: &str) -> Result<MatchRecord, AppError> {
+        let mut state = self.state();
+        let mut record = state
+            .matches
+            .iter()
+            .find(|m| m.id == match_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound("Match not found".into()))?;
+        let reversal = record.void_reversal()?;
+        for player in reversal {
+            state.user_mut(player.user_id)?;
+        }
+
+        let updated_at = now();
+        for player in reversal {
+            let user = state.user_mut(player.user_id)?;
+            user.elo -= player.elo_change;
+            user.total_games = (user.total_games - 1).max(0);
+            match player.won {
+                Some(true) => user.wins = (user.wins - 1).max(0),
+                Some(false) => user.losses = (user.losses - 1).max(0),
+                None => user.draws = (user.draws - 1).max(0),
+            }
+            user.updated_at = updated_at.clone();
+        }
+        state.history.retain(|h| h.match_id != match_id);
+        record.status = VOIDED.to_string();
+        if let Some(stored) = state.matches.iter_mut().find(|m| m.id == match_id) {
+            stored.status = VOIDED.to_string();
+        }
+        Ok(record)
+    }
+
+    async fn record_guest_match(&self, result: &MatchResult) -> Result<(), AppError> {
+        let mut state = self.state();
+        if state.guest_matches.iter().any(|m| m.id == result.id) {
+            return Ok(());
+        }
+        state.guest_matches.push(GuestMatch {
+            id: result.id.clone(),
+            player1_id: result.player1_id.clone(),
+            player2_id: result.player2_id.clone(),
+            winner_id: result.winner_id.clone(),
+            player1_score: result.player1_score,
+            player2_score: result.player2_score,
+            status: result.status.clone(),
+            finished_at: now(),
+        });
+        Ok(())
+    }
+
+    async fn claim_guest_matches(&self, guest_id: &str, user_id: &str) -> Result<u64, AppError> {
+        let mut state = self.state();
+        state.user_mut(user_id)?;
+
+        let (claimed, unclaimed): (Vec<_>, Vec<_>) = std::mem::take(&mut state.guest_matches)
+            .into_iter()
+            .partition(|m| m.player1_id == guest_id || m.player2_id == guest_id);
+        state.guest_matches = unclaimed;
+
+        let mut moved = 0;
+        for m in claimed {
+            let m = m.claimed_by(guest_id, user_id);
+            if !m.between_accounts() {
+                state.guest_matches.push(m);
+                continue;
+            }
+
+            let won = m.winner_id.as_ref().map(|winner| winner == user_id);
+            let user = state.user_mut(user_id)?;
+            user.total_games += 1;
+            match won {
+                Some(true) => user.wins += 1,
+                Some(false) => user.losses += 1,
+                None => user.draws += 1,
+            }
+            state.matches.push(MatchRecord {
+                id: m.id,
+                player1_id: m.player1_id,
+                player2_id: m.player2_id,
+                winner_id: m.winner_id,
+                is_ranked: false,
+                player1_score: m.player1_score,
+                player2_score: m.player2_score,
+                player1_elo_before: None,
+                player1_elo_after: None,
+                player2_elo_before: None,
+                player2_elo_after: None,
+                status: m.status,
+                created_at: m.finished_at.clone(),
+                finished_at: Some(m.finished_at),
+                rounds: Vec::new(),
+            });
+            moved += 1;
+        }
+        Ok(moved)
+    }
+}
+
+#[async_trait]
+impl RatingHistoryRepository for MemoryStore {
+    async fn for_user(&self, user_id: &str, limit: i32) -> Result<Vec<EloHistory>, AppError> {
+        Ok(self
+            .state()
+            .history
+            .iter()
+            .rev()
+            .filter(|h| h.user_id == user_id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+impl State {
+    fn revoke_where(&mut self, pred: impl Fn(&StoredRefreshToken) -> bool) {
+        let mut families = HashSet::new();
+        for token in self.refresh_tokens.iter_mut().filter(|t| pred(t)) {
+            token.revoked = true;
+            families.insert(token.family_id.clone());
+        }
+        let now = now();
+        for session in self
+            .login_sessions
+            .iter_mut()
+            .filter(|s| s.revoked_at.is_none() && families.contains(&s.id))
+        {
+            session.revoked_at = Some(now.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for MemoryStore {
+    async fn start_family(
+        &self,
+        user_id: &str,
+        device: &SessionDevice,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<LoginSession, AppError> {
+        let session = LoginSession {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            user_agent: device.user_agent.clone(),
+            ip_hash: device.ip_hash.clone(),
+            created_at: now(),
+            last_seen_at: now(),
+            expires_at: expires_at.to_string(),
+            revoked_at: None,
+        };
+        let mut state = self.state();
+        state.refresh_tokens.push(StoredRefreshToken {
+            user_id: user_id.to_string(),
+            family_id: session.id.clone(),
+            token_hash: token_hash.to_string(),
+            expires_at: expires_at.to_string(),
+            rotated: false,
+            revoked: false,
+        });
+        state.login_sessions.push(session.clone());
+        Ok(session)
+    }
+
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: &str,
+    ) -> Result<RefreshOutcome, AppError> {
+        let mut state = self.state();
+        let Some(token) = state
+            .refresh_tokens
+            .iter_mut()
+            .find(|t| t.token_hash == token_hash)
+        else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+
+        if token.revoked {
+            return Ok(RefreshOutcome::Invalid);
+        }
+        let user_id = token.user_id.clone();
+        let family_id = token.family_id.clone();
+        if token.rotated {
+            state.revoke_where(|t| t.family_id == family_id);
+            return Ok(RefreshOutcome::Reused {
+                user_id,
+                session_id: family_id,
+            });
+        }
+        if token.expires_at <= now() {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        token.rotated = true;
+        state.refresh_tokens.push(StoredRefreshToken {
+            user_id: user_id.clone(),
+            family_id: family_id.clone(),
+            token_hash: new_token_hash.to_string(),
+            expires_at: new_expires_at.to_string(),
+            rotated: false,
+            revoked: false,
+        });
+        if let Some(session) = state.login_sessions.iter_mut().find(|s| s.id == family_id) {
+            session.last_seen_at = now();
+            session.expires_at = new_expires_at.to_string();
+        }
+        Ok(RefreshOutcome::Rotated {
+            user_id,
+            session_id: family_id,
+        })
+    }
+
+    async fn revoke_family(&self, token_hash: &str) -> Result<Option<RevokedFamily>, AppError> {
+        let mut state = self.state();
+        let family = state
+            .refresh_tokens
+            .iter()
+            .find(|t| t.token_hash == token_hash)
+            .map(|t| RevokedFamily {
+                user_id: t.user_id.clone(),
+                session_id: t.family_id.clone(),
+            });
+        if let Some(family) = &family {
+            state.revoke_where(|t| t.family_id == family.session_id);
+        }
+        Ok(family)
+    }
+
+    async fn revoke_all_for_user(&self, user_id: &str) -> Result<(), AppError> {
+        self.state().revoke_where(|t| t.user_id == user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionRepository for MemoryStore {
+    async fn find_session(&self, id: &str) -> Result<Option<LoginSession>, AppError> {
+        Ok(self
+            .state()
+            .login_sessions
+            .iter()
+            .find(|s| s.id == id)
+            .cloned())
+    }
+
+    async fn list_sessions(&self, user_id: &str) -> Result<Vec<LoginSession>, AppError> {
+        let now = now();
+        let mut sessions: Vec<LoginSession> = self
+            .state()
+            .login_sessions
+            .iter()
+            .filter(|s| s.user_id == user_id && s.revoked_at.is_none() && s.expires_at > now)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| {
+            (&b.last_seen_at, &b.created_at).cmp(&(&a.last_seen_at, &a.created_at))
+        });
+        Ok(sessions)
+    }
+
+    async fn touch_session(&self, id: &str) -> Result<(), AppError> {
+        if let Some(session) = self.state().login_sessions.iter_mut().find(|s| s.id == id) {
+            session.last_seen_at = now();
+        }
+        Ok(())
+    }
+
+    async fn revoke_session(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let mut state = self.state();
+        let active = state
+            .login_sessions
+            .iter()
+            .any(|s| s.id == id && s.user_id == user_id && s.revoked_at.is_none());
+        state.revoke_where(|t| t.family_id == id && t.user_id == user_id);
+        Ok(active)
+    }
+}
+
+#[async_trait]
+impl EmailTokenRepository for MemoryStore {
+    async fn issue(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        self.state().email_tokens.push(StoredEmailToken {
+            id: id.to_string(),
+            user_id: user_id.to_string(),
+            purpose,
+            expires_at: expires_at.to_string(),
+            used: false,
+        });
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+    ) -> Result<bool, AppError> {
+        let mut state = self.state();
+        let now = now();
+        let Some(token) = state
+            .email_tokens
+            .iter_mut()
+            .find(|t| t.id == id && t.user_id == user_id && t.purpose == purpose && !t.used)
+        else {
+            return Ok(false);
+        };
+        if token.expires_at <= now {
+            return Ok(false);
+        }
+        token.used = true;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for MemoryStore {
+    async fn set_pending_secret(&self, user_id: &str, secret: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.totp_secret = Some(secret.to_string());
+        user.totp_enabled = false;
+        user.updated_at = now();
+        state.totp_steps.remove(user_id);
+        Ok(())
+    }
+
+    async fn enable(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.totp_enabled = true;
+        user.token_version += 1;
+        user.updated_at = now();
+        state.totp_steps.insert(user_id.to_string(), step);
+        state.recovery_codes.retain(|c| c.user_id != user_id);
+        state
+            .recovery_codes
+            .extend(recovery_code_hashes.iter().map(|hash| StoredRecoveryCode {
+                id: Uuid::new_v4().to_string(),
+                user_id: user_id.to_string(),
+                code_hash: hash.clone(),
+                used_at: None,
+                created_at: now(),
+            }));
+        Ok(())
+    }
+
+    async fn disable(&self, user_id: &str) -> Result<(), AppError> {
+        let mut state = self.state();
+        let user = state.user_mut(user_id)?;
+        user.totp_secret = None;
+        user.totp_enabled = false;
+        user.updated_at = now();
+        state.totp_steps.remove(user_id);
+        state.recovery_codes.retain(|c| c.user_id != user_id);
+        Ok(())
+    }
+
+    async fn accept_step(&self, user_id: &str, step: i64) -> Result<bool, AppError> {
+        let mut state = self.state();
+        match state.totp_steps.get(user_id) {
+            Some(&last) if last >= step => Ok(false),
+            _ => {
+                state.totp_steps.insert(user_id.to_string(), step);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str) -> Result<bool, AppError> {
+        let mut state = self.state();
+        match state
+            .recovery_codes
+            .iter_mut()
+            .find(|c| c.user_id == user_id && c.code_hash == code_hash && c.used_at.is_none())
+        {
+            Some(code) => {
+                code.used_at = Some(now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn recovery_codes_left(&self, user_id: &str) -> Result<i64, AppError> {
+        Ok(self
+            .state()
+            .recovery_codes
+            .iter()
+            .filter(|c| c.user_id == user_id && c.used_at.is_none())
+            .count() as i64)
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for MemoryStore {
+    async fn create_token(
+        &self,
+        user_id: &str,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        expires_at: Option<&str>,
+    ) -> Result<ApiToken, AppError> {
+        let token = ApiToken {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            expires_at: expires_at.map(str::to_string),
+            last_used_at: None,
+            created_at: now(),
+        };
+        self.state()
+            .api_tokens
+            .push((token_hash.to_string(), token.clone()));
+        Ok(token)
+    }
+
+    async fn list_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>, AppError> {
+        Ok(self
+            .state()
+            .api_tokens
+            .iter()
+            .rev()
+            .filter(|(_, t)| t.user_id == user_id)
+            .map(|(_, t)| t.clone())
+            .collect())
+    }
+
+    async fn revoke_token(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let mut state = self.state();
+        let before = state.api_tokens.len();
+        state
+            .api_tokens
+            .retain(|(_, t)| t.id != id || t.user_id != user_id);
+        Ok(state.api_tokens.len() < before)
+    }
+
+    async fn use_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        let now = now();
+        let mut state = self.state();
+        let Some((_, token)) = state.api_tokens.iter_mut().find(|(hash, t)| {
+            hash == token_hash && t.expires_at.as_ref().is_none_or(|at| *at > now)
+        }) else {
+            return Ok(None);
+        };
+        token.last_used_at = Some(now);
+        Ok(Some(token.clone()))
+    }
+}
+
+impl ConnectionPool for MemoryStore {
+    fn pool_metrics(&self) -> PoolMetrics {
+        PoolMetrics::default()
+    }
+}
+
+#[async_trait]
+impl SnapshotRepository for MemoryStore {
+    async fn export(&self) -> Result<Snapshot, AppError> {
+        let state = self.state();
+        Ok(Snapshot {
+            created_at: now(),
+            users: state
+                .users
+                .iter()
+                .map(|u| UserRow {
+                    id: u.id.clone(),
+                    username: u.username.clone(),
+                    email: u.email.clone(),
+                    email_verified: u.email_verified,
+                    totp_secret: u.totp_secret.clone(),
+                    totp_enabled: u.totp_enabled,
+                    totp_last_step: state.totp_steps.get(&u.id).copied(),
+                    token_version: u.token_version,
+                    pending_email: u.pending_email.clone(),
+                    username_changed_at: u.username_changed_at.clone(),
+                    allow_spectators: u.allow_spectators,
+                    password_hash: u.password_hash.clone(),
+                    google_id: u.google_id.clone(),
+                    avatar_url: u.avatar_url.clone(),
+                    elo: u.elo,
+                    total_games: u.total_games,
+                    wins: u.wins,
+                    losses: u.losses,
+                    draws: u.draws,
+                    role: u.role,
+                    is_banned: u.is_banned,
+                    banned_at: u.banned_at.clone(),
+                    banned_reason: u.banned_reason.clone(),
+                    is_ai: u.is_ai,
+                    created_at: u.created_at.clone(),
+                    updated_at: u.updated_at.clone(),
+                    sealed_totp_secret: None,
+                    plain_totp_secret: None,
+                    is_admin: false,
+                })
+                .collect(),
+            username_history: state
+                .username_history
+                .iter()
+                .map(|c| UsernameChangeRow {
+                    id: c.id.clone(),
+                    user_id: c.user_id.clone(),
+                    old_username: c.change.old_username.clone(),
+                    new_username: c.change.new_username.clone(),
+                    changed_at: c.change.changed_at.clone(),
+                })
+                .collect(),
+            recovery_codes: state
+                .recovery_codes
+                .iter()
+                .map(|c| RecoveryCodeRow {
+                    id: c.id.clone(),
+                    user_id: c.user_id.clone(),
+                    code_hash: c.code_hash.clone(),
+                    used_at: c.used_at.clone(),
+                    created_at: c.created_at.clone(),
+                })
+                .collect(),
+            matches: state
+                .matches
+                .iter()
+                .map(|m| MatchRow {
+                    id: m.id.clone(),
+                    player1_id: m.player1_id.clone(),
+                    player2_id: m.player2_id.clone(),
+                    winner_id: m.winner_id.clone(),
+                    is_ranked: m.is_ranked,
+                    player1_score: m.player1_score,
+                    player2_score: m.player2_score,
+                    player1_elo_before: m.player1_elo_before,
+                    player1_elo_after: m.player1_elo_after,
+                    player2_elo_before: m.player2_elo_before,
+                    player2_elo_after: m.player2_elo_after,
+                    status: m.status.clone(),
+                    created_at: m.created_at.clone(),
+                    finished_at: m.finished_at.clone(),
+                })
+                .collect(),
+            rounds: state
+                .matches
+                .iter()
+                .flat_map(|m| {
+                    m.rounds.iter().map(|r| RoundRow {
+                        match_id: m.id.clone(),
+                        round_number: r.round_number,
+                        player1_choice: r.player1_choice.clone(),
+                        player2_choice: r.player2_choice.clone(),
+                        winner_id: r.winner_id.clone(),
+                        started_at: r.started_at.clone(),
+                        player1_decided_at: r.player1_decided_at.clone(),
+                        player2_decided_at: r.player2_decided_at.clone(),
+                    })
+                })
+                .collect(),
+            history: state
+                .history
+                .iter()
+                .map(|h| HistoryRow {
+                    id: h.id.clone(),
+                    user_id: h.user_id.clone(),
+                    match_id: h.match_id.clone(),
+                    elo_before: h.elo_before,
+                    elo_after: h.elo_after,
+                    elo_change: h.elo_change,
+                    created_at: h.created_at.clone(),
+                })
+                .collect(),
+            guest_matches: state
+                .guest_matches
+                .iter()
+                .map(|m| GuestMatchRow {
+                    id: m.id.clone(),
+                    player1_id: m.player1_id.clone(),
+                    player2_id: m.player2_id.clone(),
+                    winner_id: m.winner_id.clone(),
+                    player1_score: m.player1_score,
+                    player2_score: m.player2_score,
+                    status: m.status.clone(),
+                    finished_at: m.finished_at.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<(), AppError> {
+        snapshot.validate().map_err(AppError::BadRequest)?;
+
+        let mut state = self.state();
+        if state.users.iter().any(|u| !u.is_ai)
+            || !state.matches.is_empty()
+            || !state.history.is_empty()
+            || !state.guest_matches.is_empty()
+        {
+            return Err(AppError::Conflict(
+                "Snapshots can only be restored into an empty database".into(),
+            ));
+        }
+
+        state.users = snapshot
+            .users
+            .iter()
+            .map(|u| User {
+                id: u.id.clone(),
+                username: u.username.clone(),
+                email: u.email.clone(),
+                email_verified: u.email_verified,
+                password_hash: u.password_hash.clone(),
+                avatar_url: u.avatar_url.clone(),
+                google_id: u.google_id.clone(),
+                elo: u.elo,
+                total_games: u.total_games,
+                wins: u.wins,
+                losses: u.losses,
+                draws: u.draws,
+                created_at: u.created_at.clone(),
+                updated_at: u.updated_at.clone(),
+                role: u.role,
+                is_banned: u.is_banned,
+                banned_at: u.banned_at.clone(),
+                banned_reason: u.banned_reason.clone(),
+                is_ai: u.is_ai,
+                token_version: u.token_version,
+                totp_secret: u.totp_secret.clone(),
+                totp_enabled: u.totp_enabled,
+                pending_email: u.pending_email.clone(),
+                username_changed_at: u.username_changed_at.clone(),
+                allow_spectators: u.allow_spectators,
+            })
+            .collect();
+        state.totp_steps = snapshot
+            .users
+            .iter()
+            .filter_map(|u| Some((u.id.clone(), u.totp_last_step?)))
+            .collect();
+        state.username_history = snapshot
+            .username_history
+            .iter()
+            .map(|c| StoredUsernameChange {
+                id: c.id.clone(),
+                user_id: c.user_id.clone(),
+                change: UsernameChange {
+                    old_username: c.old_username.clone(),
+                    new_username: c.new_username.clone(),
+                    changed_at: c.changed_at.clone(),
+                },
+            })
+            .collect();
+        state.recovery_codes = snapshot
+            .recovery_codes
+            .iter()
+            .map(|c| StoredRecoveryCode {
+                id: c.id.clone(),
+                user_id: c.user_id.clone(),
+                code_hash: c.code_hash.clone(),
+                used_at: c.used_at.clone(),
+                created_at: c.created_at.clone(),
+            })
+            .collect();
+        state.matches = snapshot
+            .matches
+            .iter()
+            .map(|m| MatchRecord {
+                id: m.id.clone(),
+                player1_id: m.player1_id.clone(),
+                player2_id: m.player2_id.clone(),
+                winner_id: m.winner_id.clone(),
+                is_ranked: m.is_ranked,
+                player1_score: m.player1_score,
+                player2_score: m.player2_score,
+                player1_elo_before: m.player1_elo_before,
+                player1_elo_after: m.player1_elo_after,
+                player2_elo_before: m.player2_elo_before,
+                player2_elo_after: m.player2_elo_after,
+                status: m.status.clone(),
+                created_at: m.created_at.clone(),
+                finished_at: m.finished_at.clone(),
+                rounds: snapshot
+                    .rounds
+                    .iter()
+                    .filter(|r| r.match_id == m.id)
+                    .map(|r| MatchRound {
+                        round_number: r.round_number,
+                        player1_choice: r.player1_choice.clone(),
+                        player2_choice: r.player2_choice.clone(),
+                        winner_id: r.winner_id.clone(),
+                        started_at: r.started_at.clone(),
+                        player1_decided_at: r.player1_decided_at.clone(),
+                        player2_decided_at: r.player2_decided_at.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        state.history = snapshot
+            .history
+            .iter()
+            .map(|h| EloHistory {
+                id: h.id.clone(),
+                user_id: h.user_id.clone(),
+                match_id: h.match_id.clone(),
+                elo_before: h.elo_before,
+                elo_after: h.elo_after,
+                elo_change: h.elo_change,
+                created_at: h.created_at.clone(),
+            })
+            .collect();
+        state.guest_matches = snapshot
+            .guest_matches
+            .iter()
+            .map(|m| GuestMatch {
+                id: m.id.clone(),
+                player1_id: m.player1_id.clone(),
+                player2_id: m.player2_id.clone(),
+                winner_id: m.winner_id.clone(),
+                player1_score: m.player1_score,
+                player2_score: m.player2_score,
+                status: m.status.clone(),
+                finished_at: m.finished_at.clone(),
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_between(p1: &User, p2: &User) -> MatchResult {
+        MatchResult {
+            id: Uuid::new_v4().to_string(),
+            player1_id: p1.id.clone(),
+            player2_id: p2.id.clone(),
+            winner_id: Some(p1.id.clone()),
+            is_ranked: true,
+            player1_score: 3,
+            player2_score: 1,
+            rounds: Vec::new(),
+            status: "completed".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn new_store_is_seeded_with_ai_players() {
+        let store = MemoryStore::new();
+        let stats = store
+            .platform_stats()
+            .await
+            .expect("stats should be available");
+        assert_eq!(stats.total_users, 100);
+
+        let ai = store.random_ai().await.expect("an AI user should exist");
+        assert!(ai.is_ai);
+        assert!(ai.id.starts_with("ai-"));
+        let stone_wall = store
+            .find_by_id("ai-001")
+            .await
+            .expect("lookup should succeed")
+            .expect("seeded AI should exist");
+        assert_eq!(stone_wall.username, "StoneWall");
+        assert_eq!(stone_wall.elo, 985);
+    }
+
+    #[actix_rt::test]
+    async fn record_result_updates_players_and_history() {
+        let store = MemoryStore::new();
+        let p1 = store
+            .create("mem_p1", "mem_p1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = store
+            .create("mem_p2", "mem_p2@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let result = result_between(&p1, &p2);
+        let recorded = store
+            .record_result(&result)
+            .await
+            .expect("result should be recorded");
+        let retried = store
+            .record_result(&result)
+            .await
+            .expect("a retry should succeed");
+        assert_eq!(retried.id, recorded.id);
+
+        let p1 = store
+            .find_by_id(&p1.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!((p1.elo, p1.wins, p1.total_games), (1020, 1, 1));
+        let history = store
+            .for_user(&p2.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].elo_change, -20);
+        assert_eq!(history[0].match_id, recorded.id);
+
+        store.delete(&p2.id).await.expect("delete should succeed");
+        let recent = store
+            .recent_for_user(&p1.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert!(recent.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn record_result_with_unknown_player_changes_nothing() {
+        let store = MemoryStore::new();
+        let p1 = store
+            .create("mem_solo", "mem_solo@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let mut ghost = p1.clone();
+        ghost.id = "missing".into();
+
+        let result = store.record_result(&result_between(&p1, &ghost)).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        let p1 = store
+            .find_by_id(&p1.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!((p1.elo, p1.total_games), (1000, 0));
+        assert_eq!(
+            store
+                .platform_stats()
+                .await
+                .expect("stats should load")
+                .total_matches,
+            0
+        );
+    }
+
+    #[actix_rt::test]
+    async fn snapshot_restore_requires_an_empty_store() {
+        let source = MemoryStore::new();
+        let p1 = source
+            .create("mem_snap1", "mem_snap1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = source
+            .create("mem_snap2", "mem_snap2@example.com", "hash")
+            .await
+            .expect("user should be created");
+        source
+            .record_result(&result_between(&p1, &p2))
+            .await
+            .expect("result should be recorded");
+        source
+            .rename(&p2.id, "mem_snap2_renamed")
+            .await
+            .expect("rename should succeed");
+        let snapshot = source.export().await.expect("export should succeed");
+
+        let result = source.restore(&snapshot).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+
+        let mut broken = snapshot.clone();
+        broken.users.retain(|u| u.id != p2.id);
+        let target = MemoryStore::new();
+        let result = target.restore(&broken).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        target
+            .restore(&snapshot)
+            .await
+            .expect("restore should succeed");
+        let history = target
+            .for_user(&p2.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        let renames = target
+            .username_history(&p2.id)
+            .await
+            .expect("renames should load");
+        assert_eq!(renames[0].old_username, "mem_snap2");
+    }
+}
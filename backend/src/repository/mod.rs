@@ -0,0 +1,427 @@
+//! Storage traits used by the HTTP handlers and game actors.
+//!
+//! Handlers and actors only see `Repositories`; which store sits behind it
+//! is decided once at startup (or per test).
+
+mod libsql;
+#[cfg(test)]
+pub mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::config::AppConfig;
+use crate::db::{
+    self, Database, DatabaseMode, MigrationError, MigrationPlan, PoolConfig, PoolMetrics,
+};
+use crate::errors::AppError;
+use crate::models::api_token::{ApiScope, ApiToken};
+use crate::models::elo_history::EloHistory;
+use crate::models::email_token::EmailTokenPurpose;
+use crate::models::login_session::{LoginSession, SessionDevice};
+use crate::models::match_record::{MatchRecord, MatchResult};
+use crate::models::match_round::ChoiceStats;
+use crate::models::refresh_token::{RefreshOutcome, RevokedFamily};
+use crate::models::role::Role;
+use crate::models::user::{PlatformStats, User, UsernameChange};
+use crate::snapshot::Snapshot;
+
+pub use self::libsql::LibsqlStore;
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresStore;
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create(
+        &self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<User, AppError>;
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, AppError>;
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError>;
+    /// Looks a username up regardless of case.
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
+    /// Creates a password-less account for a single sign-on user.
+    async fn create_external(
+        &self,
+        username: &str,
+        email: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<User, AppError>;
+    async fn find_by_google_id(&self, google_id: &str) -> Result<Option<User>, AppError>;
+    /// Links an external identity to the user, filling in the avatar if the
+    /// user has none.
+    async fn link_google_id(
+        &self,
+        user_id: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError>;
+    /// Replaces the password hash and bumps the token version, signing the
+    /// user out of every access token already issued. Pending 2FA challenges
+    /// carry the version too, so the bump voids them as well.
+    async fn set_password(&self, user_id: &str, password_hash: &str) -> Result<(), AppError>;
+    async fn mark_email_verified(&self, user_id: &str) -> Result<(), AppError>;
+    /// Stores an address to switch to once it is verified; `None` cancels.
+    async fn set_pending_email(&self, user_id: &str, email: Option<&str>) -> Result<(), AppError>;
+    /// Makes `email` the user's verified address if it is still the pending
+    /// one. Returns false if it isn't.
+    async fn confirm_email_change(&self, user_id: &str, email: &str) -> Result<bool, AppError>;
+    /// Renames the user and records the old name in their history.
+    async fn rename(&self, user_id: &str, username: &str) -> Result<(), AppError>;
+    /// The user's renames, most recent first.
+    async fn username_history(&self, user_id: &str) -> Result<Vec<UsernameChange>, AppError>;
+    async fn set_avatar(&self, user_id: &str, avatar_url: Option<&str>) -> Result<(), AppError>;
+    /// Whether others may watch the user's matches from now on.
+    async fn set_allow_spectators(&self, user_id: &str, allow: bool) -> Result<(), AppError>;
+    async fn top_by_elo(&self, limit: i32) -> Result<Vec<User>, AppError>;
+    /// Removes the user along with their matches and rating history.
+    async fn delete(&self, user_id: &str) -> Result<(), AppError>;
+    async fn set_role(&self, user_id: &str, role: Role) -> Result<(), AppError>;
+    async fn list_with_filters(
+        &self,
+        search: Option<&str>,
+        sort_by: Option<&str>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, AppError>;
+    async fn count_all(&self, search: Option<&str>) -> Result<i64, AppError>;
+    async fn ban(&self, user_id: &str, reason: &str) -> Result<(), AppError>;
+    async fn unban(&self, user_id: &str) -> Result<(), AppError>;
+    async fn update_stats(
+        &self,
+        user_id: &str,
+        username: Option<&str>,
+        elo: Option<i32>,
+        wins: Option<i32>,
+        losses: Option<i32>,
+        draws: Option<i32>,
+    ) -> Result<(), AppError>;
+    async fn platform_stats(&self) -> Result<PlatformStats, AppError>;
+    async fn random_ai(&self) -> Result<User, AppError>;
+}
+
+#[async_trait]
+pub trait MatchRepository: Send + Sync {
+    /// Stores a finished match with its rounds, rating changes and stat
+    /// updates. Either everything is written or nothing is.
+    async fn record_result(&self, result: &MatchResult) -> Result<MatchRecord, AppError>;
+    /// Finished matches involving the user, most recent first, with rounds.
+    async fn recent_for_user(
+        &self,
+        user_id: &str,
+        limit: i32,
+    ) -> Result<Vec<MatchRecord>, AppError>;
+    async fn choice_stats_for_user(&self, user_id: &str) -> Result<ChoiceStats, AppError>;
+    /// Marks a finished match voided and takes back its rating changes,
+    /// rating history and stat updates. Either everything is undone or
+    /// nothing is.
+    async fn void_match(&self, match_id: &str) -> Result<MatchRecord, AppError>;
+    /// Stores the outcome of a match with a guest player. Rounds and ratings
+    /// in `result` are ignored.
+    async fn record_guest_match(&self, result: &MatchResult) -> Result<(), AppError>;
+    /// Hands a guest's matches to the account they registered. Matches
+    /// against other accounts become unranked matches of the user and count
+    /// toward their stats; matches against another guest wait until that
+    /// guest registers too. Returns how many matches the user gained.
+    async fn claim_guest_matches(&self, guest_id: &str, user_id: &str) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait RatingHistoryRepository: Send + Sync {
+    /// Most recent rating changes for the user, newest first.
+    async fn for_user(&self, user_id: &str, limit: i32) -> Result<Vec<EloHistory>, AppError>;
+}
+
+#[async_trait]
+pub trait SnapshotRepository: Send + Sync {
+    /// Every row of every game table, read in a single transaction.
+    async fn export(&self) -> Result<Snapshot, AppError>;
+    /// Loads a validated snapshot in one transaction. The target must hold
+    /// nothing but the seeded AI players, which the snapshot replaces.
+    async fn restore(&self, snapshot: &Snapshot) -> Result<(), AppError>;
+}
+
+#[async_trait]
+pub trait RefreshTokenRepository: Send + Sync {
+    /// Stores the first token of a new family, i.e. a fresh login, and the
+    /// session it belongs to. Only the hash of the token is ever stored.
+    async fn start_family(
+        &self,
+        user_id: &str,
+        device: &SessionDevice,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<LoginSession, AppError>;
+    /// Atomically replaces a live token with a new one in the same family.
+    /// Presenting a token that was already rotated revokes its whole family.
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: &str,
+    ) -> Result<RefreshOutcome, AppError>;
+    /// Revokes the family the token belongs to, and so its session, and says
+    /// whose it was. Unknown tokens are ignored.
+    async fn revoke_family(&self, token_hash: &str) -> Result<Option<RevokedFamily>, AppError>;
+    /// Revokes every session and refresh token of the user.
+    async fn revoke_all_for_user(&self, user_id: &str) -> Result<(), AppError>;
+}
+
+/// Login sessions. Each is created by `RefreshTokenRepository::start_family`
+/// and shares its id with the refresh token family.
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn find_session(&self, id: &str) -> Result<Option<LoginSession>, AppError>;
+    /// The user's unrevoked, unexpired sessions, most recently seen first.
+    async fn list_sessions(&self, user_id: &str) -> Result<Vec<LoginSession>, AppError>;
+    /// Records that the session was just used.
+    async fn touch_session(&self, id: &str) -> Result<(), AppError>;
+    /// Revokes one of the user's sessions along with its refresh tokens.
+    /// Returns false if the user has no such active session.
+    async fn revoke_session(&self, user_id: &str, id: &str) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait EmailTokenRepository: Send + Sync {
+    /// Records a mailed token under its id so it can be used exactly once.
+    async fn issue(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+        expires_at: &str,
+    ) -> Result<(), AppError>;
+    /// Marks the token used. Returns false if it doesn't match the user and
+    /// purpose, has expired or was already used.
+    async fn consume(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+    ) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait TwoFactorRepository: Send + Sync {
+    /// Stores a new TOTP secret for enrolment. 2FA stays off until `enable`.
+    async fn set_pending_secret(&self, user_id: &str, secret: &str) -> Result<(), AppError>;
+    /// Turns 2FA on, replaces the recovery codes and bumps the token version
+    /// so that sessions started without the second factor end.
+    async fn enable(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError>;
+    /// Turns 2FA off and drops the secret and recovery codes.
+    async fn disable(&self, user_id: &str) -> Result<(), AppError>;
+    /// Records a TOTP time step as used. Returns false if it, or a later
+    /// step, was already used, so each code works once.
+    async fn accept_step(&self, user_id: &str, step: i64) -> Result<bool, AppError>;
+    /// Marks a recovery code used. Returns false if the user holds no such
+    /// unused code.
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str) -> Result<bool, AppError>;
+    async fn recovery_codes_left(&self, user_id: &str) -> Result<i64, AppError>;
+}
+
+#[async_trait]
+pub trait ApiTokenRepository: Send + Sync {
+    /// Stores a new personal API token. Only the hash of the token is stored.
+    async fn create_token(
+        &self,
+        user_id: &str,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        expires_at: Option<&str>,
+    ) -> Result<ApiToken, AppError>;
+    /// The user's tokens, newest first.
+    async fn list_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>, AppError>;
+    /// Deletes one of the user's tokens. Returns false if they hold no such token.
+    async fn revoke_token(&self, user_id: &str, id: &str) -> Result<bool, AppError>;
+    /// Looks up an unexpired token by hash and records that it was used.
+    async fn use_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError>;
+}
+
+pub trait ConnectionPool: Send + Sync {
+    /// Current connection usage and lifetime counters for the store's pool.
+    fn pool_metrics(&self) -> PoolMetrics;
+}
+
+/// The set of repositories shared through `web::Data` and handed to actors.
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub matches: Arc<dyn MatchRepository>,
+    pub ratings: Arc<dyn RatingHistoryRepository>,
+    pub snapshots: Arc<dyn SnapshotRepository>,
+    pub refresh_tokens: Arc<dyn RefreshTokenRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub email_tokens: Arc<dyn EmailTokenRepository>,
+    pub two_factor: Arc<dyn TwoFactorRepository>,
+    pub api_tokens: Arc<dyn ApiTokenRepository>,
+    pub pool: Arc<dyn ConnectionPool>,
+}
+
+impl Repositories {
+    pub fn libsql(db: Database) -> Self {
+        Self::from_store(Arc::new(LibsqlStore::new(db)))
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self::from_store(Arc::new(memory::MemoryStore::new()))
+    }
+
+    /// Uses one store for every repository, which is what lets
+    /// `MatchRepository::record_result` update users and history atomically.
+    pub fn from_store<S>(store: Arc<S>) -> Self
+    where
+        S: UserRepository
+            + MatchRepository
+            + RatingHistoryRepository
+            + SnapshotRepository
+            + RefreshTokenRepository
+            + SessionRepository
+            + EmailTokenRepository
+            + TwoFactorRepository
+            + ApiTokenRepository
+            + ConnectionPool
+            + 'static,
+    {
+        Self {
+            users: store.clone(),
+            matches: store.clone(),
+            ratings: store.clone(),
+            snapshots: store.clone(),
+            refresh_tokens: store.clone(),
+            sessions: store.clone(),
+            email_tokens: store.clone(),
+            two_factor: store.clone(),
+            api_tokens: store.clone(),
+            pool: store,
+        }
+    }
+}
+
+/// The storage backend named by `DATABASE_URL`: libSQL by default, or
+/// PostgreSQL for `postgres://` URLs when built with the `postgres` feature.
+pub enum Backend {
+    Libsql(Database),
+    #[cfg(feature = "postgres")]
+    Postgres(Arc<PostgresStore>),
+}
+
+fn is_postgres_url(url: &str) -> bool {
+    let url = url.trim();
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+impl Backend {
+    pub async fn connect(config: &AppConfig) -> Result<Self, String> {
+        if !is_postgres_url(&config.database_url) {
+            let mode = DatabaseMode::from_config(config)?;
+            let pool = PoolConfig::from_config(config)?;
+            return Ok(Backend::Libsql(db::init_pool(&mode, pool).await?));
+        }
+
+        if config.database_replica_path.is_some() {
+            return Err("DATABASE_REPLICA_PATH is only supported for libSQL databases".into());
+        }
+        #[cfg(feature = "postgres")]
+        {
+            log::info!("Using PostgreSQL database");
+            let pool = PoolConfig::from_config(config)?;
+            PostgresStore::connect(config.database_url.trim(), &pool)
+                .map(|store| Backend::Postgres(Arc::new(store)))
+        }
+        #[cfg(not(feature = "postgres"))]
+        Err("DATABASE_URL is a PostgreSQL URL but this build doesn't include the `postgres` feature".into())
+    }
+
+    pub async fn plan_migrations(&self) -> Result<MigrationPlan, MigrationError> {
+        match self {
+            Backend::Libsql(db) => db::plan_migrations(db).await,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => store.plan_migrations().await,
+        }
+    }
+
+    pub async fn run_migrations(&self) -> Result<Vec<i64>, MigrationError> {
+        match self {
+            Backend::Libsql(db) => db::run_migrations(db).await,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => store.run_migrations().await,
+        }
+    }
+
+    pub fn repositories(&self) -> Repositories {
+        match self {
+            Backend::Libsql(db) => Repositories::libsql(db.clone()),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => Repositories::from_store(store.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_url(url: &str) -> AppConfig {
+        AppConfig {
+            database_url: url.into(),
+            ..AppConfig::for_tests()
+        }
+    }
+
+    #[test]
+    fn postgres_urls_are_recognized_by_scheme() {
+        assert!(is_postgres_url("postgres://user@localhost/red_flip"));
+        assert!(is_postgres_url(" postgresql://localhost/red_flip"));
+        assert!(!is_postgres_url("libsql://red-flip.turso.io"));
+        assert!(!is_postgres_url("file:local.db"));
+    }
+
+    #[actix_rt::test]
+    async fn postgres_url_rejects_replica_path() {
+        let mut config = config_with_url("postgres://localhost/red_flip");
+        config.database_replica_path = Some("replica.db".into());
+        assert!(Backend::connect(&config).await.is_err());
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    #[actix_rt::test]
+    async fn postgres_url_requires_the_feature() {
+        let result = Backend::connect(&config_with_url("postgres://localhost/red_flip")).await;
+        assert!(matches!(result, Err(msg) if msg.contains("`postgres` feature")));
+    }
+
+    #[actix_rt::test]
+    async fn zero_pool_size_is_a_configuration_error() {
+        let mut config = config_with_url(":memory:");
+        config.database_pool_size = Some(0);
+        let result = Backend::connect(&config).await;
+        assert!(matches!(result, Err(msg) if msg.contains("DATABASE_POOL_SIZE")));
+    }
+
+    #[actix_rt::test]
+    async fn libsql_backend_is_the_default() {
+        let backend = Backend::connect(&config_with_url(":memory:"))
+            .await
+            .expect("memory database should open");
+        assert!(matches!(backend, Backend::Libsql(_)));
+        backend
+            .run_migrations()
+            .await
+            .expect("migrations should apply");
+        let repos = backend.repositories();
+        assert!(repos.users.random_ai().await.is_ok());
+    }
+}
@@ -0,0 +1,206 @@
+use deadpool_postgres::{Pool, PoolError};
+use tokio_postgres::Client;
+
+use crate::db::{plan_against, Migration, MigrationError, MigrationPlan};
+
+/// The PostgreSQL schema, kept in step with the SQLite migrations in
+/// `db::MIGRATIONS` but versioned on its own.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        sql: include_str!("../../../migrations/postgres/001_create_users.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "create_matches",
+        sql: include_str!("../../../migrations/postgres/002_create_matches.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_elo_history",
+        sql: include_str!("../../../migrations/postgres/003_create_elo_history.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "create_match_rounds",
+        sql: include_str!("../../../migrations/postgres/004_create_match_rounds.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "add_ai_players",
+        sql: include_str!("../../../migrations/postgres/005_add_ai_players.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "create_refresh_tokens",
+        sql: include_str!("../../../migrations/postgres/006_create_refresh_tokens.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "add_token_version",
+        sql: include_str!("../../../migrations/postgres/007_add_token_version.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "add_email_verification",
+        sql: include_str!("../../../migrations/postgres/008_add_email_verification.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "add_two_factor",
+        sql: include_str!("../../../migrations/postgres/009_add_two_factor.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "add_account_management",
+        sql: include_str!("../../../migrations/postgres/010_add_account_management.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "add_roles",
+        sql: include_str!("../../../migrations/postgres/011_add_roles.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "create_api_tokens",
+        sql: include_str!("../../../migrations/postgres/012_create_api_tokens.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "create_guest_matches",
+        sql: include_str!("../../../migrations/postgres/013_create_guest_matches.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "add_username_lower_index",
+        sql: include_str!("../../../migrations/postgres/014_add_username_lower_index.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "create_login_sessions",
+        sql: include_str!("../../../migrations/postgres/015_create_login_sessions.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "add_allow_spectators",
+        sql: include_str!("../../../migrations/postgres/016_add_allow_spectators.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "unique_username_lower",
+        sql: include_str!("../../../migrations/postgres/017_unique_username_lower.sql"),
+    },
+];
+
+/// Serializes concurrent `run_migrations` calls from several instances
+/// booting against the same database.
+const MIGRATION_LOCK_ID: i64 = 0x7265_645f_666c_6970; // "red_flip"
+
+impl From<tokio_postgres::Error> for MigrationError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        MigrationError::Database(err.to_string())
+    }
+}
+
+impl From<PoolError> for MigrationError {
+    fn from(err: PoolError) -> Self {
+        MigrationError::Database(err.to_string())
+    }
+}
+
+/// Compare the ledger against `MIGRATIONS` without changing anything.
+pub async fn plan_migrations(pool: &Pool) -> Result<MigrationPlan, MigrationError> {
+    let client = pool.get().await?;
+    read_plan(&client).await
+}
+
+async fn read_plan(client: &Client) -> Result<MigrationPlan, MigrationError> {
+    let ledger_exists: bool = client
+        .query_one("SELECT to_regclass('schema_migrations') IS NOT NULL", &[])
+        .await?
+        .get(0);
+
+    let mut applied: Vec<(i64, String)> = Vec::new();
+    if ledger_exists {
+        for row in client
+            .query(
+                "SELECT version, checksum FROM schema_migrations ORDER BY version",
+                &[],
+            )
+            .await?
+        {
+            applied.push((row.get(0), row.get(1)));
+        }
+    }
+
+    // Postgres support shipped with the ledger, so there is no legacy schema to adopt.
+    plan_against(MIGRATIONS, &applied, false)
+}
+
+/// Apply every pending migration in order, each inside its own transaction,
+/// and return the versions that were applied.
+pub async fn run_migrations(pool: &Pool) -> Result<Vec<i64>, MigrationError> {
+    let mut client = pool.get().await?;
+
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_ID])
+        .await?;
+    let result = apply_pending(&mut client).await;
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_ID])
+        .await?;
+    result
+}
+
+async fn apply_pending(client: &mut deadpool_postgres::Client) -> Result<Vec<i64>, MigrationError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+            )",
+        )
+        .await?;
+
+    let plan = read_plan(client).await?;
+
+    for version in &plan.unknown {
+        log::warn!("Database has migration {version} applied that this build doesn't know about");
+    }
+
+    let mut applied = Vec::new();
+    for migration in plan.pending {
+        let tx = client.transaction().await?;
+
+        for statement in migration.statements() {
+            if let Err(e) = tx.execute(statement.as_str(), &[]).await {
+                return Err(MigrationError::Failed {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                    statement,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+            &[&migration.version, &migration.name, &migration.checksum()],
+        )
+        .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Applied migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        applied.push(migration.version);
+    }
+
+    log::info!("Database migrations completed");
+    Ok(applied)
+}
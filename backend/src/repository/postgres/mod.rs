@@ -0,0 +1,2960 @@
+//! PostgreSQL storage, compiled in with the `postgres` feature.
+
+mod migrations;
+
+use async_trait::async_trait;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use postgres_native_tls::MakeTlsConnector;
+use std::collections::HashMap;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{IsolationLevel, Row};
+use uuid::Uuid;
+
+use super::{
+    ApiTokenRepository, ConnectionPool, EmailTokenRepository, MatchRepository,
+    RatingHistoryRepository, RefreshTokenRepository, SessionRepository, SnapshotRepository,
+    TwoFactorRepository, UserRepository,
+};
+use crate::db::{MigrationError, MigrationPlan, PoolConfig, PoolMetrics};
+use crate::errors::AppError;
+use crate::models::api_token::{ApiScope, ApiToken};
+use crate::models::elo_history::EloHistory;
+use crate::models::email_token::EmailTokenPurpose;
+use crate::models::guest_match::GuestMatch;
+use crate::models::login_session::{LoginSession, SessionDevice};
+use crate::models::match_record::{MatchRecord, MatchResult, VOIDED};
+use crate::models::match_round::{ChoiceStats, MatchRound};
+use crate::models::refresh_token::{RefreshOutcome, RevokedFamily};
+use crate::models::role::Role;
+use crate::models::user::{PlatformStats, User, UsernameChange};
+use crate::snapshot::{
+    GuestMatchRow, HistoryRow, MatchRow, RecoveryCodeRow, RoundRow, Snapshot, UserRow,
+    UsernameChangeRow,
+};
+
+// Timestamps are read back in the same text format SQLite produces. The
+// aliases shadow the raw columns, so ORDER BY clauses qualify them.
+const USER_COLUMNS: &str = "id, username, email, email_verified, password_hash, avatar_url, google_id, elo, total_games, wins, losses, draws, role, is_banned, to_char(banned_at, 'YYYY-MM-DD HH24:MI:SS') AS banned_at, banned_reason, is_ai, token_version, totp_secret, totp_enabled, pending_email, to_char(username_changed_at, 'YYYY-MM-DD HH24:MI:SS') AS username_changed_at, allow_spectators, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at, to_char(updated_at, 'YYYY-MM-DD HH24:MI:SS') AS updated_at";
+const MATCH_COLUMNS: &str = "id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after, status, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at, to_char(finished_at, 'YYYY-MM-DD HH24:MI:SS') AS finished_at";
+const ROUND_COLUMNS: &str = "match_id, round_number, player1_choice, player2_choice, winner_id, to_char(started_at, 'YYYY-MM-DD HH24:MI:SS.MS') AS started_at, to_char(player1_decided_at, 'YYYY-MM-DD HH24:MI:SS.MS') AS player1_decided_at, to_char(player2_decided_at, 'YYYY-MM-DD HH24:MI:SS.MS') AS player2_decided_at";
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, scopes, to_char(expires_at, 'YYYY-MM-DD HH24:MI:SS') AS expires_at, to_char(last_used_at, 'YYYY-MM-DD HH24:MI:SS') AS last_used_at, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at";
+const GUEST_MATCH_COLUMNS: &str = "id, player1_id, player2_id, winner_id, player1_score, player2_score, status, to_char(finished_at, 'YYYY-MM-DD HH24:MI:SS') AS finished_at";
+const LOGIN_SESSION_COLUMNS: &str = "id, user_id, user_agent, ip_hash, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at, to_char(last_seen_at, 'YYYY-MM-DD HH24:MI:SS') AS last_seen_at, to_char(expires_at, 'YYYY-MM-DD HH24:MI:SS') AS expires_at, to_char(revoked_at, 'YYYY-MM-DD HH24:MI:SS') AS revoked_at";
+const HISTORY_COLUMNS: &str = "id, user_id, match_id, elo_before, elo_after, elo_change, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at";
+const USERNAME_HISTORY_COLUMNS: &str = "id, user_id, old_username, new_username, to_char(changed_at, 'YYYY-MM-DD HH24:MI:SS') AS changed_at";
+const RECOVERY_CODE_COLUMNS: &str = "id, user_id, code_hash, to_char(used_at, 'YYYY-MM-DD HH24:MI:SS') AS used_at, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') AS created_at";
+
+fn internal(e: impl std::fmt::Display) -> AppError {
+    AppError::Internal(e.to_string())
+}
+
+/// Map unique violations on `users` to the same conflicts the libSQL store reports.
+fn user_write_error(e: tokio_postgres::Error) -> AppError {
+    match e.as_db_error() {
+        Some(db) if *db.code() == SqlState::UNIQUE_VIOLATION => {
+            if db.constraint().is_some_and(|c| c.contains("username")) {
+                AppError::Conflict("Username already taken".into())
+            } else {
+                AppError::Conflict("Email already registered".into())
+            }
+        }
+        _ => internal(e),
+    }
+}
+
+/// Unknown roles get no permissions.
+fn role_from_row(row: &Row) -> Result<Role, AppError> {
+    let role: String = row.try_get("role").map_err(internal)?;
+    Ok(Role::parse(&role).unwrap_or_default())
+}
+
+fn user_from_row(row: &Row) -> Result<User, AppError> {
+    Ok(User {
+        id: row.try_get("id").map_err(internal)?,
+        username: row.try_get("username").map_err(internal)?,
+        email: row.try_get("email").map_err(internal)?,
+        email_verified: row.try_get("email_verified").map_err(internal)?,
+        password_hash: row.try_get("password_hash").map_err(internal)?,
+        avatar_url: row.try_get("avatar_url").map_err(internal)?,
+        google_id: row.try_get("google_id").map_err(internal)?,
+        elo: row.try_get("elo").map_err(internal)?,
+        total_games: row.try_get("total_games").map_err(internal)?,
+        wins: row.try_get("wins").map_err(internal)?,
+        losses: row.try_get("losses").map_err(internal)?,
+        draws: row.try_get("draws").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+        updated_at: row.try_get("updated_at").map_err(internal)?,
+        role: role_from_row(row)?,
+        is_banned: row.try_get("is_banned").map_err(internal)?,
+        banned_at: row.try_get("banned_at").map_err(internal)?,
+        banned_reason: row.try_get("banned_reason").map_err(internal)?,
+        is_ai: row.try_get("is_ai").map_err(internal)?,
+        token_version: row.try_get("token_version").map_err(internal)?,
+        totp_secret: row.try_get("totp_secret").map_err(internal)?,
+        totp_enabled: row.try_get("totp_enabled").map_err(internal)?,
+        pending_email: row.try_get("pending_email").map_err(internal)?,
+        username_changed_at: row.try_get("username_changed_at").map_err(internal)?,
+        allow_spectators: row.try_get("allow_spectators").map_err(internal)?,
+    })
+}
+
+fn match_from_row(row: &Row) -> Result<MatchRecord, AppError> {
+    Ok(MatchRecord {
+        id: row.try_get("id").map_err(internal)?,
+        player1_id: row.try_get("player1_id").map_err(internal)?,
+        player2_id: row.try_get("player2_id").map_err(internal)?,
+        winner_id: row.try_get("winner_id").map_err(internal)?,
+        is_ranked: row.try_get("is_ranked").map_err(internal)?,
+        player1_score: row.try_get("player1_score").map_err(internal)?,
+        player2_score: row.try_get("player2_score").map_err(internal)?,
+        player1_elo_before: row.try_get("player1_elo_before").map_err(internal)?,
+        player1_elo_after: row.try_get("player1_elo_after").map_err(internal)?,
+        player2_elo_before: row.try_get("player2_elo_before").map_err(internal)?,
+        player2_elo_after: row.try_get("player2_elo_after").map_err(internal)?,
+        status: row.try_get("status").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+        finished_at: row.try_get("finished_at").map_err(internal)?,
+        rounds: Vec::new(),
+    })
+}
+
+fn guest_match_from_row(row: &Row) -> Result<GuestMatch, AppError> {
+    Ok(GuestMatch {
+        id: row.try_get("id").map_err(internal)?,
+        player1_id: row.try_get("player1_id").map_err(internal)?,
+        player2_id: row.try_get("player2_id").map_err(internal)?,
+        winner_id: row.try_get("winner_id").map_err(internal)?,
+        player1_score: row.try_get("player1_score").map_err(internal)?,
+        player2_score: row.try_get("player2_score").map_err(internal)?,
+        status: row.try_get("status").map_err(internal)?,
+        finished_at: row.try_get("finished_at").map_err(internal)?,
+    })
+}
+
+fn round_from_row(row: &Row) -> Result<(String, MatchRound), AppError> {
+    let match_id = row.try_get("match_id").map_err(internal)?;
+    let round = MatchRound {
+        round_number: row.try_get("round_number").map_err(internal)?,
+        player1_choice: row.try_get("player1_choice").map_err(internal)?,
+        player2_choice: row.try_get("player2_choice").map_err(internal)?,
+        winner_id: row.try_get("winner_id").map_err(internal)?,
+        started_at: row.try_get("started_at").map_err(internal)?,
+        player1_decided_at: row.try_get("player1_decided_at").map_err(internal)?,
+        player2_decided_at: row.try_get("player2_decided_at").map_err(internal)?,
+    };
+    Ok((match_id, round))
+}
+
+fn history_from_row(row: &Row) -> Result<EloHistory, AppError> {
+    Ok(EloHistory {
+        id: row.try_get("id").map_err(internal)?,
+        user_id: row.try_get("user_id").map_err(internal)?,
+        match_id: row.try_get("match_id").map_err(internal)?,
+        elo_before: row.try_get("elo_before").map_err(internal)?,
+        elo_after: row.try_get("elo_after").map_err(internal)?,
+        elo_change: row.try_get("elo_change").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+    })
+}
+
+fn api_token_from_row(row: &Row) -> Result<ApiToken, AppError> {
+    let scopes: String = row.try_get("scopes").map_err(internal)?;
+    Ok(ApiToken {
+        id: row.try_get("id").map_err(internal)?,
+        user_id: row.try_get("user_id").map_err(internal)?,
+        name: row.try_get("name").map_err(internal)?,
+        scopes: ApiScope::split(&scopes),
+        expires_at: row.try_get("expires_at").map_err(internal)?,
+        last_used_at: row.try_get("last_used_at").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+    })
+}
+
+fn login_session_from_row(row: &Row) -> Result<LoginSession, AppError> {
+    Ok(LoginSession {
+        id: row.try_get("id").map_err(internal)?,
+        user_id: row.try_get("user_id").map_err(internal)?,
+        user_agent: row.try_get("user_agent").map_err(internal)?,
+        ip_hash: row.try_get("ip_hash").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+        last_seen_at: row.try_get("last_seen_at").map_err(internal)?,
+        expires_at: row.try_get("expires_at").map_err(internal)?,
+        revoked_at: row.try_get("revoked_at").map_err(internal)?,
+    })
+}
+
+/// Repositories backed by a pooled PostgreSQL connection.
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl PostgresStore {
+    /// Build a pool for a `postgres://` URL. Connections are opened lazily,
+    /// using TLS whenever the server offers it (see `sslmode`).
+    pub fn connect(url: &str, pool: &PoolConfig) -> Result<Self, String> {
+        let config: tokio_postgres::Config = url
+            .parse()
+            .map_err(|e| format!("Invalid PostgreSQL DATABASE_URL: {e}"))?;
+        Self::from_config(config, pool)
+    }
+
+    fn from_config(config: tokio_postgres::Config, pool: &PoolConfig) -> Result<Self, String> {
+        let tls = native_tls::TlsConnector::new()
+            .map_err(|e| format!("Failed to set up TLS for PostgreSQL: {e}"))?;
+        let manager = Manager::from_config(
+            config,
+            MakeTlsConnector::new(tls),
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .max_size(pool.max_size)
+            .wait_timeout(Some(pool.acquire_timeout))
+            .runtime(Runtime::Tokio1)
+            .build()
+            .map_err(|e| format!("Failed to build PostgreSQL pool: {e}"))?;
+        Ok(Self { pool })
+    }
+
+    pub async fn plan_migrations(&self) -> Result<MigrationPlan, MigrationError> {
+        migrations::plan_migrations(&self.pool).await
+    }
+
+    pub async fn run_migrations(&self) -> Result<Vec<i64>, MigrationError> {
+        migrations::run_migrations(&self.pool).await
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Client, AppError> {
+        self.pool.get().await.map_err(internal)
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresStore {
+    async fn create(
+        &self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<User, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                &format!("INSERT INTO users (id, username, email, password_hash) VALUES ($1, $2, $3, $4) RETURNING {USER_COLUMNS}"),
+                &[&Uuid::new_v4().to_string(), &username, &email, &password_hash],
+            )
+            .await
+            .map_err(user_write_error)?;
+        user_from_row(&row)
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, AppError> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE id = $1"),
+                &[&id],
+            )
+            .await
+            .map_err(internal)?
+            .map(|row| user_from_row(&row))
+            .transpose()
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE email = $1"),
+                &[&email],
+            )
+            .await
+            .map_err(internal)?
+            .map(|row| user_from_row(&row))
+            .transpose()
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                &format!(
+                    "SELECT {USER_COLUMNS} FROM users WHERE lower(username) = lower($1) LIMIT 1"
+                ),
+                &[&username],
+            )
+            .await
+            .map_err(internal)?
+            .map(|row| user_from_row(&row))
+            .transpose()
+    }
+
+    async fn create_external(
+        &self,
+        username: &str,
+        email: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<User, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                &format!("INSERT INTO users (id, username, email, google_id, avatar_url, email_verified) VALUES ($1, $2, $3, $4, $5, TRUE) RETURNING {USER_COLUMNS}"),
+                &[&Uuid::new_v4().to_string(), &username, &email, &google_id, &avatar_url],
+            )
+            .await
+            .map_err(user_write_error)?;
+        user_from_row(&row)
+    }
+
+    async fn find_by_google_id(&self, google_id: &str) -> Result<Option<User>, AppError> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE google_id = $1"),
+                &[&google_id],
+            )
+            .await
+            .map_err(internal)?
+            .map(|row| user_from_row(&row))
+            .transpose()
+    }
+
+    async fn link_google_id(
+        &self,
+        user_id: &str,
+        google_id: &str,
+        avatar_url: Option<&str>,
+    ) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET google_id = $1, avatar_url = COALESCE(avatar_url, $2), email_verified = TRUE, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $3",
+                &[&google_id, &avatar_url, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn set_password(&self, user_id: &str, password_hash: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET password_hash = $1, token_version = token_version + 1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&password_hash, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, user_id: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET email_verified = TRUE, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn set_pending_email(&self, user_id: &str, email: Option<&str>) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET pending_email = $1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&email, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn confirm_email_change(&self, user_id: &str, email: &str) -> Result<bool, AppError> {
+        let client = self.client().await?;
+        let changed = client
+            .execute(
+                "UPDATE users SET email = pending_email, pending_email = NULL, email_verified = TRUE, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1 AND pending_email = $2",
+                &[&user_id, &email],
+            )
+            .await
+            .map_err(user_write_error)?;
+        Ok(changed > 0)
+    }
+
+    async fn rename(&self, user_id: &str, username: &str) -> Result<(), AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        tx.execute(
+            "INSERT INTO username_history (id, user_id, old_username, new_username) SELECT $1, id, username, $2 FROM users WHERE id = $3",
+            &[&Uuid::new_v4().to_string(), &username, &user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "UPDATE users SET username = $1, username_changed_at = (now() AT TIME ZONE 'utc'), updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+            &[&username, &user_id],
+        )
+        .await
+        .map_err(user_write_error)?;
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn username_history(&self, user_id: &str) -> Result<Vec<UsernameChange>, AppError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT old_username, new_username, to_char(changed_at, 'YYYY-MM-DD HH24:MI:SS') AS changed_at FROM username_history WHERE user_id = $1 ORDER BY username_history.changed_at DESC",
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+        rows.iter()
+            .map(|row| {
+                Ok(UsernameChange {
+                    old_username: row.try_get("old_username").map_err(internal)?,
+                    new_username: row.try_get("new_username").map_err(internal)?,
+                    changed_at: row.try_get("changed_at").map_err(internal)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn set_avatar(&self, user_id: &str, avatar_url: Option<&str>) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET avatar_url = $1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&avatar_url, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn set_allow_spectators(&self, user_id: &str, allow: bool) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET allow_spectators = $1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&allow, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn top_by_elo(&self, limit: i32) -> Result<Vec<User>, AppError> {
+        let client = self.client().await?;
+        client
+            .query(
+                &format!("SELECT {USER_COLUMNS} FROM users ORDER BY elo DESC LIMIT $1"),
+                &[&i64::from(limit)],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(user_from_row)
+            .collect()
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        // Foreign keys are enforced here, so the opponents' history rows for
+        // the deleted matches have to go too. Rounds cascade with their match.
+        tx.execute(
+            "DELETE FROM elo_history WHERE user_id = $1 OR match_id IN (SELECT id FROM matches WHERE player1_id = $1 OR player2_id = $1)",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "DELETE FROM matches WHERE player1_id = $1 OR player2_id = $1",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "DELETE FROM guest_matches WHERE player1_id = $1 OR player2_id = $1",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute("DELETE FROM users WHERE id = $1", &[&user_id])
+            .await
+            .map_err(internal)?;
+
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn set_role(&self, user_id: &str, role: Role) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET role = $1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&role.as_str(), &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn list_with_filters(
+        &self,
+        search: Option<&str>,
+        sort_by: Option<&str>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, AppError> {
+        let order = match sort_by {
+            Some("elo") => "elo DESC",
+            Some("created_at") => "users.created_at DESC",
+            Some("total_games") => "total_games DESC",
+            _ => "users.created_at DESC",
+        };
+        let search = search.filter(|s| !s.is_empty());
+
+        let client = self.client().await?;
+        client
+            .query(
+                &format!(
+                    "SELECT {USER_COLUMNS} FROM users WHERE ($1::text IS NULL OR username ILIKE '%' || $1 || '%' OR email ILIKE '%' || $1 || '%') ORDER BY {order} LIMIT $2 OFFSET $3"
+                ),
+                &[&search, &i64::from(limit), &i64::from(offset)],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(user_from_row)
+            .collect()
+    }
+
+    async fn count_all(&self, search: Option<&str>) -> Result<i64, AppError> {
+        let search = search.filter(|s| !s.is_empty());
+
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                "SELECT COUNT(*) FROM users WHERE ($1::text IS NULL OR username ILIKE '%' || $1 || '%' OR email ILIKE '%' || $1 || '%')",
+                &[&search],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(row.get(0))
+    }
+
+    async fn ban(&self, user_id: &str, reason: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET is_banned = TRUE, banned_at = (now() AT TIME ZONE 'utc'), banned_reason = $1, token_version = token_version + 1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&reason, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn unban(&self, user_id: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET is_banned = FALSE, banned_at = NULL, banned_reason = NULL, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn update_stats(
+        &self,
+        user_id: &str,
+        username: Option<&str>,
+        elo: Option<i32>,
+        wins: Option<i32>,
+        losses: Option<i32>,
+        draws: Option<i32>,
+    ) -> Result<(), AppError> {
+        if username.is_none()
+            && elo.is_none()
+            && wins.is_none()
+            && losses.is_none()
+            && draws.is_none()
+        {
+            return Ok(());
+        }
+        let refresh_total_games = wins.is_some() || losses.is_some() || draws.is_some();
+
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET
+                    username = COALESCE($2, username),
+                    elo = COALESCE($3, elo),
+                    wins = COALESCE($4, wins),
+                    losses = COALESCE($5, losses),
+                    draws = COALESCE($6, draws),
+                    total_games = CASE WHEN $7 THEN COALESCE($4, wins) + COALESCE($5, losses) + COALESCE($6, draws) ELSE total_games END,
+                    updated_at = (now() AT TIME ZONE 'utc')
+                 WHERE id = $1",
+                &[&user_id, &username, &elo, &wins, &losses, &draws, &refresh_total_games],
+            )
+            .await
+            .map_err(user_write_error)?;
+        Ok(())
+    }
+
+    async fn platform_stats(&self) -> Result<PlatformStats, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                "SELECT
+                    (SELECT COUNT(*) FROM users),
+                    (SELECT COUNT(DISTINCT user_id) FROM elo_history WHERE created_at > (now() AT TIME ZONE 'utc') - INTERVAL '30 days'),
+                    (SELECT COUNT(*) FROM matches),
+                    (SELECT COUNT(*) FROM users WHERE is_banned)",
+                &[],
+            )
+            .await
+            .map_err(internal)?;
+
+        Ok(PlatformStats {
+            total_users: row.get(0),
+            active_users: row.get(1),
+            total_matches: row.get(2),
+            banned_users: row.get(3),
+        })
+    }
+
+    async fn random_ai(&self) -> Result<User, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE is_ai ORDER BY random() LIMIT 1"),
+                &[],
+            )
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| AppError::Internal("No AI users available".into()))?;
+        user_from_row(&row)
+    }
+}
+
+#[async_trait]
+impl MatchRepository for PostgresStore {
+    async fn record_result(&self, result: &MatchResult) -> Result<MatchRecord, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        let match_id = &result.id;
+
+        // Lock both players so concurrent matches rate from the same values
+        // they overwrite.
+        let mut ratings = HashMap::new();
+        for row in tx
+            .query(
+                "SELECT id, elo, total_games FROM users WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+                &[&vec![result.player1_id.clone(), result.player2_id.clone()]],
+            )
+            .await
+            .map_err(internal)?
+        {
+            ratings.insert(row.get::<_, String>(0), (row.get(1), row.get(2)));
+        }
+        let rating = |user_id: &String| {
+            ratings
+                .get(user_id)
+                .copied()
+                .ok_or_else(|| AppError::NotFound(format!("User {user_id} not found")))
+        };
+        let p1_before: (i32, i32) = rating(&result.player1_id)?;
+        let p2_before: (i32, i32) = rating(&result.player2_id)?;
+        let (p1_after, p2_after) = result.ratings_after(p1_before, p2_before);
+
+        let inserted = tx
+            .execute(
+                "INSERT INTO matches (id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after, status, finished_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, (now() AT TIME ZONE 'utc'))
+                 ON CONFLICT (id) DO NOTHING",
+                &[
+                    match_id,
+                    &result.player1_id,
+                    &result.player2_id,
+                    &result.winner_id,
+                    &result.is_ranked,
+                    &result.player1_score,
+                    &result.player2_score,
+                    &p1_before.0,
+                    &p1_after,
+                    &p2_before.0,
+                    &p2_after,
+                    &result.status,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        if inserted == 0 {
+            // Already recorded by an earlier attempt
+            let row = tx
+                .query_one(
+                    &format!("SELECT {MATCH_COLUMNS} FROM matches WHERE id = $1"),
+                    &[match_id],
+                )
+                .await
+                .map_err(internal)?;
+            let mut recorded = match_from_row(&row)?;
+            for row in &tx
+                .query(
+                    &format!("SELECT {ROUND_COLUMNS} FROM match_rounds WHERE match_id = $1 ORDER BY round_number"),
+                    &[match_id],
+                )
+                .await
+                .map_err(internal)?
+            {
+                recorded.rounds.push(round_from_row(row)?.1);
+            }
+            return Ok(recorded);
+        }
+
+        for round in &result.rounds {
+            tx.execute(
+                "INSERT INTO match_rounds (match_id, round_number, player1_choice, player2_choice, winner_id, started_at, player1_decided_at, player2_decided_at)
+                 VALUES ($1, $2, $3, $4, $5, $6::text::timestamp, $7::text::timestamp, $8::text::timestamp)",
+                &[
+                    &match_id,
+                    &round.round_number,
+                    &round.player1_choice,
+                    &round.player2_choice,
+                    &round.winner_id,
+                    &round.started_at,
+                    &round.player1_decided_at,
+                    &round.player2_decided_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let players = [
+            (&result.player1_id, p1_before.0, p1_after),
+            (&result.player2_id, p2_before.0, p2_after),
+        ];
+
+        for (user_id, elo_before, elo_after) in players {
+            if result.is_ranked {
+                tx.execute(
+                    "UPDATE users SET elo = $1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                    &[&elo_after, user_id],
+                )
+                .await
+                .map_err(internal)?;
+                tx.execute(
+                    "INSERT INTO elo_history (id, user_id, match_id, elo_before, elo_after, elo_change) VALUES ($1, $2, $3, $4, $5, $6)",
+                    &[
+                        &Uuid::new_v4().to_string(),
+                        user_id,
+                        &match_id,
+                        &elo_before,
+                        &elo_after,
+                        &(elo_after - elo_before),
+                    ],
+                )
+                .await
+                .map_err(internal)?;
+            }
+
+            let won = result.winner_id.as_ref().map(|winner| winner == user_id);
+            let (wins, losses, draws) = match won {
+                Some(true) => (1, 0, 0),
+                Some(false) => (0, 1, 0),
+                None => (0, 0, 1),
+            };
+            let updated = tx
+                .execute(
+                    "UPDATE users SET total_games = total_games + 1, wins = wins + $2, losses = losses + $3, draws = draws + $4, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+                    &[user_id, &wins, &losses, &draws],
+                )
+                .await
+                .map_err(internal)?;
+            if updated == 0 {
+                return Err(AppError::NotFound(format!("User {user_id} not found")));
+            }
+        }
+
+        let row = tx
+            .query_one(
+                &format!("SELECT {MATCH_COLUMNS} FROM matches WHERE id = $1"),
+                &[&match_id],
+            )
+            .await
+            .map_err(internal)?;
+        let mut recorded = match_from_row(&row)?;
+        recorded.rounds = result.rounds.clone();
+
+        tx.commit().await.map_err(internal)?;
+        Ok(recorded)
+    }
+
+    async fn recent_for_user(
+        &self,
+        user_id: &str,
+        limit: i32,
+    ) -> Result<Vec<MatchRecord>, AppError> {
+        let client = self.client().await?;
+        let mut matches = client
+            .query(
+                &format!("SELECT {MATCH_COLUMNS} FROM matches WHERE (player1_id = $1 OR player2_id = $1) AND status != 'in_progress' ORDER BY matches.finished_at DESC NULLS LAST LIMIT $2"),
+                &[&user_id, &i64::from(limit)],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(match_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ids: Vec<String> = matches.iter().map(|m| m.id.clone()).collect();
+        let rows = client
+            .query(
+                &format!("SELECT {ROUND_COLUMNS} FROM match_rounds WHERE match_id = ANY($1) ORDER BY match_id, round_number"),
+                &[&ids],
+            )
+            .await
+            .map_err(internal)?;
+        for row in &rows {
+            let (match_id, round) = round_from_row(row)?;
+            if let Some(m) = matches.iter_mut().find(|m| m.id == match_id) {
+                m.rounds.push(round);
+            }
+        }
+
+        Ok(matches)
+    }
+
+    async fn choice_stats_for_user(&self, user_id: &str) -> Result<ChoiceStats, AppError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT CASE WHEN m.player1_id = $1 THEN r.player1_choice ELSE r.player2_choice END AS choice, COUNT(*)
+                 FROM match_rounds r JOIN matches m ON m.id = r.match_id
+                 WHERE m.player1_id = $1 OR m.player2_id = $1
+                 GROUP BY choice",
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+
+        let mut stats = ChoiceStats::default();
+        for row in &rows {
+            let count: i64 = row.get(1);
+            match row.get::<_, Option<&str>>(0) {
+                Some("rock") => stats.rock += count,
+                Some("paper") => stats.paper += count,
+                Some("scissors") => stats.scissors += count,
+                _ => stats.none += count,
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn void_match(&self, match_id: &str) -> Result<MatchRecord, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        let row = tx
+            .query_opt(
+                &format!("SELECT {MATCH_COLUMNS} FROM matches WHERE id = $1 FOR UPDATE"),
+                &[&match_id],
+            )
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| AppError::NotFound("Match not found".into()))?;
+        let mut record = match_from_row(&row)?;
+        let reversal = record.void_reversal()?;
+
+        // Lock both players in id order, as record_result does, so the two
+        // can't deadlock.
+        let player_ids = [reversal[0].user_id, reversal[1].user_id];
+        tx.query(
+            "SELECT id FROM users WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+            &[&player_ids.as_slice()],
+        )
+        .await
+        .map_err(internal)?;
+        for player in reversal {
+            let (wins, losses, draws) = match player.won {
+                Some(true) => (1, 0, 0),
+                Some(false) => (0, 1, 0),
+                None => (0, 0, 1),
+            };
+            let updated = tx
+                .execute(
+                    "UPDATE users SET elo = elo - $2, total_games = GREATEST(total_games - 1, 0), wins = GREATEST(wins - $3, 0), losses = GREATEST(losses - $4, 0), draws = GREATEST(draws - $5, 0), updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+                    &[&player.user_id, &player.elo_change, &wins, &losses, &draws],
+                )
+                .await
+                .map_err(internal)?;
+            if updated == 0 {
+                return Err(AppError::NotFound(format!(
+                    "User {} not found",
+                    player.user_id
+                )));
+            }
+        }
+        tx.execute("DELETE FROM elo_history WHERE match_id = $1", &[&match_id])
+            .await
+            .map_err(internal)?;
+        tx.execute(
+            "UPDATE matches SET status = $1 WHERE id = $2",
+            &[&VOIDED, &match_id],
+        )
+        .await
+        .map_err(internal)?;
+
+        tx.commit().await.map_err(internal)?;
+        record.status = VOIDED.to_string();
+        Ok(record)
+    }
+
+    async fn record_guest_match(&self, result: &MatchResult) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO guest_matches (id, player1_id, player2_id, winner_id, player1_score, player2_score, status) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (id) DO NOTHING",
+                &[
+                    &result.id,
+                    &result.player1_id,
+                    &result.player2_id,
+                    &result.winner_id,
+                    &result.player1_score,
+                    &result.player2_score,
+                    &result.status,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn claim_guest_matches(&self, guest_id: &str, user_id: &str) -> Result<u64, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        let matches = tx
+            .query(
+                &format!("SELECT {GUEST_MATCH_COLUMNS} FROM guest_matches WHERE player1_id = $1 OR player2_id = $1 FOR UPDATE"),
+                &[&guest_id],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(|row| Ok(guest_match_from_row(row)?.claimed_by(guest_id, user_id)))
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let mut moved = 0;
+        for m in matches {
+            if !m.between_accounts() {
+                tx.execute(
+                    "UPDATE guest_matches SET player1_id = $2, player2_id = $3, winner_id = $4 WHERE id = $1",
+                    &[&m.id, &m.player1_id, &m.player2_id, &m.winner_id],
+                )
+                .await
+                .map_err(internal)?;
+                continue;
+            }
+
+            tx.execute(
+                "INSERT INTO matches (id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, status, created_at, finished_at)
+                 SELECT id, $2, $3, $4, FALSE, player1_score, player2_score, status, finished_at, finished_at FROM guest_matches WHERE id = $1",
+                &[&m.id, &m.player1_id, &m.player2_id, &m.winner_id],
+            )
+            .await
+            .map_err(|e| match e.code() {
+                Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => {
+                    AppError::NotFound("Match player not found".into())
+                }
+                _ => internal(e),
+            })?;
+            let won = m.winner_id.as_deref().map(|winner| winner == user_id);
+            let (wins, losses, draws) = match won {
+                Some(true) => (1, 0, 0),
+                Some(false) => (0, 1, 0),
+                None => (0, 0, 1),
+            };
+            tx.execute(
+                "UPDATE users SET total_games = total_games + 1, wins = wins + $2, losses = losses + $3, draws = draws + $4, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+                &[&user_id, &wins, &losses, &draws],
+            )
+            .await
+            .map_err(internal)?;
+            tx.execute("DELETE FROM guest_matches WHERE id = $1", &[&m.id])
+                .await
+                .map_err(internal)?;
+            moved += 1;
+        }
+
+        tx.commit().await.map_err(internal)?;
+        Ok(moved)
+    }
+}
+
+#[async_trait]
+impl RatingHistoryRepository for PostgresStore {
+    async fn for_user(&self, user_id: &str, limit: i32) -> Result<Vec<EloHistory>, AppError> {
+        let client = self.client().await?;
+        client
+            .query(
+                &format!("SELECT {HISTORY_COLUMNS} FROM elo_history WHERE user_id = $1 ORDER BY elo_history.created_at DESC LIMIT $2"),
+                &[&user_id, &i64::from(limit)],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(history_from_row)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for PostgresStore {
+    async fn start_family(
+        &self,
+        user_id: &str,
+        device: &SessionDevice,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<LoginSession, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        let row = tx
+            .query_one(
+                &format!(
+                    "INSERT INTO login_sessions (id, user_id, user_agent, ip_hash, expires_at) VALUES ($1, $2, $3, $4, $5::text::timestamp) RETURNING {LOGIN_SESSION_COLUMNS}"
+                ),
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &user_id,
+                    &device.user_agent,
+                    &device.ip_hash,
+                    &expires_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        let session = login_session_from_row(&row)?;
+        tx.execute(
+            "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4, $5::text::timestamp)",
+            &[
+                &Uuid::new_v4().to_string(),
+                &user_id,
+                &session.id,
+                &token_hash,
+                &expires_at,
+            ],
+        )
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+
+        Ok(session)
+    }
+
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: &str,
+    ) -> Result<RefreshOutcome, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        // The row lock serialises concurrent attempts to rotate the same token.
+        let Some(row) = tx
+            .query_opt(
+                "SELECT user_id, family_id, expires_at > (now() AT TIME ZONE 'utc') AS live, rotated_at IS NOT NULL AS rotated, revoked_at IS NOT NULL AS revoked FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+                &[&token_hash],
+            )
+            .await
+            .map_err(internal)?
+        else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+        let user_id: String = row.try_get("user_id").map_err(internal)?;
+        let family_id: String = row.try_get("family_id").map_err(internal)?;
+        let live: bool = row.try_get("live").map_err(internal)?;
+        let rotated: bool = row.try_get("rotated").map_err(internal)?;
+        let revoked: bool = row.try_get("revoked").map_err(internal)?;
+
+        if revoked {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        if rotated {
+            tx.execute(
+                "UPDATE refresh_tokens SET revoked_at = now() AT TIME ZONE 'utc' WHERE family_id = $1 AND revoked_at IS NULL",
+                &[&family_id],
+            )
+            .await
+            .map_err(internal)?;
+            tx.execute(
+                "UPDATE login_sessions SET revoked_at = now() AT TIME ZONE 'utc' WHERE id = $1 AND revoked_at IS NULL",
+                &[&family_id],
+            )
+            .await
+            .map_err(internal)?;
+            tx.commit().await.map_err(internal)?;
+            return Ok(RefreshOutcome::Reused {
+                user_id,
+                session_id: family_id,
+            });
+        }
+
+        if !live {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        tx.execute(
+            "UPDATE refresh_tokens SET rotated_at = now() AT TIME ZONE 'utc' WHERE token_hash = $1",
+            &[&token_hash],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4, $5::text::timestamp)",
+            &[
+                &Uuid::new_v4().to_string(),
+                &user_id,
+                &family_id,
+                &new_token_hash,
+                &new_expires_at,
+            ],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "UPDATE login_sessions SET last_seen_at = now() AT TIME ZONE 'utc', expires_at = $1::text::timestamp WHERE id = $2",
+            &[&new_expires_at, &family_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+
+        Ok(RefreshOutcome::Rotated {
+            user_id,
+            session_id: family_id,
+        })
+    }
+
+    async fn revoke_family(&self, token_hash: &str) -> Result<Option<RevokedFamily>, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        let Some(row) = tx
+            .query_opt(
+                "SELECT user_id, family_id FROM refresh_tokens WHERE token_hash = $1",
+                &[&token_hash],
+            )
+            .await
+            .map_err(internal)?
+        else {
+            return Ok(None);
+        };
+        let family = RevokedFamily {
+            user_id: row.try_get("user_id").map_err(internal)?,
+            session_id: row.try_get("family_id").map_err(internal)?,
+        };
+        tx.execute(
+            "UPDATE login_sessions SET revoked_at = now() AT TIME ZONE 'utc' WHERE revoked_at IS NULL AND id = $1",
+            &[&family.session_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = now() AT TIME ZONE 'utc' WHERE revoked_at IS NULL AND family_id = $1",
+            &[&family.session_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(Some(family))
+    }
+
+    async fn revoke_all_for_user(&self, user_id: &str) -> Result<(), AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        tx.execute(
+            "UPDATE login_sessions SET revoked_at = now() AT TIME ZONE 'utc' WHERE user_id = $1 AND revoked_at IS NULL",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = now() AT TIME ZONE 'utc' WHERE user_id = $1 AND revoked_at IS NULL",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PostgresStore {
+    async fn find_session(&self, id: &str) -> Result<Option<LoginSession>, AppError> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                &format!("SELECT {LOGIN_SESSION_COLUMNS} FROM login_sessions WHERE id = $1"),
+                &[&id],
+            )
+            .await
+            .map_err(internal)?
+            .map(|row| login_session_from_row(&row))
+            .transpose()
+    }
+
+    async fn list_sessions(&self, user_id: &str) -> Result<Vec<LoginSession>, AppError> {
+        let client = self.client().await?;
+        client
+            .query(
+                &format!(
+                    "SELECT {LOGIN_SESSION_COLUMNS} FROM login_sessions s WHERE user_id = $1 AND s.revoked_at IS NULL AND s.expires_at > (now() AT TIME ZONE 'utc') ORDER BY s.last_seen_at DESC, s.created_at DESC"
+                ),
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?
+            .iter()
+            .map(login_session_from_row)
+            .collect()
+    }
+
+    async fn touch_session(&self, id: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE login_sessions SET last_seen_at = now() AT TIME ZONE 'utc' WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn revoke_session(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        let revoked = tx
+            .execute(
+                "UPDATE login_sessions SET revoked_at = now() AT TIME ZONE 'utc' WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+                &[&id, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = now() AT TIME ZONE 'utc' WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            &[&id, &user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(revoked > 0)
+    }
+}
+
+#[async_trait]
+impl EmailTokenRepository for PostgresStore {
+    async fn issue(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO email_tokens (id, user_id, purpose, expires_at) VALUES ($1, $2, $3, $4::text::timestamp)",
+                &[&id, &user_id, &purpose.as_str(), &expires_at],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        id: &str,
+        user_id: &str,
+        purpose: EmailTokenPurpose,
+    ) -> Result<bool, AppError> {
+        let client = self.client().await?;
+        let updated = client
+            .execute(
+                "UPDATE email_tokens SET used_at = now() AT TIME ZONE 'utc' WHERE id = $1 AND user_id = $2 AND purpose = $3 AND used_at IS NULL AND expires_at > (now() AT TIME ZONE 'utc')",
+                &[&id, &user_id, &purpose.as_str()],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(updated == 1)
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PostgresStore {
+    async fn set_pending_secret(&self, user_id: &str, secret: &str) -> Result<(), AppError> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET totp_secret = $1, totp_enabled = FALSE, totp_last_step = NULL, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+                &[&secret, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn enable(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        tx.execute(
+            "UPDATE users SET totp_enabled = TRUE, totp_last_step = $1, token_version = token_version + 1, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $2",
+            &[&step, &user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute("DELETE FROM recovery_codes WHERE user_id = $1", &[&user_id])
+            .await
+            .map_err(internal)?;
+        for hash in recovery_code_hashes {
+            tx.execute(
+                "INSERT INTO recovery_codes (id, user_id, code_hash) VALUES ($1, $2, $3)",
+                &[&Uuid::new_v4().to_string(), &user_id, hash],
+            )
+            .await
+            .map_err(internal)?;
+        }
+        tx.commit().await.map_err(internal)?;
+        Ok(())
+    }
+
+    async fn disable(&self, user_id: &str) -> Result<(), AppError> {
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+
+        tx.execute(
+            "UPDATE users SET totp_secret = NULL, totp_enabled = FALSE, totp_last_step = NULL, updated_at = (now() AT TIME ZONE 'utc') WHERE id = $1",
+            &[&user_id],
+        )
+        .await
+        .map_err(internal)?;
+        tx.execute("DELETE FROM recovery_codes WHERE user_id = $1", &[&user_id])
+            .await
+            .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(())
+    }
+
+    async fn accept_step(&self, user_id: &str, step: i64) -> Result<bool, AppError> {
+        let client = self.client().await?;
+        let updated = client
+            .execute(
+                "UPDATE users SET totp_last_step = $1 WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+                &[&step, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(updated == 1)
+    }
+
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str) -> Result<bool, AppError> {
+        let client = self.client().await?;
+        let updated = client
+            .execute(
+                "UPDATE recovery_codes SET used_at = now() AT TIME ZONE 'utc' WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+                &[&user_id, &code_hash],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(updated == 1)
+    }
+
+    async fn recovery_codes_left(&self, user_id: &str) -> Result<i64, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                "SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(row.get(0))
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for PostgresStore {
+    async fn create_token(
+        &self,
+        user_id: &str,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiScope],
+        expires_at: Option<&str>,
+    ) -> Result<ApiToken, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                &format!("INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6::text::timestamp) RETURNING {API_TOKEN_COLUMNS}"),
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &user_id,
+                    &name,
+                    &token_hash,
+                    &ApiScope::join(scopes),
+                    &expires_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        api_token_from_row(&row)
+    }
+
+    async fn list_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>, AppError> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                &format!("SELECT {API_TOKEN_COLUMNS} FROM api_tokens WHERE user_id = $1 ORDER BY api_tokens.created_at DESC, id"),
+                &[&user_id],
+            )
+            .await
+            .map_err(internal)?;
+        rows.iter().map(api_token_from_row).collect()
+    }
+
+    async fn revoke_token(&self, user_id: &str, id: &str) -> Result<bool, AppError> {
+        let client = self.client().await?;
+        let deleted = client
+            .execute(
+                "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2",
+                &[&id, &user_id],
+            )
+            .await
+            .map_err(internal)?;
+        Ok(deleted > 0)
+    }
+
+    async fn use_token(&self, token_hash: &str) -> Result<Option<ApiToken>, AppError> {
+        let client = self.client().await?;
+        let row = client
+            .query_opt(
+                &format!("UPDATE api_tokens SET last_used_at = now() AT TIME ZONE 'utc' WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > now() AT TIME ZONE 'utc') RETURNING {API_TOKEN_COLUMNS}"),
+                &[&token_hash],
+            )
+            .await
+            .map_err(internal)?;
+        row.as_ref().map(api_token_from_row).transpose()
+    }
+}
+
+fn snapshot_user(row: &Row) -> Result<UserRow, AppError> {
+    Ok(UserRow {
+        id: row.try_get("id").map_err(internal)?,
+        username: row.try_get("username").map_err(internal)?,
+        email: row.try_get("email").map_err(internal)?,
+        password_hash: row.try_get("password_hash").map_err(internal)?,
+        google_id: row.try_get("google_id").map_err(internal)?,
+        avatar_url: row.try_get("avatar_url").map_err(internal)?,
+        elo: row.try_get("elo").map_err(internal)?,
+        total_games: row.try_get("total_games").map_err(internal)?,
+        wins: row.try_get("wins").map_err(internal)?,
+        losses: row.try_get("losses").map_err(internal)?,
+        draws: row.try_get("draws").map_err(internal)?,
+        role: role_from_row(row)?,
+        is_banned: row.try_get("is_banned").map_err(internal)?,
+        banned_at: row.try_get("banned_at").map_err(internal)?,
+        banned_reason: row.try_get("banned_reason").map_err(internal)?,
+        is_ai: row.try_get("is_ai").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+        updated_at: row.try_get("updated_at").map_err(internal)?,
+        email_verified: row.try_get("email_verified").map_err(internal)?,
+        totp_secret: row.try_get("totp_secret").map_err(internal)?,
+        totp_enabled: row.try_get("totp_enabled").map_err(internal)?,
+        totp_last_step: row.try_get("totp_last_step").map_err(internal)?,
+        token_version: row.try_get("token_version").map_err(internal)?,
+        pending_email: row.try_get("pending_email").map_err(internal)?,
+        username_changed_at: row.try_get("username_changed_at").map_err(internal)?,
+        allow_spectators: row.try_get("allow_spectators").map_err(internal)?,
+        sealed_totp_secret: None,
+        plain_totp_secret: None,
+        is_admin: false,
+    })
+}
+
+fn snapshot_match(row: &Row) -> Result<MatchRow, AppError> {
+    let m = match_from_row(row)?;
+    Ok(MatchRow {
+        id: m.id,
+        player1_id: m.player1_id,
+        player2_id: m.player2_id,
+        winner_id: m.winner_id,
+        is_ranked: m.is_ranked,
+        player1_score: m.player1_score,
+        player2_score: m.player2_score,
+        player1_elo_before: m.player1_elo_before,
+        player1_elo_after: m.player1_elo_after,
+        player2_elo_before: m.player2_elo_before,
+        player2_elo_after: m.player2_elo_after,
+        status: m.status,
+        created_at: m.created_at,
+        finished_at: m.finished_at,
+    })
+}
+
+fn snapshot_round(row: &Row) -> Result<RoundRow, AppError> {
+    let (match_id, r) = round_from_row(row)?;
+    Ok(RoundRow {
+        match_id,
+        round_number: r.round_number,
+        player1_choice: r.player1_choice,
+        player2_choice: r.player2_choice,
+        winner_id: r.winner_id,
+        started_at: r.started_at,
+        player1_decided_at: r.player1_decided_at,
+        player2_decided_at: r.player2_decided_at,
+    })
+}
+
+fn snapshot_history(row: &Row) -> Result<HistoryRow, AppError> {
+    let h = history_from_row(row)?;
+    Ok(HistoryRow {
+        id: h.id,
+        user_id: h.user_id,
+        match_id: h.match_id,
+        elo_before: h.elo_before,
+        elo_after: h.elo_after,
+        elo_change: h.elo_change,
+        created_at: h.created_at,
+    })
+}
+
+fn snapshot_username_change(row: &Row) -> Result<UsernameChangeRow, AppError> {
+    Ok(UsernameChangeRow {
+        id: row.try_get("id").map_err(internal)?,
+        user_id: row.try_get("user_id").map_err(internal)?,
+        old_username: row.try_get("old_username").map_err(internal)?,
+        new_username: row.try_get("new_username").map_err(internal)?,
+        changed_at: row.try_get("changed_at").map_err(internal)?,
+    })
+}
+
+fn snapshot_recovery_code(row: &Row) -> Result<RecoveryCodeRow, AppError> {
+    Ok(RecoveryCodeRow {
+        id: row.try_get("id").map_err(internal)?,
+        user_id: row.try_get("user_id").map_err(internal)?,
+        code_hash: row.try_get("code_hash").map_err(internal)?,
+        used_at: row.try_get("used_at").map_err(internal)?,
+        created_at: row.try_get("created_at").map_err(internal)?,
+    })
+}
+
+fn snapshot_guest_match(row: &Row) -> Result<GuestMatchRow, AppError> {
+    let m = guest_match_from_row(row)?;
+    Ok(GuestMatchRow {
+        id: m.id,
+        player1_id: m.player1_id,
+        player2_id: m.player2_id,
+        winner_id: m.winner_id,
+        player1_score: m.player1_score,
+        player2_score: m.player2_score,
+        status: m.status,
+        finished_at: m.finished_at,
+    })
+}
+
+async fn select_all<T>(
+    tx: &deadpool_postgres::Transaction<'_>,
+    sql: &str,
+    from_row: fn(&Row) -> Result<T, AppError>,
+) -> Result<Vec<T>, AppError> {
+    tx.query(sql, &[])
+        .await
+        .map_err(internal)?
+        .iter()
+        .map(from_row)
+        .collect()
+}
+
+impl ConnectionPool for PostgresStore {
+    /// deadpool only reports current usage, so the lifetime counters stay at zero.
+    fn pool_metrics(&self) -> PoolMetrics {
+        let status = self.pool.status();
+        PoolMetrics {
+            max_size: status.max_size,
+            open: status.size,
+            idle: status.available,
+            in_use: status.size - status.available,
+            waiting: status.waiting,
+            ..PoolMetrics::default()
+        }
+    }
+}
+
+#[async_trait]
+impl SnapshotRepository for PostgresStore {
+    async fn export(&self) -> Result<Snapshot, AppError> {
+        let mut client = self.client().await?;
+        // REPEATABLE READ gives every query the same view of the database.
+        let tx = client
+            .build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()
+            .await
+            .map_err(internal)?;
+
+        let snapshot = Snapshot {
+            created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            users: select_all(
+                &tx,
+                &format!("SELECT {USER_COLUMNS}, totp_last_step FROM users ORDER BY id"),
+                snapshot_user,
+            )
+            .await?,
+            username_history: select_all(
+                &tx,
+                &format!("SELECT {USERNAME_HISTORY_COLUMNS} FROM username_history ORDER BY id"),
+                snapshot_username_change,
+            )
+            .await?,
+            recovery_codes: select_all(
+                &tx,
+                &format!("SELECT {RECOVERY_CODE_COLUMNS} FROM recovery_codes ORDER BY id"),
+                snapshot_recovery_code,
+            )
+            .await?,
+            matches: select_all(
+                &tx,
+                &format!("SELECT {MATCH_COLUMNS} FROM matches ORDER BY id"),
+                snapshot_match,
+            )
+            .await?,
+            rounds: select_all(
+                &tx,
+                &format!(
+                    "SELECT {ROUND_COLUMNS} FROM match_rounds ORDER BY match_id, round_number"
+                ),
+                snapshot_round,
+            )
+            .await?,
+            history: select_all(
+                &tx,
+                &format!("SELECT {HISTORY_COLUMNS} FROM elo_history ORDER BY id"),
+                snapshot_history,
+            )
+            .await?,
+            guest_matches: select_all(
+                &tx,
+                &format!("SELECT {GUEST_MATCH_COLUMNS} FROM guest_matches ORDER BY id"),
+                snapshot_guest_match,
+            )
+            .await?,
+        };
+
+        tx.commit().await.map_err(internal)?;
+        Ok(snapshot)
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<(), AppError> {
+        snapshot.validate().map_err(AppError::BadRequest)?;
+
+        let mut client = self.client().await?;
+        let tx = client.transaction().await.map_err(internal)?;
+        tx.batch_execute(
+            "LOCK TABLE users, username_history, recovery_codes, matches, match_rounds, elo_history, guest_matches IN EXCLUSIVE MODE",
+        )
+            .await
+            .map_err(internal)?;
+
+        let existing: i64 = tx
+            .query_one(
+                "SELECT (SELECT COUNT(*) FROM users WHERE NOT is_ai)
+                      + (SELECT COUNT(*) FROM matches)
+                      + (SELECT COUNT(*) FROM elo_history)
+                      + (SELECT COUNT(*) FROM guest_matches)",
+                &[],
+            )
+            .await
+            .map_err(internal)?
+            .get(0);
+        if existing > 0 {
+            return Err(AppError::Conflict(
+                "Snapshots can only be restored into an empty database".into(),
+            ));
+        }
+
+        tx.execute("DELETE FROM users", &[])
+            .await
+            .map_err(internal)?;
+
+        let insert_user = tx
+            .prepare(
+                "INSERT INTO users (id, username, email, password_hash, google_id, avatar_url, elo, total_games, wins, losses, draws, role, is_banned, banned_at, banned_reason, is_ai, created_at, updated_at, email_verified, totp_secret, totp_enabled, totp_last_step, token_version, pending_email, username_changed_at, allow_spectators)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14::text::timestamp, $15, $16, $17::text::timestamp, $18::text::timestamp, $19, $20, $21, $22, $23, $24, $25::text::timestamp, $26)",
+            )
+            .await
+            .map_err(internal)?;
+        for u in &snapshot.users {
+            tx.execute(
+                &insert_user,
+                &[
+                    &u.id,
+                    &u.username,
+                    &u.email,
+                    &u.password_hash,
+                    &u.google_id,
+                    &u.avatar_url,
+                    &u.elo,
+                    &u.total_games,
+                    &u.wins,
+                    &u.losses,
+                    &u.draws,
+                    &u.role.as_str(),
+                    &u.is_banned,
+                    &u.banned_at,
+                    &u.banned_reason,
+                    &u.is_ai,
+                    &u.created_at,
+                    &u.updated_at,
+                    &u.email_verified,
+                    &u.totp_secret,
+                    &u.totp_enabled,
+                    &u.totp_last_step,
+                    &u.token_version,
+                    &u.pending_email,
+                    &u.username_changed_at,
+                    &u.allow_spectators,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_username_change = tx
+            .prepare(
+                "INSERT INTO username_history (id, user_id, old_username, new_username, changed_at)
+                 VALUES ($1, $2, $3, $4, $5::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for c in &snapshot.username_history {
+            tx.execute(
+                &insert_username_change,
+                &[
+                    &c.id,
+                    &c.user_id,
+                    &c.old_username,
+                    &c.new_username,
+                    &c.changed_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_recovery_code = tx
+            .prepare(
+                "INSERT INTO recovery_codes (id, user_id, code_hash, used_at, created_at)
+                 VALUES ($1, $2, $3, $4::text::timestamp, $5::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for c in &snapshot.recovery_codes {
+            tx.execute(
+                &insert_recovery_code,
+                &[&c.id, &c.user_id, &c.code_hash, &c.used_at, &c.created_at],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_match = tx
+            .prepare(
+                "INSERT INTO matches (id, player1_id, player2_id, winner_id, is_ranked, player1_score, player2_score, player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after, status, created_at, finished_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13::text::timestamp, $14::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for m in &snapshot.matches {
+            tx.execute(
+                &insert_match,
+                &[
+                    &m.id,
+                    &m.player1_id,
+                    &m.player2_id,
+                    &m.winner_id,
+                    &m.is_ranked,
+                    &m.player1_score,
+                    &m.player2_score,
+                    &m.player1_elo_before,
+                    &m.player1_elo_after,
+                    &m.player2_elo_before,
+                    &m.player2_elo_after,
+                    &m.status,
+                    &m.created_at,
+                    &m.finished_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_round = tx
+            .prepare(
+                "INSERT INTO match_rounds (match_id, round_number, player1_choice, player2_choice, winner_id, started_at, player1_decided_at, player2_decided_at)
+                 VALUES ($1, $2, $3, $4, $5, $6::text::timestamp, $7::text::timestamp, $8::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for r in &snapshot.rounds {
+            tx.execute(
+                &insert_round,
+                &[
+                    &r.match_id,
+                    &r.round_number,
+                    &r.player1_choice,
+                    &r.player2_choice,
+                    &r.winner_id,
+                    &r.started_at,
+                    &r.player1_decided_at,
+                    &r.player2_decided_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_history = tx
+            .prepare(
+                "INSERT INTO elo_history (id, user_id, match_id, elo_before, elo_after, elo_change, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for h in &snapshot.history {
+            tx.execute(
+                &insert_history,
+                &[
+                    &h.id,
+                    &h.user_id,
+                    &h.match_id,
+                    &h.elo_before,
+                    &h.elo_after,
+                    &h.elo_change,
+                    &h.created_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        let insert_guest_match = tx
+            .prepare(
+                "INSERT INTO guest_matches (id, player1_id, player2_id, winner_id, player1_score, player2_score, status, finished_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8::text::timestamp)",
+            )
+            .await
+            .map_err(internal)?;
+        for m in &snapshot.guest_matches {
+            tx.execute(
+                &insert_guest_match,
+                &[
+                    &m.id,
+                    &m.player1_id,
+                    &m.player2_id,
+                    &m.winner_id,
+                    &m.player1_score,
+                    &m.player2_score,
+                    &m.status,
+                    &m.finished_at,
+                ],
+            )
+            .await
+            .map_err(internal)?;
+        }
+
+        tx.commit().await.map_err(internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store migrated into its own schema of the database named by
+    /// `TEST_POSTGRES_URL`, or `None` (skipping the test) when it isn't set.
+    async fn test_store() -> Option<PostgresStore> {
+        let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+        let mut config: tokio_postgres::Config =
+            url.parse().expect("TEST_POSTGRES_URL should be valid");
+
+        let schema = format!("red_flip_test_{}", Uuid::new_v4().simple());
+        PostgresStore::from_config(config.clone(), &PoolConfig::default())
+            .expect("pool should build")
+            .client()
+            .await
+            .expect("test database should be reachable")
+            .batch_execute(&format!("CREATE SCHEMA {schema}"))
+            .await
+            .expect("schema should be created");
+
+        config.options(format!("-c search_path={schema}"));
+        let store =
+            PostgresStore::from_config(config, &PoolConfig::default()).expect("pool should build");
+        store
+            .run_migrations()
+            .await
+            .expect("migrations should apply");
+        Some(store)
+    }
+
+    fn result_between(p1: &User, p2: &User, rounds: Vec<MatchRound>) -> MatchResult {
+        MatchResult {
+            id: Uuid::new_v4().to_string(),
+            player1_id: p1.id.clone(),
+            player2_id: p2.id.clone(),
+            winner_id: Some(p1.id.clone()),
+            is_ranked: true,
+            player1_score: 3,
+            player2_score: 1,
+            rounds,
+            status: "completed".into(),
+        }
+    }
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        assert!(migrations::MIGRATIONS
+            .windows(2)
+            .all(|w| w[1].version == w[0].version + 1));
+    }
+
+    #[actix_rt::test]
+    async fn migrations_are_idempotent_and_seed_ai_players() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let applied = store
+            .run_migrations()
+            .await
+            .expect("re-running migrations should succeed");
+        assert!(applied.is_empty());
+        assert!(store
+            .plan_migrations()
+            .await
+            .expect("plan should load")
+            .pending
+            .is_empty());
+
+        let stats = store.platform_stats().await.expect("stats should load");
+        assert_eq!(stats.total_users, 100);
+        assert!(store.random_ai().await.expect("AI should exist").is_ai);
+    }
+
+    #[actix_rt::test]
+    async fn users_can_be_created_found_filtered_and_banned() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let user = store
+            .create("pg_user", "pg_user@example.com", "hash")
+            .await
+            .expect("user should be created");
+        assert_eq!(user.elo, 1000);
+        assert_eq!(user.role, Role::Player);
+        assert!(matches!(
+            store.create("pg_user", "other@example.com", "hash").await,
+            Err(AppError::Conflict(msg)) if msg.contains("Username")
+        ));
+        assert!(matches!(
+            store.create("PG_User", "other@example.com", "hash").await,
+            Err(AppError::Conflict(msg)) if msg.contains("Username")
+        ));
+        assert!(matches!(
+            store.create("pg_other", "pg_user@example.com", "hash").await,
+            Err(AppError::Conflict(msg)) if msg.contains("Email")
+        ));
+
+        let found = store
+            .find_by_email("pg_user@example.com")
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(found.id, user.id);
+        let found = store
+            .find_by_username("PG_User")
+            .await
+            .expect("lookup should succeed")
+            .expect("usernames should match regardless of case");
+        assert_eq!(found.id, user.id);
+
+        store
+            .update_stats(
+                &user.id,
+                Some("pg_renamed"),
+                Some(1400),
+                Some(4),
+                None,
+                Some(1),
+            )
+            .await
+            .expect("stats update should succeed");
+        store
+            .ban(&user.id, "spam")
+            .await
+            .expect("ban should succeed");
+        let updated = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(updated.username, "pg_renamed");
+        assert_eq!((updated.elo, updated.total_games), (1400, 5));
+        assert!(updated.is_banned);
+        assert!(updated.banned_at.is_some());
+
+        let listed = store
+            .list_with_filters(Some("RENAMED"), Some("elo"), 0, 10)
+            .await
+            .expect("list should succeed");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            store
+                .count_all(Some("example.com"))
+                .await
+                .expect("count should succeed"),
+            1
+        );
+    }
+
+    #[actix_rt::test]
+    async fn record_result_writes_everything_and_delete_cleans_up() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let p1 = store
+            .create("pg_p1", "pg_p1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = store
+            .create("pg_p2", "pg_p2@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let round = MatchRound {
+            round_number: 1,
+            player1_choice: Some("rock".into()),
+            player2_choice: None,
+            winner_id: Some(p1.id.clone()),
+            started_at: Some("2026-01-01 12:00:00.000".into()),
+            player1_decided_at: Some("2026-01-01 12:00:02.250".into()),
+            player2_decided_at: None,
+        };
+        let result = result_between(&p1, &p2, vec![round]);
+        let recorded = store
+            .record_result(&result)
+            .await
+            .expect("result should be recorded");
+        assert!(recorded.finished_at.is_some());
+        let retried = store
+            .record_result(&result)
+            .await
+            .expect("a retry should succeed");
+        assert_eq!((retried.id, retried.rounds.len()), (recorded.id, 1));
+
+        let recent = store
+            .recent_for_user(&p2.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert_eq!(recent.len(), 1);
+        assert_eq!(
+            recent[0].rounds[0].player1_decided_at.as_deref(),
+            Some("2026-01-01 12:00:02.250")
+        );
+        let stats = store
+            .choice_stats_for_user(&p2.id)
+            .await
+            .expect("stats should load");
+        assert_eq!(stats.none, 1);
+
+        let history = store
+            .for_user(&p1.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].elo_change, 20);
+        let p1_after = store
+            .find_by_id(&p1.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!((p1_after.elo, p1_after.wins), (1020, 1));
+
+        store.delete(&p2.id).await.expect("delete should succeed");
+        assert!(store
+            .recent_for_user(&p1.id, 10)
+            .await
+            .expect("recent query should succeed")
+            .is_empty());
+        assert!(store
+            .for_user(&p1.id, 10)
+            .await
+            .expect("history should load")
+            .is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn voiding_a_match_takes_back_ratings_stats_and_history() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let p1 = store
+            .create("pg_void1", "pg_void1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = store
+            .create("pg_void2", "pg_void2@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let recorded = store
+            .record_result(&result_between(&p1, &p2, Vec::new()))
+            .await
+            .expect("result should be recorded");
+
+        let voided = store
+            .void_match(&recorded.id)
+            .await
+            .expect("match should be voided");
+        assert_eq!(voided.status, VOIDED);
+        assert!(matches!(
+            store.void_match(&recorded.id).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            store.void_match("missing").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        for user in [&p1, &p2] {
+            let after = store
+                .find_by_id(&user.id)
+                .await
+                .expect("lookup should succeed")
+                .expect("user should exist");
+            assert_eq!(
+                (after.elo, after.total_games, after.wins, after.losses),
+                (1000, 0, 0, 0)
+            );
+            assert!(store
+                .for_user(&user.id, 10)
+                .await
+                .expect("history should load")
+                .is_empty());
+        }
+        let recent = store
+            .recent_for_user(&p1.id, 10)
+            .await
+            .expect("recent query should succeed");
+        assert_eq!(recent[0].status, VOIDED);
+    }
+
+    #[actix_rt::test]
+    async fn record_result_with_unknown_player_changes_nothing() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let p1 = store
+            .create("pg_solo", "pg_solo@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let mut ghost = p1.clone();
+        ghost.id = "missing".into();
+
+        let result = store
+            .record_result(&result_between(&p1, &ghost, Vec::new()))
+            .await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        let p1 = store
+            .find_by_id(&p1.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!((p1.elo, p1.total_games), (1000, 0));
+        assert_eq!(
+            store
+                .platform_stats()
+                .await
+                .expect("stats should load")
+                .total_matches,
+            0
+        );
+    }
+
+    #[actix_rt::test]
+    async fn snapshot_round_trips_into_an_empty_schema() {
+        let (Some(source), Some(target)) = (test_store().await, test_store().await) else {
+            return;
+        };
+        let p1 = source
+            .create("pg_snap1", "pg_snap1@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let p2 = source
+            .create("pg_snap2", "pg_snap2@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let round = MatchRound {
+            round_number: 1,
+            player1_choice: Some("paper".into()),
+            player2_choice: Some("rock".into()),
+            winner_id: Some(p1.id.clone()),
+            started_at: Some("2026-01-01 12:00:00.000".into()),
+            player1_decided_at: Some("2026-01-01 12:00:01.500".into()),
+            player2_decided_at: None,
+        };
+        source
+            .record_result(&result_between(&p1, &p2, vec![round]))
+            .await
+            .expect("result should be recorded");
+        source
+            .rename(&p2.id, "pg_snap2_renamed")
+            .await
+            .expect("rename should succeed");
+        source
+            .set_allow_spectators(&p2.id, false)
+            .await
+            .expect("spectator setting should be stored");
+        source
+            .set_pending_secret(&p1.id, "JBSWY3DPEHPK3PXP")
+            .await
+            .expect("secret should be stored");
+        source
+            .enable(&p1.id, 42, &["code-1".into(), "code-2".into()])
+            .await
+            .expect("2FA should be enabled");
+        source
+            .record_guest_match(&MatchResult {
+                id: Uuid::new_v4().to_string(),
+                player1_id: p1.id.clone(),
+                player2_id: "guest_snap".into(),
+                winner_id: None,
+                is_ranked: false,
+                player1_score: 1,
+                player2_score: 1,
+                rounds: Vec::new(),
+                status: "completed".into(),
+            })
+            .await
+            .expect("guest match should be recorded");
+
+        let exported = source.export().await.expect("export should succeed");
+        assert_eq!(
+            (
+                exported.users.len(),
+                exported.matches.len(),
+                exported.rounds.len(),
+                exported.history.len()
+            ),
+            (102, 1, 1, 2)
+        );
+        assert_eq!(
+            (
+                exported.username_history.len(),
+                exported.recovery_codes.len(),
+                exported.guest_matches.len()
+            ),
+            (1, 2, 1)
+        );
+
+        target
+            .restore(&exported)
+            .await
+            .expect("restore into a fresh schema should succeed");
+        let mut reexported = target.export().await.expect("export should succeed");
+        reexported.created_at = exported.created_at.clone();
+        assert_eq!(reexported, exported);
+        assert_eq!(
+            target
+                .recovery_codes_left(&p1.id)
+                .await
+                .expect("codes should count"),
+            2
+        );
+        assert_eq!(
+            target
+                .username_history(&p2.id)
+                .await
+                .expect("history should load")[0]
+                .old_username,
+            "pg_snap2"
+        );
+
+        let again = target.restore(&exported).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+    }
+
+    #[actix_rt::test]
+    async fn refresh_tokens_rotate_and_reuse_revokes_the_family() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let user = store
+            .create("refresher", "refresher@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let session = store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "hash-1",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "stale",
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+
+        assert_eq!(
+            store
+                .rotate("hash-1", "hash-2", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Rotated {
+                user_id: user.id.clone(),
+                session_id: session.id.clone(),
+            }
+        );
+        assert_eq!(
+            store
+                .rotate("stale", "never", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+        assert_eq!(
+            store
+                .rotate("hash-1", "hash-3", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Reused {
+                user_id: user.id.clone(),
+                session_id: session.id.clone(),
+            }
+        );
+        let reused = store
+            .find_session(&session.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("session should exist");
+        assert!(!reused.is_active());
+        assert_eq!(
+            store
+                .rotate("hash-2", "hash-4", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "hash-5",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn login_sessions_list_touch_and_revoke() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let user = store
+            .create("sessions", "sessions@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let other = store
+            .create("other_sessions", "other_sessions@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let device = SessionDevice {
+            user_agent: Some("Firefox".into()),
+            ip_hash: Some("abc".into()),
+        };
+        let phone = store
+            .start_family(&user.id, &device, "phone-1", "2999-01-01 00:00:00")
+            .await
+            .expect("family should start");
+        assert_eq!(phone.user_agent.as_deref(), Some("Firefox"));
+        let laptop = store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "laptop-1",
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+        store
+            .start_family(
+                &user.id,
+                &SessionDevice::default(),
+                "old-1",
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("family should start");
+
+        let found = store
+            .find_session(&phone.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("session should exist");
+        assert_eq!(found, phone);
+        store
+            .touch_session(&phone.id)
+            .await
+            .expect("touch should succeed");
+        let listed = store
+            .list_sessions(&user.id)
+            .await
+            .expect("sessions should list");
+        let ids: Vec<&str> = listed.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids.len(), 2, "expired sessions are not listed");
+        assert!(ids.contains(&phone.id.as_str()) && ids.contains(&laptop.id.as_str()));
+
+        assert!(!store
+            .revoke_session(&other.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .revoke_session(&user.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(!store
+            .revoke_session(&user.id, &phone.id)
+            .await
+            .expect("revoke should succeed"));
+        assert_eq!(
+            store
+                .rotate("phone-1", "phone-2", "2999-01-01 00:00:00")
+                .await
+                .expect("rotate should succeed"),
+            RefreshOutcome::Invalid
+        );
+
+        store
+            .revoke_family("laptop-1")
+            .await
+            .expect("revoke should succeed");
+        assert!(store
+            .list_sessions(&user.id)
+            .await
+            .expect("sessions should list")
+            .is_empty());
+        store.delete(&user.id).await.expect("delete should succeed");
+        assert!(store
+            .find_session(&laptop.id)
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+    }
+
+    #[actix_rt::test]
+    async fn external_identities_can_be_created_and_linked() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let external = store
+            .create_external(
+                "sso_user",
+                "sso@example.com",
+                "sub-1",
+                Some("https://a/p.png"),
+            )
+            .await
+            .expect("external user should be created");
+        assert!(external.password_hash.is_none());
+        assert!(external.email_verified);
+        assert_eq!(external.google_id.as_deref(), Some("sub-1"));
+        let found = store
+            .find_by_google_id("sub-1")
+            .await
+            .expect("lookup should succeed")
+            .expect("identity should be found");
+        assert_eq!(found.id, external.id);
+        assert_eq!(found.avatar_url.as_deref(), Some("https://a/p.png"));
+
+        let local = store
+            .create("local_user", "local@example.com", "hash")
+            .await
+            .expect("user should be created");
+        store
+            .link_google_id(&local.id, "corp:sub-2", Some("https://a/q.png"))
+            .await
+            .expect("link should succeed");
+        let linked = store
+            .find_by_google_id("corp:sub-2")
+            .await
+            .expect("lookup should succeed")
+            .expect("identity should be linked");
+        assert_eq!(linked.id, local.id);
+        assert_eq!(linked.password_hash.as_deref(), Some("hash"));
+        assert_eq!(linked.avatar_url.as_deref(), Some("https://a/q.png"));
+        assert!(linked.email_verified);
+    }
+
+    #[actix_rt::test]
+    async fn email_tokens_are_single_use_and_scoped() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let user = store
+            .create("mailer", "mailer@example.com", "hash")
+            .await
+            .expect("user should be created");
+        assert!(!user.email_verified);
+        store
+            .issue(
+                "jti-1",
+                &user.id,
+                EmailTokenPurpose::VerifyEmail,
+                "2999-01-01 00:00:00",
+            )
+            .await
+            .expect("token should be issued");
+        store
+            .issue(
+                "jti-2",
+                &user.id,
+                EmailTokenPurpose::ResetPassword,
+                "2000-01-01 00:00:00",
+            )
+            .await
+            .expect("token should be issued");
+
+        assert!(!store
+            .consume("jti-1", &user.id, EmailTokenPurpose::ResetPassword)
+            .await
+            .expect("consume should succeed"));
+        assert!(store
+            .consume("jti-1", &user.id, EmailTokenPurpose::VerifyEmail)
+            .await
+            .expect("consume should succeed"));
+        assert!(!store
+            .consume("jti-1", &user.id, EmailTokenPurpose::VerifyEmail)
+            .await
+            .expect("consume should succeed"));
+        assert!(!store
+            .consume("jti-2", &user.id, EmailTokenPurpose::ResetPassword)
+            .await
+            .expect("consume should succeed"));
+
+        store
+            .mark_email_verified(&user.id)
+            .await
+            .expect("verify should succeed");
+        store
+            .set_password(&user.id, "new-hash")
+            .await
+            .expect("password should be set");
+        let updated = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(updated.email_verified);
+        assert_eq!(updated.password_hash.as_deref(), Some("new-hash"));
+        assert_eq!(updated.token_version, user.token_version + 1);
+
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn two_factor_steps_and_recovery_codes_are_single_use() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+
+        let user = store
+            .create("totp_user", "totp_user@example.com", "hash")
+            .await
+            .expect("user should be created");
+        store
+            .set_pending_secret(&user.id, "JBSWY3DPEHPK3PXP")
+            .await
+            .expect("secret should be stored");
+        let pending = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(pending.totp_secret.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        assert!(!pending.totp_enabled);
+
+        store
+            .enable(&user.id, 100, &["code-a".to_string(), "code-b".to_string()])
+            .await
+            .expect("2FA should be enabled");
+        let enabled = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(enabled.totp_enabled);
+        assert_eq!(enabled.token_version, user.token_version + 1);
+
+        assert!(!store
+            .accept_step(&user.id, 100)
+            .await
+            .expect("step check should succeed"));
+        assert!(store
+            .accept_step(&user.id, 101)
+            .await
+            .expect("step check should succeed"));
+        assert!(!store
+            .accept_step(&user.id, 101)
+            .await
+            .expect("step check should succeed"));
+
+        assert!(store
+            .use_recovery_code(&user.id, "code-a")
+            .await
+            .expect("recovery code check should succeed"));
+        assert!(!store
+            .use_recovery_code(&user.id, "code-a")
+            .await
+            .expect("recovery code check should succeed"));
+        assert!(!store
+            .use_recovery_code("someone-else", "code-b")
+            .await
+            .expect("recovery code check should succeed"));
+        assert_eq!(
+            store
+                .recovery_codes_left(&user.id)
+                .await
+                .expect("count should succeed"),
+            1
+        );
+
+        store
+            .disable(&user.id)
+            .await
+            .expect("2FA should be disabled");
+        let disabled = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert!(!disabled.totp_enabled);
+        assert!(disabled.totp_secret.is_none());
+        assert_eq!(
+            store
+                .recovery_codes_left(&user.id)
+                .await
+                .expect("count should succeed"),
+            0
+        );
+
+        store.delete(&user.id).await.expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn account_changes_keep_name_history_and_confirm_pending_emails() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let user = store
+            .create("old_name", "old_name@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let other = store
+            .create("other_name", "other_name@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        store
+            .rename(&user.id, "new_name")
+            .await
+            .expect("rename should succeed");
+        assert!(matches!(
+            store.rename(&user.id, "other_name").await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            store.rename(&user.id, "Other_Name").await,
+            Err(AppError::Conflict(_))
+        ));
+        let history = store
+            .username_history(&user.id)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].old_username, "old_name");
+        assert_eq!(history[0].new_username, "new_name");
+
+        store
+            .set_pending_email(&user.id, Some("other_name@example.com"))
+            .await
+            .expect("pending email should be stored");
+        assert!(matches!(
+            store
+                .confirm_email_change(&user.id, "other_name@example.com")
+                .await,
+            Err(AppError::Conflict(_))
+        ));
+        store
+            .set_pending_email(&user.id, Some("moved@example.com"))
+            .await
+            .expect("pending email should be stored");
+        assert!(!store
+            .confirm_email_change(&user.id, "stale@example.com")
+            .await
+            .expect("confirm should run"));
+        assert!(store
+            .confirm_email_change(&user.id, "moved@example.com")
+            .await
+            .expect("confirm should run"));
+        store
+            .set_avatar(&user.id, Some("https://example.com/a.png"))
+            .await
+            .expect("avatar should be set");
+        store
+            .set_allow_spectators(&user.id, false)
+            .await
+            .expect("spectator setting should be stored");
+
+        let updated = store
+            .find_by_id(&user.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(updated.username, "new_name");
+        assert!(updated.username_changed_at.is_some());
+        assert_eq!(updated.email, "moved@example.com");
+        assert_eq!(updated.pending_email, None);
+        assert!(updated.email_verified);
+        assert_eq!(
+            updated.avatar_url.as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert!(!updated.allow_spectators);
+
+        store.delete(&user.id).await.expect("delete should succeed");
+        store
+            .delete(&other.id)
+            .await
+            .expect("delete should succeed");
+    }
+
+    #[actix_rt::test]
+    async fn api_tokens_are_listed_used_and_revoked_until_they_expire() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let user = store
+            .create("token_owner", "token_owner@example.com", "hash")
+            .await
+            .expect("user should be created");
+
+        let live = store
+            .create_token(
+                &user.id,
+                "export",
+                "live-hash",
+                &[ApiScope::ReadProfile, ApiScope::Play],
+                Some("2999-01-01 00:00:00"),
+            )
+            .await
+            .expect("token should be stored");
+        assert_eq!(live.scopes, vec![ApiScope::ReadProfile, ApiScope::Play]);
+        store
+            .create_token(
+                &user.id,
+                "old",
+                "expired-hash",
+                &[ApiScope::Play],
+                Some("2000-01-01 00:00:00"),
+            )
+            .await
+            .expect("token should be stored");
+
+        let used = store
+            .use_token("live-hash")
+            .await
+            .expect("lookup should succeed")
+            .expect("live token should be found");
+        assert_eq!(used.id, live.id);
+        assert!(used.last_used_at.is_some());
+        assert!(store
+            .use_token("expired-hash")
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+        assert_eq!(
+            store
+                .list_tokens(&user.id)
+                .await
+                .expect("tokens should list")
+                .len(),
+            2
+        );
+
+        assert!(!store
+            .revoke_token("someone-else", &live.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .revoke_token(&user.id, &live.id)
+            .await
+            .expect("revoke should succeed"));
+        assert!(store
+            .use_token("live-hash")
+            .await
+            .expect("lookup should succeed")
+            .is_none());
+
+        store.delete(&user.id).await.expect("delete should succeed");
+        assert!(store
+            .list_tokens(&user.id)
+            .await
+            .expect("tokens should list")
+            .is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn guest_matches_move_to_the_accounts_that_claim_them() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let host = store
+            .create("guest_host", "guest_host@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let first = store
+            .create("claimer_a", "claimer_a@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let second = store
+            .create("claimer_b", "claimer_b@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let guest_match = |p1: &str, p2: &str, winner: &str| MatchResult {
+            id: Uuid::new_v4().to_string(),
+            player1_id: p1.into(),
+            player2_id: p2.into(),
+            winner_id: Some(winner.into()),
+            is_ranked: false,
+            player1_score: 3,
+            player2_score: 1,
+            rounds: Vec::new(),
+            status: "completed".into(),
+        };
+        store
+            .record_guest_match(&guest_match("guest_a", &host.id, "guest_a"))
+            .await
+            .expect("guest match should be recorded");
+        store
+            .record_guest_match(&guest_match("guest_b", "guest_a", "guest_b"))
+            .await
+            .expect("guest match should be recorded");
+
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_a", &first.id)
+                .await
+                .expect("claim should succeed"),
+            1
+        );
+        let history = store
+            .recent_for_user(&first.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert!(!history[0].is_ranked);
+        assert_eq!(history[0].winner_id.as_deref(), Some(first.id.as_str()));
+        assert_eq!(history[0].player1_elo_before, None);
+        assert!(history[0].finished_at.is_some());
+        let claimed = store
+            .find_by_id(&first.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(
+            (claimed.total_games, claimed.wins, claimed.elo),
+            (1, 1, 1000)
+        );
+        let untouched = store
+            .find_by_id(&host.id)
+            .await
+            .expect("lookup should succeed")
+            .expect("user should exist");
+        assert_eq!(untouched.total_games, 0);
+
+        // The match between the two guests moves once the other one registers
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_b", &second.id)
+                .await
+                .expect("claim should succeed"),
+            1
+        );
+        let history = store
+            .recent_for_user(&second.id, 10)
+            .await
+            .expect("history should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].player2_id, first.id);
+        assert_eq!(history[0].winner_id.as_deref(), Some(second.id.as_str()));
+        assert_eq!(
+            store
+                .claim_guest_matches("guest_a", &first.id)
+                .await
+                .expect("claim should succeed"),
+            0
+        );
+
+        for user in [&host, &first, &second] {
+            store.delete(&user.id).await.expect("delete should succeed");
+        }
+    }
+}
@@ -1,41 +1,116 @@
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use uuid::Uuid;
 
-use crate::api::{admin, dashboard, leaderboard, user};
-use crate::auth::handlers;
-use crate::auth::middleware::extract_optional_user_from_query;
+use crate::api::{admin, dashboard, leaderboard, live, user};
+use crate::auth::api_tokens::{self, RequiredScope};
+use crate::auth::guest::{self, Guest};
+use crate::auth::middleware::{extract_optional_claims_from_query, load_active_user, query_param};
+use crate::auth::ws_ticket::{self, Redeemed, WsTickets};
+use crate::auth::{handlers, keys, oidc, sessions, totp};
 use crate::config::AppConfig;
-use crate::db::Database;
+use crate::errors::AppError;
 use crate::game::matchmaking::MatchmakingActor;
+use crate::game::registry::{FindSession, SessionRegistry};
+use crate::game::spectator::SpectatorWsActor;
 use crate::game::ws::PlayerWsActor;
-use crate::models::user::User;
+use crate::models::api_token::ApiScope;
+use crate::repository::Repositories;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
             .route("/health", web::get().to(health))
             .route("/leaderboard", web::get().to(leaderboard::get_leaderboard))
-            .route("/dashboard", web::get().to(dashboard::get_dashboard))
+            .route("/matches/live", web::get().to(live::list_live_matches))
+            .service(
+                web::resource("/dashboard")
+                    .app_data(RequiredScope(ApiScope::ReadMatches))
+                    .route(web::get().to(dashboard::get_dashboard)),
+            )
             .route("/users/{id}", web::get().to(user::get_user))
             .route("/account/delete", web::delete().to(user::delete_account))
+            .route("/account/password", web::put().to(user::change_password))
+            .route("/account/email", web::put().to(user::change_email))
+            .route("/account/username", web::put().to(user::change_username))
+            .route("/account/avatar", web::put().to(user::set_avatar))
+            .route("/account/spectators", web::put().to(user::set_spectators))
+            .route("/account/tokens", web::get().to(api_tokens::list))
+            .route("/account/tokens", web::post().to(api_tokens::create))
+            .route("/account/tokens/{id}", web::delete().to(api_tokens::revoke))
+            .route("/account/sessions", web::get().to(sessions::list))
+            .route("/account/sessions/{id}", web::delete().to(sessions::revoke))
             .service(
                 web::scope("/admin")
-                    .route("/stats", web::get().to(admin::get_stats))
-                    .route("/users", web::get().to(admin::list_users))
+                    .service(admin_read("/stats").route(web::get().to(admin::get_stats)))
+                    .route("/snapshot", web::get().to(admin::export_snapshot))
+                    .service(admin_read("/pool").route(web::get().to(admin::get_pool_metrics)))
+                    .service(admin_read("/users").route(web::get().to(admin::list_users)))
                     .route("/users/{id}", web::put().to(admin::update_user))
+                    .route("/users/{id}/role", web::put().to(admin::set_user_role))
                     .route("/users/{id}/ban", web::post().to(admin::ban_user))
                     .route("/users/{id}/unban", web::post().to(admin::unban_user))
-                    .route("/users/{id}", web::delete().to(admin::delete_user)),
+                    .service(
+                        admin_read("/users/{id}/sessions")
+                            .route(web::get().to(admin::list_user_sessions)),
+                    )
+                    .route(
+                        "/users/{id}/sessions/{session_id}",
+                        web::delete().to(admin::revoke_user_session),
+                    )
+                    .route("/users/{id}", web::delete().to(admin::delete_user))
+                    .route("/matches/{id}/void", web::post().to(admin::void_match)),
             ),
     )
     .service(
         web::scope("/auth")
             .route("/register", web::post().to(handlers::register))
             .route("/login", web::post().to(handlers::login))
-            .route("/me", web::get().to(handlers::me)),
+            .route("/refresh", web::post().to(handlers::refresh))
+            .route("/logout", web::post().to(handlers::logout))
+            .route("/logout-all", web::post().to(handlers::logout_all))
+            .route("/guest", web::post().to(guest::issue))
+            .route(
+                "/guest/ws-ticket",
+                web::post().to(ws_ticket::issue_for_guest),
+            )
+            .service(
+                web::resource("/me")
+                    .app_data(RequiredScope(ApiScope::ReadProfile))
+                    .route(web::get().to(handlers::me)),
+            )
+            .service(
+                web::resource("/ws-ticket")
+                    .app_data(RequiredScope(ApiScope::Play))
+                    .route(web::post().to(ws_ticket::issue)),
+            )
+            .route(
+                "/verify-email/request",
+                web::post().to(handlers::request_email_verification),
+            )
+            .route("/verify-email", web::post().to(handlers::verify_email))
+            .route(
+                "/password-reset/request",
+                web::post().to(handlers::request_password_reset),
+            )
+            .route("/password-reset", web::post().to(handlers::reset_password))
+            .route("/2fa", web::get().to(totp::status))
+            .route("/2fa/setup", web::post().to(totp::setup))
+            .route("/2fa/enable", web::post().to(totp::enable))
+            .route("/2fa/disable", web::post().to(totp::disable))
+            .route("/2fa/verify", web::post().to(totp::verify))
+            .route("/oidc/providers", web::get().to(oidc::providers))
+            .route("/oidc/{provider}/start", web::get().to(oidc::start))
+            .route("/oidc/{provider}/callback", web::get().to(oidc::callback))
+            .route("/oidc/exchange", web::post().to(oidc::exchange)),
     )
-    .route("/ws", web::get().to(ws_handler));
+    .route("/.well-known/jwks.json", web::get().to(keys::jwks))
+    .route("/ws", web::get().to(ws_handler))
+    .route("/ws/spectate/{session_id}", web::get().to(spectate_handler));
+}
+
+/// An admin route that `admin:read` API tokens may call.
+fn admin_read(path: &str) -> actix_web::Resource {
+    web::resource(path).app_data(RequiredScope(ApiScope::AdminRead))
 }
 
 async fn health() -> HttpResponse {
@@ -45,26 +120,34 @@ async fn health() -> HttpResponse {
 async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
-    db: web::Data<Database>,
+    repos: web::Data<Repositories>,
     config: web::Data<AppConfig>,
+    tickets: web::Data<WsTickets>,
     matchmaking: web::Data<actix::Addr<MatchmakingActor>>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let query = req.query_string();
-    let user_id_opt = extract_optional_user_from_query(query, &config.jwt_secret);
+    // Without a ticket or token the guest identity lasts one connection
+    let identity = authenticate_ws(&req, &repos, &config, &tickets)
+        .await?
+        .unwrap_or_else(|| Redeemed::Guest(Guest::generate()));
 
-    let (user_id, username, elo, is_guest) = if let Some(uid) = user_id_opt {
-        // Authenticated user
-        let user = User::find_by_id(&db, &uid)
-            .await
-            .map_err(|_| actix_web::error::ErrorInternalServerError("DB error"))?
-            .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
-
-        (user.id, user.username, user.elo, false)
-    } else {
-        // Guest user
-        let guest_id = format!("guest_{}", Uuid::new_v4());
-        let guest_name = format!("Guest{}", &guest_id[6..10]);
-        (guest_id, guest_name, 1000, true)
+    let (user_id, username, elo, is_guest, must_verify_email, login_session_id) = match identity {
+        Redeemed::User {
+            user,
+            login_session_id,
+        } => {
+            let must_verify_email =
+                config.require_verified_email_for_ranked && !user.email_verified;
+
+            (
+                user.id,
+                user.username,
+                user.elo,
+                false,
+                must_verify_email,
+                login_session_id,
+            )
+        }
+        Redeemed::Guest(guest) => (guest.id, guest.username, 1000, true, false, None),
     };
 
     let actor = PlayerWsActor::new(
@@ -72,17 +155,77 @@ async fn ws_handler(
         username,
         elo,
         is_guest,
+        must_verify_email,
+        login_session_id,
         matchmaking.get_ref().clone(),
+        repos.get_ref().clone(),
     );
 
     ws::start(actor, &req, stream)
 }
 
+/// Watch a running match. Open to anyone, signed in or not; the session
+/// turns spectators away once it's full or if a player opted out.
+async fn spectate_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    registry: web::Data<actix::Addr<SessionRegistry>>,
+    session_id: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session = registry
+        .send(FindSession {
+            session_id: session_id.into_inner(),
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Match not found".into()))?;
+
+    ws::start(SpectatorWsActor::new(session.addr), &req, stream)
+}
+
+/// Who a WebSocket upgrade belongs to, or `None` for a guest without an
+/// identity. Clients authenticate with a ticket from `/auth/ws-ticket` or,
+/// as guests, `/auth/guest/ws-ticket`; an access token in the URL is only
+/// accepted while `allow_ws_query_token` is on.
+async fn authenticate_ws(
+    req: &HttpRequest,
+    repos: &Repositories,
+    config: &AppConfig,
+    tickets: &WsTickets,
+) -> Result<Option<Redeemed>, AppError> {
+    let query = req.query_string();
+    if let Some(ticket) = query_param(query, "ticket") {
+        // Banned accounts and revoked sessions are refused
+        return tickets.redeem(repos, ticket).await.map(Some);
+    }
+    if query_param(query, "token").is_none() {
+        return Ok(None);
+    }
+    if !config.allow_ws_query_token {
+        return Err(AppError::Unauthorized(
+            "Connect with a ticket from /auth/ws-ticket".into(),
+        ));
+    }
+    match extract_optional_claims_from_query(query, &config.jwt_keys) {
+        Some(claims) => {
+            let user = load_active_user(repos, &claims).await?;
+            Ok(Some(Redeemed::User {
+                user: Box::new(user),
+                login_session_id: claims.sid,
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use actix_web::{http::StatusCode, test, App};
 
-    use super::configure;
+    use super::*;
+    use crate::auth::jwt::create_token;
+    use crate::auth::keys::KeySet;
+    use crate::models::role::Role;
 
     #[actix_rt::test]
     async fn health_endpoint_returns_ok_status() {
@@ -95,4 +238,285 @@ mod tests {
         let body: serde_json::Value = test::read_body_json(resp).await;
         assert_eq!(body["status"], "ok");
     }
+
+    fn test_config(allow_ws_query_token: bool) -> AppConfig {
+        AppConfig {
+            allow_ws_query_token,
+            ..AppConfig::for_tests()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn websocket_upgrades_take_tickets_and_refuse_url_tokens_by_default() {
+        let repos = Repositories::in_memory();
+        let tickets = WsTickets::in_memory();
+        let user = repos
+            .users
+            .create("socket_owner", "socket_owner@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let upgrade = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/ws?{query}"))
+                .to_http_request()
+        };
+
+        let ticket = tickets
+            .issue(&user, None)
+            .await
+            .expect("ticket should be issued");
+        let authed = authenticate_ws(
+            &upgrade(&format!("ticket={ticket}")),
+            &repos,
+            &test_config(false),
+            &tickets,
+        )
+        .await
+        .expect("ticket should be accepted");
+        assert!(matches!(authed, Some(Redeemed::User { user: u, .. }) if u.id == user.id));
+        let replayed = authenticate_ws(
+            &upgrade(&format!("ticket={ticket}")),
+            &repos,
+            &test_config(false),
+            &tickets,
+        )
+        .await;
+        assert!(matches!(replayed, Err(AppError::Unauthorized(_))));
+
+        let guest = authenticate_ws(&upgrade(""), &repos, &test_config(false), &tickets)
+            .await
+            .expect("guests should be allowed");
+        assert!(guest.is_none());
+        let visitor = Guest::generate();
+        let ticket = tickets
+            .issue_for_guest(&visitor)
+            .await
+            .expect("ticket should be issued");
+        let returning = authenticate_ws(
+            &upgrade(&format!("ticket={ticket}")),
+            &repos,
+            &test_config(false),
+            &tickets,
+        )
+        .await
+        .expect("guest tickets should be accepted");
+        assert!(matches!(returning, Some(Redeemed::Guest(g)) if g == visitor));
+
+        let token = create_token(
+            &user.id,
+            user.token_version,
+            None,
+            &KeySet::from_secret("test-secret"),
+        )
+        .expect("token should be created");
+        let refused = authenticate_ws(
+            &upgrade(&format!("token={token}")),
+            &repos,
+            &test_config(false),
+            &tickets,
+        )
+        .await;
+        assert!(matches!(refused, Err(AppError::Unauthorized(_))));
+        let legacy = authenticate_ws(
+            &upgrade(&format!("token={token}")),
+            &repos,
+            &test_config(true),
+            &tickets,
+        )
+        .await
+        .expect("URL tokens should work while allowed");
+        assert!(matches!(legacy, Some(Redeemed::User { user: u, .. }) if u.id == user.id));
+    }
+
+    #[actix_rt::test]
+    async fn admin_read_tokens_cannot_download_snapshots() {
+        let repos = Repositories::in_memory();
+        let admin = repos
+            .users
+            .create("token_admin", "token_admin@example.com", "hash")
+            .await
+            .expect("user should be created");
+        repos
+            .users
+            .set_role(&admin.id, Role::Admin)
+            .await
+            .expect("role should be set");
+        let (token, hash) = api_tokens::generate();
+        repos
+            .api_tokens
+            .create_token(&admin.id, "dashboards", &hash, &[ApiScope::AdminRead], None)
+            .await
+            .expect("token should be stored");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(repos))
+                .app_data(web::Data::new(AppConfig::for_tests()))
+                .configure(configure),
+        )
+        .await;
+        let call = |path: &str| {
+            test::TestRequest::get()
+                .uri(path)
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, call("/api/admin/stats")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, call("/api/admin/snapshot")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn signing_out_closes_game_sockets() {
+        use crate::auth::handlers::{issue_tokens, logout, logout_all, RefreshTokenRequest};
+        use crate::auth::jwt::validate_token;
+        use crate::auth::middleware::AuthenticatedUser;
+        use crate::game::test_support::TestServer;
+        use crate::models::login_session::SessionDevice;
+
+        let server = TestServer::start();
+        let user = server
+            .repos
+            .users
+            .create("two_sockets", "two_sockets@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let mut sockets = Vec::new();
+        let mut refresh_tokens = Vec::new();
+        for _ in 0..2 {
+            let tokens = issue_tokens(
+                &server.repos,
+                &server.config,
+                &user,
+                &SessionDevice::default(),
+            )
+            .await
+            .expect("tokens should be issued");
+            let session_id = validate_token(&tokens.token, &server.config.jwt_keys)
+                .expect("token should be valid")
+                .sid;
+            sockets.push(server.connect(&user, session_id.as_deref()).await);
+            refresh_tokens.push(tokens.refresh_token);
+        }
+
+        logout(
+            web::Data::new(server.repos.clone()),
+            web::Data::new(server.matchmaking.clone()),
+            web::Json(RefreshTokenRequest {
+                refresh_token: refresh_tokens[0].clone(),
+            }),
+        )
+        .await
+        .expect("logout should succeed");
+        let texts = sockets[0].read_until_closed().await;
+        assert!(texts.iter().any(|t| t.contains("Signed out")), "{texts:?}");
+        assert!(sockets[1].next_frame().await.is_none());
+
+        logout_all(
+            web::Data::new(server.repos.clone()),
+            web::Data::new(server.matchmaking.clone()),
+            AuthenticatedUser {
+                user_id: user.id.clone(),
+                session_id: None,
+            },
+        )
+        .await
+        .expect("logout-all should succeed");
+        let texts = sockets[1].read_until_closed().await;
+        assert!(
+            texts.iter().any(|t| t.contains("Signed out everywhere")),
+            "{texts:?}"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn changing_the_password_closes_game_sockets() {
+        use crate::api::user::{change_password, ChangePasswordRequest};
+        use crate::auth::middleware::AuthenticatedUser;
+        use crate::game::test_support::TestServer;
+        use crate::test_support::{test_limiter, test_request};
+
+        let server = TestServer::start();
+        let hash = bcrypt::hash("old-password", 4).expect("password should hash");
+        let user = server
+            .repos
+            .users
+            .create("changing", "changing@example.com", &hash)
+            .await
+            .expect("user should be created");
+        let mut socket = server.connect(&user, None).await;
+
+        change_password(
+            test_request(),
+            web::Data::new(server.repos.clone()),
+            web::Data::new(server.config.clone()),
+            test_limiter(),
+            web::Data::new(server.matchmaking.clone()),
+            AuthenticatedUser {
+                user_id: user.id.clone(),
+                session_id: None,
+            },
+            web::Json(ChangePasswordRequest {
+                current_password: "old-password".into(),
+                new_password: "new-password".into(),
+            }),
+        )
+        .await
+        .expect("password change should succeed");
+        let texts = socket.read_until_closed().await;
+        assert!(
+            texts.iter().any(|t| t.contains("Password changed")),
+            "{texts:?}"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn replaying_a_refresh_token_closes_its_sessions_sockets() {
+        use crate::auth::handlers::{issue_tokens, refresh, RefreshTokenRequest};
+        use crate::auth::jwt::validate_token;
+        use crate::game::test_support::TestServer;
+        use crate::models::login_session::SessionDevice;
+
+        let server = TestServer::start();
+        let user = server
+            .repos
+            .users
+            .create("replayed", "replayed@example.com", "hash")
+            .await
+            .expect("user should be created");
+        let tokens = issue_tokens(
+            &server.repos,
+            &server.config,
+            &user,
+            &SessionDevice::default(),
+        )
+        .await
+        .expect("tokens should be issued");
+        let session_id = validate_token(&tokens.token, &server.config.jwt_keys)
+            .expect("token should be valid")
+            .sid;
+        let mut socket = server.connect(&user, session_id.as_deref()).await;
+
+        let call = || {
+            refresh(
+                web::Data::new(server.repos.clone()),
+                web::Data::new(server.config.clone()),
+                web::Data::new(server.matchmaking.clone()),
+                web::Json(RefreshTokenRequest {
+                    refresh_token: tokens.refresh_token.clone(),
+                }),
+            )
+        };
+        call().await.expect("first refresh should succeed");
+        assert!(socket.next_frame().await.is_none());
+        let replayed = call().await;
+        assert!(matches!(replayed, Err(AppError::Unauthorized(_))));
+        let texts = socket.read_until_closed().await;
+        assert!(
+            texts.iter().any(|t| t.contains("Session revoked")),
+            "{texts:?}"
+        );
+    }
 }
@@ -0,0 +1,943 @@
+//! Versioned JSON Lines snapshots of all game data.
+//!
+//! The first line is a header naming the format, its version and how many
+//! records of each kind follow. Every other line is one row, tagged with its
+//! table, in dependency order: users, username history, recovery codes,
+//! matches, match rounds, rating history and guest matches.
+//!
+//! Login sessions, refresh tokens, API tokens and email tokens are left out
+//! on purpose. They only mean something to the deployment that issued them,
+//! so everyone signs in again after a restore.
+//!
+//! TOTP secrets never appear in plain text. With a `SnapshotKey` they are
+//! sealed with AES-256-GCM, bound to their user id; without one they are
+//! left out and the account's 2FA is exported as off. The AES key is
+//! stretched from the passphrase with PBKDF2 and a random salt kept in the
+//! header, because AES-GCM tells whoever holds a snapshot whether a guessed
+//! passphrase is right.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::num::NonZeroU32;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::models::role::Role;
+
+pub const FORMAT: &str = "red-flip-snapshot";
+/// Version 2 replaced plain text `totp_secret` with `sealed_totp_secret`.
+/// Version 3 added the account columns and tables that 2FA and self-service
+/// account changes brought, guest matches, and the spectator setting.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// PBKDF2-HMAC-SHA256 rounds for new snapshots. Each snapshot records the
+/// count it was sealed with, so raising this keeps older ones readable.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+/// The `SNAPSHOT_KEY` passphrase that the secrets in a snapshot are sealed
+/// with.
+pub struct SnapshotKey {
+    passphrase: String,
+    iterations: u32,
+}
+
+impl SnapshotKey {
+    pub fn new(passphrase: &str) -> Self {
+        Self {
+            passphrase: passphrase.to_string(),
+            iterations: PBKDF2_ITERATIONS,
+        }
+    }
+
+    /// A key for a new snapshot, under a fresh salt.
+    fn generate(&self) -> (Kdf, SealingKey) {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .expect("system randomness should be available");
+        let kdf = Kdf {
+            salt: URL_SAFE_NO_PAD.encode(salt),
+            iterations: self.iterations,
+        };
+        let key = self
+            .derive(&kdf)
+            .expect("a generated salt and iteration count are valid");
+        (kdf, key)
+    }
+
+    /// The key a snapshot was sealed with, from the salt in its header.
+    fn derive(&self, kdf: &Kdf) -> Result<SealingKey, String> {
+        let salt = URL_SAFE_NO_PAD
+            .decode(&kdf.salt)
+            .map_err(|_| "the key salt is not valid base64".to_string())?;
+        let iterations = NonZeroU32::new(kdf.iterations)
+            .ok_or_else(|| "the key iteration count is zero".to_string())?;
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            self.passphrase.as_bytes(),
+            &mut key,
+        );
+        let key = UnboundKey::new(&AES_256_GCM, &key).expect("the key is 32 bytes long");
+        Ok(SealingKey(LessSafeKey::new(key)))
+    }
+}
+
+/// How the sealing key was stretched from the passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Kdf {
+    salt: String,
+    iterations: u32,
+}
+
+/// The AES-256-GCM key of one snapshot.
+struct SealingKey(LessSafeKey);
+
+impl SealingKey {
+    fn seal(&self, user_id: &str, secret: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .expect("system randomness should be available");
+        let mut sealed = secret.as_bytes().to_vec();
+        self.0
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(user_id.as_bytes()),
+                &mut sealed,
+            )
+            .expect("a TOTP secret fits in one AES-GCM message");
+        URL_SAFE_NO_PAD.encode([nonce.as_slice(), &sealed].concat())
+    }
+
+    fn open(&self, user_id: &str, sealed: &str) -> Result<String, String> {
+        let wrong = || format!("sealed 2FA secret of user {user_id} does not open with this key");
+        let bytes = URL_SAFE_NO_PAD.decode(sealed).map_err(|_| wrong())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(wrong());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| wrong())?;
+        let mut sealed = sealed.to_vec();
+        let secret = self
+            .0
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut sealed)
+            .map_err(|_| wrong())?;
+        String::from_utf8(secret.to_vec()).map_err(|_| wrong())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserRow {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub password_hash: Option<String>,
+    pub google_id: Option<String>,
+    pub avatar_url: Option<String>,
+    pub elo: i32,
+    pub total_games: i32,
+    pub wins: i32,
+    pub losses: i32,
+    pub draws: i32,
+    /// Absent from snapshots taken before roles existed; see `is_admin`.
+    #[serde(default)]
+    pub role: Role,
+    pub is_banned: bool,
+    pub banned_at: Option<String>,
+    pub banned_reason: Option<String>,
+    pub is_ai: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Absent from snapshots taken before email verification existed.
+    #[serde(default)]
+    pub email_verified: bool,
+    /// Never written out as is; see `sealed_totp_secret`.
+    #[serde(skip)]
+    pub totp_secret: Option<String>,
+    /// `totp_secret` sealed with the `SnapshotKey` the snapshot was taken with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_totp_secret: Option<String>,
+    /// Absent from snapshots taken before 2FA existed.
+    #[serde(default)]
+    pub totp_enabled: bool,
+    /// The fields down to `username_changed_at` are absent before version 3.
+    #[serde(default)]
+    pub totp_last_step: Option<i64>,
+    #[serde(default)]
+    pub token_version: i32,
+    #[serde(default)]
+    pub pending_email: Option<String>,
+    #[serde(default)]
+    pub username_changed_at: Option<String>,
+    #[serde(default = "allowed")]
+    pub allow_spectators: bool,
+    /// Only in version 1 snapshots, which held secrets in plain text.
+    #[serde(default, rename = "totp_secret", skip_serializing)]
+    pub plain_totp_secret: Option<String>,
+    /// Only in snapshots taken before roles existed. Read as the admin role.
+    #[serde(default, skip_serializing)]
+    pub is_admin: bool,
+}
+
+/// Spectating was allowed for everyone before the setting existed.
+fn allowed() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatchRow {
+    pub id: String,
+    pub player1_id: String,
+    pub player2_id: String,
+    pub winner_id: Option<String>,
+    pub is_ranked: bool,
+    pub player1_score: i32,
+    pub player2_score: i32,
+    pub player1_elo_before: Option<i32>,
+    pub player1_elo_after: Option<i32>,
+    pub player2_elo_before: Option<i32>,
+    pub player2_elo_after: Option<i32>,
+    pub status: String,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoundRow {
+    pub match_id: String,
+    pub round_number: i32,
+    pub player1_choice: Option<String>,
+    pub player2_choice: Option<String>,
+    pub winner_id: Option<String>,
+    pub started_at: Option<String>,
+    pub player1_decided_at: Option<String>,
+    pub player2_decided_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryRow {
+    pub id: String,
+    pub user_id: String,
+    pub match_id: String,
+    pub elo_before: i32,
+    pub elo_after: i32,
+    pub elo_change: i32,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsernameChangeRow {
+    pub id: String,
+    pub user_id: String,
+    pub old_username: String,
+    pub new_username: String,
+    pub changed_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecoveryCodeRow {
+    pub id: String,
+    pub user_id: String,
+    pub code_hash: String,
+    pub used_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestMatchRow {
+    pub id: String,
+    pub player1_id: String,
+    pub player2_id: String,
+    pub winner_id: Option<String>,
+    pub player1_score: i32,
+    pub player2_score: i32,
+    pub status: String,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Counts {
+    pub users: usize,
+    #[serde(default)]
+    pub username_history: usize,
+    #[serde(default)]
+    pub recovery_codes: usize,
+    pub matches: usize,
+    pub match_rounds: usize,
+    pub elo_history: usize,
+    #[serde(default)]
+    pub guest_matches: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Header {
+    format: String,
+    version: u32,
+    created_at: String,
+    counts: Counts,
+    /// Only in snapshots with sealed secrets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<Kdf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Line {
+    Header(Header),
+    User(UserRow),
+    UsernameChange(UsernameChangeRow),
+    RecoveryCode(RecoveryCodeRow),
+    Match(MatchRow),
+    MatchRound(RoundRow),
+    EloHistory(HistoryRow),
+    GuestMatch(GuestMatchRow),
+}
+
+/// Every row of every game table, as read in a single transaction.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub created_at: String,
+    pub users: Vec<UserRow>,
+    pub username_history: Vec<UsernameChangeRow>,
+    pub recovery_codes: Vec<RecoveryCodeRow>,
+    pub matches: Vec<MatchRow>,
+    pub rounds: Vec<RoundRow>,
+    pub history: Vec<HistoryRow>,
+    pub guest_matches: Vec<GuestMatchRow>,
+}
+
+impl Snapshot {
+    pub fn counts(&self) -> Counts {
+        Counts {
+            users: self.users.len(),
+            username_history: self.username_history.len(),
+            recovery_codes: self.recovery_codes.len(),
+            matches: self.matches.len(),
+            match_rounds: self.rounds.len(),
+            elo_history: self.history.len(),
+            guest_matches: self.guest_matches.len(),
+        }
+    }
+
+    /// Write the snapshot, sealing TOTP secrets with `key`. Without a key,
+    /// secrets are left out and 2FA is written as off.
+    pub fn write_jsonl<W: Write>(
+        &self,
+        mut out: W,
+        key: Option<&SnapshotKey>,
+    ) -> std::io::Result<()> {
+        let (kdf, key) = match key {
+            Some(key) if self.totp_secrets() > 0 => {
+                let (kdf, key) = key.generate();
+                (Some(kdf), Some(key))
+            }
+            _ => (None, None),
+        };
+        let header = Line::Header(Header {
+            format: FORMAT.to_string(),
+            version: FORMAT_VERSION,
+            created_at: self.created_at.clone(),
+            counts: self.counts(),
+            kdf,
+        });
+
+        let rows = self
+            .users
+            .iter()
+            .map(|user| {
+                let mut user = user.clone();
+                user.sealed_totp_secret = match (&key, &user.totp_secret) {
+                    (Some(key), Some(secret)) => Some(key.seal(&user.id, secret)),
+                    _ => None,
+                };
+                if user.sealed_totp_secret.is_none() {
+                    user.totp_enabled = false;
+                    user.totp_last_step = None;
+                }
+                Line::User(user)
+            })
+            .chain(
+                self.username_history
+                    .iter()
+                    .cloned()
+                    .map(Line::UsernameChange),
+            )
+            .chain(self.recovery_codes.iter().cloned().map(Line::RecoveryCode))
+            .chain(self.matches.iter().cloned().map(Line::Match))
+            .chain(self.rounds.iter().cloned().map(Line::MatchRound))
+            .chain(self.history.iter().cloned().map(Line::EloHistory))
+            .chain(self.guest_matches.iter().cloned().map(Line::GuestMatch));
+
+        for line in std::iter::once(header).chain(rows) {
+            serde_json::to_writer(&mut out, &line)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    /// How many users have a TOTP secret, which `write_jsonl` leaves out
+    /// when it has no key.
+    pub fn totp_secrets(&self) -> usize {
+        self.users
+            .iter()
+            .filter(|user| user.totp_secret.is_some())
+            .count()
+    }
+
+    /// Parse a snapshot and check it is complete and self-consistent before
+    /// anything is written. Sealed TOTP secrets are opened with `key`.
+    pub fn read_jsonl<R: BufRead>(input: R, key: Option<&SnapshotKey>) -> Result<Self, String> {
+        let mut header: Option<Header> = None;
+        let mut sealing: Option<SealingKey> = None;
+        let mut snapshot = Snapshot::default();
+
+        for (idx, line) in input.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.map_err(|e| format!("line {line_no}: {e}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed: Line =
+                serde_json::from_str(&line).map_err(|e| format!("line {line_no}: {e}"))?;
+
+            match (parsed, &header) {
+                (Line::Header(h), None) => {
+                    if h.format != FORMAT {
+                        return Err(format!("line {line_no}: not a {FORMAT} file"));
+                    }
+                    if !(1..=FORMAT_VERSION).contains(&h.version) {
+                        return Err(format!(
+                            "line {line_no}: unsupported snapshot version {} (expected {FORMAT_VERSION})",
+                            h.version
+                        ));
+                    }
+                    if let (Some(key), Some(kdf)) = (key, &h.kdf) {
+                        sealing = Some(
+                            key.derive(kdf)
+                                .map_err(|e| format!("line {line_no}: {e}"))?,
+                        );
+                    }
+                    snapshot.created_at = h.created_at.clone();
+                    header = Some(h);
+                }
+                (Line::Header(_), Some(_)) => {
+                    return Err(format!("line {line_no}: duplicate header"))
+                }
+                (_, None) => return Err(format!("line {line_no}: expected the snapshot header")),
+                (Line::User(mut row), Some(_)) => {
+                    if row.is_admin {
+                        row.role = Role::Admin;
+                        row.is_admin = false;
+                    }
+                    row.totp_secret = match (row.sealed_totp_secret.take(), &sealing) {
+                        (Some(sealed), Some(sealing)) => Some(
+                            sealing
+                                .open(&row.id, &sealed)
+                                .map_err(|e| format!("line {line_no}: {e}"))?,
+                        ),
+                        (Some(_), None) if key.is_none() => {
+                            return Err(format!(
+                                "line {line_no}: snapshot holds sealed 2FA secrets; set SNAPSHOT_KEY to restore it"
+                            ))
+                        }
+                        (Some(_), None) => {
+                            return Err(format!(
+                                "line {line_no}: snapshot holds sealed 2FA secrets but its header has no key salt"
+                            ))
+                        }
+                        (None, _) => row.plain_totp_secret.take(),
+                    };
+                    if row.totp_secret.is_none() {
+                        row.totp_enabled = false;
+                        row.totp_last_step = None;
+                    }
+                    snapshot.users.push(row)
+                }
+                (Line::UsernameChange(row), Some(_)) => snapshot.username_history.push(row),
+                (Line::RecoveryCode(row), Some(_)) => snapshot.recovery_codes.push(row),
+                (Line::Match(row), Some(_)) => snapshot.matches.push(row),
+                (Line::MatchRound(row), Some(_)) => snapshot.rounds.push(row),
+                (Line::EloHistory(row), Some(_)) => snapshot.history.push(row),
+                (Line::GuestMatch(row), Some(_)) => snapshot.guest_matches.push(row),
+            }
+        }
+
+        let header = header.ok_or("snapshot is empty")?;
+        if header.counts != snapshot.counts() {
+            return Err(format!(
+                "snapshot is truncated or padded: header lists {:?}, file contains {:?}",
+                header.counts,
+                snapshot.counts()
+            ));
+        }
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    /// Check uniqueness and that every reference points at a row in the snapshot.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut user_ids = HashSet::new();
+        let mut usernames = HashSet::new();
+        let mut emails = HashSet::new();
+        for user in &self.users {
+            if !user_ids.insert(user.id.as_str()) {
+                return Err(format!("duplicate user id {}", user.id));
+            }
+            if !usernames.insert(user.username.to_ascii_lowercase()) {
+                return Err(format!("duplicate username {}", user.username));
+            }
+            if !emails.insert(user.email.as_str()) {
+                return Err(format!("duplicate email for user {}", user.id));
+            }
+        }
+
+        let mut change_ids = HashSet::new();
+        for change in &self.username_history {
+            if !change_ids.insert(change.id.as_str()) {
+                return Err(format!("duplicate username_history id {}", change.id));
+            }
+            if !user_ids.contains(change.user_id.as_str()) {
+                return Err(format!(
+                    "username_history {} references unknown user {}",
+                    change.id, change.user_id
+                ));
+            }
+        }
+
+        let mut code_ids = HashSet::new();
+        let mut code_hashes = HashSet::new();
+        for code in &self.recovery_codes {
+            if !code_ids.insert(code.id.as_str()) {
+                return Err(format!("duplicate recovery code id {}", code.id));
+            }
+            if !code_hashes.insert(code.code_hash.as_str()) {
+                return Err(format!("duplicate hash for recovery code {}", code.id));
+            }
+            if !user_ids.contains(code.user_id.as_str()) {
+                return Err(format!(
+                    "recovery code {} references unknown user {}",
+                    code.id, code.user_id
+                ));
+            }
+        }
+
+        let mut match_ids = HashSet::new();
+        for m in &self.matches {
+            if !match_ids.insert(m.id.as_str()) {
+                return Err(format!("duplicate match id {}", m.id));
+            }
+            for player in [
+                Some(&m.player1_id),
+                Some(&m.player2_id),
+                m.winner_id.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                if !user_ids.contains(player.as_str()) {
+                    return Err(format!("match {} references unknown user {player}", m.id));
+                }
+            }
+        }
+
+        let mut round_keys = HashSet::new();
+        for round in &self.rounds {
+            if !match_ids.contains(round.match_id.as_str()) {
+                return Err(format!(
+                    "round {} references unknown match {}",
+                    round.round_number, round.match_id
+                ));
+            }
+            if !round_keys.insert((round.match_id.as_str(), round.round_number)) {
+                return Err(format!(
+                    "duplicate round {} of match {}",
+                    round.round_number, round.match_id
+                ));
+            }
+            if let Some(winner) = &round.winner_id {
+                if !user_ids.contains(winner.as_str()) {
+                    return Err(format!(
+                        "round {} of match {} references unknown user {winner}",
+                        round.round_number, round.match_id
+                    ));
+                }
+            }
+        }
+
+        let mut history_ids = HashSet::new();
+        for entry in &self.history {
+            if !history_ids.insert(entry.id.as_str()) {
+                return Err(format!("duplicate elo_history id {}", entry.id));
+            }
+            if !user_ids.contains(entry.user_id.as_str()) {
+                return Err(format!(
+                    "elo_history {} references unknown user {}",
+                    entry.id, entry.user_id
+                ));
+            }
+            if !match_ids.contains(entry.match_id.as_str()) {
+                return Err(format!(
+                    "elo_history {} references unknown match {}",
+                    entry.id, entry.match_id
+                ));
+            }
+        }
+
+        let mut guest_match_ids = HashSet::new();
+        for m in &self.guest_matches {
+            if !guest_match_ids.insert(m.id.as_str()) {
+                return Err(format!("duplicate guest match id {}", m.id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Strip credentials and personal details so the snapshot can seed a
+    /// non-production environment. Ids, usernames and game data are kept.
+    pub fn anonymize(&mut self) {
+        for user in self.users.iter_mut().filter(|u| !u.is_ai) {
+            user.email = format!("{}@users.invalid", user.id);
+            user.pending_email = None;
+            user.password_hash = None;
+            user.google_id = None;
+            user.avatar_url = None;
+            user.totp_secret = None;
+            user.totp_enabled = false;
+            user.totp_last_step = None;
+            if user.banned_reason.is_some() {
+                user.banned_reason = Some("redacted".into());
+            }
+        }
+        self.recovery_codes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Few iterations, so the tests don't spend seconds stretching keys.
+    fn test_key(passphrase: &str) -> SnapshotKey {
+        SnapshotKey {
+            passphrase: passphrase.into(),
+            iterations: 1000,
+        }
+    }
+
+    fn user(id: &str) -> UserRow {
+        UserRow {
+            id: id.into(),
+            username: format!("name_{id}"),
+            email: format!("{id}@example.com"),
+            password_hash: Some("hash".into()),
+            google_id: None,
+            avatar_url: None,
+            elo: 1000,
+            total_games: 1,
+            wins: 1,
+            losses: 0,
+            draws: 0,
+            role: Role::Player,
+            is_banned: false,
+            banned_at: None,
+            banned_reason: None,
+            is_ai: false,
+            created_at: "2026-01-01 00:00:00".into(),
+            updated_at: "2026-01-01 00:00:00".into(),
+            email_verified: false,
+            totp_secret: None,
+            sealed_totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            token_version: 2,
+            pending_email: None,
+            username_changed_at: None,
+            allow_spectators: true,
+            plain_totp_secret: None,
+            is_admin: false,
+        }
+    }
+
+    fn sample() -> Snapshot {
+        Snapshot {
+            created_at: "2026-01-02 00:00:00".into(),
+            users: vec![user("u1"), user("u2")],
+            username_history: vec![UsernameChangeRow {
+                id: "n1".into(),
+                user_id: "u2".into(),
+                old_username: "old_u2".into(),
+                new_username: "name_u2".into(),
+                changed_at: "2026-01-01 09:00:00".into(),
+            }],
+            recovery_codes: vec![RecoveryCodeRow {
+                id: "c1".into(),
+                user_id: "u1".into(),
+                code_hash: "code-hash".into(),
+                used_at: None,
+                created_at: "2026-01-01 08:00:00".into(),
+            }],
+            matches: vec![MatchRow {
+                id: "m1".into(),
+                player1_id: "u1".into(),
+                player2_id: "u2".into(),
+                winner_id: Some("u1".into()),
+                is_ranked: true,
+                player1_score: 3,
+                player2_score: 0,
+                player1_elo_before: Some(1000),
+                player1_elo_after: Some(1016),
+                player2_elo_before: Some(1000),
+                player2_elo_after: Some(984),
+                status: "completed".into(),
+                created_at: "2026-01-01 10:00:00".into(),
+                finished_at: Some("2026-01-01 10:01:00".into()),
+            }],
+            rounds: vec![RoundRow {
+                match_id: "m1".into(),
+                round_number: 1,
+                player1_choice: Some("rock".into()),
+                player2_choice: Some("scissors".into()),
+                winner_id: Some("u1".into()),
+                started_at: None,
+                player1_decided_at: None,
+                player2_decided_at: None,
+            }],
+            history: vec![HistoryRow {
+                id: "h1".into(),
+                user_id: "u1".into(),
+                match_id: "m1".into(),
+                elo_before: 1000,
+                elo_after: 1016,
+                elo_change: 16,
+                created_at: "2026-01-01 10:01:00".into(),
+            }],
+            guest_matches: vec![GuestMatchRow {
+                id: "g1".into(),
+                player1_id: "u1".into(),
+                player2_id: "guest_1".into(),
+                winner_id: None,
+                player1_score: 2,
+                player2_score: 2,
+                status: "completed".into(),
+                finished_at: "2026-01-01 11:00:00".into(),
+            }],
+        }
+    }
+
+    fn to_jsonl(snapshot: &Snapshot) -> String {
+        let mut out = Vec::new();
+        snapshot
+            .write_jsonl(&mut out, None)
+            .expect("writing to a buffer should succeed");
+        String::from_utf8(out).expect("snapshot should be utf-8")
+    }
+
+    #[test]
+    fn round_trips_through_json_lines() {
+        let snapshot = sample();
+        let text = to_jsonl(&snapshot);
+
+        let first = text.lines().next().expect("header line should exist");
+        assert!(first.contains(r#""type":"header""#));
+        assert_eq!(text.lines().count(), 9);
+
+        let parsed = Snapshot::read_jsonl(text.as_bytes(), None).expect("snapshot should parse");
+        assert_eq!(parsed, snapshot);
+    }
+
+    #[test]
+    fn reads_the_admin_flag_of_snapshots_taken_before_roles() {
+        let text = to_jsonl(&sample()).replacen(r#""role":"player""#, r#""is_admin":true"#, 1);
+
+        let parsed = Snapshot::read_jsonl(text.as_bytes(), None).expect("snapshot should parse");
+        assert_eq!(parsed.users[0].role, Role::Admin);
+        assert!(!parsed.users[0].is_admin);
+        assert_eq!(parsed.users[1].role, Role::Player);
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_files() {
+        let text = to_jsonl(&sample());
+        let truncated: String = text.lines().take(4).map(|l| format!("{l}\n")).collect();
+        let err =
+            Snapshot::read_jsonl(truncated.as_bytes(), None).expect_err("truncation should fail");
+        assert!(err.contains("truncated"));
+
+        let newer = text.replacen(
+            &format!(r#""version":{FORMAT_VERSION}"#),
+            r#""version":99"#,
+            1,
+        );
+        let err =
+            Snapshot::read_jsonl(newer.as_bytes(), None).expect_err("version should be checked");
+        assert!(err.contains("unsupported snapshot version"));
+
+        let headless: String = text.lines().skip(1).map(|l| format!("{l}\n")).collect();
+        assert!(Snapshot::read_jsonl(headless.as_bytes(), None).is_err());
+
+        let unknown_field = text.replacen(r#""elo":1000"#, r#""elo":1000,"extra":1"#, 1);
+        assert!(Snapshot::read_jsonl(unknown_field.as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn totp_secrets_are_sealed_or_left_out() {
+        let mut snapshot = sample();
+        snapshot.users[0].totp_secret = Some("JBSWY3DPEHPK3PXP".into());
+        snapshot.users[0].totp_enabled = true;
+        let key = test_key("backup passphrase");
+
+        let mut sealed = Vec::new();
+        snapshot
+            .write_jsonl(&mut sealed, Some(&key))
+            .expect("writing to a buffer should succeed");
+        let text = String::from_utf8(sealed).expect("snapshot should be utf-8");
+        assert!(!text.contains("JBSWY3DPEHPK3PXP"));
+        let parsed =
+            Snapshot::read_jsonl(text.as_bytes(), Some(&key)).expect("snapshot should parse");
+        assert_eq!(parsed, snapshot);
+        let err = Snapshot::read_jsonl(text.as_bytes(), None).expect_err("key should be needed");
+        assert!(err.contains("SNAPSHOT_KEY"));
+        let other = test_key("another passphrase");
+        assert!(Snapshot::read_jsonl(text.as_bytes(), Some(&other)).is_err());
+        // A sealed secret only opens for the user it was sealed for
+        let moved = text.replacen(r#""id":"u1""#, r#""id":"u0""#, 1);
+        assert!(Snapshot::read_jsonl(moved.as_bytes(), Some(&key)).is_err());
+        // The key depends on the salt and iteration count in the header
+        let resalted = text.replacen(r#""salt":""#, r#""salt":"AA"#, 1);
+        assert!(Snapshot::read_jsonl(resalted.as_bytes(), Some(&key)).is_err());
+        let fewer_rounds = text.replacen(r#""iterations":1000"#, r#""iterations":999"#, 1);
+        assert!(Snapshot::read_jsonl(fewer_rounds.as_bytes(), Some(&key)).is_err());
+        let (header, rows) = text.split_once('\n').expect("snapshot should have rows");
+        let mut header: serde_json::Value =
+            serde_json::from_str(header).expect("header should be JSON");
+        header
+            .as_object_mut()
+            .expect("header should be an object")
+            .remove("kdf");
+        let unsalted = format!("{header}\n{rows}");
+        let err = Snapshot::read_jsonl(unsalted.as_bytes(), Some(&key))
+            .expect_err("the salt should be needed");
+        assert!(err.contains("no key salt"));
+
+        // Every snapshot gets its own salt
+        let mut again = Vec::new();
+        snapshot
+            .write_jsonl(&mut again, Some(&key))
+            .expect("writing to a buffer should succeed");
+        let salt = |text: &str| {
+            text.split(r#""salt":""#)
+                .nth(1)
+                .map(|s| s[..22].to_string())
+        };
+        assert_ne!(
+            salt(&text),
+            salt(std::str::from_utf8(&again).expect("snapshot should be utf-8"))
+        );
+
+        let text = to_jsonl(&snapshot);
+        assert!(!text.contains("JBSWY3DPEHPK3PXP"));
+        let parsed = Snapshot::read_jsonl(text.as_bytes(), None).expect("snapshot should parse");
+        assert_eq!(parsed.users[0].totp_secret, None);
+        assert!(!parsed.users[0].totp_enabled);
+    }
+
+    #[test]
+    fn reads_plain_secrets_of_version_1_snapshots() {
+        let text = to_jsonl(&sample())
+            .replacen(
+                &format!(r#""version":{FORMAT_VERSION}"#),
+                r#""version":1"#,
+                1,
+            )
+            .replacen(
+                r#""totp_enabled":false"#,
+                r#""totp_secret":"JBSWY3DPEHPK3PXP","totp_enabled":true"#,
+                1,
+            );
+
+        let parsed = Snapshot::read_jsonl(text.as_bytes(), None).expect("snapshot should parse");
+        assert_eq!(
+            parsed.users[0].totp_secret.as_deref(),
+            Some("JBSWY3DPEHPK3PXP")
+        );
+        assert!(parsed.users[0].totp_enabled);
+    }
+
+    #[test]
+    fn reads_version_2_snapshots_without_the_account_tables() {
+        let text = concat!(
+            r#"{"type":"header","format":"red-flip-snapshot","version":2,"created_at":"2026-01-02 00:00:00","counts":{"users":1,"matches":0,"match_rounds":0,"elo_history":0}}"#,
+            "\n",
+            r#"{"type":"user","id":"u1","username":"name_u1","email":"u1@example.com","password_hash":"hash","google_id":null,"avatar_url":null,"elo":1000,"total_games":0,"wins":0,"losses":0,"draws":0,"role":"player","is_banned":false,"banned_at":null,"banned_reason":null,"is_ai":false,"created_at":"2026-01-01 00:00:00","updated_at":"2026-01-01 00:00:00","email_verified":true,"totp_enabled":false}"#,
+            "\n",
+        );
+
+        let parsed = Snapshot::read_jsonl(text.as_bytes(), None).expect("snapshot should parse");
+        assert_eq!(parsed.users[0].token_version, 0);
+        assert_eq!(parsed.users[0].username_changed_at, None);
+        assert!(parsed.users[0].allow_spectators);
+        assert!(parsed.username_history.is_empty() && parsed.guest_matches.is_empty());
+    }
+
+    #[test]
+    fn rejects_dangling_references() {
+        let mut snapshot = sample();
+        snapshot.history[0].match_id = "missing".into();
+        let err = snapshot.validate().expect_err("dangling match should fail");
+        assert!(err.contains("unknown match missing"));
+
+        let mut snapshot = sample();
+        snapshot.users.pop();
+        assert!(snapshot.validate().is_err());
+
+        let mut snapshot = sample();
+        snapshot.recovery_codes[0].user_id = "missing".into();
+        let err = snapshot.validate().expect_err("dangling user should fail");
+        assert!(err.contains("recovery code c1 references unknown user missing"));
+    }
+
+    #[test]
+    fn rejects_usernames_differing_only_in_case() {
+        let mut snapshot = sample();
+        snapshot.users[1].username = snapshot.users[0].username.to_uppercase();
+        let err = snapshot.validate().expect_err("recased name should fail");
+        assert!(err.contains("duplicate username NAME_U1"));
+    }
+
+    #[test]
+    fn anonymize_strips_credentials_but_keeps_ai_players() {
+        let mut snapshot = sample();
+        let mut ai = user("ai-001");
+        ai.is_ai = true;
+        ai.password_hash = None;
+        snapshot.users.push(ai);
+
+        snapshot.anonymize();
+
+        assert_eq!(snapshot.users[0].email, "u1@users.invalid");
+        assert!(snapshot.users[0].password_hash.is_none());
+        assert!(snapshot.recovery_codes.is_empty());
+        assert_eq!(snapshot.users[2].email, "ai-001@example.com");
+        assert!(snapshot.validate().is_ok());
+    }
+}
@@ -0,0 +1,51 @@
+//! Extractors and responses for tests that call handlers directly.
+
+use std::sync::Arc;
+
+use actix::{Actor, Addr};
+use actix_web::body::to_bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::auth::rate_limit::{MemoryRateLimitStore, RateLimiter};
+use crate::config::AppConfig;
+use crate::game::matchmaking::MatchmakingActor;
+use crate::game::registry::SessionRegistry;
+use crate::mail::{Mailer, MemoryMailer};
+use crate::repository::Repositories;
+
+pub fn test_config() -> web::Data<AppConfig> {
+    web::Data::new(AppConfig::for_tests())
+}
+
+pub fn test_request() -> HttpRequest {
+    actix_web::test::TestRequest::default().to_http_request()
+}
+
+pub fn test_limiter() -> web::Data<RateLimiter> {
+    web::Data::new(RateLimiter::new(
+        Arc::new(MemoryRateLimitStore::new()),
+        false,
+    ))
+}
+
+/// A matchmaker with its own registry, for handlers that close sockets.
+pub fn test_matchmaking(repos: &Repositories) -> web::Data<Addr<MatchmakingActor>> {
+    let registry = SessionRegistry::default().start();
+    web::Data::new(
+        MatchmakingActor::new(repos.clone(), AppConfig::for_tests().game, registry).start(),
+    )
+}
+
+/// A mailer that keeps what it sends, and the same mailer as app data.
+pub fn test_mailer() -> (Arc<MemoryMailer>, web::Data<dyn Mailer>) {
+    let mailer = Arc::new(MemoryMailer::default());
+    let data: web::Data<dyn Mailer> = web::Data::from(mailer.clone() as Arc<dyn Mailer>);
+    (mailer, data)
+}
+
+pub async fn json_body(resp: HttpResponse) -> serde_json::Value {
+    let bytes = to_bytes(resp.into_body())
+        .await
+        .expect("body should be readable");
+    serde_json::from_slice(&bytes).expect("body should be JSON")
+}
@@ -5,6 +5,7 @@ import { useRouter } from "next/navigation";
 import { useAuth } from "@/hooks/useAuth";
 import { api } from "@/lib/api";
 import { AdminStatsResponse, AdminUsersResponse } from "@/types/admin";
+import { hasPermission } from "@/types/user";
 import AdminStats from "@/components/admin/AdminStats";
 import UserManagementTable from "@/components/admin/UserManagementTable";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
@@ -19,6 +20,8 @@ export default function AdminPage() {
   const [search, setSearch] = useState("");
   const [sortBy, setSortBy] = useState("created_at");
   const [page, setPage] = useState(1);
+  const canAdminister =
+    hasPermission(user, "stats.view") && hasPermission(user, "users.view");
 
   useEffect(() => {
     if (!authLoading && !user) {
@@ -26,14 +29,14 @@ export default function AdminPage() {
       return;
     }
 
-    if (!authLoading && user && !user.is_admin) {
+    if (!authLoading && user && !canAdminister) {
       router.push("/dashboard");
       return;
     }
-  }, [user, authLoading, router]);
+  }, [user, canAdminister, authLoading, router]);
 
   useEffect(() => {
-    if (!user?.is_admin) return;
+    if (!canAdminister) return;
 
     const fetchAdminData = async () => {
       setLoading(true);
@@ -50,7 +53,9 @@ export default function AdminPage() {
         console.error("Failed to fetch admin data:", err);
         if (
           err instanceof Error &&
-          (err.message.includes("401") || err.message.includes("Admin"))
+          (err.message.includes("401") ||
+            err.message.includes("permission") ||
+            err.message.includes("Staff"))
         ) {
           router.push("/dashboard");
         }
@@ -60,10 +65,10 @@ export default function AdminPage() {
     };
 
     void fetchAdminData();
-  }, [user, search, sortBy, page, router]);
+  }, [canAdminister, search, sortBy, page, router]);
 
   const handleRefreshUsers = () => {
-    if (!user?.is_admin) return;
+    if (!canAdminister) return;
 
     api
       .get<AdminUsersResponse>(
@@ -85,7 +90,7 @@ export default function AdminPage() {
     );
   }
 
-  if (!user?.is_admin || !stats || !usersData) return null;
+  if (!canAdminister || !stats || !usersData) return null;
 
   return (
     <div className="max-w-7xl mx-auto py-8 px-4">
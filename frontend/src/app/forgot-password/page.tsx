@@ -0,0 +1,88 @@
+"use client";
+
+import { useState, FormEvent } from "react";
+import Link from "next/link";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import { faSpinner } from "@fortawesome/free-solid-svg-icons";
+import { api } from "@/lib/api";
+
+export default function ForgotPasswordPage() {
+  const [email, setEmail] = useState("");
+  const [sent, setSent] = useState(false);
+  const [error, setError] = useState("");
+  const [loading, setLoading] = useState(false);
+
+  const handleSubmit = async (e: FormEvent) => {
+    e.preventDefault();
+    setError("");
+    setLoading(true);
+
+    try {
+      await api.post<void>("/auth/password-reset/request", { email });
+      setSent(true);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Request failed");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div className="py-12 px-4">
+      <div className="max-w-md mx-auto">
+        <h1 className="font-serif text-3xl font-bold text-brand-800 text-center mb-8">
+          Reset Password
+        </h1>
+
+        {error && (
+          <div className="bg-red-50 border border-red-200 text-red-700 px-4 py-3 rounded mb-4">
+            {error}
+          </div>
+        )}
+
+        {sent ? (
+          <p className="text-center text-gray-700">
+            If an account uses {email}, we&apos;ve sent it a link to choose a
+            new password.
+          </p>
+        ) : (
+          <form onSubmit={handleSubmit} className="space-y-4">
+            <div>
+              <label
+                htmlFor="email"
+                className="block text-sm font-medium text-gray-700 mb-1"
+              >
+                Email
+              </label>
+              <input
+                id="email"
+                type="email"
+                required
+                value={email}
+                onChange={(e) => setEmail(e.target.value)}
+                className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
+              />
+            </div>
+            <button
+              type="submit"
+              disabled={loading}
+              className="w-full py-2.5 bg-brand-600 text-white font-medium rounded-lg hover:bg-brand-500 transition-colors disabled:opacity-50 cursor-pointer"
+            >
+              {loading ? (
+                <FontAwesomeIcon icon={faSpinner} spin />
+              ) : (
+                "Send Reset Link"
+              )}
+            </button>
+          </form>
+        )}
+
+        <p className="mt-6 text-center text-sm text-gray-600">
+          <Link href="/login" className="text-brand-600 hover:underline">
+            Back to log in
+          </Link>
+        </p>
+      </div>
+    </div>
+  );
+}
@@ -72,10 +72,7 @@ export default function PlayPage() {
       {isGuest && <GuestBanner />}
 
       {game.status === "idle" && (
-        <ModeSelector
-          onSelect={(ranked) => game.joinQueue(ranked)}
-          isGuest={isGuest}
-        />
+        <ModeSelector onSelect={game.joinQueue} isGuest={isGuest} />
       )}
 
       {game.status === "queued" && (
@@ -89,6 +86,8 @@ export default function PlayPage() {
           timeLeft={game.timeLeft}
           myChoice={game.myChoice}
           opponentChose={game.opponentChose}
+          opponentAway={game.opponentAway}
+          spectators={game.spectators}
           myScore={game.myScore}
           opponentScore={game.opponentScore}
           moveHistory={game.moveHistory}
@@ -0,0 +1,91 @@
+"use client";
+
+import { useState, useEffect, FormEvent } from "react";
+import Link from "next/link";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import { faSpinner } from "@fortawesome/free-solid-svg-icons";
+import { api } from "@/lib/api";
+
+export default function ResetPasswordPage() {
+  const [token, setToken] = useState<string | null>(null);
+  const [password, setPassword] = useState("");
+  const [done, setDone] = useState(false);
+  const [error, setError] = useState("");
+  const [loading, setLoading] = useState(false);
+
+  useEffect(() => {
+    setToken(new URLSearchParams(window.location.search).get("token"));
+  }, []);
+
+  const handleSubmit = async (e: FormEvent) => {
+    e.preventDefault();
+    setError("");
+    setLoading(true);
+
+    try {
+      await api.post<void>("/auth/password-reset", { token, password });
+      setDone(true);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Reset failed");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div className="py-12 px-4">
+      <div className="max-w-md mx-auto">
+        <h1 className="font-serif text-3xl font-bold text-brand-800 text-center mb-8">
+          Choose a New Password
+        </h1>
+
+        {error && (
+          <div className="bg-red-50 border border-red-200 text-red-700 px-4 py-3 rounded mb-4">
+            {error}
+          </div>
+        )}
+
+        {done ? (
+          <p className="text-center text-gray-700">
+            Your password has been changed and every other session signed
+            out.{" "}
+            <Link href="/login" className="text-brand-600 hover:underline">
+              Log in
+            </Link>
+          </p>
+        ) : (
+          <form onSubmit={handleSubmit} className="space-y-4">
+            <div>
+              <label
+                htmlFor="password"
+                className="block text-sm font-medium text-gray-700 mb-1"
+              >
+                New password
+              </label>
+              <input
+                id="password"
+                type="password"
+                required
+                minLength={6}
+                value={password}
+                onChange={(e) => setPassword(e.target.value)}
+                className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
+              />
+            </div>
+            <button
+              type="submit"
+              disabled={loading || !token}
+              className="w-full py-2.5 bg-brand-600 text-white font-medium rounded-lg hover:bg-brand-500 transition-colors disabled:opacity-50 cursor-pointer"
+            >
+              {loading ? (
+                <FontAwesomeIcon icon={faSpinner} spin />
+              ) : (
+                "Set Password"
+              )}
+            </button>
+          </form>
+        )}
+      </div>
+    </div>
+  );
+}
@@ -4,6 +4,10 @@ import { useState, useEffect } from "react";
 import { useRouter } from "next/navigation";
 import { useAuth } from "@/hooks/useAuth";
 import { api } from "@/lib/api";
+import AccountSettings from "@/components/auth/AccountSettings";
+import ApiTokenSettings from "@/components/auth/ApiTokenSettings";
+import SessionSettings from "@/components/auth/SessionSettings";
+import TwoFactorSettings from "@/components/auth/TwoFactorSettings";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import {
   faSpinner,
@@ -17,6 +21,9 @@ export default function SettingsPage() {
   const [showDeleteModal, setShowDeleteModal] = useState(false);
   const [deleting, setDeleting] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [verificationStatus, setVerificationStatus] = useState<string | null>(
+    null,
+  );
 
   useEffect(() => {
     if (!authLoading && !user) {
@@ -38,6 +45,17 @@ export default function SettingsPage() {
     }
   };
 
+  const handleResendVerification = async () => {
+    try {
+      await api.post<void>("/auth/verify-email/request", {});
+      setVerificationStatus("Check your inbox for a new verification link.");
+    } catch (err) {
+      setVerificationStatus(
+        err instanceof Error ? err.message : "Failed to send verification email",
+      );
+    }
+  };
+
   if (authLoading) {
     return (
       <div className="flex justify-center py-20">
@@ -67,6 +85,29 @@ export default function SettingsPage() {
             <label className="text-sm text-gray-600">Username</label>
             <p className="font-medium">{user.username}</p>
           </div>
+          {user.email_verified !== undefined && (
+            <div>
+              <label className="text-sm text-gray-600">Email</label>
+              {user.email_verified ? (
+                <p className="font-medium">Verified</p>
+              ) : (
+                <div className="flex items-center gap-3">
+                  <p className="font-medium">Not verified</p>
+                  <button
+                    onClick={handleResendVerification}
+                    className="text-sm text-brand-600 hover:underline"
+                  >
+                    Resend verification email
+                  </button>
+                </div>
+              )}
+              {verificationStatus && (
+                <p className="text-sm text-gray-600 mt-1">
+                  {verificationStatus}
+                </p>
+              )}
+            </div>
+          )}
           <div>
             <label className="text-sm text-gray-600">Elo Rating</label>
             <p className="font-medium">{user.elo}</p>
@@ -78,6 +119,14 @@ export default function SettingsPage() {
         </div>
       </div>
 
+      <AccountSettings />
+
+      <TwoFactorSettings />
+
+      <SessionSettings />
+
+      <ApiTokenSettings />
+
       <div className="bg-red-50 rounded-lg shadow-md p-6 border border-red-200">
         <h2 className="font-serif text-xl font-bold text-red-800 mb-4">
           Danger Zone
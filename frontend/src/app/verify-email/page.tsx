@@ -0,0 +1,61 @@
+"use client";
+
+import { useState, useEffect } from "react";
+import Link from "next/link";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import { faSpinner } from "@fortawesome/free-solid-svg-icons";
+import { api } from "@/lib/api";
+
+export default function VerifyEmailPage() {
+  const [status, setStatus] = useState<"pending" | "verified" | "failed">(
+    "pending",
+  );
+  const [error, setError] = useState("");
+
+  useEffect(() => {
+    const token = new URLSearchParams(window.location.search).get("token");
+    if (!token) {
+      setStatus("failed");
+      setError("This link is missing its token");
+      return;
+    }
+    api
+      .post<void>("/auth/verify-email", { token })
+      .then(() => setStatus("verified"))
+      .catch((err) => {
+        setStatus("failed");
+        setError(err instanceof Error ? err.message : "Verification failed");
+      });
+  }, []);
+
+  return (
+    <div className="py-12 px-4">
+      <div className="max-w-md mx-auto text-center">
+        <h1 className="font-serif text-3xl font-bold text-brand-800 mb-8">
+          Verify Email
+        </h1>
+
+        {status === "pending" && (
+          <FontAwesomeIcon
+            icon={faSpinner}
+            spin
+            className="text-brand-600 text-3xl"
+          />
+        )}
+        {status === "verified" && (
+          <p className="text-gray-700">
+            Your email address is verified.{" "}
+            <Link href="/play" className="text-brand-600 hover:underline">
+              Play ranked
+            </Link>
+          </p>
+        )}
+        {status === "failed" && (
+          <div className="bg-red-50 border border-red-200 text-red-700 px-4 py-3 rounded">
+            {error}
+          </div>
+        )}
+      </div>
+    </div>
+  );
+}
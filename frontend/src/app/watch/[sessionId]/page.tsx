@@ -0,0 +1,130 @@
+"use client";
+
+import { use } from "react";
+import Link from "next/link";
+import { useSpectator } from "@/hooks/useSpectator";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import {
+  faSpinner,
+  faClock,
+  faWifi,
+  faExclamationTriangle,
+} from "@fortawesome/free-solid-svg-icons";
+
+export default function SpectatePage({
+  params,
+}: {
+  params: Promise<{ sessionId: string }>;
+}) {
+  const { sessionId } = use(params);
+  const match = useSpectator(sessionId);
+
+  if (match.error) {
+    return (
+      <div className="max-w-lg mx-auto text-center py-20 px-4">
+        <FontAwesomeIcon
+          icon={faExclamationTriangle}
+          className="text-yellow-500 text-3xl mb-4"
+        />
+        <p className="text-gray-800 font-medium mb-4">{match.error}</p>
+        <Link href="/watch" className="text-brand-600 hover:underline">
+          Back to live matches
+        </Link>
+      </div>
+    );
+  }
+
+  if (match.status === "connecting" || !match.player1 || !match.player2) {
+    return (
+      <div className="flex justify-center py-20">
+        <FontAwesomeIcon
+          icon={faSpinner}
+          spin
+          className="text-brand-600 text-3xl"
+        />
+      </div>
+    );
+  }
+
+  const name = (player: string) =>
+    player === "player1" ? match.player1!.username : match.player2!.username;
+
+  return (
+    <div className="max-w-lg mx-auto text-center py-8 px-4">
+      <div className="flex justify-between items-center mb-6">
+        <div className="text-left">
+          <p className="font-serif font-semibold text-brand-800">
+            {match.player1.username}
+          </p>
+          <p className="text-xs text-gray-400">Elo: {match.player1.elo}</p>
+        </div>
+        <div>
+          <p className="text-sm text-gray-500">Round {match.currentRound}</p>
+          <p className="font-serif text-2xl font-bold text-brand-800">
+            {match.player1Score} - {match.player2Score}
+          </p>
+        </div>
+        <div className="text-right">
+          <p className="font-serif font-semibold text-brand-800">
+            {match.player2.username}
+          </p>
+          <p className="text-xs text-gray-400">Elo: {match.player2.elo}</p>
+        </div>
+      </div>
+
+      {match.status === "watching" && (
+        <p className="text-sm text-gray-500 mb-4">
+          <FontAwesomeIcon icon={faClock} className="mr-1" />
+          {match.timeLeft}s
+        </p>
+      )}
+
+      {match.reconnecting && (
+        <p className="text-sm text-yellow-700 bg-yellow-50 rounded-lg px-3 py-2 mb-4">
+          <FontAwesomeIcon icon={faWifi} className="mr-2" />
+          {name(match.reconnecting)} lost connection. The clock is paused.
+        </p>
+      )}
+
+      {match.final && (
+        <p className="text-lg font-semibold text-brand-800 mb-4">
+          {match.final.winner === "draw"
+            ? "Draw"
+            : `${name(match.final.winner)} wins`}
+          {match.final.status === "forfeit" && " by forfeit"}
+        </p>
+      )}
+
+      <div className="rounded-lg border border-gray-200 overflow-hidden text-sm">
+        {match.rounds.length === 0 ? (
+          <p className="px-4 py-3 text-gray-500">
+            Choices appear once each round is over.
+          </p>
+        ) : (
+          match.rounds.map((round) => (
+            <div
+              key={round.round}
+              className="grid grid-cols-[1fr_auto_1fr] gap-3 px-4 py-2 border-t border-gray-100 first:border-t-0"
+            >
+              <p
+                className={`text-left capitalize ${round.winner === "player1" ? "font-semibold text-green-700" : ""}`}
+              >
+                {round.player1_choice === "none"
+                  ? "No pick"
+                  : round.player1_choice}
+              </p>
+              <p className="text-gray-400">Round {round.round}</p>
+              <p
+                className={`text-right capitalize ${round.winner === "player2" ? "font-semibold text-green-700" : ""}`}
+              >
+                {round.player2_choice === "none"
+                  ? "No pick"
+                  : round.player2_choice}
+              </p>
+            </div>
+          ))
+        )}
+      </div>
+    </div>
+  );
+}
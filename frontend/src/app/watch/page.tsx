@@ -0,0 +1,81 @@
+"use client";
+
+import { useEffect, useState } from "react";
+import Link from "next/link";
+import { api } from "@/lib/api";
+import { LiveMatchesResponse } from "@/types/api";
+import { LiveMatch } from "@/types/game";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import { faSpinner, faEye, faTrophy } from "@fortawesome/free-solid-svg-icons";
+
+export default function WatchPage() {
+  const [matches, setMatches] = useState<LiveMatch[]>([]);
+  const [loading, setLoading] = useState(true);
+
+  useEffect(() => {
+    api
+      .get<LiveMatchesResponse>("/api/matches/live")
+      .then((data) => setMatches(data.matches))
+      .catch(() => {})
+      .finally(() => setLoading(false));
+  }, []);
+
+  if (loading) {
+    return (
+      <div className="flex justify-center py-20">
+        <FontAwesomeIcon
+          icon={faSpinner}
+          spin
+          className="text-brand-600 text-3xl"
+        />
+      </div>
+    );
+  }
+
+  return (
+    <div className="max-w-3xl mx-auto py-8 px-4">
+      <h1 className="font-hand text-3xl font-bold text-brand-800 mb-8">
+        Live Matches
+      </h1>
+
+      {matches.length === 0 ? (
+        <p className="text-center text-gray-500 py-8">
+          No matches to watch right now.
+        </p>
+      ) : (
+        <ul className="divide-y divide-gray-100 rounded-lg border border-gray-200">
+          {matches.map((match) => (
+            <li
+              key={match.session_id}
+              className="flex items-center justify-between px-4 py-3"
+            >
+              <div>
+                <p className="font-serif font-semibold text-brand-800">
+                  {match.player1.username}{" "}
+                  <span className="text-gray-400 font-normal">vs</span>{" "}
+                  {match.player2.username}
+                </p>
+                <p className="text-xs text-gray-500">
+                  {match.player1.elo} / {match.player2.elo}
+                  {match.is_ranked && (
+                    <span className="ml-2 text-brand-600">
+                      <FontAwesomeIcon icon={faTrophy} className="mr-1" />
+                      Ranked
+                    </span>
+                  )}
+                </p>
+              </div>
+              <Link
+                href={`/watch/${match.session_id}`}
+                className="flex items-center gap-1.5 px-3 py-1.5 rounded bg-brand-600 text-white hover:bg-brand-500 transition-colors text-sm"
+              >
+                <FontAwesomeIcon icon={faEye} />
+                Watch
+              </Link>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
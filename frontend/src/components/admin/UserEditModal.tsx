@@ -2,6 +2,7 @@ import { useState } from "react";
 import { User } from "@/types/user";
 import { UpdateUserRequest } from "@/types/admin";
 import { api } from "@/lib/api";
+import { errorMessage, USERNAME_PATTERN } from "@/lib/username";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import { faTimes, faSpinner } from "@fortawesome/free-solid-svg-icons";
 
@@ -58,7 +59,7 @@ export default function UserEditModal({
       await api.put(`/api/admin/users/${user.id}`, updates);
       onSuccess();
     } catch (err: unknown) {
-      setError(err instanceof Error ? err.message : "Failed to update user");
+      setError(errorMessage(err, "Failed to update user"));
     } finally {
       setLoading(false);
     }
@@ -100,6 +101,8 @@ export default function UserEditModal({
               required
               minLength={3}
               maxLength={20}
+              pattern={USERNAME_PATTERN}
+              title="Letters, digits and underscores"
             />
           </div>
 
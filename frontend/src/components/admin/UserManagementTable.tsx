@@ -1,7 +1,9 @@
 import { useState } from "react";
-import { User } from "@/types/user";
+import { hasPermission, ROLE_RANK, User } from "@/types/user";
+import { useAuth } from "@/hooks/useAuth";
 import { api } from "@/lib/api";
 import UserEditModal from "./UserEditModal";
+import UserSessionsModal from "./UserSessionsModal";
 import ConfirmModal from "./ConfirmModal";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import {
@@ -9,6 +11,7 @@ import {
   faBan,
   faUserCheck,
   faTrash,
+  faLaptop,
   faChevronLeft,
   faChevronRight,
 } from "@fortawesome/free-solid-svg-icons";
@@ -39,7 +42,11 @@ export default function UserManagementTable({
   onPageChange,
   onRefresh,
 }: UserManagementTableProps) {
+  const { user: me } = useAuth();
+  const canManage = (user: User) =>
+    !!me && ROLE_RANK[user.role] < ROLE_RANK[me.role];
   const [editingUser, setEditingUser] = useState<User | null>(null);
+  const [sessionsUser, setSessionsUser] = useState<User | null>(null);
   const [confirmAction, setConfirmAction] = useState<{
     type: "ban" | "unban" | "delete";
     user: User;
@@ -163,9 +170,9 @@ export default function UserManagementTable({
                     <span className="text-sm font-medium text-brand-900">
                       {user.username}
                     </span>
-                    {user.is_admin && (
-                      <span className="ml-2 px-2 py-0.5 text-xs font-semibold bg-purple-100 text-purple-800 rounded">
-                        Admin
+                    {user.role !== "player" && (
+                      <span className="ml-2 px-2 py-0.5 text-xs font-semibold bg-purple-100 text-purple-800 rounded capitalize">
+                        {user.role}
                       </span>
                     )}
                   </div>
@@ -191,16 +198,26 @@ export default function UserManagementTable({
                   )}
                 </td>
                 <td className="px-4 py-3 whitespace-nowrap text-sm">
-                  {!user.is_admin && (
+                  {canManage(user) && (
                     <div className="flex gap-2">
+                      {(hasPermission(me, "users.rename") ||
+                        hasPermission(me, "users.edit_stats")) && (
+                        <button
+                          onClick={() => handleEdit(user)}
+                          className="text-brand-600 hover:text-brand-800"
+                          title="Edit"
+                        >
+                          <FontAwesomeIcon icon={faEdit} />
+                        </button>
+                      )}
                       <button
-                        onClick={() => handleEdit(user)}
+                        onClick={() => setSessionsUser(user)}
                         className="text-brand-600 hover:text-brand-800"
-                        title="Edit"
+                        title="Sessions"
                       >
-                        <FontAwesomeIcon icon={faEdit} />
+                        <FontAwesomeIcon icon={faLaptop} />
                       </button>
-                      {user.is_banned ? (
+                      {!hasPermission(me, "users.ban") ? null : user.is_banned ? (
                         <button
                           onClick={() =>
                             setConfirmAction({ type: "unban", user })
@@ -221,15 +238,17 @@ export default function UserManagementTable({
                           <FontAwesomeIcon icon={faBan} />
                         </button>
                       )}
-                      <button
-                        onClick={() =>
-                          setConfirmAction({ type: "delete", user })
-                        }
-                        className="text-red-600 hover:text-red-800"
-                        title="Delete"
-                      >
-                        <FontAwesomeIcon icon={faTrash} />
-                      </button>
+                      {hasPermission(me, "users.delete") && (
+                        <button
+                          onClick={() =>
+                            setConfirmAction({ type: "delete", user })
+                          }
+                          className="text-red-600 hover:text-red-800"
+                          title="Delete"
+                        >
+                          <FontAwesomeIcon icon={faTrash} />
+                        </button>
+                      )}
                     </div>
                   )}
                 </td>
@@ -278,6 +297,14 @@ export default function UserManagementTable({
         />
       )}
 
+      {sessionsUser && (
+        <UserSessionsModal
+          user={sessionsUser}
+          canRevoke={hasPermission(me, "users.ban")}
+          onClose={() => setSessionsUser(null)}
+        />
+      )}
+
       {confirmAction && (
         <ConfirmModal
           title={
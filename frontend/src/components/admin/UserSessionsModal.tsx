@@ -0,0 +1,103 @@
+import { useState, useEffect, useCallback } from "react";
+import { User } from "@/types/user";
+import { LoginSession, LoginSessionsResponse } from "@/types/api";
+import { api } from "@/lib/api";
+import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
+import { faTimes, faSpinner } from "@fortawesome/free-solid-svg-icons";
+
+interface UserSessionsModalProps {
+  user: User;
+  canRevoke: boolean;
+  onClose: () => void;
+}
+
+export default function UserSessionsModal({
+  user,
+  canRevoke,
+  onClose,
+}: UserSessionsModalProps) {
+  const [sessions, setSessions] = useState<LoginSession[] | null>(null);
+  const [error, setError] = useState("");
+
+  const loadSessions = useCallback(async () => {
+    const data = await api.get<LoginSessionsResponse>(
+      `/api/admin/users/${user.id}/sessions`,
+    );
+    setSessions(data.sessions);
+  }, [user.id]);
+
+  useEffect(() => {
+    loadSessions().catch(() => {
+      setSessions([]);
+      setError("Failed to load sessions");
+    });
+  }, [loadSessions]);
+
+  const handleRevoke = async (sessionId: string) => {
+    setError("");
+    try {
+      await api.delete(`/api/admin/users/${user.id}/sessions/${sessionId}`);
+      await loadSessions();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to revoke session");
+    }
+  };
+
+  return (
+    <div className="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50 p-4">
+      <div className="bg-white rounded-lg shadow-xl max-w-lg w-full">
+        <div className="flex items-center justify-between p-4 border-b border-brand-200">
+          <h3 className="font-serif text-xl font-bold text-brand-800">
+            Sessions: {user.username}
+          </h3>
+          <button
+            onClick={onClose}
+            className="text-brand-400 hover:text-brand-600"
+          >
+            <FontAwesomeIcon icon={faTimes} />
+          </button>
+        </div>
+
+        <div className="p-4">
+          {error && (
+            <div className="mb-4 p-3 bg-red-50 border border-red-200 rounded text-red-700 text-sm">
+              {error}
+            </div>
+          )}
+          {sessions === null ? (
+            <FontAwesomeIcon icon={faSpinner} spin className="text-brand-600" />
+          ) : sessions.length === 0 ? (
+            <p className="text-sm text-brand-600">No active sessions.</p>
+          ) : (
+            <ul className="divide-y divide-brand-200">
+              {sessions.map((session) => (
+                <li
+                  key={session.id}
+                  className="py-2 flex items-center justify-between gap-3"
+                >
+                  <div className="text-sm min-w-0">
+                    <p className="font-medium truncate">
+                      {session.user_agent ?? "Unknown device"}
+                    </p>
+                    <p className="text-brand-600">
+                      Signed in {session.created_at} · last active{" "}
+                      {session.last_seen_at}
+                    </p>
+                  </div>
+                  {canRevoke && (
+                    <button
+                      onClick={() => handleRevoke(session.id)}
+                      className="text-sm text-red-600 hover:underline whitespace-nowrap"
+                    >
+                      Revoke
+                    </button>
+                  )}
+                </li>
+              ))}
+            </ul>
+          )}
+        </div>
+      </div>
+    </div>
+  );
+}
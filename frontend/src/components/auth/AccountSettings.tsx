@@ -0,0 +1,253 @@
+"use client";
+
+import { useState, FormEvent } from "react";
+import { useAuth } from "@/hooks/useAuth";
+import { api, storeTokens } from "@/lib/api";
+import { errorMessage, USERNAME_PATTERN } from "@/lib/username";
+import { TokenResponse, UserResponse } from "@/types/api";
+
+const inputClass =
+  "w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500";
+const buttonClass =
+  "px-4 py-2 bg-brand-600 text-white rounded hover:bg-brand-500 transition-colors font-medium disabled:opacity-50";
+
+export default function AccountSettings() {
+  const { user, refreshUser } = useAuth();
+  const [username, setUsername] = useState("");
+  const [avatarUrl, setAvatarUrl] = useState("");
+  const [email, setEmail] = useState("");
+  const [emailPassword, setEmailPassword] = useState("");
+  const [currentPassword, setCurrentPassword] = useState("");
+  const [newPassword, setNewPassword] = useState("");
+  const [message, setMessage] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  const run = async (action: () => Promise<string>) => {
+    setError(null);
+    setMessage(null);
+    setBusy(true);
+    try {
+      setMessage(await action());
+    } catch (err) {
+      setError(errorMessage(err, "Something went wrong"));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleRename = (e: FormEvent) => {
+    e.preventDefault();
+    run(async () => {
+      await api.put<UserResponse>("/api/account/username", { username });
+      setUsername("");
+      await refreshUser();
+      return "Username changed.";
+    });
+  };
+
+  const saveAvatar = (url: string | null) =>
+    run(async () => {
+      await api.put<UserResponse>("/api/account/avatar", { avatar_url: url });
+      setAvatarUrl("");
+      await refreshUser();
+      return url ? "Avatar updated." : "Avatar removed.";
+    });
+
+  const handleAvatar = (e: FormEvent) => {
+    e.preventDefault();
+    saveAvatar(avatarUrl);
+  };
+
+  const saveSpectators = (allow: boolean) =>
+    run(async () => {
+      await api.put<void>("/api/account/spectators", {
+        allow_spectators: allow,
+      });
+      await refreshUser();
+      return allow
+        ? "Others can watch your matches."
+        : "Your matches are private.";
+    });
+
+  const handleEmail = (e: FormEvent) => {
+    e.preventDefault();
+    run(async () => {
+      await api.put<void>("/api/account/email", {
+        email,
+        current_password: emailPassword || null,
+      });
+      setEmail("");
+      setEmailPassword("");
+      await refreshUser();
+      return "Check the new address for a confirmation link.";
+    });
+  };
+
+  const handlePassword = (e: FormEvent) => {
+    e.preventDefault();
+    run(async () => {
+      const tokens = await api.put<TokenResponse>("/api/account/password", {
+        current_password: currentPassword,
+        new_password: newPassword,
+      });
+      // Changing the password signs out every session; keep this one going.
+      storeTokens(tokens);
+      setCurrentPassword("");
+      setNewPassword("");
+      return "Password changed. Your other devices have been signed out.";
+    });
+  };
+
+  if (!user) return null;
+
+  return (
+    <div className="bg-white rounded-lg shadow-md p-6 mb-6">
+      <h2 className="font-serif text-xl font-bold text-brand-800 mb-4">
+        Edit Account
+      </h2>
+
+      {error && (
+        <div className="mb-4 p-3 bg-red-50 border border-red-200 rounded text-red-700 text-sm">
+          {error}
+        </div>
+      )}
+      {message && (
+        <div className="mb-4 p-3 bg-green-50 border border-green-200 rounded text-green-700 text-sm">
+          {message}
+        </div>
+      )}
+
+      <div className="space-y-6">
+        <form onSubmit={handleRename} className="space-y-2">
+          <label className="text-sm text-gray-600">Username</label>
+          <input
+            type="text"
+            required
+            minLength={3}
+            maxLength={20}
+            pattern={USERNAME_PATTERN}
+            title="Letters, digits and underscores"
+            placeholder={user.username}
+            value={username}
+            onChange={(e) => setUsername(e.target.value)}
+            className={inputClass}
+          />
+          <p className="text-xs text-gray-500">
+            You can change your username once every 30 days.
+          </p>
+          <button type="submit" disabled={busy} className={buttonClass}>
+            Change Username
+          </button>
+        </form>
+
+        <form onSubmit={handleAvatar} className="space-y-2">
+          <label className="text-sm text-gray-600">Avatar</label>
+          <div className="flex items-center gap-3">
+            {user.avatar_url && (
+              // eslint-disable-next-line @next/next/no-img-element
+              <img
+                src={user.avatar_url}
+                alt="Current avatar"
+                className="w-12 h-12 rounded-full object-cover"
+              />
+            )}
+            <input
+              type="url"
+              required
+              placeholder="https://example.com/avatar.png"
+              value={avatarUrl}
+              onChange={(e) => setAvatarUrl(e.target.value)}
+              className={inputClass}
+            />
+          </div>
+          <div className="flex gap-3">
+            <button type="submit" disabled={busy} className={buttonClass}>
+              Set Avatar
+            </button>
+            {user.avatar_url && (
+              <button
+                type="button"
+                disabled={busy}
+                onClick={() => saveAvatar(null)}
+                className="px-4 py-2 border border-gray-300 rounded hover:bg-gray-50 transition-colors font-medium disabled:opacity-50"
+              >
+                Remove Avatar
+              </button>
+            )}
+          </div>
+        </form>
+
+        <div className="space-y-2">
+          <label className="text-sm text-gray-600">Spectators</label>
+          <label className="flex items-center gap-2 cursor-pointer">
+            <input
+              type="checkbox"
+              disabled={busy}
+              checked={user.allow_spectators ?? true}
+              onChange={(e) => saveSpectators(e.target.checked)}
+            />
+            Let others watch my matches
+          </label>
+          <p className="text-xs text-gray-500">
+            A match is only open to spectators when both players allow it.
+          </p>
+        </div>
+
+        <form onSubmit={handleEmail} className="space-y-2">
+          <label className="text-sm text-gray-600">Email</label>
+          {user.pending_email && (
+            <p className="text-sm text-gray-600">
+              Waiting for {user.pending_email} to be confirmed.
+            </p>
+          )}
+          <input
+            type="email"
+            required
+            placeholder="New email address"
+            value={email}
+            onChange={(e) => setEmail(e.target.value)}
+            className={inputClass}
+          />
+          <input
+            type="password"
+            placeholder="Current password"
+            autoComplete="current-password"
+            value={emailPassword}
+            onChange={(e) => setEmailPassword(e.target.value)}
+            className={inputClass}
+          />
+          <button type="submit" disabled={busy} className={buttonClass}>
+            Change Email
+          </button>
+        </form>
+
+        <form onSubmit={handlePassword} className="space-y-2">
+          <label className="text-sm text-gray-600">Password</label>
+          <input
+            type="password"
+            required
+            placeholder="Current password"
+            autoComplete="current-password"
+            value={currentPassword}
+            onChange={(e) => setCurrentPassword(e.target.value)}
+            className={inputClass}
+          />
+          <input
+            type="password"
+            required
+            minLength={6}
+            placeholder="New password"
+            autoComplete="new-password"
+            value={newPassword}
+            onChange={(e) => setNewPassword(e.target.value)}
+            className={inputClass}
+          />
+          <button type="submit" disabled={busy} className={buttonClass}>
+            Change Password
+          </button>
+        </form>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,183 @@
+"use client";
+
+import { useState, useEffect, useCallback, FormEvent } from "react";
+import { useAuth } from "@/hooks/useAuth";
+import { api } from "@/lib/api";
+import {
+  ApiScope,
+  ApiToken,
+  ApiTokensResponse,
+  CreateApiTokenResponse,
+} from "@/types/api";
+
+const inputClass =
+  "w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500";
+const buttonClass =
+  "px-4 py-2 bg-brand-600 text-white rounded hover:bg-brand-500 transition-colors font-medium disabled:opacity-50";
+
+const SCOPES: { scope: ApiScope; label: string; staffOnly?: boolean }[] = [
+  { scope: "read:profile", label: "Read your profile" },
+  { scope: "read:matches", label: "Read your matches and stats" },
+  { scope: "play", label: "Play games" },
+  { scope: "admin:read", label: "Read admin data", staffOnly: true },
+];
+
+export default function ApiTokenSettings() {
+  const { user } = useAuth();
+  const [tokens, setTokens] = useState<ApiToken[]>([]);
+  const [name, setName] = useState("");
+  const [scopes, setScopes] = useState<ApiScope[]>(["read:profile"]);
+  const [expiresInDays, setExpiresInDays] = useState("90");
+  const [created, setCreated] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  const loadTokens = useCallback(async () => {
+    const data = await api.get<ApiTokensResponse>("/api/account/tokens");
+    setTokens(data.tokens);
+  }, []);
+
+  useEffect(() => {
+    loadTokens().catch(() => setTokens([]));
+  }, [loadTokens]);
+
+  const toggleScope = (scope: ApiScope) =>
+    setScopes((current) =>
+      current.includes(scope)
+        ? current.filter((s) => s !== scope)
+        : [...current, scope],
+    );
+
+  const handleCreate = async (e: FormEvent) => {
+    e.preventDefault();
+    setError(null);
+    setCreated(null);
+    setBusy(true);
+    try {
+      const data = await api.post<CreateApiTokenResponse>(
+        "/api/account/tokens",
+        {
+          name,
+          scopes,
+          expires_in_days: expiresInDays ? parseInt(expiresInDays) : null,
+        },
+      );
+      setCreated(data.token);
+      setName("");
+      await loadTokens();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to create token");
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleRevoke = async (id: string) => {
+    setError(null);
+    try {
+      await api.delete<void>(`/api/account/tokens/${id}`);
+      await loadTokens();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to revoke token");
+    }
+  };
+
+  if (!user) return null;
+
+  return (
+    <div className="bg-white rounded-lg shadow-md p-6 mb-6">
+      <h2 className="font-serif text-xl font-bold text-brand-800 mb-4">
+        API Tokens
+      </h2>
+      <p className="text-sm text-gray-600 mb-4">
+        Personal tokens let scripts and bots use the API as you. Send one as{" "}
+        <code>Authorization: Bearer &lt;token&gt;</code>.
+      </p>
+
+      {error && (
+        <div className="mb-4 p-3 bg-red-50 border border-red-200 rounded text-red-700 text-sm">
+          {error}
+        </div>
+      )}
+      {created && (
+        <div className="mb-4 p-3 bg-green-50 border border-green-200 rounded text-green-700 text-sm">
+          <p className="mb-1">
+            Copy your new token now. It won&apos;t be shown again.
+          </p>
+          <code className="break-all">{created}</code>
+        </div>
+      )}
+
+      <form onSubmit={handleCreate} className="space-y-2 mb-6">
+        <input
+          type="text"
+          required
+          maxLength={50}
+          placeholder="Token name, e.g. leaderboard export"
+          value={name}
+          onChange={(e) => setName(e.target.value)}
+          className={inputClass}
+        />
+        <div className="space-y-1">
+          {SCOPES.filter(
+            ({ staffOnly }) => !staffOnly || user.role !== "player",
+          ).map(({ scope, label }) => (
+            <label key={scope} className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={scopes.includes(scope)}
+                onChange={() => toggleScope(scope)}
+              />
+              <code>{scope}</code> - {label}
+            </label>
+          ))}
+        </div>
+        <select
+          value={expiresInDays}
+          onChange={(e) => setExpiresInDays(e.target.value)}
+          className={inputClass}
+        >
+          <option value="30">Expires in 30 days</option>
+          <option value="90">Expires in 90 days</option>
+          <option value="365">Expires in a year</option>
+          <option value="">Never expires</option>
+        </select>
+        <button
+          type="submit"
+          disabled={busy || scopes.length === 0}
+          className={buttonClass}
+        >
+          Create Token
+        </button>
+      </form>
+
+      {tokens.length > 0 && (
+        <ul className="divide-y divide-gray-200">
+          {tokens.map((token) => (
+            <li
+              key={token.id}
+              className="py-2 flex items-center justify-between gap-3"
+            >
+              <div className="text-sm">
+                <p className="font-medium">{token.name}</p>
+                <p className="text-gray-500">
+                  {token.scopes.join(", ")} ·{" "}
+                  {token.last_used_at
+                    ? `last used ${token.last_used_at}`
+                    : "never used"}
+                  {token.expires_at && ` · expires ${token.expires_at}`}
+                </p>
+              </div>
+              <button
+                onClick={() => handleRevoke(token.id)}
+                className="text-sm text-red-600 hover:underline"
+              >
+                Revoke
+              </button>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
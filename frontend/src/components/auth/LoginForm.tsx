@@ -1,19 +1,43 @@
 "use client";
 
-import { useState, FormEvent } from "react";
+import { useState, useEffect, FormEvent } from "react";
 import { useRouter } from "next/navigation";
 import Link from "next/link";
 import { useAuth } from "@/hooks/useAuth";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import { faSpinner } from "@fortawesome/free-solid-svg-icons";
+import { api } from "@/lib/api";
+import { API_BASE_URL } from "@/lib/constants";
+import { OidcProvider, OidcProvidersResponse } from "@/types/api";
 
 export default function LoginForm() {
-  const { login } = useAuth();
+  const { login, verifyTwoFactor } = useAuth();
   const router = useRouter();
   const [email, setEmail] = useState("");
   const [password, setPassword] = useState("");
   const [error, setError] = useState("");
   const [loading, setLoading] = useState(false);
+  const [providers, setProviders] = useState<OidcProvider[]>([]);
+  const [challengeToken, setChallengeToken] = useState<string | null>(null);
+  const [code, setCode] = useState("");
+
+  useEffect(() => {
+    // Single sign-on failures come back as /login?error=..., and accounts
+    // with 2FA as /login?challenge_token=...
+    const params = new URLSearchParams(window.location.search);
+    const ssoError = params.get("error");
+    if (ssoError) setError(ssoError);
+    const ssoChallenge = params.get("challenge_token");
+    if (ssoChallenge) {
+      setChallengeToken(ssoChallenge);
+      window.history.replaceState({}, "", window.location.pathname);
+    }
+
+    api
+      .get<OidcProvidersResponse>("/auth/oidc/providers")
+      .then((data) => setProviders(data.providers))
+      .catch(() => setProviders([]));
+  }, []);
 
   const handleSubmit = async (e: FormEvent) => {
     e.preventDefault();
@@ -21,8 +45,12 @@ export default function LoginForm() {
     setLoading(true);
 
     try {
-      await login(email, password);
-      router.push("/play");
+      const challenge = await login(email, password);
+      if (challenge) {
+        setChallengeToken(challenge);
+      } else {
+        router.push("/play");
+      }
     } catch (err) {
       setError(err instanceof Error ? err.message : "Login failed");
     } finally {
@@ -30,6 +58,80 @@ export default function LoginForm() {
     }
   };
 
+  const handleVerify = async (e: FormEvent) => {
+    e.preventDefault();
+    if (!challengeToken) return;
+    setError("");
+    setLoading(true);
+
+    try {
+      await verifyTwoFactor(challengeToken, code);
+      router.push("/play");
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Verification failed");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  if (challengeToken) {
+    return (
+      <div className="max-w-md mx-auto">
+        <h1 className="font-serif text-3xl font-bold text-brand-800 text-center mb-8">
+          Two-Factor Authentication
+        </h1>
+
+        {error && (
+          <div className="bg-red-50 border border-red-200 text-red-700 px-4 py-3 rounded mb-4">
+            {error}
+          </div>
+        )}
+
+        <form onSubmit={handleVerify} className="space-y-4">
+          <div>
+            <label
+              htmlFor="code"
+              className="block text-sm font-medium text-gray-700 mb-1"
+            >
+              Code from your authenticator app, or a recovery code
+            </label>
+            <input
+              id="code"
+              type="text"
+              required
+              autoFocus
+              autoComplete="one-time-code"
+              value={code}
+              onChange={(e) => setCode(e.target.value)}
+              className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
+            />
+          </div>
+          <button
+            type="submit"
+            disabled={loading}
+            className="w-full py-2.5 bg-brand-600 text-white font-medium rounded-lg hover:bg-brand-500 transition-colors disabled:opacity-50 cursor-pointer"
+          >
+            {loading ? <FontAwesomeIcon icon={faSpinner} spin /> : "Verify"}
+          </button>
+        </form>
+
+        <p className="mt-6 text-center text-sm text-gray-600">
+          <button
+            type="button"
+            onClick={() => {
+              setChallengeToken(null);
+              setCode("");
+              setError("");
+            }}
+            className="text-brand-600 hover:underline cursor-pointer"
+          >
+            Start over
+          </button>
+        </p>
+      </div>
+    );
+  }
+
   return (
     <div className="max-w-md mx-auto">
       <h1 className="font-serif text-3xl font-bold text-brand-800 text-center mb-8">
@@ -82,8 +184,27 @@ export default function LoginForm() {
         >
           {loading ? <FontAwesomeIcon icon={faSpinner} spin /> : "Log In"}
         </button>
+        <p className="text-right text-sm">
+          <Link href="/forgot-password" className="text-brand-600 hover:underline">
+            Forgot your password?
+          </Link>
+        </p>
       </form>
 
+      {providers.length > 0 && (
+        <div className="mt-6 space-y-2">
+          {providers.map((provider) => (
+            <a
+              key={provider.id}
+              href={`${API_BASE_URL}/auth/oidc/${provider.id}/start`}
+              className="block w-full py-2.5 text-center border border-gray-300 rounded-lg font-medium text-gray-700 hover:bg-gray-50 transition-colors"
+            >
+              Continue with {provider.name}
+            </a>
+          ))}
+        </div>
+      )}
+
       <p className="mt-6 text-center text-sm text-gray-600">
         Don&apos;t have an account?{" "}
         <Link href="/register" className="text-brand-600 hover:underline">
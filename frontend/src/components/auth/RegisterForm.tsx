@@ -4,6 +4,7 @@ import { useState, FormEvent } from "react";
 import { useRouter } from "next/navigation";
 import Link from "next/link";
 import { useAuth } from "@/hooks/useAuth";
+import { errorMessage, USERNAME_PATTERN } from "@/lib/username";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import { faSpinner } from "@fortawesome/free-solid-svg-icons";
 
@@ -25,7 +26,7 @@ export default function RegisterForm() {
       await register(username, email, password);
       router.push("/play");
     } catch (err) {
-      setError(err instanceof Error ? err.message : "Registration failed");
+      setError(errorMessage(err, "Registration failed"));
     } finally {
       setLoading(false);
     }
@@ -57,6 +58,8 @@ export default function RegisterForm() {
             required
             minLength={3}
             maxLength={20}
+            pattern={USERNAME_PATTERN}
+            title="Letters, digits and underscores"
             value={username}
             onChange={(e) => setUsername(e.target.value)}
             className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
@@ -0,0 +1,86 @@
+"use client";
+
+import { useState, useEffect, useCallback } from "react";
+import { useAuth } from "@/hooks/useAuth";
+import { api } from "@/lib/api";
+import { LoginSession, LoginSessionsResponse } from "@/types/api";
+
+export default function SessionSettings() {
+  const { user, logout } = useAuth();
+  const [sessions, setSessions] = useState<LoginSession[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  const loadSessions = useCallback(async () => {
+    const data = await api.get<LoginSessionsResponse>(
+      "/api/account/sessions",
+    );
+    setSessions(data.sessions);
+  }, []);
+
+  useEffect(() => {
+    loadSessions().catch(() => setSessions([]));
+  }, [loadSessions]);
+
+  const handleRevoke = async (session: LoginSession) => {
+    setError(null);
+    try {
+      await api.delete<void>(`/api/account/sessions/${session.id}`);
+      if (session.current) {
+        await logout();
+        return;
+      }
+      await loadSessions();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to sign out");
+    }
+  };
+
+  if (!user) return null;
+
+  return (
+    <div className="bg-white rounded-lg shadow-md p-6 mb-6">
+      <h2 className="font-serif text-xl font-bold text-brand-800 mb-4">
+        Signed-in Devices
+      </h2>
+      <p className="text-sm text-gray-600 mb-4">
+        Signing a device out ends its session and any game it is playing.
+      </p>
+
+      {error && (
+        <div className="mb-4 p-3 bg-red-50 border border-red-200 rounded text-red-700 text-sm">
+          {error}
+        </div>
+      )}
+
+      <ul className="divide-y divide-gray-200">
+        {sessions.map((session) => (
+          <li
+            key={session.id}
+            className="py-2 flex items-center justify-between gap-3"
+          >
+            <div className="text-sm min-w-0">
+              <p className="font-medium truncate">
+                {session.user_agent ?? "Unknown device"}
+                {session.current && (
+                  <span className="ml-2 px-2 py-0.5 text-xs bg-green-100 text-green-800 rounded">
+                    This device
+                  </span>
+                )}
+              </p>
+              <p className="text-gray-500">
+                Signed in {session.created_at} · last active{" "}
+                {session.last_seen_at}
+              </p>
+            </div>
+            <button
+              onClick={() => handleRevoke(session)}
+              className="text-sm text-red-600 hover:underline whitespace-nowrap"
+            >
+              Sign out
+            </button>
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+}
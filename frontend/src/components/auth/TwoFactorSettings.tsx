@@ -0,0 +1,173 @@
+"use client";
+
+import { useState, useEffect, FormEvent } from "react";
+import { api, storeTokens } from "@/lib/api";
+import {
+  TotpSetupResponse,
+  TwoFactorEnableResponse,
+  TwoFactorStatus,
+} from "@/types/api";
+
+export default function TwoFactorSettings() {
+  const [status, setStatus] = useState<TwoFactorStatus | null>(null);
+  const [setup, setSetup] = useState<TotpSetupResponse | null>(null);
+  const [recoveryCodes, setRecoveryCodes] = useState<string[] | null>(null);
+  const [code, setCode] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  const loadStatus = () =>
+    api
+      .get<TwoFactorStatus>("/auth/2fa")
+      .then(setStatus)
+      .catch(() => setStatus(null));
+
+  useEffect(() => {
+    loadStatus();
+  }, []);
+
+  const run = async (action: () => Promise<void>) => {
+    setError(null);
+    setBusy(true);
+    try {
+      await action();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Something went wrong");
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleSetup = () =>
+    run(async () => {
+      setSetup(await api.post<TotpSetupResponse>("/auth/2fa/setup", {}));
+      setCode("");
+    });
+
+  const handleEnable = (e: FormEvent) => {
+    e.preventDefault();
+    run(async () => {
+      const data = await api.post<TwoFactorEnableResponse>(
+        "/auth/2fa/enable",
+        { code },
+      );
+      // Enabling signs out every other session; keep this one going.
+      storeTokens(data);
+      setRecoveryCodes(data.recovery_codes);
+      setSetup(null);
+      setCode("");
+      await loadStatus();
+    });
+  };
+
+  const handleDisable = (e: FormEvent) => {
+    e.preventDefault();
+    run(async () => {
+      await api.post<void>("/auth/2fa/disable", { code });
+      setRecoveryCodes(null);
+      setCode("");
+      await loadStatus();
+    });
+  };
+
+  if (!status) return null;
+
+  return (
+    <div className="bg-white rounded-lg shadow-md p-6 mb-6">
+      <h2 className="font-serif text-xl font-bold text-brand-800 mb-4">
+        Two-Factor Authentication
+      </h2>
+
+      {error && (
+        <div className="mb-4 p-3 bg-red-50 border border-red-200 rounded text-red-700 text-sm">
+          {error}
+        </div>
+      )}
+
+      {recoveryCodes && (
+        <div className="mb-4 p-4 bg-yellow-50 border border-yellow-200 rounded">
+          <p className="text-sm text-gray-700 mb-2">
+            Save these recovery codes somewhere safe. Each one can be used once
+            if you lose your authenticator, and they won&apos;t be shown again.
+          </p>
+          <ul className="grid grid-cols-2 gap-1 font-mono text-sm">
+            {recoveryCodes.map((c) => (
+              <li key={c}>{c}</li>
+            ))}
+          </ul>
+        </div>
+      )}
+
+      {status.enabled ? (
+        <form onSubmit={handleDisable} className="space-y-3">
+          <p className="text-gray-700">
+            Enabled. {status.recovery_codes_left} recovery codes left.
+          </p>
+          <input
+            type="text"
+            required
+            placeholder="Authenticator or recovery code"
+            autoComplete="one-time-code"
+            value={code}
+            onChange={(e) => setCode(e.target.value)}
+            className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
+          />
+          <button
+            type="submit"
+            disabled={busy}
+            className="px-4 py-2 border border-gray-300 rounded hover:bg-gray-50 transition-colors font-medium disabled:opacity-50"
+          >
+            Disable Two-Factor Authentication
+          </button>
+        </form>
+      ) : setup ? (
+        <form onSubmit={handleEnable} className="space-y-3">
+          <p className="text-gray-700">
+            Scan this code with your authenticator app, then enter the code it
+            shows.
+          </p>
+          {/* eslint-disable-next-line @next/next/no-img-element */}
+          <img
+            src={`data:image/png;base64,${setup.qr_code}`}
+            alt="Two-factor QR code"
+            className="w-48 h-48"
+          />
+          <p className="text-sm text-gray-600">
+            Can&apos;t scan it? Enter this key instead:{" "}
+            <span className="font-mono">{setup.secret}</span>
+          </p>
+          <input
+            type="text"
+            required
+            inputMode="numeric"
+            placeholder="123456"
+            autoComplete="one-time-code"
+            value={code}
+            onChange={(e) => setCode(e.target.value)}
+            className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-brand-500"
+          />
+          <button
+            type="submit"
+            disabled={busy}
+            className="px-4 py-2 bg-brand-600 text-white rounded hover:bg-brand-500 transition-colors font-medium disabled:opacity-50"
+          >
+            Enable
+          </button>
+        </form>
+      ) : (
+        <div className="space-y-3">
+          <p className="text-gray-700">
+            Require a code from an authenticator app when you sign in.
+          </p>
+          <button
+            onClick={handleSetup}
+            disabled={busy}
+            className="px-4 py-2 bg-brand-600 text-white rounded hover:bg-brand-500 transition-colors font-medium disabled:opacity-50"
+          >
+            Set Up Two-Factor Authentication
+          </button>
+        </div>
+      )}
+    </div>
+  );
+}
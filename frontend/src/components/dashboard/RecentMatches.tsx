@@ -17,39 +17,26 @@ interface RecentMatchesProps {
   userId: string;
 }
 
-interface StoredRound {
-  round_number: number;
-  player1_choice: string | null;
-  player2_choice: string | null;
-  winner: string | null;
-}
-
 function parseMoveHistory(
   match: MatchRecord,
   userId: string,
 ): MoveHistoryEntry[] {
   const isPlayer1 = match.player1_id === userId;
 
-  try {
-    const rounds = JSON.parse(match.rounds_json) as StoredRound[];
-    return rounds
-      .map<MoveHistoryEntry>((round) => ({
-        round: round.round_number,
-        playerChoice:
-          (isPlayer1 ? round.player1_choice : round.player2_choice) ?? "none",
-        opponentChoice:
-          (isPlayer1 ? round.player2_choice : round.player1_choice) ?? "none",
-        winner:
-          !round.winner || round.winner === "draw"
-            ? "draw"
-            : round.winner === userId
-              ? "you"
-              : "opponent",
-      }))
-      .sort((a, b) => a.round - b.round);
-  } catch {
-    return [];
-  }
+  return [...(match.rounds ?? [])]
+    .map<MoveHistoryEntry>((round) => ({
+      round: round.round_number,
+      playerChoice:
+        (isPlayer1 ? round.player1_choice : round.player2_choice) ?? "none",
+      opponentChoice:
+        (isPlayer1 ? round.player2_choice : round.player1_choice) ?? "none",
+      winner: !round.winner_id
+        ? "draw"
+        : round.winner_id === userId
+          ? "you"
+          : "opponent",
+    }))
+    .sort((a, b) => a.round - b.round);
 }
 
 export default function RecentMatches({ matches, userId }: RecentMatchesProps) {
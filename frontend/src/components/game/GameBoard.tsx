@@ -7,6 +7,8 @@ import {
   faTrophy,
   faXmark,
   faEquals,
+  faWifi,
+  faEye,
 } from "@fortawesome/free-solid-svg-icons";
 import { Choice, OpponentInfo, MoveHistoryEntry } from "@/types/game";
 import ChoiceButton from "./ChoiceButton";
@@ -17,6 +19,8 @@ interface GameBoardProps {
   timeLeft: number;
   myChoice: Choice | null;
   opponentChose: boolean;
+  opponentAway: boolean;
+  spectators: number;
   myScore: number;
   opponentScore: number;
   moveHistory: MoveHistoryEntry[];
@@ -29,6 +33,8 @@ export default function GameBoard({
   timeLeft,
   myChoice,
   opponentChose,
+  opponentAway,
+  spectators,
   myScore,
   opponentScore,
   moveHistory,
@@ -78,6 +84,12 @@ export default function GameBoard({
           <p className="font-serif text-2xl font-bold text-brand-800">
             {myScore} - {opponentScore}
           </p>
+          {spectators > 0 && (
+            <p className="text-xs text-gray-400">
+              <FontAwesomeIcon icon={faEye} className="mr-1" />
+              {spectators} watching
+            </p>
+          )}
         </div>
         <div className="text-right">
           <div
@@ -97,6 +109,13 @@ export default function GameBoard({
         </div>
       </div>
 
+      {opponentAway && (
+        <p className="text-sm text-yellow-700 bg-yellow-50 rounded-lg px-3 py-2 mb-4">
+          <FontAwesomeIcon icon={faWifi} className="mr-2" />
+          Opponent lost connection. The clock is paused while they reconnect.
+        </p>
+      )}
+
       <p className="text-lg text-gray-700 mb-2">
         {myChoice ? "Waiting for opponent..." : "Make your choice!"}
       </p>
@@ -25,8 +25,8 @@ export default function GuestBanner() {
             Playing as Guest - Unranked Mode
           </h3>
           <p className="text-white/90 text-sm">
-            Create an account to improve your Elo, play ranked matches, and
-            track your match history!
+            Create an account to improve your Elo and play ranked matches.
+            The matches you play as a guest come with you!
           </p>
         </div>
         <Link
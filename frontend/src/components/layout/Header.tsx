@@ -2,6 +2,7 @@
 
 import Link from "next/link";
 import { useAuth } from "@/hooks/useAuth";
+import { hasPermission } from "@/types/user";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import {
   faHandFist,
@@ -11,6 +12,7 @@ import {
   faSignOutAlt,
   faCog,
   faShieldAlt,
+  faEye,
 } from "@fortawesome/free-solid-svg-icons";
 
 export default function Header() {
@@ -44,7 +46,14 @@ export default function Header() {
                 <FontAwesomeIcon icon={faTrophy} />
                 Leaderboard
               </Link>
-              {user.is_admin && (
+              <Link
+                href="/watch"
+                className="flex items-center gap-1.5 hover:text-brand-200 transition-colors text-sm"
+              >
+                <FontAwesomeIcon icon={faEye} />
+                Watch
+              </Link>
+              {hasPermission(user, "users.view") && (
                 <Link
                   href="/admin"
                   className="flex items-center gap-1.5 hover:text-brand-200 transition-colors text-sm"
@@ -89,6 +98,13 @@ export default function Header() {
                 <FontAwesomeIcon icon={faTrophy} />
                 Leaderboard
               </Link>
+              <Link
+                href="/watch"
+                className="flex items-center gap-1.5 hover:text-brand-200 transition-colors text-sm"
+              >
+                <FontAwesomeIcon icon={faEye} />
+                Watch
+              </Link>
               <Link
                 href="/login"
                 className="px-3 py-1.5 rounded border border-brand-300 hover:bg-brand-600 transition-colors text-sm"
@@ -9,44 +9,94 @@ import {
   ReactNode,
 } from "react";
 import { User } from "@/types/user";
-import { api } from "@/lib/api";
-import { MeResponse, AuthResponse } from "@/types/api";
+import {
+  api,
+  clearTokens,
+  refreshAccessToken,
+  storeTokens,
+  TOKEN_CHANGED_EVENT,
+} from "@/lib/api";
+import { clearGuestToken, getStoredGuestToken } from "@/lib/guest";
+import {
+  MeResponse,
+  AuthResponse,
+  LoginResponse,
+  TokenResponse,
+} from "@/types/api";
 
 interface AuthContextValue {
   user: User | null;
   token: string | null;
   loading: boolean;
-  login: (email: string, password: string) => Promise<void>;
+  /** Resolves to a challenge token when a second factor is needed. */
+  login: (email: string, password: string) => Promise<string | null>;
+  verifyTwoFactor: (challengeToken: string, code: string) => Promise<void>;
   register: (
     username: string,
     email: string,
     password: string,
   ) => Promise<void>;
-  logout: () => void;
+  logout: () => Promise<void>;
+  /** Reload the signed-in user after changing their account. */
+  refreshUser: () => Promise<void>;
 }
 
+// Renew the access token this long before it expires.
+const REFRESH_MARGIN_SECS = 60;
+
 const AuthContext = createContext<AuthContextValue | undefined>(undefined);
 
 export function AuthProvider({ children }: { children: ReactNode }) {
   const [user, setUser] = useState<User | null>(null);
   const [token, setToken] = useState<string | null>(null);
   const [loading, setLoading] = useState(true);
+  const [expiresIn, setExpiresIn] = useState<number | null>(null);
 
-  const saveToken = (t: string) => {
-    localStorage.setItem("token", t);
-    setToken(t);
+  const saveTokens = (tokens: TokenResponse) => {
+    storeTokens(tokens);
+    setToken(tokens.token);
+    setExpiresIn(tokens.expires_in);
   };
 
   const clearAuth = () => {
-    localStorage.removeItem("token");
+    clearTokens();
     setToken(null);
     setUser(null);
+    setExpiresIn(null);
   };
 
+  // api.ts refreshes on 401 as well, so follow whichever side rotated last.
+  useEffect(() => {
+    const onTokenChanged = (e: Event) => {
+      const tokens = (e as CustomEvent<TokenResponse>).detail;
+      setToken(tokens.token);
+      setExpiresIn(tokens.expires_in);
+    };
+    window.addEventListener(TOKEN_CHANGED_EVENT, onTokenChanged);
+    return () => window.removeEventListener(TOKEN_CHANGED_EVENT, onTokenChanged);
+  }, []);
+
+  useEffect(() => {
+    if (expiresIn === null) return;
+    const delay = Math.max(expiresIn - REFRESH_MARGIN_SECS, 0) * 1000;
+    const timer = setTimeout(async () => {
+      if (!(await refreshAccessToken()) && !localStorage.getItem("refresh_token")) {
+        clearAuth();
+      }
+    }, delay);
+    return () => clearTimeout(timer);
+  }, [expiresIn]);
+
   const fetchUser = useCallback(async () => {
     try {
       const data = await api.get<MeResponse>("/auth/me");
-      setUser(data.user);
+      setUser({
+        ...data.user,
+        email_verified: data.email_verified,
+        pending_email: data.pending_email,
+        allow_spectators: data.allow_spectators,
+        permissions: data.permissions,
+      });
     } catch {
       clearAuth();
     } finally {
@@ -55,18 +105,26 @@ export function AuthProvider({ children }: { children: ReactNode }) {
   }, []);
 
   useEffect(() => {
-    // Check for token in URL (Google OAuth redirect)
+    // Single sign-on redirects back with a one-time code for the tokens
     const params = new URLSearchParams(window.location.search);
-    const urlToken = params.get("token");
-    if (urlToken) {
-      saveToken(urlToken);
+    const signInCode = params.get("sign_in_code");
+    if (signInCode) {
       window.history.replaceState({}, "", window.location.pathname);
+      api
+        .post<AuthResponse>("/auth/oidc/exchange", { code: signInCode })
+        .then((data) => {
+          storeTokens(data);
+          setToken(data.token);
+          setExpiresIn(data.expires_in);
+          return fetchUser();
+        })
+        .catch(() => setLoading(false));
+      return;
     }
 
-    const stored = urlToken || localStorage.getItem("token");
+    const stored = localStorage.getItem("token");
     if (stored) {
       setToken(stored);
-      if (urlToken) saveToken(urlToken);
       fetchUser();
     } else {
       setLoading(false);
@@ -74,12 +132,24 @@ export function AuthProvider({ children }: { children: ReactNode }) {
   }, [fetchUser]);
 
   const login = async (email: string, password: string) => {
-    const data = await api.post<AuthResponse>("/auth/login", {
+    const data = await api.post<LoginResponse>("/auth/login", {
       email,
       password,
     });
-    saveToken(data.token);
-    setUser(data.user);
+    if ("two_factor_required" in data) return data.challenge_token;
+    saveTokens(data);
+    // /auth/me also carries the user's permissions.
+    await fetchUser();
+    return null;
+  };
+
+  const verifyTwoFactor = async (challengeToken: string, code: string) => {
+    const data = await api.post<AuthResponse>("/auth/2fa/verify", {
+      challenge_token: challengeToken,
+      code,
+    });
+    saveTokens(data);
+    await fetchUser();
   };
 
   const register = async (
@@ -91,18 +161,36 @@ export function AuthProvider({ children }: { children: ReactNode }) {
       username,
       email,
       password,
+      // Matches played as a guest move to the new account
+      guest_token: getStoredGuestToken(),
     });
-    saveToken(data.token);
-    setUser(data.user);
+    clearGuestToken();
+    saveTokens(data);
+    setUser({ ...data.user, email_verified: data.email_verified });
   };
 
-  const logout = () => {
+  const logout = async () => {
+    const refreshToken = localStorage.getItem("refresh_token");
     clearAuth();
+    if (refreshToken) {
+      await api
+        .post<void>("/auth/logout", { refresh_token: refreshToken })
+        .catch(() => {});
+    }
   };
 
   return (
     <AuthContext.Provider
-      value={{ user, token, loading, login, register, logout }}
+      value={{
+        user,
+        token,
+        loading,
+        login,
+        verifyTwoFactor,
+        register,
+        logout,
+        refreshUser: fetchUser,
+      }}
     >
       {children}
     </AuthContext.Provider>
@@ -9,6 +9,7 @@ import {
   Choice,
   MoveHistoryEntry,
   RoundWinner,
+  GameStateSnapshot,
 } from "@/types/game";
 
 interface UseGameStateProps {
@@ -24,6 +25,9 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
   const [currentRound, setCurrentRound] = useState(1);
   const [timeLeft, setTimeLeft] = useState(15);
   const [opponentChose, setOpponentChose] = useState(false);
+  // The server stops the round clock while the opponent reconnects
+  const [opponentAway, setOpponentAway] = useState(false);
+  const [spectators, setSpectators] = useState(0);
   const [roundResult, setRoundResult] = useState<RoundResult | null>(null);
   const [matchResult, setMatchResult] = useState<MatchResult | null>(null);
   const [myChoice, setMyChoice] = useState<Choice | null>(null);
@@ -46,10 +50,36 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
           setCurrentRound(1);
           setMyChoice(null);
           setOpponentChose(false);
+          setOpponentAway(false);
+          setSpectators(0);
           setMyScore(0);
           setOpponentScore(0);
           setMoveHistory([]);
           break;
+        case "game_state": {
+          const state = data as unknown as GameStateSnapshot;
+          setOpponent(state.opponent);
+          setCurrentRound(state.round);
+          setTimeLeft(Math.ceil(state.time_left_ms / 1000));
+          setMyChoice(state.your_choice);
+          setOpponentChose(state.opponent_chose);
+          setOpponentAway(!state.opponent_connected);
+          setMyScore(state.your_score);
+          setOpponentScore(state.opponent_score);
+          setRoundResult(null);
+          setError(null);
+          setStatus("playing");
+          break;
+        }
+        case "opponent_reconnecting":
+          setOpponentAway(true);
+          break;
+        case "opponent_reconnected":
+          setOpponentAway(false);
+          break;
+        case "spectators":
+          setSpectators(data.count as number);
+          break;
         case "round_start":
           setCurrentRound(data.round as number);
           setTimeLeft(data.timeout_secs as number);
@@ -85,7 +115,21 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
           setMatchResult(data as unknown as MatchResult);
           setStatus("match_complete");
           break;
+        case "result_not_recorded":
+          setMatchResult({
+            result: data.result as MatchResult["result"],
+            your_score: data.your_score as number,
+            opponent_score: data.opponent_score as number,
+            elo_change: null,
+            new_elo: null,
+          });
+          setError(
+            "This match could not be recorded. Your rating is unchanged.",
+          );
+          setStatus("match_complete");
+          break;
         case "opponent_disconnected":
+          setOpponentAway(false);
           setError("Opponent disconnected");
           break;
         case "error":
@@ -99,7 +143,7 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
 
   // Countdown timer
   useEffect(() => {
-    if (status !== "playing" || timeLeft <= 0) return;
+    if (status !== "playing" || opponentAway || timeLeft <= 0) return;
 
     const interval = setInterval(() => {
       setTimeLeft((t) => {
@@ -112,7 +156,7 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
     }, 1000);
 
     return () => clearInterval(interval);
-  }, [status, timeLeft]);
+  }, [status, opponentAway, timeLeft]);
 
   const joinQueue = useCallback(
     (ranked = true) => {
@@ -144,6 +188,8 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
     setCurrentRound(1);
     setTimeLeft(15);
     setOpponentChose(false);
+    setOpponentAway(false);
+    setSpectators(0);
     setRoundResult(null);
     setMatchResult(null);
     setMyChoice(null);
@@ -159,6 +205,8 @@ export function useGameState({ send, addMessageHandler }: UseGameStateProps) {
     currentRound,
     timeLeft,
     opponentChose,
+    opponentAway,
+    spectators,
     roundResult,
     matchResult,
     myChoice,
@@ -0,0 +1,119 @@
+"use client";
+
+import { useEffect, useState } from "react";
+import { createSpectatorSocket } from "@/lib/ws";
+import {
+  OpponentInfo,
+  SpectatedRound,
+  SpectatorWinner,
+} from "@/types/game";
+
+export type SpectatorStatus = "connecting" | "watching" | "ended";
+
+export interface SpectatorFinal {
+  winner: SpectatorWinner;
+  status: "completed" | "forfeit";
+}
+
+/** Follows a match from a spectator socket. */
+export function useSpectator(sessionId: string) {
+  const [status, setStatus] = useState<SpectatorStatus>("connecting");
+  const [player1, setPlayer1] = useState<OpponentInfo | null>(null);
+  const [player2, setPlayer2] = useState<OpponentInfo | null>(null);
+  const [currentRound, setCurrentRound] = useState(1);
+  const [timeLeft, setTimeLeft] = useState(0);
+  const [player1Score, setPlayer1Score] = useState(0);
+  const [player2Score, setPlayer2Score] = useState(0);
+  const [rounds, setRounds] = useState<SpectatedRound[]>([]);
+  // Which player dropped, while the clock is stopped for them
+  const [reconnecting, setReconnecting] = useState<string | null>(null);
+  const [final, setFinal] = useState<SpectatorFinal | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    const socket = createSpectatorSocket(sessionId);
+
+    socket.onmessage = (event) => {
+      let data: Record<string, unknown>;
+      try {
+        data = JSON.parse(event.data);
+      } catch {
+        return;
+      }
+
+      switch (data.type as string) {
+        case "spectating":
+          setPlayer1(data.player1 as OpponentInfo);
+          setPlayer2(data.player2 as OpponentInfo);
+          setCurrentRound(data.round as number);
+          setTimeLeft(Math.ceil((data.time_left_ms as number) / 1000));
+          setPlayer1Score(data.player1_score as number);
+          setPlayer2Score(data.player2_score as number);
+          setStatus("watching");
+          break;
+        case "round_start":
+          setCurrentRound(data.round as number);
+          setTimeLeft(data.timeout_secs as number);
+          break;
+        case "round_result":
+          setPlayer1Score(data.player1_score as number);
+          setPlayer2Score(data.player2_score as number);
+          setRounds((prev) => [
+            ...prev.filter((r) => r.round !== data.round),
+            {
+              round: data.round as number,
+              player1_choice: data.player1_choice as string,
+              player2_choice: data.player2_choice as string,
+              winner: data.winner as SpectatorWinner,
+            },
+          ]);
+          break;
+        case "player_reconnecting":
+          setReconnecting(data.player as string);
+          break;
+        case "player_reconnected":
+          setReconnecting(null);
+          break;
+        case "match_complete":
+          setPlayer1Score(data.player1_score as number);
+          setPlayer2Score(data.player2_score as number);
+          setFinal({
+            winner: data.winner as SpectatorWinner,
+            status: data.status as SpectatorFinal["status"],
+          });
+          setReconnecting(null);
+          setStatus("ended");
+          break;
+        case "error":
+          setError(data.message as string);
+          break;
+      }
+    };
+    socket.onclose = () =>
+      setStatus((s) => (s === "connecting" ? "ended" : s));
+
+    return () => socket.close();
+  }, [sessionId]);
+
+  // Countdown timer
+  useEffect(() => {
+    if (status !== "watching" || reconnecting || timeLeft <= 0) return;
+
+    const timer = setTimeout(() => setTimeLeft((t) => Math.max(t - 1, 0)), 1000);
+    return () => clearTimeout(timer);
+  }, [status, reconnecting, timeLeft]);
+
+  return {
+    status,
+    player1,
+    player2,
+    currentRound,
+    timeLeft,
+    player1Score,
+    player2Score,
+    rounds,
+    reconnecting,
+    final,
+    error,
+  };
+}
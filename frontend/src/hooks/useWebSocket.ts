@@ -5,10 +5,18 @@ import { createGameSocket } from "@/lib/ws";
 
 type MessageHandler = (data: Record<string, unknown>) => void;
 
+// The server holds a match open for a dropped player for a short while, so
+// retry quickly at first.
+const RECONNECT_DELAYS_MS = [500, 1000, 2000, 4000, 8000];
+// Closed by the server on purpose, e.g. after a ban or a revoked session.
+const POLICY_CLOSE = 1008;
+
 export function useWebSocket(token: string | null, allowGuest = false) {
   const wsRef = useRef<WebSocket | null>(null);
   const [connected, setConnected] = useState(false);
   const handlersRef = useRef<MessageHandler[]>([]);
+  const retriesRef = useRef(0);
+  const [attempt, setAttempt] = useState(0);
 
   const addMessageHandler = useCallback((handler: MessageHandler) => {
     handlersRef.current.push(handler);
@@ -28,28 +36,60 @@ export function useWebSocket(token: string | null, allowGuest = false) {
     // This prevents connection attempts during initial auth loading
     if (!token && !allowGuest) return;
 
-    const ws = createGameSocket(token);
-    wsRef.current = ws;
-
-    ws.onopen = () => setConnected(true);
-    ws.onclose = () => setConnected(false);
-    ws.onerror = () => setConnected(false);
+    let cancelled = false;
+    let ws: WebSocket | null = null;
+    let retryTimer: ReturnType<typeof setTimeout> | undefined;
 
-    ws.onmessage = (event) => {
-      try {
-        const data = JSON.parse(event.data);
-        handlersRef.current.forEach((handler) => handler(data));
-      } catch {
-        // ignore invalid messages
+    const retry = () => {
+      if (cancelled || retriesRef.current >= RECONNECT_DELAYS_MS.length) {
+        return;
       }
+      const delay = RECONNECT_DELAYS_MS[retriesRef.current];
+      retriesRef.current += 1;
+      retryTimer = setTimeout(() => setAttempt((a) => a + 1), delay);
     };
 
+    createGameSocket(token)
+      .then((socket) => {
+        if (cancelled) {
+          socket.close();
+          return;
+        }
+        ws = socket;
+        wsRef.current = socket;
+
+        socket.onopen = () => {
+          retriesRef.current = 0;
+          setConnected(true);
+        };
+        socket.onclose = (event) => {
+          setConnected(false);
+          if (event.code !== POLICY_CLOSE) retry();
+        };
+        socket.onerror = () => setConnected(false);
+
+        socket.onmessage = (event) => {
+          try {
+            const data = JSON.parse(event.data);
+            handlersRef.current.forEach((handler) => handler(data));
+          } catch {
+            // ignore invalid messages
+          }
+        };
+      })
+      .catch(() => {
+        setConnected(false);
+        retry();
+      });
+
     return () => {
-      ws.close();
+      cancelled = true;
+      clearTimeout(retryTimer);
+      ws?.close();
       wsRef.current = null;
       setConnected(false);
     };
-  }, [token, allowGuest]);
+  }, [token, allowGuest, attempt]);
 
   return { connected, send, addMessageHandler };
 }
@@ -1,11 +1,72 @@
 import { API_BASE_URL } from "./constants";
+import { ApiError, TokenResponse, UsernameViolation } from "@/types/api";
+
+export const TOKEN_CHANGED_EVENT = "auth:token-changed";
+
+/** A failed request, with the structured reason the server gave, if any. */
+export class RequestError extends Error {
+  constructor(
+    readonly status: number,
+    message: string,
+    readonly reason?: UsernameViolation,
+  ) {
+    super(message);
+  }
+}
 
 function getToken(): string | null {
   if (typeof window === "undefined") return null;
   return localStorage.getItem("token");
 }
 
-async function request<T>(path: string, options: RequestInit = {}): Promise<T> {
+function getRefreshToken(): string | null {
+  if (typeof window === "undefined") return null;
+  return localStorage.getItem("refresh_token");
+}
+
+export function storeTokens(tokens: TokenResponse) {
+  localStorage.setItem("token", tokens.token);
+  localStorage.setItem("refresh_token", tokens.refresh_token);
+  window.dispatchEvent(new CustomEvent(TOKEN_CHANGED_EVENT, { detail: tokens }));
+}
+
+export function clearTokens() {
+  localStorage.removeItem("token");
+  localStorage.removeItem("refresh_token");
+}
+
+// Refresh tokens rotate on every use, so concurrent 401s must share a single
+// refresh call; a second call with the same token would revoke the session.
+let refreshInFlight: Promise<boolean> | null = null;
+
+export function refreshAccessToken(): Promise<boolean> {
+  if (!refreshInFlight) {
+    refreshInFlight = (async () => {
+      const refreshToken = getRefreshToken();
+      if (!refreshToken) return false;
+      const res = await fetch(`${API_BASE_URL}/auth/refresh`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ refresh_token: refreshToken }),
+      }).catch(() => null);
+      if (!res?.ok) {
+        if (res?.status === 401) clearTokens();
+        return false;
+      }
+      storeTokens(await res.json());
+      return true;
+    })().finally(() => {
+      refreshInFlight = null;
+    });
+  }
+  return refreshInFlight;
+}
+
+async function request<T>(
+  path: string,
+  options: RequestInit = {},
+  retry = true,
+): Promise<T> {
   const token = getToken();
   const headers: Record<string, string> = {
     "Content-Type": "application/json",
@@ -21,9 +82,23 @@ async function request<T>(path: string, options: RequestInit = {}): Promise<T> {
     headers,
   });
 
+  if (res.status === 401 && token && retry && (await refreshAccessToken())) {
+    return request<T>(path, options, false);
+  }
+
   if (!res.ok) {
-    const body = await res.json().catch(() => ({ error: "Unknown error" }));
-    throw new Error(body.error || `Request failed: ${res.status}`);
+    const body: ApiError = await res
+      .json()
+      .catch(() => ({ error: "Unknown error" }));
+    throw new RequestError(
+      res.status,
+      body.error || `Request failed: ${res.status}`,
+      body.reason,
+    );
+  }
+
+  if (res.status === 204) {
+    return undefined as T;
   }
 
   return res.json();
@@ -0,0 +1,26 @@
+import { api } from "./api";
+import { GuestResponse } from "@/types/api";
+
+const GUEST_TOKEN_KEY = "guest_token";
+
+/** The stored guest token, if this browser has played as a guest. */
+export function getStoredGuestToken(): string | null {
+  if (typeof window === "undefined") return null;
+  return localStorage.getItem(GUEST_TOKEN_KEY);
+}
+
+export function clearGuestToken() {
+  localStorage.removeItem(GUEST_TOKEN_KEY);
+}
+
+/**
+ * A guest token for the game socket. Renewing the stored one keeps the same
+ * guest name and id, so the matches played stay claimable at sign-up.
+ */
+export async function getGuestToken(): Promise<string> {
+  const { guest_token } = await api.post<GuestResponse>("/auth/guest", {
+    guest_token: getStoredGuestToken(),
+  });
+  localStorage.setItem(GUEST_TOKEN_KEY, guest_token);
+  return guest_token;
+}
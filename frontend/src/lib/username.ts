@@ -0,0 +1,32 @@
+import { RequestError } from "./api";
+import { UsernameViolation } from "@/types/api";
+
+/** Mirrors the server's charset so the form can refuse obvious typos early. */
+export const USERNAME_PATTERN = "[A-Za-z0-9_]+";
+
+export function describeUsernameViolation(reason: UsernameViolation): string {
+  switch (reason.code) {
+    case "too_short":
+      return `Usernames need at least ${reason.min} characters.`;
+    case "too_long":
+      return `Usernames can be at most ${reason.max} characters.`;
+    case "invalid_characters":
+      return "Use only letters, digits and underscores.";
+    case "confusable":
+      return "That name uses characters that imitate Latin letters. Type it with a regular keyboard layout.";
+    case "reserved":
+      return "That name is reserved. Please pick another.";
+    case "profanity":
+      return "That name isn't allowed. Please pick another.";
+    case "taken":
+      return "Someone already has that name (names ignore upper and lower case).";
+  }
+}
+
+/** The message to show for a failed request that may have refused a username. */
+export function errorMessage(err: unknown, fallback: string): string {
+  if (err instanceof RequestError && err.reason) {
+    return describeUsernameViolation(err.reason);
+  }
+  return err instanceof Error ? err.message : fallback;
+}
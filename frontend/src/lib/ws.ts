@@ -1,6 +1,27 @@
 import { WS_BASE_URL } from "./constants";
+import { api } from "./api";
+import { getGuestToken } from "./guest";
+import { WsTicketResponse } from "@/types/api";
 
-export function createGameSocket(token: string | null): WebSocket {
-  const url = token ? `${WS_BASE_URL}/ws?token=${token}` : `${WS_BASE_URL}/ws`;
-  return new WebSocket(url);
+/**
+ * Open the game socket. Players trade their access token, or guests their
+ * guest token, for a single-use ticket first, so neither token ever appears
+ * in a URL.
+ */
+export async function createGameSocket(
+  token: string | null,
+): Promise<WebSocket> {
+  const { ticket } = token
+    ? await api.post<WsTicketResponse>("/auth/ws-ticket", {})
+    : await api.post<WsTicketResponse>("/auth/guest/ws-ticket", {
+        guest_token: await getGuestToken(),
+      });
+  return new WebSocket(`${WS_BASE_URL}/ws?ticket=${encodeURIComponent(ticket)}`);
+}
+
+/** Watch a running match. Spectating needs no sign-in. */
+export function createSpectatorSocket(sessionId: string): WebSocket {
+  return new WebSocket(
+    `${WS_BASE_URL}/ws/spectate/${encodeURIComponent(sessionId)}`,
+  );
 }
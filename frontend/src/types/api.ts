@@ -1,25 +1,152 @@
 import { User } from "./user";
-import { MatchRecord } from "./game";
+import { ChoiceStats, EloHistoryEntry, LiveMatch, MatchRecord } from "./game";
 
-export interface AuthResponse {
+export interface TokenResponse {
   token: string;
+  refresh_token: string;
+  /** Seconds until the access token expires. */
+  expires_in: number;
+}
+
+export interface AuthResponse extends TokenResponse {
   user: User;
+  email_verified: boolean;
+}
+
+/** Returned by login instead of tokens when the account has 2FA enabled. */
+export interface TwoFactorChallenge {
+  two_factor_required: true;
+  challenge_token: string;
+}
+
+export type LoginResponse = AuthResponse | TwoFactorChallenge;
+
+export interface TwoFactorStatus {
+  enabled: boolean;
+  recovery_codes_left: number;
+}
+
+export interface TotpSetupResponse {
+  secret: string;
+  otpauth_url: string;
+  /** Base64 PNG of the QR code for otpauth_url. */
+  qr_code: string;
+}
+
+export interface TwoFactorEnableResponse extends TokenResponse {
+  recovery_codes: string[];
+}
+
+/** Single-use ticket for opening the game WebSocket. */
+export interface WsTicketResponse {
+  ticket: string;
+  /** Seconds until the ticket expires. */
+  expires_in: number;
+}
+
+export interface Guest {
+  id: string;
+  username: string;
+}
+
+/** A guest identity for playing without an account. */
+export interface GuestResponse {
+  guest_token: string;
+  guest: Guest;
+  /** Seconds until the guest token expires. */
+  expires_in: number;
+}
+
+export interface OidcProvider {
+  id: string;
+  name: string;
+}
+
+export interface OidcProvidersResponse {
+  providers: OidcProvider[];
 }
 
 export interface MeResponse {
   user: User;
+  email_verified: boolean;
+  pending_email: string | null;
+  allow_spectators: boolean;
+  permissions: string[];
+}
+
+export interface UserResponse {
+  user: User;
+}
+
+export interface UserProfileResponse extends UserResponse {
+  /** Earlier usernames, most recent first. */
+  previous_usernames: string[];
 }
 
 export interface DashboardResponse {
   user: User;
   recent_matches: MatchRecord[];
   win_rate: number;
+  choice_stats: ChoiceStats;
+  rating_history: EloHistoryEntry[];
 }
 
 export interface LeaderboardResponse {
   leaderboard: User[];
 }
 
+export interface LiveMatchesResponse {
+  matches: LiveMatch[];
+}
+
+/** Why the server refused a username; `code` picks the message to show. */
+export type UsernameViolation =
+  | { code: "too_short"; min: number }
+  | { code: "too_long"; max: number }
+  | { code: "invalid_characters" }
+  | { code: "confusable" }
+  | { code: "reserved" }
+  | { code: "profanity" }
+  | { code: "taken" };
+
 export interface ApiError {
   error: string;
+  /** Set when a username was refused. */
+  reason?: UsernameViolation;
+}
+
+export type ApiScope = "read:profile" | "read:matches" | "admin:read" | "play";
+
+/** A personal API token as listed; the token itself is only shown once. */
+export interface ApiToken {
+  id: string;
+  name: string;
+  scopes: ApiScope[];
+  expires_at: string | null;
+  last_used_at: string | null;
+  created_at: string;
+}
+
+export interface ApiTokensResponse {
+  tokens: ApiToken[];
+}
+
+export interface CreateApiTokenResponse {
+  token: string;
+  api_token: ApiToken;
+}
+
+/** A signed-in device. Revoking it signs the device out at once. */
+export interface LoginSession {
+  id: string;
+  user_agent: string | null;
+  created_at: string;
+  last_seen_at: string;
+  expires_at: string;
+  /** Whether this is the session viewing the list. */
+  current?: boolean;
+}
+
+export interface LoginSessionsResponse {
+  sessions: LoginSession[];
 }
@@ -14,6 +14,37 @@ export interface OpponentInfo {
   elo: number;
 }
 
+/** Sent when a new socket rejoins a match in progress. */
+export interface GameStateSnapshot {
+  round: number;
+  timeout_secs: number;
+  time_left_ms: number;
+  your_choice: Choice | null;
+  opponent_chose: boolean;
+  your_score: number;
+  opponent_score: number;
+  opponent: OpponentInfo;
+  opponent_connected: boolean;
+}
+
+/** A running match that can be watched. */
+export interface LiveMatch {
+  session_id: string;
+  player1: OpponentInfo;
+  player2: OpponentInfo;
+  is_ranked: boolean;
+  spectators_allowed: boolean;
+}
+
+export type SpectatorWinner = "player1" | "player2" | "draw";
+
+export interface SpectatedRound {
+  round: number;
+  player1_choice: string;
+  player2_choice: string;
+  winner: SpectatorWinner;
+}
+
 export interface RoundResult {
   round: number;
   your_choice: string;
@@ -40,6 +71,16 @@ export interface MoveHistoryEntry {
   winner: RoundWinner;
 }
 
+export interface StoredRound {
+  round_number: number;
+  player1_choice: string | null;
+  player2_choice: string | null;
+  winner_id: string | null;
+  started_at: string | null;
+  player1_decided_at: string | null;
+  player2_decided_at: string | null;
+}
+
 export interface MatchRecord {
   id: string;
   player1_id: string;
@@ -48,7 +89,7 @@ export interface MatchRecord {
   is_ranked: boolean;
   player1_score: number;
   player2_score: number;
-  rounds_json: string;
+  rounds: StoredRound[];
   player1_elo_before: number | null;
   player1_elo_after: number | null;
   player2_elo_before: number | null;
@@ -57,3 +98,20 @@ export interface MatchRecord {
   created_at: string;
   finished_at: string | null;
 }
+
+export interface ChoiceStats {
+  rock: number;
+  paper: number;
+  scissors: number;
+  none: number;
+}
+
+export interface EloHistoryEntry {
+  id: string;
+  user_id: string;
+  match_id: string;
+  elo_before: number;
+  elo_after: number;
+  elo_change: number;
+  created_at: string;
+}
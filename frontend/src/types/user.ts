@@ -1,16 +1,37 @@
+export type Role = "player" | "support" | "moderator" | "admin";
+
 export interface User {
   id: string;
   username: string;
   email?: string;
+  email_verified?: boolean;
+  /** Address waiting to be confirmed before it replaces the current one. */
+  pending_email?: string | null;
+  /** Whether others may watch this user's matches; only set for the signed-in user. */
+  allow_spectators?: boolean;
   avatar_url: string | null;
   elo: number;
   total_games: number;
   wins: number;
   losses: number;
   draws: number;
-  is_admin: boolean;
+  role: Role;
+  /** Only set for the signed-in user, see `hasPermission`. */
+  permissions?: string[];
   is_banned?: boolean;
   banned_at?: string | null;
   banned_reason?: string | null;
   created_at?: string;
 }
+
+/** Staff can only manage users ranked below them. */
+export const ROLE_RANK: Record<Role, number> = {
+  player: 0,
+  support: 1,
+  moderator: 2,
+  admin: 3,
+};
+
+export function hasPermission(user: User | null, permission: string): boolean {
+  return user?.permissions?.includes(permission) ?? false;
+}